@@ -0,0 +1,52 @@
+// The C API for the fantasy engine, implemented in src/ffi.rs.
+//
+// Grammars, parsers, and correctors are opaque handles that the caller frees with the
+// matching _free function. Parsers and correctors borrow their grammar, so it must
+// outlive them.
+//
+// Calls that can fail return a status code. On success, they write their result to
+// their out pointer: a handle, or a UTF-8 JSON string that the caller frees with
+// fantasy_string_free. On failure, fantasy_last_error returns a message for the
+// thread's last error, which the caller also frees with fantasy_string_free.
+
+#ifndef FANTASY_H
+#define FANTASY_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define FANTASY_OK 0
+#define FANTASY_NULL_POINTER 1
+#define FANTASY_INVALID_UTF8 2
+#define FANTASY_FAILED 3
+#define FANTASY_PANIC 4
+
+typedef struct FantasyGrammar FantasyGrammar;
+typedef struct FantasyParser FantasyParser;
+typedef struct FantasyCorrector FantasyCorrector;
+
+int fantasy_grammar_new(const char* text, FantasyGrammar** out);
+void fantasy_grammar_free(FantasyGrammar* grammar);
+
+int fantasy_parser_new(const FantasyGrammar* grammar, FantasyParser** out);
+void fantasy_parser_free(FantasyParser* parser);
+int fantasy_parser_parse(
+    const FantasyParser* parser, const char* input, const char* scheme, char** out);
+
+int fantasy_corrector_new(const FantasyGrammar* grammar, FantasyCorrector** out);
+void fantasy_corrector_free(FantasyCorrector* corrector);
+int fantasy_corrector_correct(
+    const FantasyCorrector* corrector, const FantasyParser* parser, const char* input,
+    uint64_t seed, const char* scheme, char** out);
+
+char* fantasy_last_error(void);
+void fantasy_string_free(char* text);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif  // FANTASY_H
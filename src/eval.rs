@@ -0,0 +1,278 @@
+use super::lib::base::{HashMap, HashSet, Result};
+use super::nlu::base::{Grammar, Rule, Term};
+use super::nlu::parser::Parser;
+use super::payload::base::Payload;
+use super::payload::lambda::{Binary, Expr, Lambda};
+
+// Evaluates a grammar against a corpus, for measuring regressions as it changes.
+// Each row of the corpus is an utterance and its gold semantics, separated by a tab:
+//
+//   mujhe pani chahie<TAB>Tell(I, want.type.water)
+//
+// Blank lines and lines starting with "#" are skipped. We parse each utterance and
+// score its value against the gold semantics with the metrics below. We also count
+// how often each rule appears in the corpus's parses, so that rules that no test
+// covers stand out.
+
+type Value = Grammar<Option<Lambda>, Lambda>;
+
+pub struct Failure {
+  pub line: usize,
+  pub input: String,
+  pub expected: String,
+  pub actual: Option<String>,
+}
+
+pub struct Evaluation {
+  pub total: usize,
+  pub parsed: usize,
+  pub metrics: Metrics,
+  pub failures: Vec<Failure>,
+  pub usage: Vec<(String, usize)>,
+}
+
+impl Evaluation {
+  pub fn summary(&self) -> Vec<String> {
+    let rate = 100.0 * self.parsed as f32 / std::cmp::max(self.total, 1) as f32;
+    let mut result = vec![format!("Parsed: {}/{} ({:.1}%)", self.parsed, self.total, rate)];
+    result.append(&mut self.metrics.summary());
+    for failure in &self.failures {
+      let Failure { line, input, expected, actual } = failure;
+      let problem = if actual.is_some() { "Wrong value" } else { "Failed to parse" };
+      result.push(format!("{} (line {}): {}", problem, line, input));
+      result.push(format!("  expected: {}", expected));
+      actual.iter().for_each(|x| result.push(format!("  actual:   {}", x)));
+    }
+    result.push("Rule usage:".into());
+    self.usage.iter().for_each(|(x, y)| result.push(format!("{:>6}  {}", y, x)));
+    result
+  }
+}
+
+// Metrics for predicted semantics against gold semantics, for any semantic parser:
+//
+//   exact: The number of predictions that equal the gold semantics, after we normalize
+//          both, so that, e.g., the order of the terms of a conjunction doesn't matter.
+//
+//   f1: The mean F1 score of the predictions' conjuncts against the gold conjuncts. A
+//       lambda's conjuncts are the paths from its root to each of its leaves, so that
+//       "Tell(I, want.(count.2 & type.bread))" has the conjuncts "Tell", "Tell[0]:I",
+//       "Tell[1]:want.count.2", and "Tell[1]:want.type.bread". A missing prediction
+//       scores 0, and an empty prediction of empty gold semantics scores 1.
+//
+//   confusion: Counts of (gold intent, predicted intent) pairs, most common first. An
+//              intent is the name of a lambda's root custom function, like "Tell".
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Metrics {
+  pub total: usize,
+  pub exact: usize,
+  pub f1: f32,
+  pub confusion: Vec<(String, String, usize)>,
+}
+
+impl Metrics {
+  pub fn summary(&self) -> Vec<String> {
+    let rate = 100.0 * self.exact as f32 / std::cmp::max(self.total, 1) as f32;
+    let mut result = vec![
+      format!("Exact match: {}/{} ({:.1}%)", self.exact, self.total, rate),
+      format!("Conjunct F1: {:.3}", self.f1),
+      "Intent confusion:".into(),
+    ];
+    for (gold, predicted, count) in &self.confusion {
+      result.push(format!("{:>6}  {} -> {}", count, gold, predicted));
+    }
+    result
+  }
+}
+
+pub fn score(pairs: &[(Option<Lambda>, Lambda)]) -> Metrics {
+  let (mut exact, mut f1, mut counts) = (0, 0.0, HashMap::<_, usize>::default());
+  for (predicted, gold) in pairs {
+    exact += predicted.as_ref().map(|x| matches(x, gold)).unwrap_or_default() as usize;
+    f1 += predicted.as_ref().map(|x| conjunct_f1(x, gold)).unwrap_or_default();
+    let predicted = predicted.as_ref().map(intent).unwrap_or_else(|| "(unparsed)".into());
+    *counts.entry((intent(gold), predicted)).or_default() += 1;
+  }
+  let mut confusion: Vec<_> = counts.into_iter().map(|((x, y), z)| (x, y, z)).collect();
+  confusion.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| (&a.0, &a.1).cmp(&(&b.0, &b.1))));
+  let f1 = if pairs.is_empty() { 0.0 } else { f1 / pairs.len() as f32 };
+  Metrics { total: pairs.len(), exact, f1, confusion }
+}
+
+pub fn matches(predicted: &Lambda, gold: &Lambda) -> bool {
+  predicted.normalize(false) == gold.normalize(false)
+}
+
+pub fn conjunct_f1(predicted: &Lambda, gold: &Lambda) -> f32 {
+  let (predicted, gold) = (conjuncts(predicted), conjuncts(gold));
+  if predicted.is_empty() && gold.is_empty() {
+    return 1.0;
+  }
+  let overlap = predicted.intersection(&gold).count() as f32;
+  2.0 * overlap / (predicted.len() + gold.len()) as f32
+}
+
+pub fn conjuncts(x: &Lambda) -> HashSet<String> {
+  let mut result = HashSet::default();
+  conjuncts_helper(&x.normalize(false), "", &mut result);
+  result
+}
+
+fn conjuncts_helper(x: &Lambda, prefix: &str, result: &mut HashSet<String>) {
+  match x.expr() {
+    Expr::Binary(Binary::Conjunction, xs) => {
+      xs.iter().for_each(|y| conjuncts_helper(y, prefix, result));
+    }
+    Expr::Binary(Binary::Join, xs) if !xs.is_empty() => {
+      let path: Vec<_> = xs[..xs.len() - 1].iter().map(|y| format!("{}.", y.repr())).collect();
+      conjuncts_helper(&xs[xs.len() - 1], &format!("{}{}", prefix, path.join("")), result);
+    }
+    Expr::Custom(name, xs) => {
+      result.insert(format!("{}{}", prefix, name));
+      for (i, y) in xs.iter().enumerate() {
+        conjuncts_helper(y, &format!("{}{}[{}]:", prefix, name, i), result);
+      }
+    }
+    Expr::Unknown => {}
+    _ => {
+      result.insert(format!("{}{}", prefix, x.repr()));
+    }
+  }
+}
+
+pub fn intent(x: &Lambda) -> String {
+  match x.expr() {
+    Expr::Custom(name, _) => name.clone(),
+    Expr::Unknown => "(empty)".into(),
+    _ => "(other)".into(),
+  }
+}
+
+// Reads a corpus's rows as (line, utterance, gold semantics) triples.
+pub fn read_corpus(corpus: &str) -> Result<Vec<(usize, &str, Lambda)>> {
+  let mut result = vec![];
+  for (i, row) in corpus.lines().enumerate() {
+    let row = row.trim_end_matches('\r');
+    if row.trim().is_empty() || row.trim_start().starts_with('#') {
+      continue;
+    }
+    let (input, gold) = row.split_once('\t').ok_or_else(|| {
+      format!("Invalid row (line {}): expected: utterance<TAB>semantics", i + 1)
+    })?;
+    let expected = Lambda::parse(gold.trim())
+      .map_err(|x| format!("Invalid semantics (line {}): {:?}\n\n{:?}", i + 1, gold, x))?;
+    result.push((i + 1, input.trim(), expected));
+  }
+  Ok(result)
+}
+
+pub fn evaluate(grammar: &Value, corpus: &str) -> Result<Evaluation> {
+  let parser = Parser::new(grammar);
+  let (mut total, mut parsed, mut pairs) = (0, 0, vec![]);
+  let (mut failures, mut counts) = (vec![], HashMap::default());
+  for (line, input, expected) in read_corpus(corpus)? {
+    total += 1;
+    let actual = parser.parse(input).map(|x| {
+      x.nodes().for_each(|(y, _)| *counts.entry(y.rule as *const Rule<_, _>).or_default() += 1);
+      x.value
+    });
+    parsed += actual.is_some() as usize;
+    if !actual.as_ref().map(|x| matches(x, &expected)).unwrap_or_default() {
+      let (expected, actual) = (expected.repr().into(), actual.as_ref().map(|x| x.repr().into()));
+      failures.push(Failure { line, input: input.into(), expected, actual });
+    }
+    pairs.push((actual, expected));
+  }
+  let usage = grammar.rules.iter().map(|x| {
+    let n = counts.get(&(x as *const _)).cloned().unwrap_or_default();
+    (describe(grammar, x), n)
+  });
+  let mut usage: Vec<_> = usage.collect();
+  usage.sort_by_key(|x| std::cmp::Reverse(x.1));
+  Ok(Evaluation { total, parsed, metrics: score(&pairs), failures, usage })
+}
+
+pub fn describe<S, T>(grammar: &Grammar<S, T>, rule: &Rule<S, T>) -> String {
+  let terms = rule.rhs.iter().map(|x| match x {
+    Term::Symbol(y) => grammar.names[*y].as_str(),
+    Term::Terminal(y) => y.as_str(),
+  });
+  let rhs = terms.collect::<Vec<_>>().join(" ");
+  let lhs = &grammar.names[rule.lhs];
+  match rule.metadata.line {
+    Some(x) => format!("{} = {} (line {})", lhs, rhs, x),
+    None => format!("{} = {}", lhs, rhs),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::hindi::lexer::HindiLexer;
+  use super::super::nlu::fantasy::compile;
+  use super::*;
+
+  #[test]
+  fn corpus_is_evaluated() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar = compile(&data, HindiLexer::new).unwrap();
+    let corpus = "
+      # A comment, then a correct row and two wrong ones. The grammar is fault-tolerant,
+      # so it parses unknown words as a Mention.
+      mujhe pani chahie\tTell(I, want.type.water)
+      mujhe pani chahie\tTell(I, want.type.food)
+      xyzzy\tTell(I, want.type.water)
+    ";
+    let evaluation = evaluate(&grammar, corpus).unwrap();
+    let summary = evaluation.summary();
+    assert_eq!(summary[0..13], [
+      "Parsed: 3/3 (100.0%)",
+      "Exact match: 1/3 (33.3%)",
+      "Conjunct F1: 0.556",
+      "Intent confusion:",
+      "     2  Tell -> Tell",
+      "     1  Tell -> Mention",
+      "Wrong value (line 5): mujhe pani chahie",
+      "  expected: Tell(I, want.type.food)",
+      "  actual:   Tell(I, want.type.water)",
+      "Wrong value (line 6): xyzzy",
+      "  expected: Tell(I, want.type.water)",
+      "  actual:   Mention(xyzzy)",
+      "Rule usage:",
+    ]);
+    assert_eq!(evaluation.usage.len(), grammar.rules.len());
+    assert!(evaluation.usage[0].1 > 0);
+    assert_eq!(evaluation.usage.last().unwrap().1, 0);
+
+    let error = evaluate(&grammar, "mujhe pani chahie").err().unwrap();
+    assert_eq!(error, "Invalid row (line 1): expected: utterance<TAB>semantics".into());
+  }
+
+  #[test]
+  fn metrics_give_partial_credit() {
+    let l = |x: &str| Lambda::parse(x).unwrap();
+    let gold = l("Tell(I, want.(count.2 & type.bread))");
+    let mut conjuncts: Vec<_> = conjuncts(&gold).into_iter().collect();
+    conjuncts.sort();
+    assert_eq!(conjuncts, ["Tell", "Tell[0]:I", "Tell[1]:want.count.2", "Tell[1]:want.type.bread"]);
+
+    let pairs = vec![
+      (Some(l("Tell(I, want.(type.bread & count.2))")), gold.clone()),
+      (Some(l("Tell(I, want.type.bread)")), gold.clone()),
+      (Some(l("Ask(R[want].you)")), gold.clone()),
+      (None, l("Hello()")),
+      (Some(l("Hello()")), l("Hello()")),
+    ];
+    assert_eq!(conjunct_f1(&pairs[1].0.clone().unwrap(), &gold), 6.0 / 7.0);
+    let metrics = score(&pairs);
+    assert_eq!((metrics.total, metrics.exact), (5, 2));
+    assert!((metrics.f1 - (2.0 + 6.0 / 7.0) / 5.0).abs() < 1e-6);
+    assert_eq!(metrics.summary()[2..], [
+      "Intent confusion:",
+      "     2  Tell -> Tell",
+      "     1  Hello -> (unparsed)",
+      "     1  Hello -> Hello",
+      "     1  Tell -> Ask",
+    ]);
+  }
+}
@@ -4,6 +4,21 @@
 extern crate rand;
 extern crate regex;
 extern crate rustc_hash;
+extern crate smallvec;
+extern crate unicode_normalization;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
+// PyO3's macros refer to ::core, which needs an explicit import in Rust 2015.
+#[cfg(feature = "python")]
+extern crate core;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "wasm")]
+extern crate js_sys;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
 
 #[cfg(test)]
 extern crate test;
@@ -11,63 +26,345 @@ extern crate test;
 #[macro_use]
 mod lib;
 mod hindi;
+mod eval;
+mod ffi;
 mod nlu;
 mod payload;
+#[cfg(feature = "python")]
+mod python;
+mod sample;
+#[cfg(feature = "serve")]
+mod serve;
+mod train;
+#[cfg(feature = "wasm")]
+mod wasm;
 
 use hindi::lexer::HindiLexer;
-use lib::base::Result;
-use nlu::base::{Grammar, Match};
-use nlu::corrector::{Corrector, Diff};
-use nlu::fantasy::compile;
+use hindi::wx::Scheme;
+use lib::base::{Error, Result};
+use nlu::base::{Grammar, Match, TenseSchema};
+use nlu::corrector::{Correction, Corrector, Diff};
+use nlu::fantasy::{compile, lint};
 use nlu::generator::Generator;
 use nlu::parser::Parser;
 use payload::base::Payload;
+use payload::json::{Expr, Json};
 use payload::lambda::Lambda;
+use std::cell::RefCell;
 use std::fs::read_to_string;
-use std::rc::Rc;
+use std::sync::Arc;
 use std::time::SystemTime;
 
-fn render<T>(matches: &[Rc<Match<T>>]) -> String {
-  let texts = matches.iter().map(|x| x.texts.get("latin").map(|y| y.as_str()).unwrap_or("?"));
+// Renders matches in the given text scheme: "latin" for the informal romanization,
+// "hindi" for WX, or one of the hindi::wx::Scheme keys. Matches that lack a text
+// for that scheme, like unlexed tokens, fall back to their Latin text.
+
+fn render<T>(matches: &[Arc<Match<T>>], scheme: &str) -> String {
+  let text = |x: &Arc<Match<T>>| x.texts.get(scheme).or_else(|| x.texts.get("latin")).cloned();
+  let texts = matches.iter().map(|x| text(x).unwrap_or_else(|| "?".into()));
   texts.collect::<Vec<_>>().join(" ")
 }
 
+// With "--format json", we print one JSON object instead of text. Its keys are:
+//
+//   input:  the input, as given
+//   seed:   the random seed used for generation and correction
+//   value:  the repr of the derivation's value
+//   old:    the text of the derivation, in the output scheme, and its matches
+//   new:    the same, for the corrected derivation
+//   diffs:  the corrected phrases, with their category, errors, and old and new matches
+//
+// Each match has a "texts" dict with its text in every scheme that it supports.
+
+fn report<T>(
+  input: &str,
+  seed: u64,
+  scheme: &str,
+  value: &str,
+  old: &[Arc<Match<T>>],
+  correction: &Correction<T>,
+) -> Json {
+  let side = |x: &[Arc<Match<T>>]| describe(x, scheme);
+  let diffs = correction.diff.iter().filter_map(|x| match x {
+    Diff::Right(_) => None,
+    Diff::Wrong(x) => Some(dict(vec![
+      ("category", string(&x.category.to_string())),
+      ("errors", Json::new(Expr::List(x.errors.iter().map(|y| string(y)).collect()))),
+      ("new", side(&x.new_matches)),
+      ("old", side(&x.old_matches)),
+    ])),
+  });
+  dict(vec![
+    ("diffs", Json::new(Expr::List(diffs.collect()))),
+    ("input", string(input)),
+    ("new", side(&correction.tree.matches())),
+    ("old", side(old)),
+    ("seed", Json::new(Expr::Integer(seed as i64))),
+    ("value", string(value)),
+  ])
+}
+
+// Returns a dict with the matches' text in the given scheme and with each match's texts.
+
+fn describe<T>(matches: &[Arc<Match<T>>], scheme: &str) -> Json {
+  let texts = |x: &Arc<Match<T>>| {
+    let texts = x.texts.iter().map(|(k, v)| (k.to_string(), string(v))).collect();
+    dict(vec![("texts", Json::new(Expr::Dict(texts)))])
+  };
+  let (text, matches) = (render(matches, scheme), matches.iter().map(texts).collect());
+  dict(vec![("matches", Json::new(Expr::List(matches))), ("text", string(&text))])
+}
+
+fn dict(items: Vec<(&str, Json)>) -> Json {
+  Json::new(Expr::Dict(items.into_iter().map(|(k, v)| (k.into(), v)).collect()))
+}
+
+fn string(text: &str) -> Json {
+  Json::new(Expr::String(text.into()))
+}
+
+// "./main $grammar check" is a pre-commit check for grammar authors. It compiles the
+// grammar, which validates its symbols and terminals, and then runs the rule lints and
+// the vocabulary checks. It returns a list of errors and a list of warnings; compile
+// errors come with line numbers, as do lints that refer to rules.
+
+fn check(data: &str) -> (Vec<String>, Vec<String>) {
+  let vocabulary = RefCell::new(vec![]);
+  let lexer = |x: &str, y: &TenseSchema| {
+    let entries = hindi::vocabulary::vocabulary(x, y)?;
+    vocabulary.borrow_mut().extend(hindi::vocabulary::lint(&entries));
+    HindiLexer::new(x, y)
+  };
+  let grammar: Result<Grammar<Option<Lambda>, Lambda>> = compile(data, lexer);
+  match grammar {
+    Ok(x) => (vec![], [lint(&x), vocabulary.into_inner()].concat()),
+    Err(x) => (vec![format!("{:?}", x)], vocabulary.into_inner()),
+  }
+}
+
+// Removes a command-line flag, like "--seed 17" or "--deterministic", from the args.
+// Returns None if the flag is absent, or else its value, which is empty for flags
+// that don't take one.
+
+fn take_flag(args: &mut Vec<String>, name: &str, takes_value: bool) -> Option<String> {
+  let i = args.iter().position(|x| x == name)?;
+  let value = if takes_value { args.get(i + 1).cloned().unwrap_or_default() } else { "".into() };
+  let n = if takes_value { 2 } else { 1 };
+  args.drain(i..std::cmp::min(i + n, args.len()));
+  Some(value)
+}
+
+// Reports include the seed as a JSON integer, so we reject seeds that don't fit in an i64.
+fn parse_seed(seed: &str) -> Result<u64> {
+  let valid = seed.parse::<u64>().ok().filter(|x| *x <= i64::MAX as u64);
+  Ok(valid.ok_or_else(|| format!("Invalid seed: {:?}", seed))?)
+}
+
+fn check_scheme(scheme: &str) -> Result<()> {
+  if scheme != "latin" && scheme != "urdu" {
+    scheme.parse::<Scheme>()?;
+  }
+  Ok(())
+}
+
+fn unparsed(input: &str) -> Error {
+  Error::ParseFailure(format!("Failed to parse input: {:?}", input), None)
+}
+
 fn main() -> Result<()> {
-  let args: Vec<_> = std::env::args().collect();
-  if args.len() != 4 || !(args[2] == "generate" || args[2] == "parse") {
-    Err("Usage: ./main $gramar [generate|parse] $input")?;
+  let mut args: Vec<_> = std::env::args().collect();
+  let format = take_flag(&mut args, "--format", true);
+  let seed = take_flag(&mut args, "--seed", true);
+  let seed = seed.map(|x| parse_seed(&x));
+  let deterministic = take_flag(&mut args, "--deterministic", false).is_some();
+  let count = take_flag(&mut args, "--count", true);
+  let count = count.map(|x| x.parse::<usize>().map_err(|_| format!("Invalid count: {:?}", x)));
+  let semantics = take_flag(&mut args, "--semantics", true);
+  let epochs = take_flag(&mut args, "--epochs", true);
+  let epochs = epochs.map(|x| x.parse::<usize>().map_err(|_| format!("Invalid epochs: {:?}", x)));
+  let depth = take_flag(&mut args, "--depth", true);
+  let depth = depth.map(|x| x.parse::<usize>().map_err(|_| format!("Invalid depth: {:?}", x)));
+  let format = format.unwrap_or_else(|| "text".into());
+  if !["conllu", "cypher", "json", "sparql", "text"].contains(&format.as_str()) {
+    let formats = "conllu, cypher, json, sparql, or text";
+    Err(format!("Invalid format: {:?}; expected: {}", format, formats))?;
   }
-  let (file, generate, input) = (&args[1], args[2] == "generate", &args[3]);
+  let json = format == "json";
+  let command = args.get(2).map(|x| x.as_str()).unwrap_or_default();
+  let valid = match command {
+    "check" | "export" | "import" => args.len() == 3,
+    "eval" | "fst" | "select" | "train" => args.len() == 4,
+    "generate" | "parse" => args.len() >= 4 && args.len() <= 5,
+    "sample" => args.len() <= 4,
+    "serve" => args.len() <= 4,
+    _ => false,
+  };
+  if !valid {
+    let schemes = "[latin|hk|iast|iso|urdu]";
+    Err(format!(
+      "Usage: ./main $gramar [generate|parse] $input {} [--format json|conllu|sparql|cypher] \
+       [--seed N] [--deterministic]\n       ./main $grammar check\n       \
+       ./main $grammar export\n       ./main $grammar fst $prefix [--depth N]\n       \
+       ./main $speech_grammar import\n       \
+       ./main $grammar eval $corpus\n       ./main $grammar train $corpus [--epochs N]\n       \
+       ./main $grammar select $pool [--count N]\n       \
+       ./main $grammar sample {} [--count N] \
+       [--semantics $file] [--format json] [--seed N] [--deterministic]\n       \
+       ./main $grammar serve [$address]",
+      schemes, schemes
+    ))?;
+  }
+  let file = &args[1];
   let data = read_to_string(file).map_err(|x| format!("Failed to read file {}: {}", file, x))?;
-  let grammar = compile(&data, HindiLexer::new)
+  if command == "check" {
+    let (errors, warnings) = check(&data);
+    errors.iter().for_each(|x| println!("{}: error: {}", file, x));
+    warnings.iter().for_each(|x| println!("{}: warning: {}", file, x));
+    println!("Checked {}: {} errors, {} warnings", file, errors.len(), warnings.len());
+    if !errors.is_empty() {
+      Err(format!("Failed to check grammar: {}", file))?;
+    }
+    return Ok(());
+  }
+  if command == "import" {
+    let text = nlu::jsgf::import(&data).map_err(|x| format!("Failed to import {}: {:?}", file, x))?;
+    print!("{}", text);
+    return Ok(());
+  }
+  let mut grammar = compile(&data, HindiLexer::new)
     .map_err(|x| format!("Failed to compile grammar: {}\n\n{:?}", file, x))?;
+  if command == "export" {
+    print!("{}", nlu::nearley::export(&grammar));
+    return Ok(());
+  }
+  if command == "fst" {
+    let (prefix, depth) = (&args[3], depth.transpose()?.unwrap_or(0));
+    let fsts = nlu::fst::export(&grammar, "latin", depth);
+    let write = |path: String, text: String| {
+      std::fs::write(&path, text).map_err(|x| format!("Failed to write file {}: {}", path, x))
+    };
+    // The list names each FST's file, with the root first, in the order fstreplace wants.
+    let mut list = vec![];
+    for (i, (name, fst)) in fsts.fsts.iter().enumerate() {
+      let path = format!("{}.{}.txt", prefix, i);
+      let line = format!("{} {}\n", name, path);
+      if *name == fsts.root { list.insert(0, line) } else { list.push(line) };
+      write(path, fst.to_text())?;
+    }
+    write(format!("{}.list", prefix), list.concat())?;
+    write(format!("{}.syms", prefix), fsts.symbols())?;
+    let arcs: usize = fsts.fsts.iter().map(|x| x.1.arcs.len()).sum();
+    println!("Wrote {} FSTs with {} arcs, listed in {}.list", fsts.fsts.len(), arcs, prefix);
+    return Ok(());
+  }
+  if command == "eval" {
+    let corpus = &args[3];
+    let text =
+      read_to_string(corpus).map_err(|x| format!("Failed to read file {}: {}", corpus, x))?;
+    eval::evaluate(&grammar, &text)?.summary().iter().for_each(|x| println!("{}", x));
+    return Ok(());
+  }
+  if command == "select" {
+    let (pool, count) = (&args[3], count.transpose()?.unwrap_or(10));
+    let text = read_to_string(pool).map_err(|x| format!("Failed to read file {}: {}", pool, x))?;
+    let selections = train::select(&grammar, &text, count);
+    selections.iter().flat_map(|x| x.summary()).for_each(|x| println!("{}", x));
+    return Ok(());
+  }
+  if command == "train" {
+    let (corpus, epochs) = (&args[3], epochs.transpose()?.unwrap_or(10));
+    let text =
+      read_to_string(corpus).map_err(|x| format!("Failed to read file {}: {}", corpus, x))?;
+    let training = train::train(&mut grammar, &text, epochs)?;
+    training.summary().iter().for_each(|x| println!("{}", x));
+    return Ok(());
+  }
+  if command == "serve" {
+    let address = args.get(3).map(|x| x.as_str()).unwrap_or("127.0.0.1:8080");
+    #[cfg(feature = "serve")]
+    return serve::serve(&grammar, address);
+    #[cfg(not(feature = "serve"))]
+    Err(format!("Failed to serve on {}: build with --features serve", address))?;
+  }
+  let sample = command == "sample";
+  let scheme = args.get(if sample { 3 } else { 4 }).map(|x| x.as_str()).unwrap_or("latin");
+  check_scheme(scheme)?;
 
+  // When sampling, stdout is the corpus, so we log the seed to stderr instead.
   let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
-  println!("Using seed: {}", time);
-  let mut rng = rand::SeedableRng::seed_from_u64(time);
+  let seed = seed.transpose()?.unwrap_or(time);
+  let note = format!("Using seed: {}{}", seed, if deterministic { " (deterministic)" } else { "" });
+  if sample || (format != "json" && format != "text") {
+    eprintln!("{}", note);
+  } else if !json {
+    println!("{}", note);
+  }
+  let mut rng = rand::SeedableRng::seed_from_u64(seed);
+
+  if sample {
+    let read = |x: &String| read_to_string(x).map_err(|y| format!("Failed to read {}: {}", x, y));
+    let text = semantics.as_ref().map(read).transpose()?;
+    let semantics = sample::read_semantics(text.as_deref().unwrap_or_default())?;
+    let generator = Generator::new(&grammar).with_deterministic(deterministic);
+    let count = count.transpose()?.unwrap_or(1);
+    for tree in sample::sample(&generator, &mut rng, &semantics, count)? {
+      let (matches, value) = (tree.matches(), tree.value.repr());
+      if json {
+        let json = describe(&matches, scheme).set_path("value", string(value))?;
+        println!("{}", json.to_string_json());
+      } else if text.is_some() {
+        println!("{}\t{}", render(&matches, scheme), value);
+      } else {
+        println!("{}", render(&matches, scheme));
+      }
+    }
+    return Ok(());
+  }
+  let (generate, input) = (command == "generate", &args[3]);
 
   let tree = if generate {
-    let generator = Generator::new(&grammar);
+    let generator = Generator::new(&grammar).with_deterministic(deterministic);
     let maybe = generator.generate(&mut rng, &Some(Lambda::parse(input)?));
     maybe.ok_or_else(|| format!("Failed to generate output: {:?}", input))?
   } else {
-    let maybe = Parser::new(&grammar).set_debug(true).parse(input);
-    maybe.ok_or_else(|| format!("Failed to parse input: {:?}", input))?
+    let maybe = Parser::new(&grammar).set_debug(format == "text").parse(input);
+    maybe.ok_or_else(|| unparsed(input))?
+  };
+
+  // Queries use a placeholder IRI prefix, to be replaced with the knowledge graph's own.
+  let output = match format.as_str() {
+    "conllu" => Some(nlu::conllu::export(&grammar, &tree, scheme)),
+    "cypher" => Some(payload::query::cypher(&tree.value)?),
+    "sparql" => Some(payload::query::sparql(&tree.value, "http://example.org/")?),
+    _ => None,
   };
+  if let Some(x) = output {
+    print!("{}", x);
+    return Ok(());
+  }
 
+  let corrector = Corrector::new(&grammar).with_deterministic(deterministic);
+  let correction = corrector.correct(&mut rng, &tree);
+  if json {
+    let value = tree.value.repr();
+    let report = report(input, seed, scheme, value, &tree.matches(), &correction);
+    println!("{}", report.to_string_json());
+    return Ok(());
+  }
   println!("Old value repr: {}", tree.value.repr());
-  println!("Old Latin text: {}", render(&tree.matches()));
-  let correction = Corrector::new(&grammar).correct(&mut rng, &tree);
-  println!("New Latin text: {}", render(&correction.tree.matches()));
+  println!("Old Latin text: {}", render(&tree.matches(), scheme));
+  println!("New Latin text: {}", render(&correction.tree.matches(), scheme));
   for diff in correction.diff {
     if let Diff::Wrong(x) = diff {
-      println!("Corrected {} -> {}:", render(&x.old_matches), render(&x.new_matches));
+      let (old, new) = (render(&x.old_matches, scheme), render(&x.new_matches, scheme));
+      println!("Corrected {} -> {} ({}):", old, new, x.category);
       x.errors.iter().for_each(|y| println!("- {}", y));
     }
   }
   Ok(())
 }
 
+#[cfg(any(test, target_arch = "wasm32"))]
 fn make_grammar() -> Result<Grammar<Option<Lambda>, Lambda>> {
   let data = r#"
 # TODO(skishore): Deal with count semantics correctly. Right now we are not
@@ -81,56 +378,84 @@ fn make_grammar() -> Result<Grammar<Option<Lambda>, Lambda>> {
 # relation words to the noun table, but we need to make sure they don't get
 # expanded by %noun, only by %relation.
 #
-# TODO(skishore): Support parsing text with punctuation. That will also be
-# useful for lists, as well as helping with, e.g. the ? at the end of AskName.
-# We can use the existing fault-tolerance, along with support for explicit
-# punctuation text terms.
-#
 # TODO(skishore): Create a way to pass hints to the lexer, like the gender of
 # "I" and of "you", the tone, and the current pronoun categories in scope.
 # This step is probably relatively easy, as long as we can define the API.
 
+# Grammatical features. Tenses in rules, like "(? count singular)", must use these.
+# The lexer uses their codes, in order, to write the tenses of inflected forms.
+# The corrector reports errors in politeness features as politeness errors.
+
+feature count: plural/p singular/s
+feature gender: female/f male/m
+feature person: first/1 second/2 third/3
+feature time: past/< present/= future/>
+feature tone (politeness): casual/c formal/f intimate/i
+feature case: direct/d oblique/o
+feature aspect: perfective/f habitual/h progressive/p
+
 # Top-level intents.
 
 $AskFood! (= 'Ask(R[want].$0)')
-= YOU[$Person]:0^ kya $Khana? chahte^ hain^
-= YOU[$Person]:0^ kya $Leenge^
-= YOU[$PersonKo]:0 kya $Khana? chahie
-= YOU[$PersonKo]:0 $Main?^ kya $La sakta^ hun^ (> -1)
-= $Main?^ YOU[$PersonKo]:0 kya $La sakta^ hun^ (> -1)
+= YOU[$Person]:0^ kya $Khana? chahte^ hain^ %punct_question?
+= YOU[$Person]:0^ kya $Leenge^ %punct_question?
+= YOU[$PersonKo]:0! kya $Khana? chahie %punct_question?
+= YOU[$PersonKo]:0 $Main?^ kya $La sakta^ hun^ %punct_question? (> -1)
+= $Main?^ YOU[$PersonKo]:0 kya $La sakta^ hun^ %punct_question? (> -1)
 
 $AskName! (= 'Ask(R[name].$0)')
-= $Person^ kaun hai^
-= $PersonKa^ nam* kya hai^ (? count singular)
+= $Person^ kaun hai^ %punct_question?
+= $PersonKa^ nam* kya hai^ %punct_question? (? count singular)
 
 $Hello! (= 'Hello()')
-= hello
-= namaste
+= hello $Stop?
+= namaste $Stop?
 
 $Mention! (= 'Mention($0)')
-= $Noun (< -10)
+= $Noun $Stop? (< -10)
 
 $TellName! (= 'Tell($0, name.$1)')
-= $Person:0^ %token:1 hai^ (< -10)
-= $PersonKa:0^ nam* %token:1 hai^ (< -10) (? count singular)
+= $Person:0^ $Name:1 hai^ $Stop?
+= $PersonKa:0^ nam* $Name:1 hai^ $Stop? (? count singular)
 
 $TellWant! (= 'Tell($0, want.$1)')
-= I[$Person]^ $Noun $WantActive^
-= I[$Person]^ $Drink piega^
-= I[$Person]^ $Food khaega^
-= I[$PersonKo] $Noun^ $WantPassive^
-= I[$PersonKo] $Drink^ pina hai^
-= I[$PersonKo] $Food^ khana hai^
+= I[$Person]^ $Noun $WantActive^ $Stop?
+= I[$Person]^ $Drink piega^ $Stop?
+= I[$Person]^ $Drink PROGRESSIVE[pi]^ $Stop?
+= I[$Person]^ $Food khaega^ $Stop?
+= I[$Person]^ $Food PROGRESSIVE[kha]^ $Stop?
+= I[$PersonKo]! $Noun^ $WantPassive^ $Stop?
+= I[$PersonKo]! $Drink^ pina hai^ $Stop?
+= I[$PersonKo]! $Food^ khana hai^ $Stop?
+= I[$PersonNe]! $Drink^ piya^ $Stop? (? aspect perfective)
+= I[$PersonNe]! $Food^ khaya^ $Stop? (? aspect perfective)
+= I[$PersonNe]! $Noun^ $WantPerfective^ $Stop? (? aspect perfective)
+
+# Statements may end in a period or an exclamation mark. Questions end in "?" above.
+
+$Stop
+= %punct_period
+= %punct_exclamation
 
 # Noun-phrase helpers.
 
 $Adjectives (= '$0 & $1')
 = $Adjectives? %adjective
 
+$Count (= '$0')
+= %number
+= %number sau* (= '$0 * 100')
+= %number hazar* (= '$0 * 1000')
+= %number lakh* (= '$0 * 100000')
+
 $Determiner
 = $NounKa (= 'owner.$0')
 = %determiner (= 'context.$0')
 
+$Name (= '$0')
+= %proper_noun
+= %token (< -10)
+
 $Relation
 = $PersonKa^ baccha* (= 'parent.$0')
 = $PersonKa^ larka* (= 'gender.male & parent.$0')
@@ -139,9 +464,10 @@ $Relation
 LIST[@item]
 = @item (= '$0')
 = @item aur^ @item (= '$0 | $2') (? count plural) (? person third)
+= @item %punct_comma @item aur^ @item (= '$0 | $2 | $4') (? count plural) (? person third)
 
 NOUN[@term] (= '$0 & count.$1 & $2 & $3')
-= $Determiner?^ %number?* $Adjectives?^ @term*
+= $Determiner?^ $Count?* $Adjectives?^ @term*
 
 NOUN_OR_RELATION[@term] (= '$0')
 = NOUN[@term]
@@ -155,6 +481,15 @@ YOU[@person]
 = @person (= '$0')
 = NONE (= 'you') (? person second)
 
+OBLIQUE[@term]
+= @term (= '$0') (? case oblique)
+
+COMPOUND[@stem, @vector]
+= @stem @vector (= '$0')
+
+PROGRESSIVE[@stem]
+= @stem raha^ hai^ (? aspect progressive)
+
 # Specific subtypes of noun phrase.
 
 $Drink (= '$0')
@@ -166,9 +501,10 @@ $Food (= '$0')
 $Noun (= '$0')
 = LIST[NOUN[%noun]]
 = %direct
+< NOUN[%english] (< -1)
 
 $NounKa (= '$0')
-= NOUN_OR_RELATION[%noun] ka^
+= OBLIQUE[NOUN_OR_RELATION[%noun]] ka^
 = %genitive
 < %direct ka^ (< -0.5)
 
@@ -177,15 +513,20 @@ $Person (= '$0')
 = %direct
 
 $PersonKa (= '$0')
-= LIST[NOUN_OR_RELATION[%person]] ka^
+= OBLIQUE[LIST[NOUN_OR_RELATION[%person]]] ka^
 = %genitive
 < %direct ka^ (< -0.5)
 
 $PersonKo (= '$0')
-= LIST[NOUN_OR_RELATION[%person]] ko^
+= OBLIQUE[LIST[NOUN_OR_RELATION[%person]]] ko^
 = %dative
 < %direct ko^ (< -0.5)
 
+$PersonNe (= '$0')
+= OBLIQUE[LIST[NOUN_OR_RELATION[%person]]] ne^
+= %ergative
+< %direct ne^ (< -0.5)
+
 # Simple substitutions.
 
 $Khana
@@ -194,8 +535,9 @@ $Khana
 
 $Leenge
 = khaenge
-= leenge
+= lenge
 = pienge
+= COMPOUND[%compound_lena, lenge] (> 2)
 
 $La
 = de
@@ -215,7 +557,9 @@ $MainPlural
 < hame
 
 $WantActive
-= leega
+= lega
+= COMPOUND[%compound_lena, lega] (> 1)
+= PROGRESSIVE[le]
 < khaega (< -0.5)
 < piega (< -0.5)
 
@@ -227,6 +571,10 @@ $WantPassive
 < khana hai^ (< -0.5)
 < pina hai^ (< -0.5)
 
+$WantPerfective
+= liya
+= COMPOUND[%compound_lena, liya] (> 1)
+
 # The base vocabulary, including several classes of words.
 
 lexer: ```
@@ -253,7 +601,7 @@ lexer: ```
          drink | type.tea                   | chai/cAy      | f.
              ^ | type.water                 | pani/pAnI     | m.
           food | type.apple                 | seb/seb       | m.
-             ^ | type.bread                 | roti/rotI     | m.
+             ^ | type.bread                 | roti/rotI     | f.
              ^ | type.food                  | khana/KAnA    | m.
         person | type.child                 | baccha/baccA  | ms
              ^ | type.adult                 | log/log       | m.
@@ -283,13 +631,16 @@ lexer: ```
           7 | sat/sAw
           8 | ath/AT
           9 | nau/nO
+        100 | sau/sO
+       1000 | hazar/hajZAr
+     100000 | lakh/lAK
 
   $PARTICLES:
 
     # TODO(skishore): The "temporary" category here contains words that should
     # appear in some part-of-speech list, but for which we don't yet have the
-    # proper declension. For example, we haven't implemented the reflective
-    # tense "chahie" for "chahna" or the command tense "dijie" for "dena".
+    # proper declension. For example, we haven't implemented the command tense
+    # "dijie" for "dena".
 
       category | meaning | word            | declines
     -----------|---------|-----------------|---------
@@ -301,6 +652,7 @@ lexer: ```
              ^ |       - | ko/ko           | n
              ^ |       - | liye/liye       | n
              ^ |       - | namaste/namaswe | n
+             ^ |       - | ne/ne           | n
              ^ |       - | sakta/sakwA     | y
       question |     how | kaisa/kEsA      | y
              ^ |    what | kya/kyA         | n
@@ -308,42 +660,114 @@ lexer: ```
              ^ |   where | kaha/kahA       | n
              ^ |     who | kaun/kOn        | n
              ^ |     why | kyun/kyUM       | n
-     temporary |       - | chahie/cAhIe    | n
-             ^ |       - | dijie/dijIe     | n
+     temporary |       - | dijie/dijIe     | n
 
   $PRONOUNS:
 
     # The "role" column encodes person, number, and, for the 2nd person, tone.
-    # The tone is either i (intimate), c (casual), or f (formal).
-
-    role | direct   | genitive        | dative_1     | dative_2    | copula
-    -----|----------|-----------------|--------------|-------------|---------
-     1s. | main/mEM | mera/merA       | mujhko/muJko | mujhe/muJe  | hun/hUz
-     2si | tu/wU    | tera/werA       | tujhko/wuJko | tujhe/wuJe  | hai/hE
-     3s. | voh/vah  | uska/uskA       | usko/usko    | use/use     | ^
-     1p. | ham/ham  | hamara/hamArA   | hamko/hamko  | hame/hame   | hain/hEM
-     2pc | tum/wum  | tumhara/wumhArA | tumko/wumko  | tumhe/wumhe | ho/ho
-     2pf | ap/Ap    | apka/ApkA       | apko/Apko    | <           | ^
-     3p. | voh/vah  | uska/uskA       | unko/unko    | usne/usne   | hai/hE
+    # The tone is either i (intimate), c (casual), or f (formal). The formal
+    # "ap" is honorific: it takes plural agreement even when addressing one person.
+
+    role | direct   | genitive        | dative_1     | dative_2    | ergative       | copula
+    -----|----------|-----------------|--------------|-------------|----------------|---------
+     1s. | main/mEM | mera/merA       | mujhko/muJko | mujhe/muJe  | maine/mEMne    | hun/hUz
+     2si | tu/wU    | tera/werA       | tujhko/wuJko | tujhe/wuJe  | tune/wUne      | hai/hE
+     3s. | voh/vah  | uska/uskA       | usko/usko    | use/use     | usne/usne      | ^
+     1p. | ham/ham  | hamara/hamArA   | hamko/hamko  | hame/hame   | hamne/hamne    | hain/hEM
+     2pc | tum/wum  | tumhara/wumhArA | tumko/wumko  | tumhe/wumhe | tumne/wumne    | ho/ho
+     2pf | ap/Ap    | apka/ApkA       | apko/Apko    | <           | apne/Apne      | hain/hEM
+     3p. | voh/vah  | uska/uskA       | unko/unko    | unhe/unheM  | unhone/unhoMne | hai/hE
 
   $VERBS:
 
-    meaning | word
-    --------|-------------
-      bring | lana/lAnA
-         do | karna/karnA
-      drink | pina/pInA
-        eat | khana/KAnA
-       give | dena/xenA
-      sleep | sona/sonA
-       take | lena/lenA
-       want | chahna/cAhnA
+    # The "subject" column is "dative" for verbs like "chahna" that take a dative
+    # subject and agree with their objects, and "direct" for all others.
+
+    meaning | word         | subject
+    --------|--------------|--------
+         be | hona/honA    | direct
+      bring | lana/lAnA    | direct
+       come | ana/AnA      | direct
+         do | karna/karnA  | direct
+      drink | pina/pInA    | direct
+        eat | khana/KAnA   | direct
+       give | dena/xenA    | direct
+         go | jana/jAnA    | direct
+      sleep | sona/sonA    | direct
+       take | lena/lenA    | direct
+       want | chahna/cAhnA | dative
+
+  $VERB_COMPOUNDS:
+
+    # Compound verbs pair the stem of one verb with a conjugated "vector" verb,
+    # as in "kha lena" or "de dena". The compound takes the first column as its
+    # meaning, and the vector verb supplies agreement.
+
+    meaning | stem       | vector
+    --------|------------|----------
+      bring | lena/lenA  | ana/AnA
+      drink | pina/pInA  | lena/lenA
+        eat | khana/KAnA | lena/lenA
+       give | dena/xenA  | dena/xenA
+       take | lena/lenA  | lena/lenA
+
+  $VERB_IRREGULARS:
+
+    # Forms that override the regular conjugations above. The "code" column is a
+    # tense code: count, gender, person, time (< past, = present, > future), tone.
+    # Forms with no gender decline like regular ones, with -I for the feminine.
+
+           verb | code  | word
+    ------------|-------|------------
+      dena/xenA | sm.<. | diya/xiyA
+              ^ | pm.<. | diye/xiye
+              ^ | .f.<. | di/xI
+              ^ | s.1>. | dunga/xUngA
+              ^ | s.2>i | dega/xegA
+              ^ | s.3>. | dega/xegA
+              ^ | p.1>. | denge/xenge
+              ^ | p.2>c | doge/xoge
+              ^ | p.2>f | denge/xenge
+              ^ | p.3>. | denge/xenge
+      hona/honA | sm.<. | hua/huA
+              ^ | pm.<. | hue/hue
+              ^ | .f.<. | hui/huI
+              ^ | s.1>. | hunga/hUngA
+              ^ | s.2>i | hoga/hogA
+              ^ | s.3>. | hoga/hogA
+              ^ | p.1>. | honge/honge
+              ^ | p.2>c | hoge/hoge
+              ^ | p.2>f | honge/honge
+              ^ | p.3>. | honge/honge
+      jana/jAnA | sm.<. | gaya/gayA
+              ^ | pm.<. | gaye/gaye
+              ^ | .f.<. | gayi/gayI
+    karna/karnA | sm.<. | kiya/kiyA
+              ^ | pm.<. | kiye/kiye
+              ^ | .f.<. | ki/kI
+      lena/lenA | sm.<. | liya/liyA
+              ^ | pm.<. | liye/liye
+              ^ | .f.<. | li/lI
+              ^ | s.1>. | lunga/lUngA
+              ^ | s.2>i | lega/legA
+              ^ | s.3>. | lega/legA
+              ^ | p.1>. | lenge/lenge
+              ^ | p.2>c | loge/loge
+              ^ | p.2>f | lenge/lenge
+              ^ | p.3>. | lenge/lenge
+      pina/pInA | sm.<. | piya/piyA
+              ^ | pm.<. | piye/piye
+              ^ | .f.<. | pi/pI
 
 ```"#;
   let grammar = compile(data, HindiLexer::new);
   Ok(grammar.map_err(|x| format!("Failed to compile grammar:\n\n{:?}", x))?)
 }
 
+// Benchmarks, called by name with runwasm.js. They are raw wasm exports, so we leave them
+// out of native builds, where the library's C symbols are the API in ffi.rs.
+
+#[cfg(target_arch = "wasm32")]
 #[no_mangle]
 pub extern "C" fn correction_benchmark(i: f64) -> f64 {
   let grammar = make_grammar().unwrap();
@@ -356,6 +780,7 @@ pub extern "C" fn correction_benchmark(i: f64) -> f64 {
   0.0
 }
 
+#[cfg(target_arch = "wasm32")]
 #[no_mangle]
 pub extern "C" fn generation_benchmark(i: f64) -> f64 {
   let grammar = make_grammar().unwrap();
@@ -368,6 +793,7 @@ pub extern "C" fn generation_benchmark(i: f64) -> f64 {
   0.0
 }
 
+#[cfg(target_arch = "wasm32")]
 #[no_mangle]
 pub extern "C" fn parsing_benchmark(i: f64) -> f64 {
   let grammar = make_grammar().unwrap();
@@ -377,3 +803,91 @@ pub extern "C" fn parsing_benchmark(i: f64) -> f64 {
   }
   0.0
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn json_reports_include_matches_and_diffs() {
+    let grammar = make_grammar().unwrap();
+    let input = "do accha acche larki ko pani chahie";
+    let tree = Parser::new(&grammar).parse(input).unwrap();
+    let mut rng = rand::SeedableRng::from_seed([17; 32]);
+    let correction = Corrector::new(&grammar).correct(&mut rng, &tree);
+    let report = report(input, 17, "iso", tree.value.repr(), &tree.matches(), &correction);
+    let text = |x: &str| report.get_path(x).map(|y| y.to_string_json());
+    assert_eq!(text("input"), Some(format!("{:?}", input)));
+    assert_eq!(text("seed"), Some("17".into()));
+    assert_eq!(text("value"), Some(format!("{:?}", tree.value.repr())));
+    assert_eq!(text("old.matches[0].texts.latin"), Some("\"do\"".into()));
+    assert_eq!(text("old.text"), Some(format!("{:?}", render(&tree.matches(), "iso"))));
+    assert_eq!(text("diffs[0].category"), Some("\"agreement\"".into()));
+    assert!(text("diffs[0].errors[0]").is_some());
+    assert!(text("diffs[0].new.matches[0].texts.hindi").is_some());
+  }
+
+  #[test]
+  fn check_reports_errors_and_warnings() {
+    let data = read_to_string("src/hindi/hindi.grammar").unwrap();
+    let (errors, base) = check(&data);
+    assert_eq!(errors, Vec::<String>::new());
+
+    let water = "^ | type.water                 | pani/pAnI     | m.";
+    let data = data.replace("= namaste $Stop?\n", "= namaste $Stop?\n= hello $Stop?\n");
+    let data = data.replacen(water, &format!("{}\n             {}", water, water), 1);
+    let lines = |data: &str, x: &str| {
+      let lines = data.lines().enumerate().filter(|y| y.1 == x).map(|y| y.0 + 1);
+      lines.collect::<Vec<_>>()
+    };
+    let hello = lines(&data, "= hello $Stop?");
+    let (errors, warnings) = check(&data);
+    let warnings: Vec<_> = warnings.into_iter().filter(|x| !base.contains(x)).collect();
+    assert_eq!((errors, warnings), (vec![], vec![
+      format!("Ambiguous rules: $Hello (line {}) and $Hello (line {})", hello[0], hello[1]),
+      "Duplicate entry for noun-pAnI: pani".into(),
+    ]));
+    let data = data.replace("= namaste $Stop?\n", "= namaste $Stop?\n= klingon\n");
+    let (errors, _) = check(&data);
+    let klingon = lines(&data, "= klingon");
+    assert_eq!(errors, [format!("Unknown terminals: klingon (line {})", klingon[0])]);
+    let data = read_to_string("src/hindi/hindi.grammar").unwrap();
+    let typo = data.replacen("$Stop? (? count singular)", "$Stop? (? count singlar)", 1);
+    let (errors, _) = check(&typo);
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("Invalid value for feature count: singlar"));
+  }
+
+  #[test]
+  fn flags_are_taken_from_args() {
+    let args = "main x --seed 17 parse --deterministic y --format".split(' ');
+    let mut args: Vec<_> = args.map(|x| x.to_string()).collect();
+    assert_eq!(take_flag(&mut args, "--seed", true), Some("17".into()));
+    assert_eq!(take_flag(&mut args, "--deterministic", false), Some("".into()));
+    assert_eq!(take_flag(&mut args, "--format", true), Some("".into()));
+    assert_eq!(take_flag(&mut args, "--seed", true), None);
+    assert_eq!(args, ["main", "x", "parse", "y"]);
+  }
+
+  #[test]
+  fn seeds_must_fit_in_reports() {
+    assert_eq!(parse_seed("17").unwrap(), 17);
+    assert_eq!(parse_seed(&i64::MAX.to_string()).unwrap(), i64::MAX as u64);
+    assert!(parse_seed(&(i64::MAX as u64 + 1).to_string()).is_err());
+    assert!(parse_seed("-1").is_err());
+  }
+
+  #[test]
+  fn deterministic_correction_ignores_the_seed() {
+    let grammar = make_grammar().unwrap();
+    let tree = Parser::new(&grammar).parse("do accha acche larki ko pani chahie").unwrap();
+    let corrector = Corrector::new(&grammar).with_deterministic(true);
+    let texts: Vec<_> = (0..4)
+      .map(|x| {
+        let mut rng = rand::SeedableRng::seed_from_u64(x);
+        render(&corrector.correct(&mut rng, &tree).tree.matches(), "latin")
+      })
+      .collect();
+    assert_eq!(texts, vec![texts[0].clone(); 4]);
+  }
+}
@@ -0,0 +1,246 @@
+use super::hindi::lexer::HindiLexer;
+use super::lib::base::Error;
+use super::nlu::base::Grammar;
+use super::nlu::corrector::Corrector;
+use super::nlu::fantasy::compile;
+use super::nlu::parser::Parser;
+use super::payload::json::Json;
+use super::payload::lambda::Lambda;
+use super::{check_scheme, describe, report, string, unparsed};
+use rand::SeedableRng;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr::null_mut;
+
+// A C API, for embedding the engine in an app. Grammars, parsers, and correctors are
+// opaque handles that the caller frees with the matching _free function. Parsers and
+// correctors borrow their grammar, so it must outlive them.
+//
+// Calls that can fail return a status code. On success, they write their result to
+// their out pointer: a handle, or a UTF-8 JSON string that the caller frees with
+// fantasy_string_free. The JSON matches the wasm bindings: parse returns the value's
+// repr with the derivation's matches and text, and correct returns the CLI's report.
+// On failure, fantasy_last_error returns a message for the thread's last error.
+// The declarations for C callers are in include/fantasy.h.
+
+pub const FANTASY_OK: c_int = 0;
+pub const FANTASY_NULL_POINTER: c_int = 1;
+pub const FANTASY_INVALID_UTF8: c_int = 2;
+pub const FANTASY_FAILED: c_int = 3;
+pub const FANTASY_PANIC: c_int = 4;
+
+pub struct FantasyGrammar(Grammar<Option<Lambda>, Lambda>);
+
+pub struct FantasyParser(Parser<'static, Option<Lambda>, Lambda>);
+
+pub struct FantasyCorrector(Corrector<'static, Lambda>);
+
+type Status<T> = std::result::Result<T, (c_int, Error)>;
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fantasy_grammar_new(
+  text: *const c_char,
+  out: *mut *mut FantasyGrammar,
+) -> c_int {
+  guard(|| {
+    let grammar = compile(arg("text", text)?, HindiLexer::new);
+    let grammar = grammar.map_err(|x| failed(format!("Failed to compile grammar:\n\n{:?}", x)))?;
+    write(out, Box::into_raw(Box::new(FantasyGrammar(grammar))))
+  })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fantasy_grammar_free(grammar: *mut FantasyGrammar) {
+  free(grammar);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fantasy_parser_new(
+  grammar: *const FantasyGrammar,
+  out: *mut *mut FantasyParser,
+) -> c_int {
+  guard(|| {
+    let parser = Parser::new(&handle("grammar", grammar)?.0);
+    write(out, Box::into_raw(Box::new(FantasyParser(parser))))
+  })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fantasy_parser_free(parser: *mut FantasyParser) {
+  free(parser);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fantasy_parser_parse(
+  parser: *const FantasyParser,
+  input: *const c_char,
+  scheme: *const c_char,
+  out: *mut *mut c_char,
+) -> c_int {
+  guard(|| {
+    let parser = handle("parser", parser)?;
+    let (input, scheme) = (arg("input", input)?, arg("scheme", scheme)?);
+    check_scheme(scheme).map_err(failed)?;
+    let tree = parser.0.parse(input);
+    let tree = tree.ok_or_else(|| failed(unparsed(input)))?;
+    let json = describe(&tree.matches(), scheme).set_path("value", string(tree.value.repr()));
+    write(out, json_string(json.map_err(failed)?)?)
+  })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fantasy_corrector_new(
+  grammar: *const FantasyGrammar,
+  out: *mut *mut FantasyCorrector,
+) -> c_int {
+  guard(|| {
+    let corrector = Corrector::new(&handle("grammar", grammar)?.0);
+    write(out, Box::into_raw(Box::new(FantasyCorrector(corrector))))
+  })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fantasy_corrector_free(corrector: *mut FantasyCorrector) {
+  free(corrector);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fantasy_corrector_correct(
+  corrector: *const FantasyCorrector,
+  parser: *const FantasyParser,
+  input: *const c_char,
+  seed: u64,
+  scheme: *const c_char,
+  out: *mut *mut c_char,
+) -> c_int {
+  guard(|| {
+    let (corrector, parser) = (handle("corrector", corrector)?, handle("parser", parser)?);
+    let (input, scheme) = (arg("input", input)?, arg("scheme", scheme)?);
+    check_scheme(scheme).map_err(failed)?;
+    let tree = parser.0.parse(input);
+    let tree = tree.ok_or_else(|| failed(unparsed(input)))?;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let correction = corrector.0.correct(&mut rng, &tree);
+    let json = report(input, seed, scheme, tree.value.repr(), &tree.matches(), &correction);
+    write(out, json_string(json)?)
+  })
+}
+
+// Returns the message for this thread's last error, or null if the last call succeeded.
+// The caller frees the message with fantasy_string_free.
+
+#[no_mangle]
+pub extern "C" fn fantasy_last_error() -> *mut c_char {
+  LAST_ERROR.with(|x| x.borrow().clone().map_or(null_mut(), |y| y.into_raw()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fantasy_string_free(text: *mut c_char) {
+  if !text.is_null() {
+    std::mem::drop(CString::from_raw(text));
+  }
+}
+
+// Helpers for checking arguments and recording errors.
+
+unsafe fn arg<'a>(name: &str, text: *const c_char) -> Status<&'a str> {
+  if text.is_null() {
+    return Err((FANTASY_NULL_POINTER, format!("{} is null", name).into()));
+  }
+  let error = |_| (FANTASY_INVALID_UTF8, format!("{} is not valid UTF-8", name).into());
+  CStr::from_ptr(text).to_str().map_err(error)
+}
+
+fn failed<T: Into<Error>>(error: T) -> (c_int, Error) {
+  (FANTASY_FAILED, error.into())
+}
+
+unsafe fn free<T>(value: *mut T) {
+  if !value.is_null() {
+    std::mem::drop(Box::from_raw(value));
+  }
+}
+
+fn guard<F: FnOnce() -> Status<()>>(f: F) -> c_int {
+  let result = catch_unwind(AssertUnwindSafe(f));
+  let result = result.unwrap_or_else(|_| Err((FANTASY_PANIC, "Internal error (panic)".into())));
+  let (code, message) = match result {
+    Ok(()) => (FANTASY_OK, None),
+    Err((code, error)) => (code, CString::new(format!("{:?}", error).replace('\0', "")).ok()),
+  };
+  LAST_ERROR.with(|x| *x.borrow_mut() = message);
+  code
+}
+
+unsafe fn handle<'a, T>(name: &str, value: *const T) -> Status<&'a T> {
+  value.as_ref().ok_or_else(|| (FANTASY_NULL_POINTER, format!("{} is null", name).into()))
+}
+
+fn json_string(json: Json) -> Status<*mut c_char> {
+  let text = CString::new(json.to_string_json()).map_err(|_| failed("Output contains a NUL"))?;
+  Ok(text.into_raw())
+}
+
+unsafe fn write<T>(out: *mut T, value: T) -> Status<()> {
+  if out.is_null() {
+    return Err((FANTASY_NULL_POINTER, "out is null".into()));
+  }
+  *out = value;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn c(text: &str) -> CString {
+    CString::new(text).unwrap()
+  }
+
+  unsafe fn take(text: *mut c_char) -> String {
+    let result = CStr::from_ptr(text).to_str().unwrap().to_string();
+    fantasy_string_free(text);
+    result
+  }
+
+  #[test]
+  fn c_api_parses_and_corrects() {
+    unsafe {
+      let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+      let mut grammar = null_mut();
+      assert_eq!(fantasy_grammar_new(c(&data).as_ptr(), &mut grammar), FANTASY_OK);
+      let (mut parser, mut corrector, mut out) = (null_mut(), null_mut(), null_mut());
+      assert_eq!(fantasy_parser_new(grammar, &mut parser), FANTASY_OK);
+      assert_eq!(fantasy_corrector_new(grammar, &mut corrector), FANTASY_OK);
+
+      let latin = c("latin");
+      let parse = |x: *const c_char, y: &CString, z| fantasy_parser_parse(parser, x, y.as_ptr(), z);
+      let input = c("mujhe pani chahie");
+      assert_eq!(parse(input.as_ptr(), &latin, &mut out), FANTASY_OK);
+      assert!(take(out).contains(r#""value":"Tell(I, want.type.water)""#));
+      assert!(fantasy_last_error().is_null());
+
+      let input = c("do accha acche larki ko pani chahie");
+      let (x, y) = (input.as_ptr(), latin.as_ptr());
+      assert_eq!(fantasy_corrector_correct(corrector, parser, x, 17, y, &mut out), FANTASY_OK);
+      assert!(take(out).contains(r#""category":"agreement""#));
+
+      assert_eq!(parse(input.as_ptr(), &c("klingon"), &mut out), FANTASY_FAILED);
+      assert!(take(fantasy_last_error()).contains("klingon"));
+      assert_eq!(parse(null_mut(), &latin, &mut out), FANTASY_NULL_POINTER);
+      assert_eq!(take(fantasy_last_error()), "input is null");
+      let invalid = CString::new(vec![0xff]).unwrap();
+      assert_eq!(parse(invalid.as_ptr(), &latin, &mut out), FANTASY_INVALID_UTF8);
+
+      fantasy_corrector_free(corrector);
+      fantasy_parser_free(parser);
+      fantasy_grammar_free(grammar);
+    }
+  }
+}
@@ -5,6 +5,11 @@ pub type Bytes = &'static [u8];
 thread_local! {
   pub static VOWEL_SKIP_LOG_FREQUENCY: f32 = 0.1_f32.log2();
 
+  // Users double a vowel to lengthen it, as in "nahiin". We charge the repeated vowel what
+  // the table charges for spelling A as "aa", the one long vowel whose row records it.
+  pub static DOUBLED_VOWEL_LOG_FREQUENCY: f32 =
+    LOG_FREQUENCY.with(|a| a[&(b"A" as Bytes)].1[&(b"aa" as Bytes)]);
+
   pub static LOG_FREQUENCY: HashMap<Bytes, (Bytes, HashMap<Bytes, f32>)> = {
     let mut result = HashMap::default();
     result.insert("a", items(&[("a", 10), ("e", 0), ("i", 0), ("o", 0), ("u", 0), ("aa", 5), ("", 0)]));
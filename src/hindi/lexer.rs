@@ -1,13 +1,18 @@
+use hindi::normalize::{Normalizer, Pipeline};
 use hindi::transliterator::Transliterator;
+use hindi::urdu::{is_urdu, urdu_to_wx, wx_to_urdu};
 use hindi::vocabulary::{vocabulary, Entry};
-use lib::base::{HashMap, Result};
-use nlu::base::{Lexer, Match, Tense, Token};
+use hindi::wx::{wx_to_scheme, Scheme};
+use lib::base::{Error, HashMap, HashSet, Result};
+use lib::lru::{Lru, Stats};
+use lib::table::read_tables;
+use nlu::base::{Child, Derivation, Lexer, Match, Tense, TenseSchema, Term, Terminal, Token};
 use payload::base::Payload;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
 struct XEntry<T: Payload> {
-  match_rc: Rc<Match<T>>,
-  scores: HashMap<String, f32>,
+  match_rc: Arc<Match<T>>,
+  scores: HashMap<Terminal, f32>,
 }
 
 fn common_prefix<'a>(a: &'a str, b: &'a str) -> &'a str {
@@ -16,94 +21,525 @@ fn common_prefix<'a>(a: &'a str, b: &'a str) -> &'a str {
 
 fn create_xentry<T: Payload>(entry: Entry) -> Result<XEntry<T>> {
   let Entry { head, hindi, latin, scores, tenses, value } = entry;
-  let texts = vec![("head", head), ("hindi", hindi), ("latin", latin)].into_iter().collect();
+  let mut texts: HashMap<_, _> = Scheme::all()
+    .iter()
+    .map(|x| Ok((x.key(), wx_to_scheme(&hindi, *x)?.into())))
+    .collect::<Result<_>>()?;
+  let urdu = wx_to_urdu(&hindi)?;
+  let extra = vec![("head", head), ("hindi", hindi), ("latin", latin), ("urdu", urdu)];
+  texts.extend(extra.into_iter().map(|(k, v)| (k, v.into())));
   let value = T::parse(&value)?;
-  let match_rc = Rc::new(Match { tenses, texts, value });
+  let match_rc = Arc::new(Match { tenses, texts, value });
+  let scores = scores.into_iter().map(|(k, v)| (Terminal::new(&k), v)).collect();
   Ok(XEntry { match_rc, scores })
 }
 
-fn default_match<T: Payload>(text: &str) -> Rc<Match<T>> {
+// Users often code-switch: "mujhe coffee chahie". Latin tokens that match no entry in
+// our vocabulary are tagged %english, with the raw token as their value, so a grammar
+// can allow English words in certain slots. They are penalized relative to Hindi words.
+
+const ENGLISH_PENALTY: f32 = -1.0;
+
+// Tokens that could be names are also tagged %proper_noun. Names registered with
+// add_name always match; otherwise, out-of-vocabulary alphabetic tokens match, with a
+// smaller penalty if they're capitalized. Other junk tokens are only ever %token.
+
+// Learners sometimes leave out spaces: "mujhepani". We split unknown tokens into two
+// vocabulary words when we can, choosing the split with the most likely pair of words,
+// and penalize the matches for both halves. Capitalized tokens are likely names, like
+// "Mohan", so we keep them whole, along with their %proper_noun match.
+
+const MIN_SPLIT_LENGTH: usize = 2;
+const SPLIT_PENALTY: f32 = -1.0;
+
+const CAPITALIZED_NAME_PENALTY: f32 = -0.5;
+const LOWERCASE_NAME_PENALTY: f32 = -2.0;
+
+// Common words like "hai" and "kya" show up in almost every utterance, so we cache the
+// transliterations of recent tokens, keyed by their lowercase text. Set the cache size
+// with set_cache_size; a size of 0 disables the cache.
+
+const CACHE_SIZE: usize = 4096;
+
+type Candidates = Arc<[(String, f32)]>;
+
+fn default_match<T: Payload>(text: &str) -> Arc<Match<T>> {
+  let text: Arc<str> = text.into();
+  let mut texts = HashMap::default();
+  texts.insert("hindi", Arc::clone(&text));
+  texts.insert("latin", Arc::clone(&text));
+  Arc::new(Match { tenses: vec![], texts, value: T::base_lex(&text) })
+}
+
+// Numbers written in digits, like "2 roti" or "२ रोटी", lex as %number. Their values
+// are integers, just like the values of numbers spelled out as words. Simple ranges, like
+// "2-3 roti", lex as %number too, with a Range(2, 3) value and plural count.
+
+fn digit_value(text: &str) -> Option<(u64, String)> {
+  let digit = |c: char| match c {
+    '0'..='9' => c.to_digit(10),
+    '\u{966}'..='\u{96f}' => Some(c as u32 - 0x966),
+    _ => None,
+  };
+  let digits = text.chars().map(digit).collect::<Option<Vec<_>>>()?;
+  if digits.is_empty() || digits.len() > 18 {
+    return None;
+  }
+  let value = digits.iter().fold(0, |acc, x| 10 * acc + *x as u64);
+  Some((value, digits.iter().filter_map(|x| std::char::from_u32(0x966 + x)).collect()))
+}
+
+fn digit_match<T: Payload>(text: &str) -> Option<Arc<Match<T>>> {
+  let (value, latin, hindi, count) = match text.find(['-', '\u{2013}']) {
+    Some(i) => {
+      let dash = text[i..].chars().next()?.len_utf8();
+      let ((a, x), (b, y)) = (digit_value(&text[..i])?, digit_value(&text[i + dash..])?);
+      if a >= b {
+        return None;
+      }
+      (format!("Range({}, {})", a, b), format!("{}-{}", a, b), format!("{}-{}", x, y), "plural")
+    }
+    None => {
+      let (value, hindi) = digit_value(text)?;
+      let count = if value == 1 { "singular" } else { "plural" };
+      (value.to_string(), value.to_string(), hindi, count)
+    }
+  };
+  let tense = Tense::new(&std::iter::once(("count", count)).collect()).ok()?;
   let mut texts = HashMap::default();
-  texts.insert("hindi", text.to_string());
-  texts.insert("latin", text.to_string());
-  Rc::new(Match { tenses: vec![], texts, value: T::base_lex(text) })
+  texts.insert("hindi", hindi.into());
+  texts.insert("latin", latin.into());
+  let value = T::parse(&value).ok()?;
+  Some(Arc::new(Match { tenses: vec![tense], texts, value }))
+}
+
+// Punctuation lexes as its own token, even when it's attached to a word, as in "hain?",
+// so grammars can require it, e.g. a question mark, or use it, e.g. commas in lists.
+// Each mark only matches its own terminal; there's no %token match for punctuation.
+
+const PUNCTUATION: [(char, &str); 7] = [
+  ('?', "%punct_question"),
+  ('\u{61f}', "%punct_question"),
+  (',', "%punct_comma"),
+  ('\u{60c}', "%punct_comma"),
+  ('.', "%punct_period"),
+  ('\u{964}', "%punct_period"),
+  ('!', "%punct_exclamation"),
+];
+
+fn is_punctuation(c: char) -> bool {
+  PUNCTUATION.iter().any(|x| x.0 == c)
 }
 
-fn update_scores<'a, T: Payload>(
-  entry: &'a XEntry<T>,
-  matches: &mut HashMap<&'a str, (f32, Rc<Match<T>>)>,
+fn punctuation_match<T: Payload>(text: &str) -> Option<(Terminal, Arc<Match<T>>)> {
+  let mut chars = text.chars();
+  let (c, rest) = (chars.next()?, chars.next());
+  let name = PUNCTUATION.iter().find(|x| x.0 == c && rest.is_none())?.1;
+  Some((Terminal::new(name), default_match(text)))
+}
+
+// Splits leading and trailing punctuation off of a word, one token per mark. Marks inside
+// a word, like the "." in "1.5", are left alone.
+
+fn split_punctuation(word: &str) -> Vec<&str> {
+  let marks = |a: usize, b: usize| {
+    word[a..b].char_indices().map(move |(i, x)| &word[a + i..a + i + x.len_utf8()])
+  };
+  let start = word.len() - word.trim_start_matches(is_punctuation).len();
+  let end = word.trim_end_matches(is_punctuation).len();
+  if word.is_empty() {
+    vec![word]
+  } else if start >= end {
+    marks(0, word.len()).collect()
+  } else {
+    let core = std::iter::once(&word[start..end]);
+    marks(0, start).chain(core).chain(marks(end, word.len())).collect()
+  }
+}
+
+// Echo words replace a word's initial consonants with "v", or with "sh" if the word
+// already starts with "v": "pani-vani", "khana-vana". An echo pair matches with the base
+// word's semantics, plus an "approximative" text that holds the echo word alone.
+
+const LATIN_VOWELS: &str = "aeiou";
+const WX_VOWELS: &str = "aeiouAEIOU";
+
+fn echo_stem<'a>(word: &'a str, vowels: &str) -> &'a str {
+  &word[word.find(|x| vowels.contains(x)).unwrap_or(word.len())..]
+}
+
+fn echo_latin(latin: &str) -> Option<String> {
+  let stem = echo_stem(latin, LATIN_VOWELS);
+  let prefix = if latin.starts_with('v') || latin.starts_with('w') { "sh" } else { "v" };
+  if stem.is_empty() { None } else { Some(format!("{}{}", prefix, stem)) }
+}
+
+fn echo_match<T: Payload>(m: &Match<T>, echo: &str) -> Option<Arc<Match<T>>> {
+  let (hindi, latin) = (m.texts.get("hindi")?, m.texts.get("latin")?);
+  let stem = echo_stem(hindi, WX_VOWELS);
+  if stem.is_empty() {
+    return None;
+  }
+  let wx = format!("{}{}", if hindi.starts_with('v') { "S" } else { "v" }, stem);
+  let mut texts = m.texts.clone();
+  for scheme in Scheme::all().iter() {
+    let base = texts.get(scheme.key())?;
+    let text = format!("{}-{}", base, wx_to_scheme(&wx, *scheme).ok()?);
+    texts.insert(scheme.key(), text.into());
+  }
+  if let Some(base) = m.texts.get("urdu") {
+    texts.insert("urdu", format!("{}-{}", base, wx_to_urdu(&wx).ok()?).into());
+  }
+  texts.insert("approximative", echo.into());
+  texts.insert("hindi", format!("{}-{}", hindi, wx).into());
+  texts.insert("latin", format!("{}-{}", latin, echo).into());
+  Some(Arc::new(Match { tenses: m.tenses.clone(), texts, value: m.value.clone() }))
+}
+
+fn split_echo(token: &str) -> Option<(&str, &str)> {
+  let index = token.find('-')?;
+  let (base, echo) = (&token[..index], &token[index + 1..]);
+  let (lower, stem) = (echo.to_lowercase(), echo_stem(base, LATIN_VOWELS).to_lowercase());
+  let prefix = if stem.is_empty() { None } else { lower.strip_suffix(stem.as_str()) }?;
+  if ["sh", "v", "w"].contains(&prefix) { Some((base, echo)) } else { None }
+}
+
+// Unknown words carry a provisional guess at their gender, so that the corrector can
+// still check agreement with them: nouns ending in -i are usually feminine, and ones
+// ending in -a are usually masculine. Since these guesses are unreliable, we never
+// guess the count, and we don't guess at all for proper nouns, like "Ravi".
+
+fn guess_tenses(text: &str) -> Vec<Tense> {
+  let gender = match text.to_lowercase().chars().last() {
+    Some('a') => "male",
+    Some('i') => "female",
+    _ => return vec![],
+  };
+  Tense::new(&std::iter::once(("gender", gender)).collect()).into_iter().collect()
+}
+
+fn urdu_to_latin(urdu: &str) -> Option<String> {
+  wx_to_scheme(&urdu_to_wx(urdu).ok()?, Scheme::HarvardKyoto).ok()
+}
+
+fn update_scores<T: Payload>(
+  entry: &XEntry<T>,
+  matches: &mut HashMap<Terminal, (f32, Arc<Match<T>>)>,
   offset: f32,
 ) {
   for (name, base) in &entry.scores {
     let score = base + offset;
-    let items = matches.entry(name).or_insert((score, Rc::clone(&entry.match_rc)));
+    let items = matches.entry(*name).or_insert((score, Arc::clone(&entry.match_rc)));
     if items.0 < offset {
-      *items = (score, Rc::clone(&entry.match_rc));
+      *items = (score, Arc::clone(&entry.match_rc));
     }
   }
 }
 
+// Usage counts how often each word was used as each terminal, such as in parses of a
+// corpus. The lexer can re-estimate its entries' scores from these counts with add-one
+// smoothing: a word's score as a terminal is the log of its smoothed count relative to
+// the smoothed count of the word's most common terminal, so that the most common use
+// of each word scores 0.0, as hand-written scores do.
+
+const USAGE_SMOOTHING: f32 = 1.0;
+
+#[derive(Default)]
+pub struct Usage {
+  counts: HashMap<String, HashMap<Terminal, usize>>,
+}
+
+impl Usage {
+  pub fn add(&mut self, word: &str, terminal: &str) {
+    let counts = self.counts.entry(word.to_string()).or_default();
+    *counts.entry(Terminal::new(terminal)).or_default() += 1;
+  }
+
+  // Counts each of the tree's leaves as a use of its Hindi text as the terminal it matched.
+  pub fn add_tree<S, T>(&mut self, tree: &Derivation<S, T>) {
+    for (node, _) in tree.nodes() {
+      for (term, child) in node.rule.rhs.iter().zip(&node.children) {
+        if let (Term::Terminal(x), Child::Leaf(y)) = (term, child) {
+          y.texts.get("hindi").iter().for_each(|z| self.add(z, x));
+        }
+      }
+    }
+  }
+
+  pub fn count(&self, word: &str, terminal: &str) -> usize {
+    let counts = self.counts.get(word);
+    counts.and_then(|x| x.get(&Terminal::new(terminal))).cloned().unwrap_or_default()
+  }
+}
+
 pub struct HindiLexer<T: Payload> {
-  from_head: HashMap<String, Vec<Rc<XEntry<T>>>>,
-  from_name: HashMap<String, Vec<Rc<XEntry<T>>>>,
-  from_word: HashMap<String, Vec<Rc<XEntry<T>>>>,
+  from_head: HashMap<String, Vec<Arc<XEntry<T>>>>,
+  from_name: HashMap<String, Vec<Arc<XEntry<T>>>>,
+  from_urdu: HashMap<String, Vec<Arc<XEntry<T>>>>,
+  from_word: HashMap<String, Vec<Arc<XEntry<T>>>>,
+  cache: Mutex<Lru<String, Candidates>>,
+  echo_words: bool,
+  names: HashSet<String>,
+  normalizer: Box<dyn Normalizer>,
   transliterator: Transliterator,
 }
 
 impl<T: Payload> HindiLexer<T> {
-  pub fn new(text: &str) -> Result<Box<dyn Lexer<Option<T>, T>>> {
-    let mut from_head = HashMap::default();
-    let mut from_name = HashMap::default();
-    let mut from_word = HashMap::default();
-    for entry in vocabulary(text)? {
-      let (head, hindi) = (entry.head.clone(), entry.hindi.clone());
-      let entry = Rc::new(create_xentry(entry)?);
-      from_head.entry(head).or_insert(vec![]).push(Rc::clone(&entry));
-      from_word.entry(hindi).or_insert(vec![]).push(Rc::clone(&entry));
-      for name in entry.scores.keys() {
-        from_name.entry(name.clone()).or_insert(vec![]).push(Rc::clone(&entry));
+  pub fn new(text: &str, features: &TenseSchema) -> Result<Box<dyn Lexer<Option<T>, T>>> {
+    Ok(Box::new(Self::create(text, features)?))
+  }
+
+  pub fn create(text: &str, features: &TenseSchema) -> Result<Self> {
+    let mut result = Self {
+      from_head: HashMap::default(),
+      from_name: HashMap::default(),
+      from_urdu: HashMap::default(),
+      from_word: HashMap::default(),
+      cache: Mutex::new(Lru::new(CACHE_SIZE)),
+      echo_words: false,
+      names: HashSet::default(),
+      normalizer: Box::new(Pipeline::default()),
+      transliterator: Transliterator::new(&[]),
+    };
+    let entries = vocabulary(text, features).map_err(|x| x.tag(Error::Lexer))?;
+    entries.into_iter().try_for_each(|x| result.insert(x)).map_err(|x| x.tag(Error::Lexer))?;
+    let words: Vec<_> = result.from_word.keys().map(|x| x.as_str()).collect();
+    result.transliterator = Transliterator::new(&words);
+    Ok(result)
+  }
+
+  pub fn from_files(
+    paths: &[&str],
+    features: &TenseSchema,
+  ) -> Result<Box<dyn Lexer<Option<T>, T>>> {
+    Self::new(&read_tables(paths)?, features)
+  }
+
+  // Add a word after construction, such as a contact name from a per-user dictionary.
+  // Only the new word is added to the transliterator; the rest of the lexer is reused.
+  pub fn add_entry(&mut self, entry: Entry) -> Result<()> {
+    let hindi = entry.hindi.clone();
+    let novel = !self.from_word.contains_key(&hindi);
+    self.insert(entry)?;
+    if novel {
+      self.transliterator.add(&hindi);
+      self.cache.get_mut().unwrap().clear();
+    }
+    Ok(())
+  }
+
+  pub fn cache_stats(&self) -> Stats {
+    self.cache.lock().unwrap().stats()
+  }
+
+  pub fn set_cache_size(mut self, size: usize) -> Self {
+    self.cache = Mutex::new(Lru::new(size));
+    self
+  }
+
+  // With echo words set, generation may produce echo pairs, like "pani-vani", for nouns.
+  pub fn set_echo_words(mut self, echo_words: bool) -> Self {
+    self.echo_words = echo_words;
+    self
+  }
+
+  // Replace the normalization applied to each word before it's transliterated.
+  pub fn set_normalizer(mut self, normalizer: Box<dyn Normalizer>) -> Self {
+    self.normalizer = normalizer;
+    self
+  }
+
+  // Re-estimate the scores of entries for words that appear in the usage counts. Entries
+  // for other words keep the scores from their vocabulary tables.
+  pub fn learn_scores(&mut self, usage: &Usage) {
+    let mut learned = HashMap::default();
+    for (word, entries) in &self.from_word {
+      let counts = match usage.counts.get(word) {
+        Some(x) => x,
+        None => continue,
+      };
+      let count = |x: &Terminal| counts.get(x).cloned().unwrap_or_default() as f32;
+      let terminals = entries.iter().flat_map(|x| x.scores.keys());
+      let max = terminals.map(count).fold(0.0, f32::max);
+      for entry in entries {
+        let score = |x: &Terminal| ((count(x) + USAGE_SMOOTHING) / (max + USAGE_SMOOTHING)).ln();
+        let scores = entry.scores.keys().map(|x| (*x, score(x))).collect();
+        let match_rc = Arc::clone(&entry.match_rc);
+        learned.insert(Arc::as_ptr(entry), Arc::new(XEntry { match_rc, scores }));
+      }
+    }
+    let replace = |map: &mut HashMap<String, Vec<Arc<XEntry<T>>>>| {
+      for entry in map.values_mut().flat_map(|x| x.iter_mut()) {
+        if let Some(x) = learned.get(&Arc::as_ptr(entry)) {
+          *entry = Arc::clone(x);
+        }
       }
+    };
+    replace(&mut self.from_head);
+    replace(&mut self.from_name);
+    replace(&mut self.from_urdu);
+    replace(&mut self.from_word);
+  }
+
+  // Register a known name, such as a contact, so that it always lexes as %proper_noun.
+  pub fn add_name(&mut self, name: &str) {
+    self.names.insert(name.to_lowercase());
+  }
+
+  fn transliterate(&self, x: &str) -> Candidates {
+    let key = x.to_lowercase();
+    if let Some(x) = self.cache.lock().unwrap().get(&key) {
+      return Arc::clone(x);
+    }
+    let result: Candidates = self.transliterator.transliterate(&key).into();
+    self.cache.lock().unwrap().insert(key, Arc::clone(&result));
+    result
+  }
+
+  fn lex_word(&self, x: &str) -> HashMap<Terminal, (f32, Arc<Match<T>>)> {
+    let x = &*self.normalizer.normalize(x);
+    let mut matches = HashMap::default();
+    matches.insert(Terminal::new("%token"), (0.0, default_match(x)));
+    // Tokens in Urdu script are usually spelled the way we render our vocabulary, so we
+    // look for exact matches first. Other Urdu words may drop or add letters, so we read
+    // them back into Latin and transliterate them like any other token.
+    let urdu = x.chars().any(is_urdu);
+    let exact = if urdu { self.from_urdu.get(x).map(|x| x.as_slice()) } else { None };
+    let exact = exact.unwrap_or_default();
+    exact.iter().for_each(|x| update_scores(x, &mut matches, 0.0));
+    let latin = if urdu && exact.is_empty() { urdu_to_latin(x) } else { None };
+    // Score each candidate by its log-probability relative to the most likely one.
+    let options = self.transliterate(latin.as_deref().unwrap_or(x));
+    let best = options.first().map(|x| x.1).unwrap_or_default();
+    for (option, score) in options.iter() {
+      let entries = self.from_word.get(option).unwrap();
+      entries.iter().for_each(|x| update_scores(x, &mut matches, score - best));
+    }
+    if let Some(number) = digit_match(x) {
+      matches.insert(Terminal::new("%number"), (0.0, number));
+    }
+    let unknown = matches.len() == 1 && x.chars().all(|y| y.is_ascii_alphabetic());
+    if unknown {
+      let (base, tenses) = (default_match::<T>(x), guess_tenses(x));
+      let guess = Arc::new(Match { tenses, texts: base.texts.clone(), value: base.value.clone() });
+      matches.insert(Terminal::new("%english"), (ENGLISH_PENALTY, Arc::clone(&guess)));
+      matches.insert(Terminal::new("%token"), (0.0, guess));
     }
-    let t = Transliterator::new(&from_word.keys().map(|x| x.as_str()).collect::<Vec<_>>());
-    Ok(Box::new(Self { from_head, from_name, from_word, transliterator: t }))
+    let name = if self.names.contains(&x.to_lowercase()) {
+      Some(0.0)
+    } else if unknown && x.starts_with(|y: char| y.is_ascii_uppercase()) {
+      Some(CAPITALIZED_NAME_PENALTY)
+    } else if unknown {
+      Some(LOWERCASE_NAME_PENALTY)
+    } else {
+      None
+    };
+    if let Some(score) = name {
+      matches.insert(Terminal::new("%proper_noun"), (score, default_match(x)));
+    }
+    matches
+  }
+
+  // We split the raw token, which may hold characters that the normalizer drops, so we
+  // only cut it at char boundaries.
+  fn split_merged<'b>(&self, x: &'b str) -> Option<(&'b str, &'b str)> {
+    let best = |y: &str| self.transliterate(&self.normalizer.normalize(y)).first().map(|z| z.1);
+    let cuts = MIN_SPLIT_LENGTH..=x.len().saturating_sub(MIN_SPLIT_LENGTH);
+    let splits = cuts.filter(|i| x.is_char_boundary(*i)).filter_map(|i| {
+      Some((i, best(&x[..i])? + best(&x[i..])?))
+    });
+    let split = splits.max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    split.map(|(i, _)| (&x[..i], &x[i..]))
+  }
+
+  fn insert(&mut self, entry: Entry) -> Result<()> {
+    let (head, hindi) = (entry.head.clone(), entry.hindi.clone());
+    let entry = Arc::new(create_xentry(entry)?);
+    self.from_head.entry(head).or_insert(vec![]).push(Arc::clone(&entry));
+    self.from_word.entry(hindi).or_insert(vec![]).push(Arc::clone(&entry));
+    if let Some(urdu) = entry.match_rc.texts.get("urdu") {
+      self.from_urdu.entry(urdu.to_string()).or_insert(vec![]).push(Arc::clone(&entry));
+    }
+    for name in entry.scores.keys() {
+      self.from_name.entry(name.name().to_string()).or_insert(vec![]).push(Arc::clone(&entry));
+    }
+    Ok(())
   }
 }
 
 impl<T: Payload> Lexer<Option<T>, T> for HindiLexer<T> {
-  fn fix(&self, m: &Match<T>, t: &Tense) -> Vec<Rc<Match<T>>> {
+  fn fix(&self, m: &Match<T>, t: &Tense) -> Vec<Arc<Match<T>>> {
     let (head, latin) = (m.texts.get("head"), m.texts.get("latin"));
     if head.is_none() || latin.is_none() {
       return vec![];
     }
     let (head, latin) = (head.unwrap(), latin.unwrap());
-    let check = |x: &&Rc<XEntry<T>>| {
+    let check = |x: &&Arc<XEntry<T>>| {
       x.match_rc.value == m.value && x.match_rc.tenses.iter().any(|y| y.agree(t))
     };
-    let score = |x: &&Rc<XEntry<T>>| {
+    let score = |x: &&Arc<XEntry<T>>| {
       x.match_rc.texts.get("latin").map(|x| common_prefix(x, latin).len()).unwrap_or_default()
     };
-    let by_heads = self.from_head.get(head).map(|x| x.as_slice()).unwrap_or_default();
+    // Prefer entries that keep the old match's values for categories that the tense leaves
+    // open. For example, when "larka" must be oblique, it should stay singular: "larke".
+    let wanted = m.tenses.iter().map(|x| {
+      let mut x = x.clone();
+      x.union(t);
+      x
+    });
+    let wanted: Vec<_> = wanted.collect();
+    let keeps = |x: &&Arc<XEntry<T>>| {
+      x.match_rc.tenses.iter().any(|y| wanted.iter().any(|z| y.agree(z)))
+    };
+    let by_heads = self.from_head.get(&**head).map(|x| x.as_slice()).unwrap_or_default();
     let by_value: Vec<_> = by_heads.iter().filter(check).collect();
+    let kept: Vec<_> = by_value.iter().cloned().filter(keeps).collect();
+    let by_value = if kept.is_empty() { by_value } else { kept };
     let max_score = by_value.iter().map(score).max().unwrap_or_default();
     let by_score: Vec<_> = by_value.iter().filter(|x| score(x) == max_score).collect();
-    by_score.into_iter().map(|x| Rc::clone(&x.match_rc)).collect()
+    by_score.into_iter().map(|x| Arc::clone(&x.match_rc)).collect()
   }
 
   fn lex<'a: 'b, 'b>(&'a self, input: &'b str) -> Vec<Token<'b, T>> {
-    let xs = input.split(' ').map(|x| {
-      let mut matches = HashMap::default();
-      matches.insert("%token", (0.0, default_match(x)));
-      for (i, option) in self.transliterator.transliterate(x).into_iter().enumerate() {
-        let entries = self.from_word.get(&option).unwrap();
-        entries.iter().for_each(|x| update_scores(x, &mut matches, -(i as f32)));
+    let xs = input.split(' ').flat_map(split_punctuation).flat_map(|x| {
+      if let Some((name, m)) = punctuation_match(x) {
+        let matches = std::iter::once((name, (0.0, m))).collect();
+        return vec![Token { matches, text: x }];
+      }
+      let mut matches = self.lex_word(x);
+      let english = matches.contains_key(&Terminal::new("%english"));
+      let name = self.names.contains(&x.to_lowercase()) || x.starts_with(char::is_uppercase);
+      if english && !name {
+        if let Some((a, b)) = self.split_merged(x) {
+          let halves = vec![a, b].into_iter().map(|y| {
+            let mut matches = self.lex_word(y);
+            matches.values_mut().for_each(|z| z.0 += SPLIT_PENALTY);
+            Token { matches, text: y }
+          });
+          return halves.collect();
+        }
       }
-      Token { matches: matches.into_iter().collect(), text: x }
+      if let Some((base, echo)) = split_echo(x) {
+        for (name, (score, m)) in self.lex_word(base) {
+          if !m.texts.contains_key("head") {
+            continue;
+          }
+          if let Some(m) = echo_match(&m, echo) {
+            matches.entry(name).or_insert((score, m));
+          }
+        }
+      }
+      vec![Token { matches, text: x }]
     });
     xs.collect()
   }
 
-  fn unlex(&self, name: &str, value: &Option<T>) -> Vec<Rc<Match<T>>> {
-    if name == "%token" {
+  fn unlex(&self, name: &str, value: &Option<T>) -> Vec<Arc<Match<T>>> {
+    if let Some(x) = PUNCTUATION.iter().find(|x| x.1 == name) {
+      let m = default_match(&x.0.to_string());
+      return if value.iter().all(|y| *y == m.value) { vec![m] } else { vec![] };
+    }
+    if name == "%english" || name == "%proper_noun" || name == "%token" {
       if let Some(value) = value {
         if let Some(text) = T::base_unlex(value) {
           return vec![default_match(text)];
@@ -115,13 +551,42 @@ impl<T: Payload> Lexer<Option<T>, T> for HindiLexer<T> {
       if let Some(value) = value {
         entries = entries.into_iter().filter(|x| x.match_rc.value == *value).collect();
       }
-      let min = std::f32::NEG_INFINITY;
-      let max = entries.iter().fold(min, |a, x| a.max(x.scores.get(name).cloned().unwrap_or(min)));
-      entries
-        .into_iter()
-        .filter(|x| x.scores.get(name).cloned().unwrap_or(min) == max)
-        .map(|x| Rc::clone(&x.match_rc))
-        .collect()
+      let (min, terminal) = (std::f32::NEG_INFINITY, Terminal::new(name));
+      let score = |x: &&Arc<XEntry<T>>| x.scores.get(&terminal).cloned().unwrap_or(min);
+      let max = entries.iter().fold(min, |a, x| a.max(score(x)));
+      let entries: Vec<_> = entries.into_iter().filter(|x| score(x) == max).collect();
+      let mut result: Vec<_> = entries.iter().map(|x| Arc::clone(&x.match_rc)).collect();
+      if self.echo_words {
+        let nouns = entries.iter().filter(|x| x.scores.contains_key(&Terminal::new("%noun")));
+        let echoes = nouns.filter_map(|x| {
+          let echo = echo_latin(x.match_rc.texts.get("latin")?)?;
+          echo_match(&x.match_rc, &echo)
+        });
+        result.extend(echoes.collect::<Vec<_>>());
+      }
+      result
     }
   }
+
+  fn vocabulary(&self) -> Vec<(Terminal, (f32, Arc<Match<T>>))> {
+    let mut result = vec![];
+    for (c, name) in PUNCTUATION.iter() {
+      result.push((Terminal::new(name), (0.0, default_match(&c.to_string()))));
+    }
+    for (name, entries) in &self.from_name {
+      let terminal = Terminal::new(name);
+      for x in entries {
+        let score = x.scores.get(&terminal).cloned().unwrap_or(f32::NEG_INFINITY);
+        result.push((terminal, (score, Arc::clone(&x.match_rc))));
+      }
+    }
+    result
+  }
+
+  fn terminals(&self) -> Vec<Terminal> {
+    let open = ["%english", "%number", "%proper_noun", "%token"];
+    let names = PUNCTUATION.iter().map(|x| x.1).chain(open.iter().cloned());
+    let names = names.chain(self.from_name.keys().map(|x| x.as_str()));
+    names.map(Terminal::new).collect()
+  }
 }
@@ -1,5 +1,7 @@
 pub mod frequencies;
 pub mod lexer;
+pub mod normalize;
 pub mod transliterator;
+pub mod urdu;
 pub mod vocabulary;
 pub mod wx;
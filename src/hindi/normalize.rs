@@ -0,0 +1,128 @@
+use std::borrow::Cow;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+// The lexer normalizes each word before transliterating it, so that inputs that differ
+// only in encoding or in informal spelling match the same vocabulary entries. Each step
+// of the pipeline is a Normalizer, and returns its input unchanged when it has nothing
+// to do, so that the common case doesn't allocate.
+//
+// The default pipeline composes Unicode characters (NFC), strips zero-width characters,
+// and cuts runs of a repeated letter down to two, so "pleeease" becomes "pleease" and
+// "paaaani" becomes "paani". We keep two because doubled vowels are how informal Hindi
+// romanization spells long vowels. We don't lowercase by default, because the lexer
+// uses capitalization as evidence for proper nouns; add a Lowercase step to do so.
+
+pub trait Normalizer: Send + Sync {
+  fn normalize<'a>(&self, input: &'a str) -> Cow<'a, str>;
+}
+
+pub struct Pipeline(Vec<Box<dyn Normalizer>>);
+
+impl Pipeline {
+  pub fn new(steps: Vec<Box<dyn Normalizer>>) -> Self {
+    Self(steps)
+  }
+}
+
+impl Default for Pipeline {
+  fn default() -> Self {
+    Self(vec![Box::new(Nfc), Box::new(StripZeroWidth), Box::new(CollapseRepeats(2))])
+  }
+}
+
+impl Normalizer for Pipeline {
+  fn normalize<'a>(&self, input: &'a str) -> Cow<'a, str> {
+    let mut result = Cow::Borrowed(input);
+    for step in &self.0 {
+      let next = match step.normalize(&result) {
+        Cow::Borrowed(_) => None,
+        Cow::Owned(x) => Some(x),
+      };
+      if let Some(x) = next {
+        result = Cow::Owned(x);
+      }
+    }
+    result
+  }
+}
+
+// The individual steps. CollapseRepeats only applies to letters, so "1000" is unchanged.
+
+pub struct CollapseRepeats(pub usize);
+
+pub struct Lowercase;
+
+pub struct Nfc;
+
+pub struct StripZeroWidth;
+
+const ZERO_WIDTH: [char; 5] = ['\u{200b}', '\u{200c}', '\u{200d}', '\u{2060}', '\u{feff}'];
+
+impl Normalizer for CollapseRepeats {
+  fn normalize<'a>(&self, input: &'a str) -> Cow<'a, str> {
+    let (mut result, mut last, mut count) = (String::new(), None, 0);
+    for x in input.chars() {
+      count = if last == Some(x) { count + 1 } else { 1 };
+      last = Some(x);
+      if count <= self.0 || !x.is_alphabetic() {
+        result.push(x);
+      }
+    }
+    if result.len() == input.len() {
+      Cow::Borrowed(input)
+    } else {
+      Cow::Owned(result)
+    }
+  }
+}
+
+impl Normalizer for Lowercase {
+  fn normalize<'a>(&self, input: &'a str) -> Cow<'a, str> {
+    if input.chars().any(char::is_uppercase) {
+      Cow::Owned(input.to_lowercase())
+    } else {
+      Cow::Borrowed(input)
+    }
+  }
+}
+
+impl Normalizer for Nfc {
+  fn normalize<'a>(&self, input: &'a str) -> Cow<'a, str> {
+    if is_nfc(input) {
+      Cow::Borrowed(input)
+    } else {
+      Cow::Owned(input.nfc().collect())
+    }
+  }
+}
+
+impl Normalizer for StripZeroWidth {
+  fn normalize<'a>(&self, input: &'a str) -> Cow<'a, str> {
+    if input.contains(&ZERO_WIDTH[..]) {
+      Cow::Owned(input.chars().filter(|x| !ZERO_WIDTH.contains(x)).collect())
+    } else {
+      Cow::Borrowed(input)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn normalizers_work() {
+    let pipeline = Pipeline::default();
+    let normalize = |x: &str| pipeline.normalize(x).into_owned();
+    assert_eq!(normalize("pleeease"), "pleease");
+    assert_eq!(normalize("paaaani 1000"), "paani 1000");
+    assert_eq!(normalize("Ravi"), "Ravi");
+    assert_eq!(normalize("\u{915}\u{94d}\u{200d}\u{937}"), "\u{915}\u{94d}\u{937}");
+    assert_eq!(normalize("\u{627}\u{653}"), "\u{622}");
+    assert!(matches!(pipeline.normalize("pani"), Cow::Borrowed(_)));
+
+    let pipeline = Pipeline::new(vec![Box::new(Lowercase), Box::new(CollapseRepeats(1))]);
+    assert_eq!(pipeline.normalize("PAANI\u{200b}"), "pani\u{200b}");
+    assert_eq!(Pipeline::new(vec![]).normalize("pleeease"), "pleeease");
+  }
+}
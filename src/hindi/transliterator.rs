@@ -1,5 +1,7 @@
-use super::super::lib::base::{HashMap, HashSet};
-use super::frequencies::{Bytes, LOG_FREQUENCY, VOWEL_SKIP_LOG_FREQUENCY};
+use super::super::lib::base::{HashMap, HashSet, Result};
+use super::frequencies::{
+  Bytes, DOUBLED_VOWEL_LOG_FREQUENCY, LOG_FREQUENCY, VOWEL_SKIP_LOG_FREQUENCY,
+};
 use lib::dawg::Dawg;
 use std::str::from_utf8;
 
@@ -134,7 +136,12 @@ fn viterbi(latin: &str, wx: &str) -> f32 {
                 break;
               }
               let index = tail + k - j - option.len();
-              let score = VOWEL_SKIP_LOG_FREQUENCY.with(|a| memo[index] + *a);
+              let skip = if k > 0 && latin[k] == latin[k - 1] {
+                DOUBLED_VOWEL_LOG_FREQUENCY.with(|a| *a)
+              } else {
+                VOWEL_SKIP_LOG_FREQUENCY.with(|a| *a)
+              };
+              let score = memo[index] + skip;
               if memo[index + 1] >= score {
                 break;
               }
@@ -148,35 +155,98 @@ fn viterbi(latin: &str, wx: &str) -> f32 {
   })
 }
 
+// Scores a word whose hash keys are the given number of edits away from the input's, so
+// the input has at least that many typos. Deleting a character from the input fixes one
+// typo; we charge a penalty for each deletion and for each edit that they don't cover.
+
+fn typo(latin: &str, wx: &str, edits: usize, deletions: usize) -> f32 {
+  let base = viterbi(latin, wx) + TYPO_PENALTY * edits as f32;
+  if deletions == 0 {
+    return base;
+  }
+  let options = latin.char_indices().map(|(i, x)| {
+    let latin = format!("{}{}", &latin[..i], &latin[i + x.len_utf8()..]);
+    TYPO_PENALTY + typo(&latin, wx, edits.saturating_sub(1), deletions - 1)
+  });
+  options.fold(base, f32::max)
+}
+
 // We wrap the transliteration logic in a simple interface.
 
+// A typo costs more than the least likely spelling of a word, so that a word that the
+// input spells, however unusually, outranks a word that it would spell with a typo.
+
+const MAX_CANDIDATES: usize = 64;
+const TYPO_PENALTY: f32 = -20.0;
+
 pub struct Transliterator {
   dawg: Dawg<u8, String>,
 }
 
 impl Transliterator {
   pub fn new(words: &[&str]) -> Self {
-    let mut dawg = Dawg::new(&[]);
-    for wx in words {
-      let wx = wx.to_string();
-      for key in hash_keys_from_wx(&wx) {
-        dawg.add(key.as_bytes(), &wx);
-      }
+    let mut result = Self { dawg: Dawg::new(&[]) };
+    words.iter().for_each(|x| result.add(x));
+    Self { dawg: result.dawg.compress() }
+  }
+
+  // A transliterator can be saved and loaded, to skip building its index on startup.
+  pub fn deserialize(bytes: &[u8]) -> Result<Self> {
+    Ok(Self { dawg: Dawg::deserialize(bytes)? })
+  }
+
+  pub fn serialize(&self) -> Vec<u8> {
+    self.dawg.serialize()
+  }
+
+  pub fn add(&mut self, wx: &str) {
+    self.add_weighted(wx, 0.0);
+  }
+
+  // Adds a word with a log-probability prior, such as its log-frequency, which is added
+  // to its score. Only the MAX_CANDIDATES words with the best priors for each key, and
+  // any that tie with the last of them, are scored, so common words are never crowded
+  // out by rare ones, and words with equal priors are never cut.
+  pub fn add_weighted(&mut self, wx: &str, prior: f32) {
+    let wx = wx.to_string();
+    for key in hash_keys_from_wx(&wx) {
+      self.dawg.add_weighted(key.as_bytes(), &wx, prior);
     }
-    Self { dawg: dawg.compress() }
   }
 
-  pub fn transliterate(&self, latin: &str) -> Vec<String> {
+  // Returns candidate WX words with their log-probabilities, from most to least likely.
+  pub fn transliterate(&self, latin: &str) -> Vec<(String, f32)> {
+    self.transliterate_within(latin, 0)
+  }
+
+  // Like transliterate, but tolerates typos: "chahiw" -> "cAhIe". We consider words whose
+  // hash keys are within max_edits of the input's keys. A typo usually makes the input
+  // impossible to align with the word, so we score these words by deleting up to that
+  // many characters from the input, with a penalty per typo. "chahye" -> "cAhIe" needs no
+  // typos, so it outranks "cAhe", even though "chahe" is a more likely spelling of it.
+  pub fn transliterate_within(&self, latin: &str, max_edits: usize) -> Vec<(String, f32)> {
     let latin = latin.to_lowercase();
     let mut scores = HashMap::default();
     for key in hash_keys_from_latin(&latin) {
-      for wx in self.dawg.get(key.as_bytes()) {
-        scores.entry(wx.clone()).or_insert_with(|| viterbi(&latin, &wx));
+      let candidates = if max_edits == 0 {
+        let top = self.dawg.get_top_k(key.as_bytes(), MAX_CANDIDATES);
+        top.into_iter().map(|x| (x.0, 0, x.1)).collect()
+      } else {
+        self.dawg.get_within(key.as_bytes(), max_edits)
+      };
+      for (wx, edits, prior) in candidates {
+        let score = match edits {
+          0 => viterbi(&latin, &wx),
+          _ => typo(&latin, &wx, edits, max_edits),
+        };
+        let score = score + prior;
+        let entry = scores.entry(wx).or_insert(score);
+        *entry = entry.max(score);
       }
     }
     let mut scores: Vec<_> = scores.into_iter().filter(|x| x.1 > std::f32::NEG_INFINITY).collect();
     scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-    scores.into_iter().map(|x| x.0).collect()
+    scores
   }
 }
 
@@ -185,51 +255,122 @@ mod tests {
   use super::*;
   use test::Bencher;
 
+  fn ranked(t: &Transliterator, latin: &str) -> Vec<String> {
+    t.transliterate(latin).into_iter().map(|x| x.0).collect()
+  }
+
   #[test]
   fn empty_list_returned_without_transliterations() {
     let t = Transliterator::new(&"hE hEM ho hUz".split(' ').collect::<Vec<_>>());
-    assert_eq!(t.transliterate("main"), &[] as &[&str]);
+    assert_eq!(ranked(&t, "main"), &[] as &[&str]);
   }
 
   #[test]
   fn hard_d_sound_matched_with_latin_r() {
     let t = Transliterator::new(&"ladZakA ladZakI larkA larkI".split(' ').collect::<Vec<_>>());
-    assert_eq!(t.transliterate("larka"), &["larkA", "ladZakA", "larkI", "ladZakI"] as &[&str]);
+    assert_eq!(ranked(&t, "larka"), &["larkA", "ladZakA", "larkI", "ladZakI"] as &[&str]);
+  }
+
+  #[test]
+  fn final_nasalization_is_optional() {
+    let t = Transliterator::new(&"nahI nahIM kahA kahAz".split(' ').collect::<Vec<_>>());
+    assert_eq!(ranked(&t, "nahi"), &["nahI", "nahIM"]);
+    assert_eq!(ranked(&t, "nahin"), &["nahIM", "nahI"]);
+    assert_eq!(ranked(&t, "nahiin"), &["nahIM", "nahI"]);
+    assert_eq!(ranked(&t, "kaha"), &["kahA", "kahAz"]);
+    assert_eq!(ranked(&t, "kahan"), &["kahAz", "kahA"]);
+    assert_eq!(ranked(&t, "kahaan"), &["kahAz", "kahA"]);
+  }
+
+  #[test]
+  fn doubled_vowels_are_low_cost() {
+    let doubled = DOUBLED_VOWEL_LOG_FREQUENCY.with(|a| *a);
+    assert_eq!(viterbi("suuraj", "sUraj"), viterbi("suraj", "sUraj") + doubled);
+    assert_eq!(viterbi("nahiin", "nahIM"), viterbi("nahin", "nahIM") + doubled);
+    assert!(viterbi("huun", "hUz") > viterbi("huon", "hUz"));
+    assert!(viterbi("nahiin", "nahIM") > viterbi("nahiin", "nahI"));
+  }
+
+  #[test]
+  fn transliterations_include_scores() {
+    let t = Transliterator::new(&"hE hEM ho hUz".split(' ').collect::<Vec<_>>());
+    let result = t.transliterate("hain");
+    assert_eq!(result.len(), 4);
+    assert!(result.iter().all(|x| x.1 == viterbi("hain", &x.0) && x.1 < 0.0));
+    assert!(result.windows(2).all(|x| x[0].1 >= x[1].1));
+  }
+
+  #[test]
+  fn words_added_after_construction() {
+    let mut t = Transliterator::new(&"larkA larkI".split(' ').collect::<Vec<_>>());
+    t.add("ladZakA");
+    assert_eq!(ranked(&t, "larka"), &["larkA", "ladZakA", "larkI"] as &[&str]);
+  }
+
+  #[test]
+  fn typos_tolerated_within_edit_bound() {
+    let t = Transliterator::new(&"cAhIe cAhe cAhI cAh Cah cAhA".split(' ').collect::<Vec<_>>());
+    let fuzzy = t.transliterate_within("chahye", 1);
+    assert_eq!(fuzzy[0], ("cAhIe".into(), viterbi("chahye", "cAhIe")));
+    let chahe = fuzzy.iter().find(|x| x.0 == "cAhe").unwrap();
+    assert_eq!(chahe.1, viterbi("chahe", "cAhe") + TYPO_PENALTY);
+    assert!(fuzzy.iter().skip(1).all(|x| x.1 < TYPO_PENALTY));
+    assert_eq!(t.transliterate_within("chahie", 1), t.transliterate("chahie"));
+  }
+
+  #[test]
+  fn priors_adjust_scores() {
+    let mut t = Transliterator::new(&"hE ho hUz".split(' ').collect::<Vec<_>>());
+    let base = t.transliterate("hain");
+    t.add_weighted("hEM", -2.0);
+    let result = t.transliterate("hain");
+    assert_eq!(result.iter().find(|x| x.0 == "hEM").unwrap().1, viterbi("hain", "hEM") - 2.0);
+    assert!(base.iter().all(|x| result.contains(x)));
+    let copy = Transliterator::deserialize(&t.serialize()).unwrap();
+    assert_eq!(copy.transliterate("hain"), result);
+  }
+
+  #[test]
+  fn transliterators_can_be_saved() {
+    let t = Transliterator::new(&"hE hEM ho hUz".split(' ').collect::<Vec<_>>());
+    let copy = Transliterator::deserialize(&t.serialize()).unwrap();
+    assert_eq!(copy.transliterate("hain"), t.transliterate("hain"));
+    assert!(Transliterator::deserialize(b"junk").is_err());
   }
 
   #[test]
   fn mismatched_consonant_values_filtered() {
     let t = Transliterator::new(&"tA wA dZA".split(' ').collect::<Vec<_>>());
-    assert_eq!(t.transliterate("tha"), &["wA", "tA"]);
+    assert_eq!(ranked(&t, "tha"), &["wA", "tA"]);
   }
 
   #[test]
   fn mismatched_initial_vowels_filtered() {
     let t = Transliterator::new(&"aBI BI".split(' ').collect::<Vec<_>>());
-    assert_eq!(t.transliterate("abhi"), &["aBI"]);
+    assert_eq!(ranked(&t, "abhi"), &["aBI"]);
   }
 
   #[test]
   fn transliterations_ranked_correctly() {
     let t = Transliterator::new(&"hE hEM ho hUz".split(' ').collect::<Vec<_>>());
-    assert_eq!(t.transliterate("hain".trim()), &["hEM", "hE", "hUz", "ho"]);
-    assert_eq!(t.transliterate("hai ".trim()), &["hE", "hEM", "ho", "hUz"]);
-    assert_eq!(t.transliterate("ho  ".trim()), &["ho", "hE", "hEM", "hUz"]);
-    assert_eq!(t.transliterate("hoon".trim()), &["hUz", "ho", "hEM", "hE"]);
-    assert_eq!(t.transliterate("hu  ".trim()), &["hUz", "ho", "hE", "hEM"]);
+    assert_eq!(ranked(&t, "hain".trim()), &["hEM", "hE", "hUz", "ho"]);
+    assert_eq!(ranked(&t, "hai ".trim()), &["hE", "hEM", "ho", "hUz"]);
+    assert_eq!(ranked(&t, "ho  ".trim()), &["ho", "hE", "hEM", "hUz"]);
+    assert_eq!(ranked(&t, "hoon".trim()), &["hUz", "ho", "hEM", "hE"]);
+    assert_eq!(ranked(&t, "hu  ".trim()), &["hUz", "ho", "hE", "hEM"]);
   }
 
   #[test]
   fn transliteration_allows_vowel_skips() {
     let t = Transliterator::new(&"khaUnga king".split(' ').collect::<Vec<_>>());
-    assert_eq!(t.transliterate("khunga"), &["khaUnga", "king"]);
+    assert_eq!(ranked(&t, "khunga"), &["khaUnga", "king"]);
   }
 
   #[test]
   fn transliteration_allows_y_between_vowels() {
     let t = Transliterator::new(&"leenge leyenge".split(' ').collect::<Vec<_>>());
-    assert_eq!(t.transliterate("leenge ".trim()), &["leenge"]);
-    assert_eq!(t.transliterate("leyenge".trim()), &["leyenge", "leenge"]);
+    assert_eq!(ranked(&t, "leenge ".trim()), &["leenge"]);
+    assert_eq!(ranked(&t, "leyenge".trim()), &["leyenge", "leenge"]);
   }
 
   #[bench]
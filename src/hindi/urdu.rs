@@ -0,0 +1,203 @@
+use super::super::lib::base::Result;
+
+// Hindi and Urdu are close enough that one vocabulary can serve both. We render WX in
+// Urdu's Perso-Arabic script by spelling out consonants and long vowels and dropping
+// short vowels, as Urdu usually does. Vowels have different forms at the start and
+// end of a word: "aBI" is ابھی, with an initial alef, and "xe" is دے, with a final ے.
+//
+// Going back from Urdu to WX is lossy: short vowels are only recovered when written
+// with diacritics, and some letters are ambiguous (و may be "v", "o", "U", or "O"). We
+// pick the most common reading for each letter.
+
+// Each row is: a WX consonant, then its Urdu form. Aspirates end in do-chashmi he (ھ).
+type Row = (char, &'static str);
+
+static CONSONANTS: &[Row] = &[
+  ('k', "ک"),
+  ('K', "کھ"),
+  ('g', "گ"),
+  ('G', "گھ"),
+  ('f', "ن"),
+  ('c', "چ"),
+  ('C', "چھ"),
+  ('j', "ج"),
+  ('J', "جھ"),
+  ('F', "ن"),
+  ('t', "ٹ"),
+  ('T', "ٹھ"),
+  ('d', "ڈ"),
+  ('D', "ڈھ"),
+  ('N', "ن"),
+  ('w', "ت"),
+  ('W', "تھ"),
+  ('x', "د"),
+  ('X', "دھ"),
+  ('n', "ن"),
+  ('p', "پ"),
+  ('P', "پھ"),
+  ('b', "ب"),
+  ('B', "بھ"),
+  ('m', "م"),
+  ('y', "ی"),
+  ('r', "ر"),
+  ('l', "ل"),
+  ('v', "و"),
+  ('S', "ش"),
+  ('R', "ش"),
+  ('s', "س"),
+  ('h', "ہ"),
+  ('q', "ر"),
+  ('Q', "ر"),
+  ('L', "ل"),
+  ('H', "ہ"),
+];
+
+// The same, but for consonants followed by a nukta ('Z' in WX).
+static NUKTA_CONSONANTS: &[Row] = &[
+  ('k', "ق"),
+  ('K', "خ"),
+  ('g', "غ"),
+  ('j', "ز"),
+  ('d', "ڑ"),
+  ('D', "ڑھ"),
+  ('P', "ف"),
+];
+
+// Each row is: a WX vowel, then its initial, medial, and final Urdu forms.
+static VOWELS: &[(char, &str, &str, &str)] = &[
+  ('a', "ا", "", ""),
+  ('A', "آ", "ا", "ا"),
+  ('i', "ا", "", ""),
+  ('I', "ای", "ی", "ی"),
+  ('u', "ا", "", ""),
+  ('U', "او", "و", "و"),
+  ('e', "ای", "ی", "ے"),
+  ('E', "ای", "ی", "ے"),
+  ('o', "او", "و", "و"),
+  ('O', "او", "و", "و"),
+];
+
+static ASPIRATE: char = '\u{6be}';
+static ZABAR: char = '\u{64e}';
+static PESH: char = '\u{64f}';
+static ZER: char = '\u{650}';
+static SHADDA: char = '\u{651}';
+
+pub fn is_urdu(ch: char) -> bool {
+  ('\u{600}'..='\u{6ff}').contains(&ch)
+}
+
+pub fn wx_to_urdu(wx: &str) -> Result<String> {
+  let chars: Vec<_> = wx.chars().collect();
+  let mut result = String::with_capacity(2 * wx.len());
+  for (i, x) in chars.iter().cloned().enumerate() {
+    if x == 'Z' {
+      continue;
+    }
+    let nukta = chars.get(i + 1) == Some(&'Z');
+    let last = chars[i + 1..].iter().all(|y| *y == 'Z');
+    let (initial, vowel) = (i == 0, VOWELS.iter().find(|y| y.0 == x));
+    let hiatus = chars.get(i + 1).map(|y| VOWELS.iter().any(|z| z.0 == *y)).unwrap_or(false);
+    let text = if let Some(vowel) = vowel {
+      // Short "i" is written out before another vowel, as in "cAhie" (چاہیے).
+      if initial {
+        vowel.1
+      } else if last {
+        vowel.3
+      } else if x == 'i' && hiatus {
+        "ی"
+      } else {
+        vowel.2
+      }
+    } else if x == 'M' || x == 'z' {
+      if last { "ں" } else { "ن" }
+    } else {
+      let table = if nukta { NUKTA_CONSONANTS } else { CONSONANTS };
+      let row = table.iter().find(|y| y.0 == x).or_else(|| CONSONANTS.iter().find(|y| y.0 == x));
+      row.ok_or_else(|| format!("Invalid WX: {}", wx))?.1
+    };
+    result.push_str(text);
+  }
+  Ok(result)
+}
+
+pub fn urdu_to_wx(urdu: &str) -> Result<String> {
+  let mut result = String::with_capacity(2 * urdu.len());
+  let mut prev: Option<char> = None;
+  for x in urdu.chars() {
+    let initial = result.is_empty();
+    let consonant = prev.map(|y| !"aAiIuUeEoOM".contains(y)).unwrap_or(false);
+    let text = match x {
+      'آ' => "A".to_string(),
+      'ا' => (if initial { "a" } else { "A" }).to_string(),
+      'ی' => (if initial || !consonant { "y" } else { "I" }).to_string(),
+      'ے' => "e".to_string(),
+      'و' => (if initial || !consonant { "v" } else { "o" }).to_string(),
+      'ں' => "M".to_string(),
+      'ن' => "n".to_string(),
+      'ئ' => "".to_string(),
+      _ if x == ZABAR => "a".to_string(),
+      _ if x == ZER => "i".to_string(),
+      _ if x == PESH => "u".to_string(),
+      _ if x == SHADDA => prev.map(|y| y.to_string()).unwrap_or_default(),
+      _ if x == ASPIRATE => {
+        let base = prev.and_then(|y| CONSONANTS.iter().find(|z| z.0 == y)).map(|y| y.1);
+        let aspirate = base.map(|y| format!("{}{}", y, ASPIRATE));
+        let row = aspirate.and_then(|y| CONSONANTS.iter().find(|z| z.1 == y));
+        let row = row.ok_or_else(|| format!("Invalid Urdu: {}", urdu))?;
+        result.pop();
+        row.0.to_string()
+      }
+      _ => {
+        let nukta = NUKTA_CONSONANTS.iter().find(|y| y.1 == x.to_string());
+        let plain = CONSONANTS.iter().find(|y| y.1 == x.to_string());
+        match (nukta, plain) {
+          (Some(y), _) => format!("{}Z", y.0),
+          (None, Some(y)) => y.0.to_string(),
+          (None, None) => Err(format!("Invalid Urdu: {}", urdu))?,
+        }
+      }
+    };
+    result.push_str(&text);
+    prev = result.chars().last().or(prev);
+  }
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  thread_local! {
+    static ITEMS: Vec<[&'static str; 2]> = vec![
+      ["aBI", "ابھی"],
+      ["cAhie", "چاہیے"],
+      ["KAnA", "کھانا"],
+      ["ladZakA", "لڑکا"],
+      ["mEM", "میں"],
+      ["pAnI", "پانی"],
+      ["xe", "دے"],
+    ];
+  }
+
+  #[test]
+  fn test_wx_to_urdu() {
+    ITEMS.with(|a| a.iter().for_each(|x| assert_eq!(wx_to_urdu(x[0]).unwrap(), x[1])));
+    assert_eq!(wx_to_urdu("k1"), Err("Invalid WX: k1".into()));
+  }
+
+  #[test]
+  fn test_urdu_to_wx() {
+    let items = [["پانی", "pAnI"], ["کھانا", "KAnA"], ["دے", "xe"], ["آپ", "Ap"], ["قلم", "kZlm"]];
+    items.iter().for_each(|x| assert_eq!(urdu_to_wx(x[0]).unwrap(), x[1]));
+    assert_eq!(urdu_to_wx("\u{644}\u{64e}\u{688}\u{6a9}\u{627}").unwrap(), "ladkA");
+    assert_eq!(urdu_to_wx("x"), Err("Invalid Urdu: x".into()));
+  }
+
+  #[test]
+  fn test_round_trips_without_short_vowels() {
+    for wx in &["pAnI", "KAnA", "cAy", "xo", "Ap", "hEM"] {
+      assert_eq!(urdu_to_wx(&wx_to_urdu(wx).unwrap()).unwrap(), wx.replace('E', "I"));
+    }
+  }
+}
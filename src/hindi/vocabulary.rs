@@ -1,105 +1,99 @@
 use super::super::lib::base::{HashMap, Result};
-use super::super::nlu::base::Tense;
+use super::super::lib::morphology::{split, stem, zip, Case, Morphology, Paradigm};
+use super::super::lib::table::{choice, FromCell};
+use super::super::nlu::base::{Tense, TenseSchema};
 use super::wx::wx_to_hindi;
 
-pub struct Entry {
-  pub head: String,
-  pub hindi: String,
-  pub latin: String,
-  pub scores: HashMap<String, f32>,
-  pub tenses: Vec<Tense>,
-  pub value: String,
-}
-
-thread_local! {
-  static CATEGORIES: Vec<(&'static str, Vec<(u8, &'static str)>)> = vec![
-    ("count", vec![(b'p', "plural"), (b's', "singular")]),
-    ("gender", vec![(b'f', "female"), (b'm', "male")]),
-    ("person", vec![(b'1', "first"), (b'2', "second"), (b'3', "third")]),
-    ("time", vec![(b'<', "past"), (b'=', "present"), (b'>', "future")]),
-    ("tone", vec![(b'c', "casual"), (b'f', "formal"), (b'i', "intimate")]),
-  ];
-}
+pub use super::super::lib::morphology::Entry;
 
-// Some helpers. Call rollup to construct a list of related vocabulary result. Call tense
-// to build a Tense object with interned (statically-allocated) string keys and values.
+// Tense codes in the paradigms below use the one-character codes of the grammar's features,
+// in the order that it declares them: count, gender, person, time, tone, case, and aspect.
+// Codes may omit trailing categories (usually, case), which are left unspecified.
+//
+// A grammar must declare these features first, in this order, with at least these codes.
+// We check that up front. A grammar that declares no features gets this layout.
 
-struct Case {
-  hindi: String,
-  latin: String,
-  tense: Tense,
-}
+const FEATURES: [(&str, &[&str]); 7] = [
+  ("count", &["plural/p", "singular/s"]),
+  ("gender", &["female/f", "male/m"]),
+  ("person", &["first/1", "second/2", "third/3"]),
+  ("time", &["past/<", "present/=", "future/>"]),
+  ("tone", &["casual/c", "formal/f", "intimate/i"]),
+  ("case", &["direct/d", "oblique/o"]),
+  ("aspect", &["perfective/f", "habitual/h", "progressive/p"]),
+];
 
-fn rollup(cases: &[Case], class: &str, value: &str) -> Result<Vec<Entry>> {
-  assert!(!cases.is_empty());
-  let head = format!("{}-{}", class, cases[0].hindi);
-  let mut result: Vec<Entry> = vec![];
+const DECLENSIONS: &Paradigm =
+  &[("A", "a", "sm...d"), ("e", "e", "sm...o"), ("e", "e", "pm..."), ("I", "i", ".f...")];
 
-  for case in cases {
-    if result.iter().any(|x| x.hindi == case.hindi) {
-      continue;
-    } else if case.latin != case.latin.to_lowercase() {
-      Err(format!("Invalid Latin: {}", case.latin))?;
-    }
-    wx_to_hindi(&case.hindi)?;
-    let (hindi, latin) = (case.hindi.clone(), case.latin.clone());
-    let scores = std::iter::once((format!("%{}", class), 0.0))
-      .chain(cases.iter().map(|x| (x.latin.clone(), if x.hindi == hindi { 0.0 } else { -1.0 })))
-      .collect();
-    let tenses = cases.iter().filter(|x| x.hindi == hindi).map(|x| x.tense.clone()).collect();
-    result.push(Entry { head: head.clone(), hindi, latin, scores, tenses, value: value.into() });
+fn morphology(features: &TenseSchema) -> Result<Morphology> {
+  let check = |x: &str| wx_to_hindi(x).map(|_| ());
+  let expected: Vec<_> = FEATURES.iter().map(|x| (x.0, x.1.to_vec())).collect();
+  let expected = TenseSchema::new(&expected)?;
+  if features.is_empty() {
+    return Ok(Morphology::new(&expected, check));
   }
-  Ok(result)
-}
-
-fn split(word: &str) -> Result<(String, String)> {
-  let index = word.find('/').ok_or_else(|| format!("Invalid word (missing slash): {}", word))?;
-  let (hindi, latin) = (&word[index + 1..], &word[..index]);
-  Ok((hindi.to_string(), latin.to_string()))
-}
-
-fn tense(code: &str) -> Result<Tense> {
-  CATEGORIES.with(|categories| {
-    if code.len() != categories.len() {
-      Err(format!("Invalid tense code: {}", code))?
+  let actual = features.codes();
+  for (i, (name, values)) in expected.codes().into_iter().enumerate() {
+    match actual.iter().position(|x| x.0 == name) {
+      Some(j) if j == i => (),
+      Some(j) => Err(format!("Feature {} must be declared at {}, not {}", name, i + 1, j + 1))?,
+      None => Err(format!("Missing feature: {}", name))?,
     }
-    let mut result = HashMap::default();
-    for (i, ch) in code.as_bytes().iter().cloned().enumerate().filter(|x| x.1 != b'.') {
-      let (category, values) = &categories[i];
-      let maybe = values.iter().find(|x| x.0 == ch);
-      let value = maybe.ok_or_else(|| format!("Invalid tense code: {}", code))?;
-      result.insert(*category, value.1);
+    for (code, value) in values {
+      if !actual[i].1.contains(&(code, value.clone())) {
+        Err(format!("Missing value for feature {}: {}/{}", name, value, code as char))?;
+      }
     }
-    Tense::new(&result)
-  })
+  }
+  Ok(Morphology::new(features, check))
 }
 
-fn zip(hindis: Vec<String>, latins: Vec<String>, tenses: Vec<Tense>) -> Vec<Case> {
-  assert!(hindis.len() == latins.len() && latins.len() == tenses.len());
-  let iter = hindis.into_iter().zip(latins.into_iter()).zip(tenses.into_iter());
-  iter.map(|x| Case { hindi: (x.0).0, latin: (x.0).1, tense: x.1 }).collect()
+fn declensions(m: &Morphology, hstem: &str, lstem: &str) -> Result<Vec<Case>> {
+  m.inflect(DECLENSIONS, hstem, lstem)
 }
 
 // Our public interface is a series of functions that can be used to build vocabulary result.
 
-pub fn adjectives(table: &str) -> Result<Vec<Entry>> {
+pub fn adjectives(m: &Morphology, table: &str) -> Result<Vec<Entry>> {
   let mut result = vec![];
   for_each_row!(table, [meaning, word], {
     let (hindi, latin) = split(word)?;
-    if hindi.ends_with('A') && latin.ends_with('a') {
-      let (hstem, lstem) = (&hindi[..hindi.len() - 1], &latin[..latin.len() - 1]);
-      let hindis: Vec<_> = ['A', 'e', 'I'].iter().map(|x| format!("{}{}", hstem, x)).collect();
-      let latins: Vec<_> = ['a', 'e', 'i'].iter().map(|x| format!("{}{}", lstem, x)).collect();
-      let tenses: Vec<_> = ["sm...", "pm...", ".f..."].iter().map(|x| tense(x).unwrap()).collect();
-      result.push(rollup(&zip(hindis, latins, tenses), "adjective", meaning)?);
+    if let Some((hstem, lstem)) = stem(&hindi, &latin, ("A", "a")) {
+      result.push(m.rollup(&declensions(m, hstem, lstem)?, "adjective", meaning)?);
     } else {
-      result.push(rollup(&[Case { hindi, latin, tense: Tense::default() }], "adjective", meaning)?);
+      let cases = [Case { hindi, latin, tense: Tense::default() }];
+      result.push(m.rollup(&cases, "adjective", meaning)?);
     }
   });
   Ok(result.into_iter().flatten().collect())
 }
 
-pub fn nouns(main: &str, supplement: &str) -> Result<Vec<Entry>> {
+pub fn compounds(m: &Morphology, table: &str, verbs: &[Entry]) -> Result<Vec<Entry>> {
+  let infinitives = verbs.iter().filter(|x| x.scores.contains_key("%verb_infinitive"));
+  let known: Vec<_> = infinitives.map(|x| format!("{}/{}", x.latin, x.hindi)).collect();
+  let mut result = vec![];
+  for_each_row!(table, [meaning, stem, vector], {
+    if let Some(x) = [stem, vector].iter().find(|x| !known.iter().any(|y| y == *x)) {
+      Err(format!("Unknown verb in compound: {}", x))?
+    }
+    let (hindi, latin) = split(stem)?;
+    let (hstem, lstem) = (&hindi[..hindi.len() - 2], &latin[..latin.len() - 2]);
+    let (hindi, latin) = (hstem.to_string(), lstem.to_string());
+    result.push(m.rollup(&[Case { hindi, latin, tense: Tense::default() }], "compound", meaning)?);
+
+    // The stem carries the compound's semantics. It is keyed by the vector verb, which
+    // supplies the conjugation: for example, "kha" is a %compound_lena in "kha lenge".
+    let name = format!("%compound_{}", split(vector)?.1);
+    let last = result.last_mut().unwrap();
+    last.iter_mut().for_each(|x| {
+      x.scores.insert(name.clone(), 0.0);
+    });
+  });
+  Ok(result.into_iter().flatten().collect())
+}
+
+pub fn nouns(m: &Morphology, main: &str, supplement: &str) -> Result<Vec<Entry>> {
   let mut plurals = HashMap::default();
   for_each_row!(supplement, [singular, plural], {
     plurals.insert(singular, plural);
@@ -116,22 +110,40 @@ pub fn nouns(main: &str, supplement: &str) -> Result<Vec<Entry>> {
       _ => Err(format!("Invalid noun role: {}", role))?,
     };
 
-    // Create singular and plural forms for nouns that decline.
+    // Create singular and plural forms for nouns that decline. Masculine nouns ending in A
+    // take their plural form in the oblique singular; all plurals take -oM in the oblique.
     if declines {
+      let a_stem = if gender == 'm' { stem(&hindi, &latin, ("A", "a")) } else { None };
+      let i_stem = if gender == 'f' { stem(&hindi, &latin, ("I", "i")) } else { None };
       let (hp, lp) = plurals.remove(word).map(split).unwrap_or_else(|| {
-        if gender == 'm' && hindi.ends_with('A') && latin.ends_with('a') {
-          let (hstem, lstem) = (&hindi[..hindi.len() - 1], &latin[..latin.len() - 1]);
+        if let Some((hstem, lstem)) = a_stem {
           return Ok((format!("{}e", hstem), format!("{}e", lstem)));
-        } else if gender == 'f' && hindi.ends_with('I') && latin.ends_with('i') {
+        } else if i_stem.is_some() {
           return Ok((format!("{}yAM", hindi), format!("{}ya", latin)));
         }
         Err(format!("Unable to pluralize noun: {}", word))?
       })?;
-      let tenses = vec![tense(&format!("s{}3..", gender))?, tense(&format!("p{}3..", gender))?];
-      result.push(rollup(&zip(vec![hindi, hp], vec![latin, lp], tenses), "noun", meaning)?);
+      let (ho, lo) = if let Some((hstem, lstem)) = a_stem {
+        (format!("{}oM", hstem), format!("{}on", lstem))
+      } else if i_stem.is_some() {
+        (format!("{}yoM", hindi), format!("{}yon", latin))
+      } else {
+        (format!("{}oM", hindi), format!("{}on", latin))
+      };
+      let cases = if a_stem.is_some() {
+        let codes = ["sm3..d", "sm3..o", "pm3..d", "pm3..o"];
+        let tenses = codes.iter().map(|x| m.tense(x)).collect::<Result<Vec<_>>>()?;
+        zip(vec![hindi, hp.clone(), hp, ho], vec![latin, lp.clone(), lp, lo], tenses)
+      } else {
+        let (s, p) = (format!("s{}3..", gender), format!("p{}3..", gender));
+        let codes = [s, format!("{}d", p), format!("{}o", p)];
+        let tenses = codes.iter().map(|x| m.tense(x)).collect::<Result<Vec<_>>>()?;
+        zip(vec![hindi, hp, ho], vec![latin, lp, lo], tenses)
+      };
+      result.push(m.rollup(&cases, "noun", meaning)?);
     } else {
-      let tense = tense(&format!(".{}3..", gender))?;
-      result.push(rollup(&[Case { hindi, latin, tense }], "noun", meaning)?);
+      let tense = m.tense(&format!(".{}3..", gender))?;
+      result.push(m.rollup(&[Case { hindi, latin, tense }], "noun", meaning)?);
     }
 
     // Add types to each entry based on the category and the count.
@@ -154,39 +166,66 @@ pub fn nouns(main: &str, supplement: &str) -> Result<Vec<Entry>> {
   Ok(result.into_iter().flatten().collect())
 }
 
-pub fn numbers(table: &str) -> Result<Vec<Entry>> {
-  let mut result = vec![];
-  for_each_row!(table, [meaning, word], {
-    let value = meaning.parse::<usize>().map_err(|_| format!("Invalid number: {}", meaning))?;
-    let (hindi, latin) = split(word)?;
-    let tense = tense(if value == 1 { "s...." } else { "p...." }).unwrap();
-    result.push(rollup(&[Case { hindi, latin, tense }], "number", meaning)?);
+// Hindi numerals from 10 to 99 don't follow a regular pattern, so we list them here.
+// A numbers table may override any of them; multipliers (sau, hazar, lakh) belong in
+// the table, and the grammar combines them with these numerals to form products.
+
+const COMPOUND_NUMERALS: [&str; 90] = [
+  "das/xas", "gyarah/gyArah", "barah/bArah", "terah/werah", "chaudah/cOxah",
+  "pandrah/panxrah", "solah/solah", "satrah/sawrah", "atharah/aTArah", "unnis/unnIs",
+  "bis/bIs", "ikkis/ikkIs", "bais/bAIs", "teis/weIs", "chaubis/cObIs",
+  "pachchis/paccIs", "chhabbis/CabbIs", "sattais/sawwAIs", "atthais/aTTAIs", "untis/unawIs",
+  "tis/wIs", "ikattis/ikawIs", "battis/bawwIs", "taintis/wEMwIs", "chauntis/cOMwIs",
+  "paintis/pEMwIs", "chhattis/CawwIs", "saintis/sEMwIs", "artis/adZawIs", "untalis/unawAlIs",
+  "chalis/cAlIs", "iktalis/ikawAlIs", "bayalis/bayAlIs", "taitalis/wEMwAlIs", "chavalis/cavAlIs",
+  "paintalis/pEMwAlIs", "chhiyalis/CiyAlIs", "saintalis/sEMwAlIs", "artalis/adZawAlIs",
+  "unchas/unacAs", "pachas/pacAs", "ikyavan/ikyAvan", "bavan/bAvan", "tirepan/wirepan",
+  "chauvan/cOvan", "pachpan/pacpan", "chhappan/Cappan", "sattavan/sawwAvan",
+  "atthavan/aTTAvan", "unsath/unasaT", "sath/sAT", "iksath/iksaT", "basath/bAsaT",
+  "tiresath/wiresaT", "chausath/cOsaT", "painsath/pEMsaT", "chhiyasath/CiyAsaT",
+  "sarsath/sadZsaT", "arsath/adZsaT", "unhattar/unahawwar", "sattar/sawwar",
+  "ikhattar/ikahawwar", "bahattar/bahawwar", "tihattar/wihawwar", "chauhattar/cOhawwar",
+  "pachhattar/pacahawwar", "chhihattar/Cihawwar", "satattar/sawahawwar", "athattar/aTahawwar",
+  "unasi/unAsI", "assi/assI", "ikyasi/ikyAsI", "bayasi/bayAsI", "tirasi/wirAsI",
+  "chaurasi/cOrAsI", "pachasi/pacAsI", "chhiyasi/CiyAsI", "sattasi/sawwAsI", "atthasi/aTTAsI",
+  "navasi/navAsI", "nabbe/nabbe", "ikyanve/ikyAnave", "banve/bAnave", "tiranve/wirAnave",
+  "chauranve/cOrAnave", "pachanve/pacAnave", "chhiyanve/CiyAnave", "sattanve/sawwAnave",
+  "atthanve/aTTAnave", "ninyanve/ninyAnave",
+];
+
+pub fn numbers(m: &Morphology, table: &str) -> Result<Vec<Entry>> {
+  let mut rows = vec![];
+  for_each_typed_row!(table, [meaning: usize, word: String], {
+    rows.push((meaning, word));
   });
+  for (i, word) in COMPOUND_NUMERALS.iter().enumerate() {
+    if !rows.iter().any(|x| x.0 == i + 10) {
+      rows.push((i + 10, word.to_string()));
+    }
+  }
+  let mut result = vec![];
+  for (value, word) in rows {
+    let (hindi, latin) = split(&word)?;
+    let tense = m.tense(if value == 1 { "s...." } else { "p...." })?;
+    result.push(m.rollup(&[Case { hindi, latin, tense }], "number", &value.to_string())?);
+  }
   Ok(result.into_iter().flatten().collect())
 }
 
-pub fn particles(table: &str) -> Result<Vec<Entry>> {
+pub fn particles(m: &Morphology, table: &str) -> Result<Vec<Entry>> {
   let mut result = vec![];
-  for_each_row!(table, [category, meaning, word, declines], {
+  for_each_typed_row!(table, [category: &str, meaning: &str, word: &str, declines: bool], {
     let (hindi, latin) = split(word)?;
-    let declines = match declines {
-      "n" => false,
-      "y" => true,
-      _ => Err(format!("declines must be n or y; got: {}", declines))?,
-    };
 
-    // Create male singular, male plural, and female forms for particles that decline.
+    // Create male direct, male oblique, and female forms for particles that decline.
     if declines {
-      if !(hindi.ends_with('A') && latin.ends_with('a')) {
-        Err(format!("Declining particles must end in A. Got: {}", word))?
-      }
-      let (hstem, lstem) = (&hindi[..hindi.len() - 1], &latin[..latin.len() - 1]);
-      let hindis: Vec<_> = ['A', 'e', 'I'].iter().map(|x| format!("{}{}", hstem, x)).collect();
-      let latins: Vec<_> = ['a', 'e', 'i'].iter().map(|x| format!("{}{}", lstem, x)).collect();
-      let tenses: Vec<_> = ["sm...", "pm...", ".f..."].iter().map(|x| tense(x).unwrap()).collect();
-      result.push(rollup(&zip(hindis, latins, tenses), "particle", meaning)?);
+      let maybe = stem(&hindi, &latin, ("A", "a"));
+      let (hstem, lstem) =
+        maybe.ok_or_else(|| format!("Declining particles must end in A. Got: {}", word))?;
+      result.push(m.rollup(&declensions(m, hstem, lstem)?, "particle", meaning)?);
     } else {
-      result.push(rollup(&[Case { hindi, latin, tense: Tense::default() }], "particle", meaning)?);
+      let cases = [Case { hindi, latin, tense: Tense::default() }];
+      result.push(m.rollup(&cases, "particle", meaning)?);
     }
 
     // Add types to particles based on their category.
@@ -196,21 +235,21 @@ pub fn particles(table: &str) -> Result<Vec<Entry>> {
   Ok(result.into_iter().flatten().collect())
 }
 
-pub fn pronouns(table: &str) -> Result<Vec<Entry>> {
+pub fn pronouns(m: &Morphology, table: &str) -> Result<Vec<Entry>> {
   let mut groups = HashMap::default();
-  for_each_row!(table, [role, direct, genitive, dative_1, dative_2, copula], {
+  for_each_row!(table, [role, direct, genitive, dative_1, dative_2, ergative, copula], {
     if !(role.len() == 3 && role.is_ascii() && role.find(|c| ('1'..='3').contains(&c)) == Some(0)) {
       Err(format!("Invalid pronoun role: {}", role))?
     }
     let (person, count, tone) = (&role[..1], &role[1..2], &role[2..]);
-    let basis = tense(&format!("{}.{}.{}", count, person, tone))?;
-    let entry = (basis, copula, dative_1, dative_2, direct, genitive);
+    let basis = m.tense(&format!("{}.{}.{}", count, person, tone))?;
+    let entry = (basis, copula, dative_1, dative_2, direct, ergative, genitive);
     groups.entry(person).or_insert(vec![]).push(entry);
   });
   let (mut copula_cases, mut result) = (vec![], vec![]);
   for (person, value) in &[("1", "I"), ("2", "you"), ("3", "they")] {
     for entry in groups.get(person).unwrap_or(&vec![]) {
-      let (basis, copula, dative_1, dative_2, direct, genitive) = entry;
+      let (basis, copula, dative_1, dative_2, direct, ergative, genitive) = entry;
       copula_cases.push({
         let (hindi, latin) = split(copula)?;
         Case { hindi, latin, tense: basis.clone() }
@@ -219,16 +258,16 @@ pub fn pronouns(table: &str) -> Result<Vec<Entry>> {
         let (hindi, latin) = split(direct)?;
         vec![Case { hindi, latin, tense: basis.clone() }]
       };
+      let ergative_cases = {
+        let (hindi, latin) = split(ergative)?;
+        vec![Case { hindi, latin, tense: basis.clone() }]
+      };
       let genitive_cases = {
         let (hindi, latin) = split(genitive)?;
-        if !(hindi.ends_with('A') && latin.ends_with('a')) {
-          Err(format!("Genitive pronouns must end in A. Got: {}", genitive))?
-        }
-        let (hstem, lstem) = (&hindi[..hindi.len() - 1], &latin[..latin.len() - 1]);
-        let hindis: Vec<_> = ['A', 'e', 'I'].iter().map(|x| format!("{}{}", hstem, x)).collect();
-        let latins: Vec<_> = ['a', 'e', 'i'].iter().map(|x| format!("{}{}", lstem, x)).collect();
-        let ts: Vec<_> = ["sm...", "pm...", ".f..."].iter().map(|x| tense(x).unwrap()).collect();
-        zip(hindis, latins, ts)
+        let maybe = stem(&hindi, &latin, ("A", "a"));
+        let (hstem, lstem) =
+          maybe.ok_or_else(|| format!("Genitive pronouns must end in A. Got: {}", genitive))?;
+        declensions(m, hstem, lstem)?
       };
       let dative_cases = {
         let datives = if dative_1 == dative_2 { vec![dative_1] } else { vec![dative_1, dative_2] };
@@ -238,50 +277,121 @@ pub fn pronouns(table: &str) -> Result<Vec<Entry>> {
         let tenses: Vec<_> = splits.iter().map(|_| basis.clone()).collect();
         zip(hindis, latins, tenses)
       };
-      result.push(rollup(&dative_cases, "dative", value)?);
-      result.push(rollup(&direct_cases, "direct", value)?);
-      result.push(rollup(&genitive_cases, "genitive", value)?);
+      result.push(m.rollup(&dative_cases, "dative", value)?);
+      result.push(m.rollup(&direct_cases, "direct", value)?);
+      result.push(m.rollup(&ergative_cases, "ergative", value)?);
+      result.push(m.rollup(&genitive_cases, "genitive", value)?);
     }
   }
-  result.push(rollup(&copula_cases, "copula", "be")?);
+  result.push(m.rollup(&copula_cases, "copula", "be")?);
   Ok(result.into_iter().flatten().collect())
 }
 
-pub fn verbs(table: &str) -> Result<Vec<Entry>> {
+// Irregular verbs override some of the regular conjugations built in verbs(). Each
+// override has a tense code that must include a time; it replaces the regular form
+// for each case of that time that agrees with its tense. When several overrides
+// apply, the most specific one wins. Overrides that leave the gender unspecified
+// are declined like regular forms: the female form ends in I instead of A or e.
+
+struct Irregular {
+  code: String,
+  hindi: String,
+  latin: String,
+  specificity: usize,
+  tense: Tense,
+  time: String,
+  used: bool,
+}
+
+fn irregular(m: &Morphology, code: &str, word: &str) -> Result<Vec<Irregular>> {
+  let (hindi, latin) = split(word)?;
+  let base = m.tense(code)?;
+  let time = base.get("time").ok_or_else(|| format!("Irregular forms need a time: {}", code))?;
+  let specificity = code.chars().filter(|x| *x != '.').count();
+  let make = |hindi: String, latin: String, tense: Tense| {
+    let (code, time) = (code.to_string(), time.clone());
+    Irregular { code, hindi, latin, specificity, tense, time, used: false }
+  };
+  if base.get("gender").is_some() {
+    return Ok(vec![make(hindi, latin, base)]);
+  }
+  let (hstem, lstem) = (&hindi[..hindi.len() - 1], &latin[..latin.len() - 1]);
+  let (mut male, mut female) = (base.clone(), base);
+  male.union(&m.tense(".m...")?);
+  female.union(&m.tense(".f...")?);
+  let feminine = (format!("{}I", hstem), format!("{}i", lstem));
+  Ok(vec![make(hindi, latin, male), make(feminine.0, feminine.1, female)])
+}
+
+fn override_cases(mut cases: Vec<Case>, time: &str, irregulars: &mut [Irregular]) -> Vec<Case> {
+  for case in cases.iter_mut() {
+    let options = irregulars.iter_mut().filter(|x| x.time == time && x.tense.agree(&case.tense));
+    if let Some(best) = options.max_by_key(|x| x.specificity) {
+      case.hindi = best.hindi.clone();
+      case.latin = best.latin.clone();
+      best.used = true;
+    }
+  }
+  cases
+}
+
+// A verb's subject is direct ("main khata hun") or dative ("mujhe chahie").
+
+#[derive(Clone, Copy, PartialEq)]
+enum Subject {
+  Direct,
+  Dative,
+}
+
+impl<'a> FromCell<'a> for Subject {
+  fn from_cell(cell: &'a str) -> Result<Self> {
+    choice(cell, &[("direct", Subject::Direct), ("dative", Subject::Dative)])
+  }
+}
+
+pub fn verbs(m: &Morphology, table: &str, irregulars: &str) -> Result<Vec<Entry>> {
   // TODO(skishore): Add command forms here.
-  // TODO(skishore): Handle "reversed" verbs like "chahna".
-  // TODO(skishore): Handle irregular verbs here ("hona", "jana", etc.)
+  let mut overrides: HashMap<&str, Vec<Irregular>> = HashMap::default();
+  for_each_row!(irregulars, [verb, code, word], {
+    overrides.entry(verb).or_default().append(&mut irregular(m, code, word)?);
+  });
   let mut result = vec![];
   let base_forms = [("", "", "stem"), ("ne", "ne", "gerund"), ("nA", "na", "infinitive")];
-  let time_forms = [("", "", "past", true), ("w", "t", "present", false)];
-  let (male, female) = (tense(".m...").unwrap(), tense(".f...").unwrap());
+  let time_forms =
+    [("", "", "past", "perfective", true), ("w", "t", "present", "habitual", false)];
+  let (male, female) = (m.tense(".m...")?, m.tense(".f...")?);
 
-  for_each_row!(table, [meaning, word], {
+  for_each_typed_row!(table, [meaning: &str, word: &str, subject: Subject], {
     let (hindi, latin) = split(word)?;
-    if !(hindi.ends_with('A') && latin.ends_with('a')) {
-      Err(format!("Verbs must end in nA. Got: {}", word))?
-    }
-    let (hstem, lstem) = (&hindi[..hindi.len() - 2], &latin[..latin.len() - 2]);
+    let maybe = stem(&hindi, &latin, ("nA", "na"));
+    let (hstem, lstem) = maybe.ok_or_else(|| format!("Verbs must end in nA. Got: {}", word))?;
+    let dative = subject == Subject::Dative;
+    let start = result.len();
     let vowel = "aeiou".chars().any(|x| hstem.to_lowercase().ends_with(x));
+    let mut irregulars = overrides.remove(word).unwrap_or_default();
 
     // For each base type, add an entry for the verb.
     for (h, l, t) in &base_forms {
       let hindi = format!("{}{}", hstem, h);
       let latin = format!("{}{}", lstem, l);
-      result.push(rollup(&[Case { hindi, latin, tense: Tense::default() }], "verb", meaning)?);
+      result.push(m.rollup(&[Case { hindi, latin, tense: Tense::default() }], "verb", meaning)?);
       let last = result.last_mut().unwrap();
       last.iter_mut().for_each(|x| std::mem::drop(x.scores.insert(format!("%verb_{}", t), 0.0)));
     }
 
-    // For each temporal type, add declined entries for the verb.
-    for (h, l, time, prefix) in &time_forms {
-      let base = Tense::new(&vec![("time", *time)].into_iter().collect()).unwrap();
+    // For each temporal type, add declined entries for the verb. These participles also
+    // combine with an auxiliary: "piya hai" is perfective and "pita hai" is habitual.
+    for (h, l, time, aspect, prefix) in &time_forms {
+      let base = vec![("aspect", *aspect), ("time", *time)];
+      let base = Tense::new(&base.into_iter().collect()).unwrap();
       let y = if vowel && *prefix { "y" } else { "" };
       let h: Vec<_> = ['A', 'e', 'I'].iter().map(|x| format!("{}{}{}{}", hstem, h, y, x)).collect();
       let l: Vec<_> = ['a', 'e', 'i'].iter().map(|x| format!("{}{}{}{}", lstem, l, y, x)).collect();
-      let mut t: Vec<_> = ["sm...", "pm...", ".f..."].iter().map(|x| tense(x).unwrap()).collect();
+      let t = ["sm...", "pm...", ".f..."].iter().map(|x| m.tense(x));
+      let mut t: Vec<_> = t.collect::<Result<_>>()?;
       t.iter_mut().for_each(|x| x.union(&base));
-      result.push(rollup(&zip(h, l, t), "verb", meaning)?);
+      let cases = override_cases(zip(h, l, t), time, &mut irregulars);
+      result.push(m.rollup(&cases, "verb", meaning)?);
       let last = result.last_mut().unwrap();
       last.iter_mut().for_each(|x| std::mem::drop(x.scores.insert(format!("%verb_{}", time), 0.0)));
     }
@@ -300,45 +410,295 @@ pub fn verbs(table: &str) -> Result<Vec<Entry>> {
       let latins = latins
         .chain(ls.iter().map(|x| format!("{}i", &x[..x.len() - 1])))
         .map(|x| format!("{}{}", lstem, x));
-      let tenses = ts.iter().map(|x| tense(x)).collect::<Result<Vec<_>>>()?;
-      let (mut m, mut f) = (tenses.clone(), tenses);
-      m.iter_mut().for_each(|x| x.union(&male));
-      f.iter_mut().for_each(|x| x.union(&female));
-      let tenses = m.into_iter().chain(f.into_iter()).collect();
-      result.push(rollup(&zip(hindis.collect(), latins.collect(), tenses), "verb", meaning)?);
+      let tenses = ts.iter().map(|x| m.tense(x)).collect::<Result<Vec<_>>>()?;
+      let (mut ms, mut fs) = (tenses.clone(), tenses);
+      ms.iter_mut().for_each(|x| x.union(&male));
+      fs.iter_mut().for_each(|x| x.union(&female));
+      let tenses = ms.into_iter().chain(fs.into_iter()).collect();
+      let cases = zip(hindis.collect(), latins.collect(), tenses);
+      let cases = override_cases(cases, time, &mut irregulars);
+      result.push(m.rollup(&cases, "verb", meaning)?);
       let last = result.last_mut().unwrap();
       last.iter_mut().for_each(|x| std::mem::drop(x.scores.insert(format!("%verb_{}", time), 0.0)));
     }
+
+    // Verbs with dative subjects, like "mujhe chai chahie", agree with their objects.
+    // They have an extra modal form, used without an auxiliary: "chahna" -> "chahie".
+    if dative {
+      let (hindi, latin) = (format!("{}Ie", hstem), format!("{}ie", lstem));
+      result.push(m.rollup(&[Case { hindi, latin, tense: Tense::default() }], "verb", meaning)?);
+      let entries = result[start..].iter_mut().flat_map(|x| x.iter_mut());
+      entries.for_each(|x| {
+        x.scores.insert("%verb_dative".into(), 0.0);
+      });
+      let last = result.last_mut().unwrap();
+      last.iter_mut().for_each(|x| {
+        x.scores.insert("%verb_modal".into(), 0.0);
+      });
+    }
+
+    if let Some(unused) = irregulars.iter().find(|x| !x.used) {
+      Err(format!("Unused irregular form for {}: {}", word, unused.code))?
+    }
   });
+
+  if !overrides.is_empty() {
+    let unused: Vec<_> = overrides.into_iter().map(|x| x.0.to_string()).collect();
+    Err(format!("Unused irregular verbs: {}", unused.join(", ")))?
+  }
+
+  // Progressive forms pair a verb's stem with a declined "raha" and an auxiliary, as in
+  // "main pani pi raha hun". The "raha" entries are shared by all verbs.
+  {
+    let base = Tense::new(&vec![("aspect", "progressive")].into_iter().collect()).unwrap();
+    let h: Vec<_> = ['A', 'e', 'I'].iter().map(|x| format!("rah{}", x)).collect();
+    let l: Vec<_> = ['a', 'e', 'i'].iter().map(|x| format!("rah{}", x)).collect();
+    let t = ["sm...", "pm...", ".f..."].iter().map(|x| m.tense(x));
+    let mut t: Vec<_> = t.collect::<Result<_>>()?;
+    t.iter_mut().for_each(|x| x.union(&base));
+    result.push(m.rollup(&zip(h, l, t), "verb", "-")?);
+    let last = result.last_mut().unwrap();
+    last.iter_mut().for_each(|x| {
+      x.scores.insert("%verb_progressive".into(), 0.0);
+    });
+  }
   Ok(result.into_iter().flatten().collect())
 }
 
 // Our overall entry point calls each of the helpers above.
 
-pub fn vocabulary(text: &str) -> Result<Vec<Entry>> {
+pub fn vocabulary(text: &str, features: &TenseSchema) -> Result<Vec<Entry>> {
+  let m = &morphology(features)?;
   let mut entries = vec![];
   let (a, b, c, d, e, f) = (adjectives, nouns, numbers, particles, pronouns, verbs);
-  for_each_table!(text, [adjectives, nouns, noun_plurals, numbers, particles, pronouns, verbs], {
-    entries.extend(a(adjectives)?.into_iter());
-    entries.extend(b(nouns, noun_plurals)?.into_iter());
-    entries.extend(c(numbers)?.into_iter());
-    entries.extend(d(particles)?.into_iter());
-    entries.extend(e(pronouns)?.into_iter());
-    entries.extend(f(verbs)?.into_iter());
-  });
+  for_each_table!(
+    text,
+    [
+      adjectives,
+      nouns,
+      noun_plurals,
+      numbers,
+      particles,
+      pronouns,
+      verbs,
+      verb_compounds,
+      verb_irregulars
+    ],
+    {
+      entries.extend(a(m, adjectives)?.into_iter());
+      entries.extend(b(m, nouns, noun_plurals)?.into_iter());
+      entries.extend(c(m, numbers)?.into_iter());
+      entries.extend(d(m, particles)?.into_iter());
+      entries.extend(e(m, pronouns)?.into_iter());
+      let verbs = f(m, verbs, verb_irregulars)?;
+      entries.extend(compounds(m, verb_compounds, &verbs)?.into_iter());
+      entries.extend(verbs.into_iter());
+    }
+  );
   Ok(entries)
 }
 
+// Consistency checks over a vocabulary, for grammar authors. They warn about words that
+// are listed twice with the same head, meaning, and tense, and about words whose Latin
+// spelling differs from entry to entry, which makes corrections inconsistent.
+
+pub fn lint(entries: &[Entry]) -> Vec<String> {
+  let mut result = vec![];
+  let (mut latin, mut seen) = (HashMap::default(), HashMap::default());
+  for entry in entries {
+    let key = (&entry.head, &entry.hindi, &entry.value);
+    let prior: &mut Vec<&Entry> = seen.entry(key).or_default();
+    let agree = |x: &&Entry| x.tenses.iter().any(|y| entry.tenses.iter().any(|z| y.agree(z)));
+    if prior.iter().any(agree) {
+      result.push(format!("Duplicate entry for {}: {}", entry.head, entry.latin));
+    }
+    prior.push(entry);
+    latin.entry(&entry.hindi).or_insert_with(Vec::new).push(entry.latin.as_str());
+  }
+  let mut spellings: Vec<_> = latin.into_iter().collect();
+  spellings.sort();
+  for (hindi, mut xs) in spellings {
+    xs.sort();
+    xs.dedup();
+    if xs.len() > 1 {
+      result.push(format!("Inconsistent Latin spellings for {}: {}", hindi, xs.join(", ")));
+    }
+  }
+  result
+}
+
 #[cfg(test)]
 mod test {
+  use super::super::super::lib::base::Error;
   use super::*;
 
+  fn features() -> TenseSchema {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let re = regex::Regex::new(r"(?m)^feature (\w+)(?: \(politeness\))?: (.*)$").unwrap();
+    let features = re.captures_iter(&data).map(|x| {
+      (x[1].to_string(), x[2].split(' ').map(|y| y.to_string()).collect())
+    });
+    TenseSchema::new(&features.collect::<Vec<_>>()).unwrap()
+  }
+
+  fn hindi() -> Morphology {
+    morphology(&features()).unwrap()
+  }
+
+  #[test]
+  fn test_feature_layout() {
+    let error = |x: &[(&str, Vec<&str>)]| {
+      let features = TenseSchema::new(x).unwrap();
+      morphology(&features).err().map(|x| format!("{:?}", x))
+    };
+    let mut layout: Vec<_> = FEATURES.iter().map(|x| (x.0, x.1.to_vec())).collect();
+    assert_eq!(error(&[]), None);
+    assert_eq!(error(&layout), None);
+    layout.push(("animacy", vec!["animate/a", "inanimate/i"]));
+    assert_eq!(error(&layout), None);
+    layout.swap(0, 1);
+    assert!(error(&layout).unwrap().contains("Feature count must be declared at 1, not 2"));
+    layout.swap(0, 1);
+    layout[4].1 = vec!["casual/c", "formal/f", "intimate"];
+    assert!(error(&layout).unwrap().contains("Missing value for feature tone: intimate/i"));
+    assert!(error(&layout[..3]).unwrap().contains("Missing feature: time"));
+  }
+
   #[test]
   fn test_all_vocabulary_entries() {
     let file = "src/hindi/hindi.grammar";
     let data = std::fs::read_to_string(file).unwrap();
     let base = regex::Regex::new(r#"lexer: ```[\s\S]*```"#).unwrap().find(&data).unwrap();
     let text = &data[base.start() + 10..base.end() - 3];
-    vocabulary(text).unwrap();
+    vocabulary(text, &features()).unwrap();
+  }
+
+  fn forms(verbs: &[Entry], score: &str) -> Vec<String> {
+    let mut result: Vec<_> = verbs.iter().filter(|x| x.scores.contains_key(score)).collect();
+    result.sort_by_key(|x| x.latin.clone());
+    result.into_iter().map(|x| x.latin.clone()).collect()
+  }
+
+  fn tenses(entries: &[Entry], latin: &str) -> Vec<String> {
+    let entry = entries.iter().find(|x| x.latin == latin).unwrap();
+    let codes = entry.tenses.iter().map(|x| {
+      let case = x.get("case").unwrap_or_else(|| "*".into());
+      format!("{}/{}", x.get("count").unwrap(), case)
+    });
+    let mut result: Vec<_> = codes.collect();
+    result.sort();
+    result
+  }
+
+  #[test]
+  fn test_lint_vocabulary() {
+    let table = "meaning | word\n--|--\ngood | accha/acCA\nfine | acha/acCA\ngood | accha/acCA";
+    assert_eq!(
+      lint(&adjectives(&hindi(), table).unwrap()),
+      [
+        "Duplicate entry for adjective-acCA: accha",
+        "Duplicate entry for adjective-acCA: acche",
+        "Duplicate entry for adjective-acCA: acchi",
+        "Inconsistent Latin spellings for acCA: accha, acha",
+        "Inconsistent Latin spellings for acCI: acchi, achi",
+        "Inconsistent Latin spellings for acCe: acche, ache",
+      ]
+    );
+  }
+
+  #[test]
+  fn test_compound_numerals() {
+    let entries = numbers(&hindi(), "meaning | word\n--|--\n1 | ek/ek\n10 | dus/xus").unwrap();
+    assert_eq!(entries.len(), 91);
+    let value = |latin: &str| entries.iter().find(|x| x.latin == latin).unwrap().value.clone();
+    assert_eq!([value("ek"), value("dus"), value("pachas")], ["1", "10", "50"]);
+    assert_eq!(tenses(&entries, "ek"), ["singular/*"]);
+    assert_eq!(tenses(&entries, "ninyanve"), ["plural/*"]);
+    assert!(numbers(&hindi(), "meaning | word\n--|--\nten | das/xas").is_err());
+  }
+
+  #[test]
+  fn test_oblique_nouns() {
+    let table = "category | meaning | word | role\n--|--|--|--\n\
+                 person | boy | larka/ladZakA | ms\n\
+                 person | girl | larki/ladZakI | fs\n\
+                 person | woman | aurat/Oraw | fs";
+    let plurals = "singular | plural\n--|--\naurat/Oraw | aurte/Orwe";
+    let nouns = nouns(&hindi(), table, plurals).unwrap();
+    assert_eq!(tenses(&nouns, "larka"), ["singular/direct"]);
+    assert_eq!(tenses(&nouns, "larke"), ["plural/direct", "singular/oblique"]);
+    assert_eq!(tenses(&nouns, "larkon"), ["plural/oblique"]);
+    assert_eq!(tenses(&nouns, "larki"), ["singular/*"]);
+    assert_eq!(tenses(&nouns, "larkiya"), ["plural/direct"]);
+    assert_eq!(tenses(&nouns, "larkiyon"), ["plural/oblique"]);
+    assert_eq!(tenses(&nouns, "aurte"), ["plural/direct"]);
+    assert_eq!(tenses(&nouns, "auraton"), ["plural/oblique"]);
+  }
+
+  #[test]
+  fn test_compound_verbs() {
+    let table = "meaning | word | subject\n--|--|--\neat | khana/KAnA | direct\n\
+                 take | lena/lenA | direct";
+    let irregulars = "verb | code | word\n--|--|--\nlena/lenA | sm.<. | liya/liyA";
+    let verbs = verbs(&hindi(), table, irregulars).unwrap();
+    let table = "meaning | stem | vector\n--|--|--\neat | khana/KAnA | lena/lenA";
+    let compounds = compounds(&hindi(), table, &verbs).unwrap();
+    assert_eq!(compounds.len(), 1);
+    assert_eq!((compounds[0].latin.as_str(), compounds[0].value.as_str()), ("kha", "eat"));
+    assert!(compounds[0].scores.contains_key("%compound_lena"));
+    let table = "meaning | stem | vector\n--|--|--\neat | khana/KAnA | ana/AnA";
+    let error = super::compounds(&hindi(), table, &verbs).err().unwrap();
+    assert_eq!(error, "Unknown verb in compound: ana/AnA".into());
+  }
+
+  #[test]
+  fn test_irregular_verbs() {
+    let table = "meaning | word | subject\n--|--|--
+      do | karna/karnA | direct
+      be | hona/honA | direct";
+    let irregulars = "verb | code | word\n--|--|--
+      karna/karnA | sm.<. | kiya/kiyA
+                ^ | pm.<. | kiye/kiye
+                ^ | .f.<. | ki/kI
+        hona/honA | ...<. | hua/huA
+                ^ | pm.<. | hue/hue
+                ^ | s.3>. | hoga/hogA";
+    let verbs = verbs(&hindi(), table, irregulars).unwrap();
+    let (karna, hona): (Vec<_>, Vec<_>) = verbs.into_iter().partition(|x| x.value == "do");
+    assert_eq!(forms(&karna, "%verb_past"), ["ki", "kiya", "kiye"]);
+    assert_eq!(forms(&karna, "%verb_present"), ["karta", "karte", "karti"]);
+    assert_eq!(forms(&hona, "%verb_past"), ["hua", "hue", "hui"]);
+    let future = forms(&hona, "%verb_future");
+    assert!(["hoga", "hogi", "hoega", "hoegi"].iter().all(|x| future.contains(&x.to_string())));
+  }
+
+  #[test]
+  fn test_dative_verbs() {
+    let table = "meaning | word | subject\n--|--|--\nwant | chahna/cAhnA | dative";
+    let irregulars = "verb | code | word\n--|--|--\nchahna/cAhnA | sm.<. | chaha/cAhA";
+    let verbs = verbs(&hindi(), table, irregulars).unwrap();
+    let mut want = verbs.iter().filter(|x| x.value == "want");
+    assert!(want.all(|x| x.scores.contains_key("%verb_dative")));
+    assert_eq!(forms(&verbs, "%verb_modal"), ["chahie"]);
+    assert_eq!(forms(&verbs, "%verb_past"), ["chaha", "chahe", "chahi"]);
+    let table = "meaning | word | subject\n--|--|--\nwant | chahna/cAhnA | ergative";
+    let error = super::verbs(&hindi(), table, irregulars).err().unwrap();
+    let message = "expected one of: direct, dative; got: ergative";
+    let message = format!("Invalid cell row 1, column subject: {}", message);
+    assert_eq!(error, Error::Table(message));
+  }
+
+  #[test]
+  fn test_unused_irregular_verbs() {
+    let table = "meaning | word | subject\n--|--|--\ndo | karna/karnA | direct";
+    let irregulars = "verb | code | word\n--|--|--\njana/jAnA | sm.<. | gaya/gayA";
+    let error = verbs(&hindi(), table, irregulars).err().unwrap();
+    assert_eq!(error, "Unused irregular verbs: jana/jAnA".into());
+    let irregulars = "verb | code | word\n--|--|--\nkarna/karnA | sm=<. | kiya/kiyA";
+    let error = verbs(&hindi(), table, irregulars).err().unwrap();
+    assert_eq!(error, "Invalid tense code: sm=<.".into());
+    let irregulars = "verb | code | word\n--|--|--\nkarna/karnA | sm... | kiya/kiyA";
+    let error = verbs(&hindi(), table, irregulars).err().unwrap();
+    assert_eq!(error, "Irregular forms need a time: sm...".into());
   }
 }
@@ -108,6 +108,142 @@ pub fn wx_to_hindi(wx: &str) -> Result<String> {
   Ok(result)
 }
 
+// We also support rendering WX in a few standard romanization schemes. IAST and
+// ISO 15919 use diacritics; Harvard-Kyoto is ASCII-only. ISO 15919 also inserts a
+// colon to separate letters that would otherwise read as a digraph, as in "a:i"
+// for अइ (vs. "ai" for ऐ) and "k:h" for क्ह (vs. "kh" for ख). Neither of the other
+// schemes defines nukta consonants, so we use common extensions for them.
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Scheme {
+  HarvardKyoto,
+  Iast,
+  Iso15919,
+}
+
+// Each row is: a WX character, then its IAST, ISO 15919, and Harvard-Kyoto forms.
+type Row = (char, &'static str, &'static str, &'static str);
+
+static SCHEMES: &[Row] = &[
+  ('a', "a", "a", "a"),
+  ('A', "ā", "ā", "A"),
+  ('i', "i", "i", "i"),
+  ('I', "ī", "ī", "I"),
+  ('u', "u", "u", "u"),
+  ('U', "ū", "ū", "U"),
+  ('e', "e", "ē", "e"),
+  ('E', "ai", "ai", "ai"),
+  ('o', "o", "ō", "o"),
+  ('O', "au", "au", "au"),
+  ('k', "k", "k", "k"),
+  ('K', "kh", "kh", "kh"),
+  ('g', "g", "g", "g"),
+  ('G', "gh", "gh", "gh"),
+  ('f', "ṅ", "ṅ", "G"),
+  ('c', "c", "c", "c"),
+  ('C', "ch", "ch", "ch"),
+  ('j', "j", "j", "j"),
+  ('J', "jh", "jh", "jh"),
+  ('F', "ñ", "ñ", "J"),
+  ('t', "ṭ", "ṭ", "T"),
+  ('T', "ṭh", "ṭh", "Th"),
+  ('d', "ḍ", "ḍ", "D"),
+  ('D', "ḍh", "ḍh", "Dh"),
+  ('N', "ṇ", "ṇ", "N"),
+  ('w', "t", "t", "t"),
+  ('W', "th", "th", "th"),
+  ('x', "d", "d", "d"),
+  ('X', "dh", "dh", "dh"),
+  ('n', "n", "n", "n"),
+  ('p', "p", "p", "p"),
+  ('P', "ph", "ph", "ph"),
+  ('b', "b", "b", "b"),
+  ('B', "bh", "bh", "bh"),
+  ('m', "m", "m", "m"),
+  ('y', "y", "y", "y"),
+  ('r', "r", "r", "r"),
+  ('l', "l", "l", "l"),
+  ('v', "v", "v", "v"),
+  ('S', "ś", "ś", "z"),
+  ('R', "ṣ", "ṣ", "S"),
+  ('s', "s", "s", "s"),
+  ('h', "h", "h", "h"),
+  ('q', "ṛ", "r̥", "R"),
+  ('Q', "ṝ", "r̥̄", "RR"),
+  ('L', "ḷ", "l̥", "lR"),
+  ('M', "ṃ", "ṁ", "M"),
+  ('H', "ḥ", "ḥ", "H"),
+  ('z', "m̐", "m̐", "~"),
+];
+
+// The same, but for consonants followed by a nukta ('Z' in WX).
+static NUKTA_SCHEMES: &[Row] = &[
+  ('k', "q", "q", "q"),
+  ('K', "k͟h", "k͟h", "kh"),
+  ('g', "ġ", "ġ", "g"),
+  ('j', "z", "z", "z"),
+  ('d', "ṛ", "ṛ", "D"),
+  ('D', "ṛh", "ṛh", "Dh"),
+  ('P', "f", "f", "f"),
+];
+
+impl Scheme {
+  pub fn all() -> [Scheme; 3] {
+    [Scheme::HarvardKyoto, Scheme::Iast, Scheme::Iso15919]
+  }
+
+  pub fn key(&self) -> &'static str {
+    match self {
+      Scheme::HarvardKyoto => "hk",
+      Scheme::Iast => "iast",
+      Scheme::Iso15919 => "iso",
+    }
+  }
+
+  fn get(&self, table: &[Row], ch: char) -> Option<&'static str> {
+    let row = table.iter().find(|x| x.0 == ch)?;
+    Some(match self {
+      Scheme::HarvardKyoto => row.3,
+      Scheme::Iast => row.1,
+      Scheme::Iso15919 => row.2,
+    })
+  }
+}
+
+impl std::str::FromStr for Scheme {
+  type Err = String;
+  fn from_str(x: &str) -> std::result::Result<Self, String> {
+    let scheme = Scheme::all().iter().cloned().find(|y| y.key() == x);
+    scheme.ok_or_else(|| format!("Unknown scheme: {}", x))
+  }
+}
+
+pub fn wx_to_scheme(wx: &str, scheme: Scheme) -> Result<String> {
+  let mut prev = None;
+  let mut result = String::with_capacity(2 * wx.len());
+  let mut chars = wx.chars().peekable();
+  while let Some(x) = chars.next() {
+    let nukta = chars.peek() == Some(&'Z');
+    let text = if nukta {
+      chars.next();
+      scheme.get(NUKTA_SCHEMES, x)
+    } else {
+      scheme.get(SCHEMES, x)
+    };
+    let text = text.ok_or_else(|| format!("Invalid WX: {}", wx))?;
+    if scheme == Scheme::Iso15919 {
+      let vowel = prev == Some('a') && (x == 'i' || x == 'u');
+      let aspirate = prev.map(|y| "kgcjtdwxpb".contains(y)).unwrap_or(false) && x == 'h';
+      if vowel || aspirate {
+        result.push(':');
+      }
+    }
+    result.push_str(text);
+    prev = if nukta { None } else { Some(x) };
+  }
+  Ok(result)
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -133,4 +269,32 @@ mod tests {
   fn test_wx_to_hindi() {
     ITEMS.with(|a| a.iter().for_each(|x| assert_eq!(wx_to_hindi(x[0]).unwrap(), x[1])));
   }
+
+  #[test]
+  fn test_wx_to_scheme() {
+    let items = [
+      ["badZe", "baṛe", "baṛē", "baDe"],
+      ["evaM", "evaṃ", "ēvaṁ", "evaM"],
+      ["ladZakiyoM", "laṛakiyoṃ", "laṛakiyōṁ", "laDakiyoM"],
+      ["miSr", "miśr", "miśr", "mizr"],
+      ["pAMc", "pāṃc", "pāṁc", "pAMc"],
+      ["kqRNa", "kṛṣṇa", "kr̥ṣṇa", "kRSNa"],
+    ];
+    for [wx, iast, iso, hk] in items.iter() {
+      assert_eq!(wx_to_scheme(wx, Scheme::Iast).unwrap(), *iast);
+      assert_eq!(wx_to_scheme(wx, Scheme::Iso15919).unwrap(), *iso);
+      assert_eq!(wx_to_scheme(wx, Scheme::HarvardKyoto).unwrap(), *hk);
+    }
+  }
+
+  #[test]
+  fn test_wx_to_scheme_disambiguates_digraphs() {
+    // WX "ai" is अइ and "kh" is क्ह; only ISO 15919 distinguishes them from ऐ and ख.
+    assert_eq!(wx_to_scheme("gaI", Scheme::Iso15919).unwrap(), "gaī");
+    assert_eq!(wx_to_scheme("kai", Scheme::Iso15919).unwrap(), "ka:i");
+    assert_eq!(wx_to_scheme("kai", Scheme::Iast).unwrap(), "kai");
+    assert_eq!(wx_to_scheme("Kuxh", Scheme::Iso15919).unwrap(), "khud:h");
+    assert_eq!(wx_to_scheme("kE", Scheme::Iso15919).unwrap(), "kai");
+    assert_eq!(wx_to_scheme("k1", Scheme::Iast), Err("Invalid WX: k1".into()));
+  }
 }
@@ -3,17 +3,89 @@ pub type HashSet<T> = rustc_hash::FxHashSet<T>;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(PartialEq)]
-pub struct Error(String);
+// Errors have a kind, so that callers can handle them programmatically, and a message for
+// humans. Most code raises plain messages with Err(format!(...))?, which yields an Other
+// error; entry points like compile() and read_tables() tag those errors with their kind.
+//
+// Syntax errors found by lib::combine parsers record the location of the error. Errors
+// compare equal if their kinds, messages, and locations do.
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Location {
+  pub line: usize,
+  pub column: usize,
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum Error {
+  GrammarCompile(String, Option<Location>),
+  Lexer(String),
+  ParseFailure(String, Option<Location>),
+  Template(String),
+  Table(String),
+  Other(String),
+}
+
+impl Error {
+  pub fn message(&self) -> &str {
+    match self {
+      Error::GrammarCompile(x, _) | Error::ParseFailure(x, _) => x,
+      Error::Lexer(x) | Error::Template(x) | Error::Table(x) | Error::Other(x) => x,
+    }
+  }
+
+  pub fn location(&self) -> Option<Location> {
+    match self {
+      Error::GrammarCompile(_, x) | Error::ParseFailure(_, x) => *x,
+      _ => None,
+    }
+  }
+
+  // Gives an Other error the given kind. Errors that already have a kind keep it.
+  pub fn tag(self, kind: fn(String) -> Error) -> Error {
+    match self {
+      Error::Other(x) => kind(x),
+      x => x,
+    }
+  }
+}
 
 impl std::fmt::Debug for Error {
   fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-    write!(f, "{}", self.0)
+    write!(f, "{}", self.message())
+  }
+}
+
+impl std::fmt::Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "{}", self.message())
   }
 }
 
+impl std::error::Error for Error {}
+
 impl<T: Into<String>> From<T> for Error {
   fn from(x: T) -> Error {
-    Error(x.into())
+    Error::Other(x.into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn errors_are_tagged() {
+    let error: Error = "Missing column".into();
+    assert!(matches!(error.clone().tag(Error::Table), Error::Table(_)));
+    let location = Some(Location { line: 2, column: 3 });
+    let error = Error::ParseFailure("At line 2, column 3".into(), location);
+    let error = error.tag(|x| Error::GrammarCompile(x, None));
+    assert!(matches!(error, Error::ParseFailure(_, _)));
+    assert_eq!(error.location(), location);
+    assert_eq!(error.to_string(), "At line 2, column 3");
+    assert_eq!(error, Error::ParseFailure("At line 2, column 3".into(), location));
+    assert_ne!(error, "At line 2, column 3".into());
+    assert_ne!(Error::Table("x".into()), Error::Other("x".into()));
   }
 }
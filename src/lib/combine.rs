@@ -1,15 +1,31 @@
-use super::base::Result;
+use super::base::{Error, HashMap, Location, Result};
 use regex::Regex;
+use std::any::Any;
 use std::borrow::Borrow;
 use std::cell::RefCell;
+use std::io::Read;
+use std::ops::Range;
 use std::rc::Rc;
 
+type Memo = HashMap<(usize, usize), Option<(Box<dyn Any>, usize)>>;
+
 struct State<'a> {
+  errors: Vec<Error>,
   expected: Vec<Rc<String>>,
+  fatal: Option<Error>,
   input: &'a str,
+  memo: Memo,
+  offset: (usize, usize),
   remainder: usize,
 }
 
+impl<'a> State<'a> {
+  fn new(input: &'a str, offset: (usize, usize)) -> Self {
+    let (errors, expected, memo) = (vec![], vec![], HashMap::default());
+    State { errors, expected, fatal: None, input, memo, offset, remainder: input.len() }
+  }
+}
+
 type Method<T> = dyn for<'a> Fn(&'a str, &mut State<'a>) -> Option<(T, &'a str)>;
 
 pub struct Parser<T>(Rc<Method<T>>);
@@ -26,15 +42,133 @@ impl<T: 'static> Parser<T> {
   }
 
   pub fn parse(&self, x: &str) -> Result<T> {
-    let mut state = State { expected: vec![], input: x, remainder: x.len() };
-    match (self.0)(x, &mut state) {
-      Some((value, "")) => Ok(value),
-      Some((_, x)) => Err(format(Some(x.len()), &mut state).into()),
-      None => Err(format(None, &mut state).into()),
+    self.parse_all(x).map_err(|mut x| x.remove(0))
+  }
+
+  // Like parse, but returns every error, including those that recover() skipped past.
+  pub fn parse_all(&self, x: &str) -> std::result::Result<T, Vec<Error>> {
+    let mut state = State::new(x, (0, 0));
+    let result = (self.0)(x, &mut state);
+    if let Some(fatal) = state.fatal {
+      return Err(vec![fatal]);
     }
+    let error = match result {
+      Some((value, "")) if state.errors.is_empty() => return Ok(value),
+      Some((_, "")) => None,
+      Some((_, x)) => Some(format(Some(x.len()), &mut state)),
+      None => Some(format(None, &mut state)),
+    };
+    state.errors.extend(error);
+    Err(state.errors)
+  }
+
+  // Parse a sequence of items from chunked input, such as the output of chunks() below.
+  // See Stream for the details.
+  pub fn stream<I>(&self, chunks: I) -> Stream<T, I::IntoIter>
+  where
+    I: IntoIterator<Item = Result<String>>,
+  {
+    let (buffer, parser) = (String::new(), self.into());
+    Stream { buffer, chunks: chunks.into_iter(), done: false, offset: (0, 0), parser }
   }
 }
 
+// Streaming input. A Stream applies an item parser repeatedly, yielding one result per
+// item, and only buffers input that it hasn't parsed yet. We only accept an item that
+// ends before the end of the buffer, and that didn't fail any match there, because more
+// input might extend it. Item parsers should therefore end with a delimiter, like "\n".
+// After an error, the stream ends.
+
+pub struct Stream<T, I: Iterator<Item = Result<String>>> {
+  buffer: String,
+  chunks: I,
+  done: bool,
+  offset: (usize, usize),
+  parser: Parser<T>,
+}
+
+impl<T: 'static, I: Iterator<Item = Result<String>>> Iterator for Stream<T, I> {
+  type Item = Result<T>;
+
+  fn next(&mut self) -> Option<Result<T>> {
+    loop {
+      if self.done && self.buffer.is_empty() {
+        return None;
+      }
+      let mut state = State::new(&self.buffer, self.offset);
+      let result = (self.parser.0)(&self.buffer, &mut state);
+      let (n, done) = (self.buffer.len(), self.done);
+      let stable = done || state.remainder > 0;
+      let complete = |x: &str| x.len() < n && (done || (!x.is_empty() && stable));
+      let step = match (result, state.fatal.take()) {
+        (_, Some(fatal)) => Err(fatal),
+        (Some((value, x)), None) if complete(x) => Ok(Some((value, x.len()))),
+        (Some(_), None) if done => Err(locate(0, "expected: progress", &state)),
+        (None, None) if done => Err(format(None, &mut state)),
+        _ => Ok(None),
+      };
+      match step {
+        Ok(Some((value, rest))) => {
+          self.consume(n - rest);
+          return Some(Ok(value));
+        }
+        Ok(None) => match self.chunks.next() {
+          Some(Ok(chunk)) => self.buffer.push_str(&chunk),
+          Some(Err(x)) => return self.fail(x),
+          None => self.done = true,
+        },
+        Err(x) => return self.fail(x),
+      }
+    }
+  }
+}
+
+impl<T, I: Iterator<Item = Result<String>>> Stream<T, I> {
+  fn consume(&mut self, n: usize) {
+    let consumed: String = self.buffer.drain(..n).collect();
+    let (lines, column) = (consumed.matches('\n').count(), consumed.rfind('\n').map(|x| x + 1));
+    let column = consumed[column.unwrap_or(0)..].chars().count();
+    let (l, c) = self.offset;
+    self.offset = if lines > 0 { (l + lines, column) } else { (l, c + column) };
+  }
+
+  fn fail(&mut self, error: Error) -> Option<Result<T>> {
+    self.buffer.clear();
+    self.done = true;
+    Some(Err(error))
+  }
+}
+
+// Reads UTF-8 text in chunks of roughly the given size, never splitting a character.
+pub fn chunks<R: Read>(mut reader: R, size: usize) -> impl Iterator<Item = Result<String>> {
+  let mut pending = vec![];
+  std::iter::from_fn(move || {
+    let mut buffer = vec![0; size];
+    let n = loop {
+      match reader.read(&mut buffer) {
+        Ok(n) => break n,
+        Err(x) if x.kind() == std::io::ErrorKind::Interrupted => continue,
+        Err(x) => return Some(Err(format!("Failed to read input: {}", x).into())),
+      }
+    };
+    if n == 0 {
+      if pending.is_empty() {
+        return None;
+      }
+      pending.clear();
+      return Some(Err("Invalid UTF-8 at end of input".into()));
+    }
+    pending.extend_from_slice(&buffer[..n]);
+    let valid = match std::str::from_utf8(&pending) {
+      Ok(_) => pending.len(),
+      Err(x) if x.error_len().is_none() => x.valid_up_to(),
+      Err(_) => return Some(Err("Invalid UTF-8 in input".into())),
+    };
+    let rest = pending.split_off(valid);
+    Some(Ok(String::from_utf8(std::mem::replace(&mut pending, rest)).unwrap()))
+  })
+}
+
 // Methods for constructing parsers.
 
 pub fn any<A: 'static>(parsers: &[impl Borrow<Parser<A>>]) -> Parser<A> {
@@ -42,6 +176,13 @@ pub fn any<A: 'static>(parsers: &[impl Borrow<Parser<A>>]) -> Parser<A> {
   Parser::new(move |x, s| parsers.iter().filter_map(|y| (y.0)(x, s)).next())
 }
 
+pub fn followed_by<A: 'static, B: 'static>(
+  parser: impl Into<Parser<A>>,
+  lookahead: impl Into<Parser<B>>,
+) -> Parser<A> {
+  seq2((parser, peek(lookahead)), |x| x.0)
+}
+
 pub fn fail<A: 'static>(message: &str) -> Parser<A> {
   let expected = Rc::new(message.to_string());
   Parser::new(move |x, s| {
@@ -50,9 +191,63 @@ pub fn fail<A: 'static>(message: &str) -> Parser<A> {
   })
 }
 
-pub fn lazy<A: 'static>() -> (Rc<RefCell<Parser<A>>>, Parser<A>) {
-  let result = Rc::new(RefCell::new(fail("Uninitialized lazy!")));
-  (Rc::clone(&result), Parser::new(move |x, s| (RefCell::borrow(&result).0)(x, s)))
+// Matches an identifier: an ASCII letter or "_", then any number of those or digits.
+pub fn ident() -> Parser<String> {
+  let expected = Rc::new("identifier".to_string());
+  Parser::new(move |x, s| {
+    let first = x.starts_with(|y: char| y.is_ascii_alphabetic() || y == '_');
+    let n = if first { prefix(x, |y| y.is_ascii_alphanumeric() || y == '_') } else { 0 };
+    if n == 0 {
+      update(Rc::clone(&expected), x.len(), s);
+      return None;
+    }
+    Some((x[..n].to_string(), &x[n..]))
+  })
+}
+
+// Replaces the expectations of a parser that fails at its starting position with the
+// given label, so errors say "expected: identifier" instead of showing a raw regex. If
+// the parser fails after consuming some input, its own, more specific errors are kept.
+pub fn label<A: 'static>(parser: impl Into<Parser<A>>, label: &str) -> Parser<A> {
+  let (parser, label) = (parser.into(), Rc::new(label.to_string()));
+  Parser::new(move |x, s| {
+    let (expected, remainder) = (std::mem::take(&mut s.expected), s.remainder);
+    s.remainder = x.len() + 1;
+    let result = (parser.0)(x, s);
+    let inner = std::mem::replace(&mut s.expected, expected);
+    let inner_remainder = std::mem::replace(&mut s.remainder, remainder);
+    if inner_remainder == x.len() {
+      update(Rc::clone(&label), x.len(), s);
+    } else if inner_remainder < x.len() {
+      inner.into_iter().for_each(|y| update(y, inner_remainder, s));
+    }
+    result
+  })
+}
+
+// A lazy parser is a forward reference, used to build recursive parsers. We don't
+// support left recursion: if a lazy parser is re-entered at a position where it is
+// already active, it would loop forever, so we fail the whole parse with an error
+// naming that parser instead.
+pub fn lazy<A: 'static>(name: &str) -> (Rc<RefCell<Parser<A>>>, Parser<A>) {
+  let result = Rc::new(RefCell::new(fail(&format!("Uninitialized lazy: {}", name))));
+  let (active, name) = (RefCell::new(vec![]), name.to_string());
+  let cell = Rc::clone(&result);
+  (
+    cell,
+    Parser::new(move |x, s| {
+      if s.fatal.is_some() {
+        return None;
+      } else if active.borrow().contains(&x.len()) {
+        s.fatal = Some(locate(s.input.len() - x.len(), &format!("left recursion in {}", name), s));
+        return None;
+      }
+      active.borrow_mut().push(x.len());
+      let value = (RefCell::borrow(&result).0)(x, s);
+      active.borrow_mut().pop();
+      value
+    }),
+  )
 }
 
 pub fn map<A: 'static, B: 'static, F: Fn(A) -> B + 'static>(
@@ -63,6 +258,49 @@ pub fn map<A: 'static, B: 'static, F: Fn(A) -> B + 'static>(
   Parser::new(move |x, s| (parser.0)(x, s).map(|(value, x)| (callback(value), x)))
 }
 
+// Packrat memoization. A memoized parser caches its result at each input position, so
+// when several alternatives of an any() start with it, it only runs once per position.
+// Cache entries are keyed by the parser's identity, so clones of it share the cache.
+// Errors are recorded on the first run, so replaying a cached failure needs no update.
+// Each call costs a hash lookup and a clone of the value, so memoize only parsers whose
+// work is repeated, like a long prefix shared by alternatives. The grammar-file parser
+// doesn't: its alternatives fail within a token, and memoizing "id" made it slower.
+pub fn memo<A: Clone + 'static>(parser: impl Into<Parser<A>>) -> Parser<A> {
+  let parser = parser.into();
+  let id = &*parser.0 as *const Method<A> as *const () as usize;
+  Parser::new(move |x, s| {
+    let key = (id, x.len());
+    if let Some(entry) = s.memo.get(&key) {
+      let value = |y: &Box<dyn Any>| y.downcast_ref::<A>().unwrap().clone();
+      return entry.as_ref().map(|(y, n)| (value(y), &x[x.len() - n..]));
+    }
+    let result = (parser.0)(x, s);
+    let entry = result.as_ref().map(|(y, z)| (Box::new(y.clone()) as Box<dyn Any>, z.len()));
+    s.memo.insert(key, entry);
+    result
+  })
+}
+
+// Negative lookahead: succeeds, consuming no input, if and only if the parser fails.
+// The parser's own expectations are discarded, since its failure is what we want.
+// If it succeeds, we report the text it matched, as in: expected: not ":".
+pub fn not<A: 'static>(parser: impl Into<Parser<A>>) -> Parser<()> {
+  let parser = parser.into();
+  Parser::new(move |x, s| {
+    let (expected, remainder) = (s.expected.clone(), s.remainder);
+    let result = (parser.0)(x, s);
+    s.expected = expected;
+    s.remainder = remainder;
+    match result {
+      Some((_, y)) => {
+        update(Rc::new(format!("not {:?}", &x[..x.len() - y.len()])), x.len(), s);
+        None
+      }
+      None => Some(((), x)),
+    }
+  })
+}
+
 pub fn opt<A: 'static>(parser: impl Into<Parser<A>>) -> Parser<Option<A>> {
   let parser = parser.into();
   Parser::new(move |x, s| match (parser.0)(x, s) {
@@ -71,6 +309,47 @@ pub fn opt<A: 'static>(parser: impl Into<Parser<A>>) -> Parser<Option<A>> {
   })
 }
 
+// Positive lookahead: runs the parser and returns its value without consuming input.
+pub fn peek<A: 'static>(parser: impl Into<Parser<A>>) -> Parser<A> {
+  let parser = parser.into();
+  Parser::new(move |x, s| (parser.0)(x, s).map(|(value, _)| (value, x)))
+}
+
+pub fn position() -> Parser<usize> {
+  Parser::new(|x, s| Some((s.input.len() - x.len(), x)))
+}
+
+// Error recovery. If the parser fails, we record the error, skip ahead from the point of
+// failure to just past the next match of the sync parser (or to the end of the input),
+// and succeed with None. Use parse_all to collect the recorded errors. At the end of the
+// input, there is nothing to recover, so we fail as usual.
+pub fn recover<A: 'static, B: 'static>(
+  parser: impl Into<Parser<A>>,
+  sync: impl Into<Parser<B>>,
+) -> Parser<Option<A>> {
+  let (parser, sync) = (parser.into(), sync.into());
+  Parser::new(move |x, s| {
+    if let Some((value, y)) = (parser.0)(x, s) {
+      return Some((Some(value), y));
+    } else if x.is_empty() || s.fatal.is_some() {
+      return None;
+    }
+    let error = format(None, s);
+    s.errors.push(error);
+    let mut rest = &s.input[s.input.len() - std::cmp::min(s.remainder, x.len())..];
+    while !rest.is_empty() {
+      if let Some((_, y)) = (sync.0)(rest, s) {
+        rest = y;
+        break;
+      }
+      rest = &rest[rest.chars().next().map_or(0, |y| y.len_utf8())..];
+    }
+    s.expected.clear();
+    s.remainder = rest.len();
+    Some((None, rest))
+  })
+}
+
 pub fn regexp<A: 'static, F: Fn(&str) -> A + 'static>(re: &str, callback: F) -> Parser<A> {
   let expected = Rc::new(format!("/{}/", re));
   let re = Box::new(Regex::new(&format!("^{}", re)).unwrap());
@@ -112,29 +391,54 @@ pub fn separate<A: 'static, B: 'static>(
   if min == 0 { any(&[list, succeed(std::vec::Vec::new)]) } else { list }
 }
 
+// Sequencing. seq takes a tuple of up to eight parsers and calls the callback with the
+// tuple of their values. The seqN functions are kept as shorthands with explicit arity.
+
+pub trait Sequence<T> {
+  fn sequence(self) -> Parser<T>;
+}
+
+macro_rules! sequence {
+  ($($P:ident $A:ident $a:ident $i:tt),+) => {
+    impl<$($A: 'static, $P: Into<Parser<$A>>),+> Sequence<($($A,)+)> for ($($P,)+) {
+      fn sequence(self) -> Parser<($($A,)+)> {
+        let parsers = ($(self.$i.into(),)+);
+        Parser::new(move |x, s| {
+          $(let ($a, x) = (parsers.$i.0)(x, s)?;)+
+          Some((($($a,)+), x))
+        })
+      }
+    }
+  };
+}
+
+sequence!(PA A a 0, PB B b 1);
+sequence!(PA A a 0, PB B b 1, PC C c 2);
+sequence!(PA A a 0, PB B b 1, PC C c 2, PD D d 3);
+sequence!(PA A a 0, PB B b 1, PC C c 2, PD D d 3, PE E e 4);
+sequence!(PA A a 0, PB B b 1, PC C c 2, PD D d 3, PE E e 4, PF F f 5);
+sequence!(PA A a 0, PB B b 1, PC C c 2, PD D d 3, PE E e 4, PF F f 5, PG G g 6);
+sequence!(PA A a 0, PB B b 1, PC C c 2, PD D d 3, PE E e 4, PF F f 5, PG G g 6, PH H h 7);
+
+pub fn seq<S: Sequence<A>, A: 'static, F: Fn(A) -> T + 'static, T: 'static>(
+  parsers: S,
+  callback: F,
+) -> Parser<T> {
+  map(parsers.sequence(), callback)
+}
+
 pub fn seq2<A: 'static, B: 'static, F: Fn((A, B)) -> T + 'static, T: 'static>(
   parsers: (impl Into<Parser<A>>, impl Into<Parser<B>>),
   callback: F,
 ) -> Parser<T> {
-  let (a, b) = (parsers.0.into(), parsers.1.into());
-  Parser::new(move |x, s| {
-    let (a, x) = a.0(x, s)?;
-    let (b, x) = b.0(x, s)?;
-    Some((callback((a, b)), x))
-  })
+  seq(parsers, callback)
 }
 
 pub fn seq3<A: 'static, B: 'static, C: 'static, F: Fn((A, B, C)) -> T + 'static, T: 'static>(
   parsers: (impl Into<Parser<A>>, impl Into<Parser<B>>, impl Into<Parser<C>>),
   callback: F,
 ) -> Parser<T> {
-  let (a, b, c) = (parsers.0.into(), parsers.1.into(), parsers.2.into());
-  Parser::new(move |x, s| {
-    let (a, x) = a.0(x, s)?;
-    let (b, x) = b.0(x, s)?;
-    let (c, x) = c.0(x, s)?;
-    Some((callback((a, b, c)), x))
-  })
+  seq(parsers, callback)
 }
 
 pub fn seq4<A: 'static, B: 'static, C: 'static, D: 'static, F: 'static, T: 'static>(
@@ -144,13 +448,17 @@ pub fn seq4<A: 'static, B: 'static, C: 'static, D: 'static, F: 'static, T: 'stat
 where
   F: Fn((A, B, C, D)) -> T,
 {
-  let (a, b, c, d) = (parsers.0.into(), parsers.1.into(), parsers.2.into(), parsers.3.into());
+  seq(parsers, callback)
+}
+
+// Returns the parser's value along with the byte range of input that it consumed. Spans
+// nest naturally inside seqN: wrap each item whose location a callback needs.
+pub fn spanned<A: 'static>(parser: impl Into<Parser<A>>) -> Parser<(A, Range<usize>)> {
+  let parser = parser.into();
   Parser::new(move |x, s| {
-    let (a, x) = a.0(x, s)?;
-    let (b, x) = b.0(x, s)?;
-    let (c, x) = c.0(x, s)?;
-    let (d, x) = d.0(x, s)?;
-    Some((callback((a, b, c, d)), x))
+    let start = s.input.len() - x.len();
+    let (value, y) = (parser.0)(x, s)?;
+    Some(((value, start..s.input.len() - y.len()), y))
   })
 }
 
@@ -171,21 +479,70 @@ pub fn succeed<A: 'static, F: Fn() -> A + 'static>(callback: F) -> Parser<A> {
   Parser::new(move |x, _| Some((callback(), x)))
 }
 
+// Fast paths for common token parsers, which don't need the regex engine. take_while
+// matches the longest prefix of characters satisfying the predicate, which may be empty;
+// take_while1 requires at least one character. Label these parsers for good errors.
+
+pub fn take_while<A: 'static, P, F>(predicate: P, callback: F) -> Parser<A>
+where
+  P: Fn(char) -> bool + 'static,
+  F: Fn(&str) -> A + 'static,
+{
+  Parser::new(move |x, _| {
+    let n = prefix(x, &predicate);
+    Some((callback(&x[..n]), &x[n..]))
+  })
+}
+
+pub fn take_while1<A: 'static, P, F>(predicate: P, callback: F) -> Parser<A>
+where
+  P: Fn(char) -> bool + 'static,
+  F: Fn(&str) -> A + 'static,
+{
+  let expected = Rc::new("character".to_string());
+  Parser::new(move |x, s| {
+    let n = prefix(x, &predicate);
+    if n == 0 {
+      update(Rc::clone(&expected), x.len(), s);
+      return None;
+    }
+    Some((callback(&x[..n]), &x[n..]))
+  })
+}
+
+pub fn whitespace() -> Parser<()> {
+  take_while(char::is_whitespace, |_| ())
+}
+
+fn prefix(x: &str, predicate: impl Fn(char) -> bool) -> usize {
+  x.char_indices().find(|y| !predicate(y.1)).map_or(x.len(), |y| y.0)
+}
+
 // Internal helpers used for error handling.
 
-fn format<'a>(remainder: Option<usize>, state: &mut State<'a>) -> String {
+fn format<'a>(remainder: Option<usize>, state: &mut State<'a>) -> Error {
   if let Some(remainder) = remainder {
     update(Rc::new("EOF".to_string()), remainder, state);
   }
   let total = state.input.len();
   let index = std::cmp::max(std::cmp::min(total - state.remainder, total), 0);
+  let mut expected: Vec<_> = state.expected.iter().map(|x| x.to_string()).collect();
+  expected.sort();
+  locate(index, &format!("expected: {}", expected.join(" | ")), state)
+}
+
+fn locate<'a>(index: usize, message: &str, state: &State<'a>) -> Error {
+  let total = state.input.len();
   let start = state.input[..index].rfind('\n').map_or(0, |x| x + 1);
   let end = state.input[start..].find('\n').map_or(total, |x| x + start);
-  let (l, c) = (state.input[..end].split('\n').count(), index - start + 1);
+  // Columns count characters, not bytes, so that they're correct for non-ASCII text.
+  let l = state.input[..end].split('\n').count();
+  let c = state.input[start..index].chars().count() + 1;
   let (h, w) = (&state.input[start..end], " ".repeat(c - 1));
-  let mut expected: Vec<_> = state.expected.iter().map(|x| x.to_string()).collect();
-  expected.sort();
-  format!("At line {}, column {}: expected: {}\n\n  {}\n  {}^\n", l, c, expected.join(" | "), h, w)
+  let c = if l == 1 { c + state.offset.1 } else { c };
+  let l = l + state.offset.0;
+  let text = format!("At line {}, column {}: {}\n\n  {}\n  {}^\n", l, c, message, h, w);
+  Error::ParseFailure(text, Some(Location { line: l, column: c }))
 }
 
 fn update<'a>(expected: Rc<String>, remainder: usize, state: &mut State<'a>) {
@@ -232,6 +589,24 @@ mod tests {
     test_error(parser.parse("-1.23e45 "), "At line 1, column 9: expected: EOF");
   }
 
+  #[test]
+  fn multibyte_columns_test() {
+    let parser = separate(regexp("[^ ]", |x| x.to_string()), tag(" "), 1);
+    let error = format!("{:?}", parser.parse("\u{915} \u{916}\u{917}").unwrap_err());
+    assert_eq!(error.lines().next(), Some(r#"At line 1, column 4: expected: " " | EOF"#));
+    assert_eq!(error.lines().last(), Some("     ^"));
+    let text = "\u{915}\n\u{916} \u{917}?";
+    test_error(parser.parse(text), r#"At line 1, column 2: expected: " " | EOF"#);
+    let parser = separate(parser, tag("\n"), 1);
+    test_error(parser.parse(text), r#"At line 2, column 4: expected: " " | "\n" | EOF"#);
+    let location = parser.parse(text).unwrap_err().location();
+    assert_eq!(location, Some(Location { line: 2, column: 4 }));
+    let line = seq((regexp("[^\n]+", |x| x.to_string()), any(&[tag("\n"), tag("!\n")])), |x| x.0);
+    let mut items = line.stream(chunks("\u{915}\u{916}!\nx".as_bytes(), 4));
+    assert_eq!(items.next(), Some(Ok("\u{915}\u{916}!".into())));
+    test_error(items.next().unwrap(), r#"At line 2, column 2: expected: "!\n" | "\n""#);
+  }
+
   #[test]
   fn repeat_test() {
     let parser = repeat(tag("a"), 0);
@@ -262,9 +637,158 @@ mod tests {
     test_error(parser.parse("a,a,?"), r#"At line 1, column 5: expected: "a""#);
   }
 
+  #[test]
+  fn left_recursion_test() {
+    let (cell, expr) = lazy("expr");
+    cell.replace(any(&[seq3((&expr, tag("+"), tag("a")), |_| ()), tag("a")]));
+    test_error(expr.parse("a+a"), "At line 1, column 1: left recursion in expr");
+    let (cell, expr) = lazy("expr");
+    cell.replace(any(&[seq3((tag("a"), tag("+"), &expr), |_| ()), tag("a")]));
+    assert_eq!(expr.parse("a+a+a"), Ok(()));
+    test_error(lazy::<()>("expr").1.parse("a"), "At line 1, column 1: expected: Uninitialized");
+  }
+
+  #[test]
+  fn label_test() {
+    let id = label(regexp("[a-z]+", |x| x.to_string()), "identifier");
+    let pair = label(seq3((&id, tag("="), &id), |x| (x.0, x.2)), "pair");
+    let parser = separate(pair, tag(","), 1);
+    assert_eq!(parser.parse("a=b"), Ok(vec![("a".into(), "b".into())]));
+    test_error(parser.parse("?"), "At line 1, column 1: expected: pair");
+    test_error(parser.parse("a=b,?"), "At line 1, column 5: expected: pair");
+    test_error(parser.parse("a=?"), "At line 1, column 3: expected: identifier");
+    test_error(parser.parse("a?"), r#"At line 1, column 2: expected: "=""#);
+    test_error(parser.parse("a=b?"), r#"At line 1, column 4: expected: "," | EOF"#);
+  }
+
+  #[test]
+  fn lookahead_test() {
+    let id = regexp("[a-z]+", |x| x.to_string());
+    let key = followed_by(&id, tag(":"));
+    let name = seq2((&id, not(tag(":"))), |x| x.0);
+    let parser = any(&[seq2((&key, tag(":")), |x| format!("key {}", x.0)), name]);
+    assert_eq!(parser.parse("abc:"), Ok("key abc".to_string()));
+    assert_eq!(parser.parse("abc"), Ok("abc".to_string()));
+    test_error(parser.parse("abc?"), r#"At line 1, column 4: expected: ":" | EOF"#);
+    test_error(parser.parse("?"), "At line 1, column 1: expected: /[a-z]+/");
+
+    let parser = seq2((&id, not(tag(":"))), |x| x.0);
+    test_error(parser.parse("abc:"), r#"At line 1, column 4: expected: not ":""#);
+    let parser = seq2((peek(&id), &id), |x| x.0 + &x.1);
+    assert_eq!(parser.parse("ab"), Ok("abab".to_string()));
+  }
+
+  #[test]
+  fn recover_test() {
+    let item = recover(seq2((tag("a"), regexp("[0-9]", |x| x.to_string())), |x| x.1), tag(";"));
+    let parser = separate(item, tag(" "), 0);
+    assert_eq!(parser.parse_all("a1 a2"), Ok(vec![Some("1".into()), Some("2".into())]));
+    let errors = parser.parse_all("a1 ax; a2 b3; a4 a?").unwrap_err();
+    let errors: Vec<_> = errors.iter().map(|x| format!("{:?}", x)).collect();
+    assert_eq!(errors.len(), 3);
+    assert!(errors[0].starts_with("At line 1, column 5: expected: /[0-9]/"));
+    assert!(errors[1].starts_with(r#"At line 1, column 11: expected: "a""#));
+    assert!(errors[2].starts_with("At line 1, column 19: expected: /[0-9]/"));
+    test_error(parser.parse("a1 ax; a2"), "At line 1, column 5: expected: /[0-9]/");
+  }
+
+  #[test]
+  fn stream_test() {
+    let line = seq2((regexp("[^\n]+", |x| x.to_string()), tag("\n")), |x| x.0);
+    for size in 1..6 {
+      let items = line.stream(chunks("ab\n\u{939}\u{93f}\nc\n".as_bytes(), size));
+      let expected = vec!["ab".to_string(), "\u{939}\u{93f}".into(), "c".into()];
+      assert_eq!(items.collect::<Result<Vec<_>>>(), Ok(expected));
+    }
+    let mut items = line.stream(chunks("ab\n\nc\n".as_bytes(), 2));
+    assert_eq!(items.next(), Some(Ok("ab".into())));
+    test_error(items.next().unwrap(), "At line 2, column 1: expected: /[^\n]+/");
+    assert_eq!(items.next(), None);
+    let mut items = line.stream(chunks("ab\ncd".as_bytes(), 3));
+    assert_eq!(items.next(), Some(Ok("ab".into())));
+    test_error(items.next().unwrap(), r#"At line 2, column 3: expected: "\n""#);
+    let word = seq2((regexp("[a-z]+", |x| x.to_string()), opt(tag(" "))), |x| x.0);
+    let items = word.stream(vec![Ok("ab c".into()), Ok("d ef".into())]);
+    assert_eq!(items.collect::<Result<Vec<_>>>(), Ok(vec!["ab".into(), "cd".into(), "ef".into()]));
+  }
+
+  #[test]
+  fn seq_test() {
+    let digit = regexp("[0-9]", |x| x.parse::<u32>().unwrap());
+    let date = seq((&digit, &digit, tag("-"), &digit, &digit, tag("-"), &digit, &digit), |x| {
+      (10 * x.0 + x.1, 10 * x.3 + x.4, 10 * x.6 + x.7)
+    });
+    assert_eq!(date.parse("12-34-56"), Ok((12, 34, 56)));
+    test_error(date.parse("12-3x-56"), "At line 1, column 5: expected: /[0-9]/");
+  }
+
+  #[test]
+  fn take_while_test() {
+    let word = seq((take_while1(char::is_alphabetic, |x| x.to_string()), whitespace()), |x| x.0);
+    let parser = seq((whitespace(), repeat(word, 1)), |x| x.1);
+    let words = vec!["a".to_string(), "\u{939}\u{93f}".into(), "bc".into()];
+    assert_eq!(parser.parse(" a \u{939}\u{93f}\n bc "), Ok(words));
+    test_error(parser.parse(" 1"), "At line 1, column 2: expected: character");
+    test_error(parser.parse("\u{939}\u{93f} 1"), "At line 1, column 4: expected: EOF | character");
+    let parser = separate(ident(), tag("."), 1);
+    assert_eq!(parser.parse("_a.b2"), Ok(vec!["_a".to_string(), "b2".into()]));
+    test_error(parser.parse("a.2b"), "At line 1, column 3: expected: identifier");
+    assert_eq!(take_while(|x| x == 'a', |x| x.len()).parse(""), Ok(0));
+  }
+
+  #[test]
+  fn spanned_test() {
+    let word = spanned(regexp("[a-z]+", |x| x.to_string()));
+    let parser = separate(word, tag(" "), 1);
+    assert_eq!(parser.parse("ab cde"), Ok(vec![("ab".into(), 0..2), ("cde".into(), 3..6)]));
+    let parser = spanned(seq3((tag("("), spanned(tag("x")), tag(")")), |x| x.1));
+    assert_eq!(parser.parse("(x)"), Ok((((), 1..2), 0..3)));
+  }
+
+  #[test]
+  fn memo_test() {
+    let count = Rc::new(std::cell::Cell::new(0));
+    let counter = Rc::clone(&count);
+    let digits = memo(regexp("[0-9]+", move |x| {
+      counter.set(counter.get() + 1);
+      x.to_string()
+    }));
+    let parser = any(&[seq2((&digits, tag("!")), |x| x.0), seq2((&digits, tag("?")), |x| x.0)]);
+    assert_eq!(parser.parse("123?"), Ok("123".to_string()));
+    assert_eq!(count.get(), 1);
+    test_error(parser.parse("123."), r#"At line 1, column 4: expected: "!" | "?""#);
+    test_error(parser.parse("x"), "At line 1, column 1: expected: /[0-9]+/");
+    assert_eq!(count.get(), 2);
+  }
+
   #[bench]
   fn float_parser_benchmark(b: &mut Bencher) {
     let parser = float_parser();
     b.iter(|| parser.parse("-1.23e45"));
   }
+
+  // Each item is a list of numbers with a unit, so the alternatives share a long prefix.
+  fn units_parser(memoize: bool) -> Parser<Vec<usize>> {
+    let numbers = separate(float_parser(), tag(","), 1);
+    let numbers = if memoize { memo(numbers) } else { numbers };
+    let unit = |x: &'static str, i: usize| seq2((&numbers, tag(x)), move |_| i);
+    let item = any(&[unit("kg", 0), unit("km", 1), unit("m", 2), unit("s", 3)]);
+    separate(item, tag(" "), 1)
+  }
+
+  fn units_input() -> String {
+    vec!["1,2.5,-3e4,4.25,5,6,7,8s"; 100].join(" ")
+  }
+
+  #[bench]
+  fn memo_benchmark(b: &mut Bencher) {
+    let (parser, input) = (units_parser(true), units_input());
+    b.iter(|| parser.parse(&input).unwrap());
+  }
+
+  #[bench]
+  fn no_memo_benchmark(b: &mut Bencher) {
+    let (parser, input) = (units_parser(false), units_input());
+    b.iter(|| parser.parse(&input).unwrap());
+  }
 }
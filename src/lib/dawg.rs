@@ -2,44 +2,165 @@
 // Unlike a trie, the edges of a DAWG can form an arbitrary directed acyclic
 // graph and paths can share nodes. Compress minimizes the number of nodes.
 
-use super::super::lib::base::{HashMap, HashSet};
+use super::super::lib::base::{HashMap, Result};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 use std::hash::Hash;
-use std::rc::Rc;
+use std::marker::PhantomData;
+use std::sync::Arc;
 
 pub trait Item: Clone + Eq + Hash + Ord {}
 
 impl<T: Clone + Eq + Hash + Ord> Item for T {}
 
+// Keys and values that can be serialized. Implementations must be self-delimiting:
+// decode reads one item from the front of its input and returns the rest.
+
+pub trait Codec: Sized {
+  fn encode(&self, out: &mut Vec<u8>);
+  fn decode(input: &[u8]) -> Option<(Self, &[u8])>;
+}
+
+impl Codec for bool {
+  fn encode(&self, out: &mut Vec<u8>) {
+    out.push(*self as u8);
+  }
+  fn decode(input: &[u8]) -> Option<(Self, &[u8])> {
+    let (x, rest) = u8::decode(input)?;
+    if x > 1 { None } else { Some((x == 1, rest)) }
+  }
+}
+
+impl Codec for u8 {
+  fn encode(&self, out: &mut Vec<u8>) {
+    out.push(*self);
+  }
+  fn decode(input: &[u8]) -> Option<(Self, &[u8])> {
+    input.split_first().map(|(x, rest)| (*x, rest))
+  }
+}
+
+impl Codec for u32 {
+  fn encode(&self, out: &mut Vec<u8>) {
+    out.extend_from_slice(&self.to_le_bytes());
+  }
+  fn decode(input: &[u8]) -> Option<(Self, &[u8])> {
+    if input.len() < 4 {
+      return None;
+    }
+    let (x, rest) = input.split_at(4);
+    Some((u32::from_le_bytes([x[0], x[1], x[2], x[3]]), rest))
+  }
+}
+
+impl Codec for usize {
+  fn encode(&self, out: &mut Vec<u8>) {
+    (*self as u32).encode(out);
+  }
+  fn decode(input: &[u8]) -> Option<(Self, &[u8])> {
+    u32::decode(input).map(|(x, rest)| (x as usize, rest))
+  }
+}
+
+impl Codec for f32 {
+  fn encode(&self, out: &mut Vec<u8>) {
+    self.to_bits().encode(out);
+  }
+  fn decode(input: &[u8]) -> Option<(Self, &[u8])> {
+    u32::decode(input).map(|(x, rest)| (f32::from_bits(x), rest))
+  }
+}
+
+impl Codec for char {
+  fn encode(&self, out: &mut Vec<u8>) {
+    (*self as u32).encode(out);
+  }
+  fn decode(input: &[u8]) -> Option<(Self, &[u8])> {
+    u32::decode(input).and_then(|(x, rest)| Some((std::char::from_u32(x)?, rest)))
+  }
+}
+
+impl Codec for String {
+  fn encode(&self, out: &mut Vec<u8>) {
+    self.len().encode(out);
+    out.extend_from_slice(self.as_bytes());
+  }
+  fn decode(input: &[u8]) -> Option<(Self, &[u8])> {
+    let (n, rest) = usize::decode(input)?;
+    if rest.len() < n {
+      return None;
+    }
+    let (x, rest) = rest.split_at(n);
+    Some((std::str::from_utf8(x).ok()?.to_string(), rest))
+  }
+}
+
+// A compressed DAWG keeps a register of its nodes, keyed by their contents, so that
+// later adds can reuse existing nodes and keep the graph minimal. Adds still leave the
+// nodes that they replace behind, so we recompress once the node count doubles.
+//
+// Each value has a weight, such as a log-frequency prior, and each node stores its
+// values best first along with the best weight of any value below it. The top-k
+// lookups use these to stop early. Unweighted values have a weight of zero.
+
 pub struct Dawg<K: Item, V: Item> {
   data: Vec<Node<K, V>>,
+  register: Option<Register<K, V>>,
 }
 
 struct Memo<K: Item, V: Item> {
   dawg: Dawg<K, V>,
-  memo: HashMap<(Vec<(K, usize)>, Vec<V>), usize>,
+  memo: HashMap<Contents<K, V>, usize>,
+}
+
+struct Register<K: Item, V: Item> {
+  limit: usize,
+  nodes: HashMap<Contents<K, V>, usize>,
 }
 
+// Weights are stored by their bits in register keys, since floats aren't hashable.
+type Contents<K, V> = (Vec<(K, usize)>, Vec<(V, u32)>);
+
+type Record<K, V> = (Vec<(K, usize)>, Vec<(V, f32)>);
+
 #[derive(Clone)]
 struct Node<K: Item, V: Item> {
-  edges: Option<Rc<HashMap<K, usize>>>,
-  nodes: Option<Rc<HashSet<V>>>,
+  best: f32,
+  edges: Option<Arc<HashMap<K, usize>>>,
+  nodes: Option<Arc<Vec<(V, f32)>>>,
 }
 
 impl<K: Item, V: Item> Dawg<K, V> {
   pub fn new(items: &[(&[K], V)]) -> Self {
-    let mut dawg = Self { data: vec![Node { edges: None, nodes: None }] };
+    let root = Node { best: f32::NEG_INFINITY, edges: None, nodes: None };
+    let mut dawg = Self { data: vec![root], register: None };
     items.iter().for_each(|(k, v)| dawg.add(k, v));
     dawg
   }
 
   pub fn add(&mut self, keys: &[K], value: &V) {
+    self.add_weighted(keys, value, 0.0);
+  }
+
+  // Adds a value with the given weight, replacing its weight if the key already has it.
+  pub fn add_weighted(&mut self, keys: &[K], value: &V, weight: f32) {
     let size = self.size();
-    self.add_helper(size, keys, value);
+    let root = self.add_helper(size, keys, value, weight);
+    // The root must be the last node, even if the register found an equivalent one.
+    if root != self.size() {
+      let node = self.data[root].clone();
+      self.data.push(node);
+    }
+    if self.register.as_ref().is_some_and(|x| self.data.len() > x.limit) {
+      *self = self.compress();
+    }
   }
 
   pub fn compress(&self) -> Self {
     let mut memo = Memo { dawg: Self::new(&[]), memo: HashMap::default() };
     self.compress_helper(self.size(), &mut memo);
+    let limit = 2 * memo.dawg.data.len();
+    memo.dawg.register = Some(Register { limit, nodes: memo.memo });
     memo.dawg
   }
 
@@ -50,80 +171,254 @@ impl<K: Item, V: Item> Dawg<K, V> {
   }
 
   pub fn get(&self, keys: &[K]) -> Vec<V> {
-    let mut prev = self.size();
-    for k in keys.iter() {
-      if let Some(next) = self.data[prev].edges.as_ref().and_then(|x| x.get(k)) {
-        prev = *next;
-      } else {
-        return vec![];
+    let values = self.find(keys).and_then(|x| self.data[x].nodes.as_ref());
+    values.map(|x| x.iter().map(|y| y.0.clone()).collect()).unwrap_or_default()
+  }
+
+  // Returns the values for the given keys with the k highest weights, best first. Nodes
+  // store their values in that order, so we only read the values that we return. Values
+  // that tie with the k-th are returned as well, so equal weights never truncate.
+  pub fn get_top_k(&self, keys: &[K], k: usize) -> Vec<(V, f32)> {
+    let values = self.find(keys).and_then(|x| self.data[x].nodes.as_ref());
+    let values = values.map(|x| x.as_slice()).unwrap_or_default();
+    let n = match k.checked_sub(1).and_then(|x| values.get(x)) {
+      Some(last) => values.iter().take_while(|x| x.1 >= last.1).count(),
+      None => if k == 0 { 0 } else { values.len() },
+    };
+    values[..n].to_vec()
+  }
+
+  // Like get_top_k, but over all keys that start with the given prefix, returning their
+  // suffixes too. We search best first, and since a node's best weight bounds the values
+  // below it, we stop as soon as the best unexpanded node can't beat the k-th value.
+  pub fn get_top_k_prefix(&self, keys: &[K], k: usize) -> Vec<(Vec<K>, V, f32)> {
+    let mut heap = BinaryHeap::new();
+    let mut result: Vec<(Vec<K>, V, f32)> = vec![];
+    if let Some(i) = self.find(keys).filter(|_| k > 0) {
+      heap.push(Search { weight: self.data[i].best, suffix: vec![], value: None, node: i });
+    }
+    while let Some(x) = heap.pop() {
+      if result.len() >= k && result.last().is_some_and(|y| x.weight < y.2) {
+        break;
+      }
+      if let Some(value) = x.value {
+        result.push((x.suffix, value, x.weight));
+        continue;
+      }
+      let node = &self.data[x.node];
+      for (value, weight) in node.nodes.iter().flat_map(|y| y.iter()) {
+        let (suffix, value) = (x.suffix.clone(), Some(value.clone()));
+        heap.push(Search { weight: *weight, suffix, value, node: x.node });
+      }
+      for (key, child) in node.edges.iter().flat_map(|y| y.iter()) {
+        let mut suffix = x.suffix.clone();
+        suffix.push(key.clone());
+        heap.push(Search { weight: self.data[*child].best, suffix, value: None, node: *child });
       }
     }
-    let nodes = &self.data[prev].nodes;
-    nodes.as_ref().map(|x| x.iter().cloned().collect()).unwrap_or_default()
+    result
+  }
+
+  // Returns values whose keys are within the given Levenshtein distance of the query,
+  // with their distances and weights, closest first. We walk the graph with one row of
+  // the edit distance table per node and prune paths whose rows all exceed the bound. The
+  // rows on the current path share one buffer, used as a stack, so the walk reuses memory.
+  pub fn get_within(&self, keys: &[K], max_edits: usize) -> Vec<(V, usize, f32)> {
+    let mut result = HashMap::default();
+    let mut rows: Vec<_> = (0..=keys.len()).collect();
+    rows.reserve((keys.len() + max_edits) * (keys.len() + 1));
+    self.get_within_helper(self.size(), keys, max_edits, &mut rows, &mut result);
+    let mut result: Vec<_> = result.into_iter().map(|(v, (d, w))| (v, d, w)).collect();
+    result.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+    result
+  }
+
+  // Returns an iterator over the (suffix, value) pairs for all keys that start with the
+  // given prefix. Suffixes come out in lexicographic order, so shorter keys come first.
+  pub fn iter_prefix(&self, keys: &[K]) -> Prefix<'_, K, V> {
+    let stack = self.find(keys).map(|x| vec![(x, vec![])]).unwrap_or_default();
+    Prefix { dawg: self, stack, values: vec![] }
   }
 
   pub fn size(&self) -> usize {
     self.data.len() - 1
   }
 
-  fn add_helper(&mut self, i: usize, keys: &[K], value: &V) -> usize {
+  // Serializes the DAWG to the binary format read by deserialize and by DawgView. We
+  // store a table of node offsets so that a view can jump straight to any node.
+  pub fn serialize(&self) -> Vec<u8>
+  where
+    K: Codec,
+    V: Codec,
+  {
+    let mut nodes = vec![];
+    let mut offsets = Vec::with_capacity(self.data.len());
+    for node in self.data.iter() {
+      offsets.push(nodes.len());
+      let mut edges: Vec<_> = node.edges.iter().flat_map(|x| x.iter()).collect();
+      let values: Vec<_> = node.nodes.iter().flat_map(|x| x.iter()).collect();
+      edges.sort();
+      edges.len().encode(&mut nodes);
+      edges.into_iter().for_each(|(k, i)| {
+        k.encode(&mut nodes);
+        i.encode(&mut nodes);
+      });
+      values.len().encode(&mut nodes);
+      values.into_iter().for_each(|(v, w)| {
+        v.encode(&mut nodes);
+        w.encode(&mut nodes);
+      });
+    }
+    let mut result = MAGIC.to_vec();
+    self.data.len().encode(&mut result);
+    let base = result.len() + 4 * self.data.len();
+    offsets.into_iter().for_each(|x| (base + x).encode(&mut result));
+    result.extend(nodes);
+    result
+  }
+
+  pub fn deserialize(bytes: &[u8]) -> Result<Self>
+  where
+    K: Codec,
+    V: Codec,
+  {
+    let view = DawgView::<K, V>::new(bytes)?;
+    let mut dawg = Self { data: Vec::with_capacity(view.nodes), register: None };
+    for i in 0..view.nodes {
+      let (edges, mut values) = view.node(i).ok_or_else(|| format!("Invalid DAWG node: {}", i))?;
+      // Children always precede their parents, which also guarantees that we're acyclic.
+      if edges.iter().any(|x| x.1 >= i) {
+        Err(format!("Invalid DAWG node: {}", i))?;
+      }
+      sort_values(&mut values);
+      let edges = Some(edges).filter(|x| !x.is_empty()).map(|x| Arc::new(x.into_iter().collect()));
+      let nodes = Some(values).filter(|x| !x.is_empty()).map(Arc::new);
+      let node = Node { best: f32::NEG_INFINITY, edges, nodes };
+      let best = dawg.best(&node);
+      dawg.data.push(Node { best, ..node });
+    }
+    Ok(dawg)
+  }
+
+  fn add_helper(&mut self, i: usize, keys: &[K], value: &V, weight: f32) -> usize {
     if keys.is_empty() {
-      if self.data[i].nodes.as_ref().map_or(false, |x| x.contains(value)) {
+      if self.data[i].nodes.iter().flat_map(|x| x.iter()).any(|x| x == &(value.clone(), weight)) {
         return i;
       }
       let mut entry = self.data[i].clone();
       let mut nodes = entry.nodes.as_ref().map(|x| (**x).clone()).unwrap_or_default();
-      nodes.insert(value.clone());
-      entry.nodes.replace(Rc::new(nodes));
-      self.data.push(entry);
-      return self.data.len() - 1;
+      nodes.retain(|x| x.0 != *value);
+      nodes.push((value.clone(), weight));
+      sort_values(&mut nodes);
+      entry.nodes.replace(Arc::new(nodes));
+      return self.push(entry);
     }
     let (head, tail) = (&keys[0], &keys[1..]);
     let index = self.data[i].edges.as_ref().and_then(|x| x.get(head).cloned()).unwrap_or(0);
-    let child = self.add_helper(index, tail, value);
+    let child = self.add_helper(index, tail, value, weight);
     if child == index {
       return i;
     }
     let mut entry = self.data[i].clone();
     let mut edges = entry.edges.as_ref().map(|x| (**x).clone()).unwrap_or_default();
     edges.insert(head.clone(), child);
-    entry.edges.replace(Rc::new(edges));
-    self.data.push(entry);
-    self.data.len() - 1
+    entry.edges.replace(Arc::new(edges));
+    self.push(entry)
+  }
+
+  // Returns the best weight of any value at or below the node, whose children must
+  // already be in the DAWG.
+  fn best(&self, node: &Node<K, V>) -> f32 {
+    let best = node.nodes.as_ref().and_then(|x| x.first()).map_or(f32::NEG_INFINITY, |x| x.1);
+    let children = node.edges.iter().flat_map(|x| x.values());
+    children.fold(best, |acc, x| acc.max(self.data[*x].best))
   }
 
   fn compress_helper(&self, i: usize, memo: &mut Memo<K, V>) -> usize {
     let entry = &self.data[i];
-    let (mut edges, mut nodes): (Vec<_>, Vec<_>) = {
+    let (mut edges, nodes): (Vec<_>, Vec<_>) = {
       let f = |(k, i): (&K, &usize)| (k.clone(), self.compress_helper(*i, memo));
-      let g = |x: &V| x.clone();
       let edges = entry.edges.as_ref().map(|x| x.iter().map(f).collect()).unwrap_or_default();
-      let nodes = entry.nodes.as_ref().map(|x| x.iter().map(g).collect()).unwrap_or_default();
+      let nodes = entry.nodes.as_ref().map(|x| (**x).clone()).unwrap_or_default();
       (edges, nodes)
     };
-    edges.sort();nodes.sort();
+    edges.sort();
     let new_index = memo.dawg.data.len();
-    let result = *memo.memo.entry((edges.clone(), nodes.clone())).or_insert(new_index);
+    let result = *memo.memo.entry((edges.clone(), contents(&nodes))).or_insert(new_index);
     if result == new_index {
       let edges = if edges.is_empty() {
         None
       } else {
         let mut new = HashMap::default();
         edges.into_iter().for_each(|(k, i)| std::mem::drop(new.insert(k, i)));
-        Some(Rc::new(new))
+        Some(Arc::new(new))
       };
-      let nodes = if nodes.is_empty() {
-        None
-      } else {
-        let mut new = HashSet::default();
-        nodes.into_iter().for_each(|x| std::mem::drop(new.insert(x)));
-        Some(Rc::new(new))
-      };
-      memo.dawg.data.push(Node { edges, nodes });
+      let nodes = if nodes.is_empty() { None } else { Some(Arc::new(nodes)) };
+      let node = Node { best: f32::NEG_INFINITY, edges, nodes };
+      let best = memo.dawg.best(&node);
+      memo.dawg.data.push(Node { best, ..node });
     }
     result
   }
 
+  fn find(&self, keys: &[K]) -> Option<usize> {
+    let mut prev = self.size();
+    for k in keys.iter() {
+      prev = *self.data[prev].edges.as_ref().and_then(|x| x.get(k))?;
+    }
+    Some(prev)
+  }
+
+  fn get_within_helper(
+    &self,
+    i: usize,
+    keys: &[K],
+    max_edits: usize,
+    rows: &mut Vec<usize>,
+    result: &mut HashMap<V, (usize, f32)>,
+  ) {
+    let (n, start) = (keys.len() + 1, rows.len() - keys.len() - 1);
+    let distance = rows[start + keys.len()];
+    if distance <= max_edits {
+      for (value, weight) in self.data[i].nodes.iter().flat_map(|x| x.iter()) {
+        let entry = result.entry(value.clone()).or_insert((distance, *weight));
+        if distance < entry.0 || (distance == entry.0 && *weight > entry.1) {
+          *entry = (distance, *weight);
+        }
+      }
+    }
+    for (k, child) in self.data[i].edges.iter().flat_map(|x| x.iter()) {
+      let next = start + n;
+      rows.push(rows[start] + 1);
+      for (j, key) in keys.iter().enumerate() {
+        let substitute = rows[start + j] + if key == k { 0 } else { 1 };
+        let insert = std::cmp::min(rows[start + j + 1], rows[next + j]) + 1;
+        rows.push(std::cmp::min(substitute, insert));
+      }
+      if rows[next..].iter().any(|x| *x <= max_edits) {
+        self.get_within_helper(*child, keys, max_edits, rows, result);
+      }
+      rows.truncate(next);
+    }
+  }
+
+  fn push(&mut self, node: Node<K, V>) -> usize {
+    let index = self.data.len();
+    if let Some(register) = self.register.as_mut() {
+      let edges = node.edges.iter().flat_map(|x| x.iter());
+      let mut edges: Vec<_> = edges.map(|(k, i)| (k.clone(), *i)).collect();
+      let nodes = node.nodes.as_ref().map(|x| contents(x)).unwrap_or_default();
+      edges.sort();
+      let result = *register.nodes.entry((edges, nodes)).or_insert(index);
+      if result != index {
+        return result;
+      }
+    }
+    let best = self.best(&node);
+    self.data.push(Node { best, ..node });
+    index
+  }
+
   fn entries_helper(&self, i: usize) -> Vec<(Vec<K>, V)> {
     let mut result = vec![];
     if let Some(edges) = &self.data[i].edges {
@@ -135,12 +430,163 @@ impl<K: Item, V: Item> Dawg<K, V> {
       }
     }
     if let Some(nodes) = &self.data[i].nodes {
-      nodes.iter().for_each(|x| result.push((vec![], x.clone())));
+      nodes.iter().for_each(|x| result.push((vec![], x.0.clone())));
     }
     result
   }
 }
 
+fn contents<V: Item>(values: &[(V, f32)]) -> Vec<(V, u32)> {
+  values.iter().map(|(v, w)| (v.clone(), w.to_bits())).collect()
+}
+
+fn sort_values<V: Item>(values: &mut [(V, f32)]) {
+  values.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal).then(a.0.cmp(&b.0)));
+}
+
+// An entry in the best-first search of get_top_k_prefix: either a value, or a node that
+// we have yet to expand, weighted by the best value below it. Ties pop in suffix order.
+
+struct Search<K, V> {
+  weight: f32,
+  suffix: Vec<K>,
+  value: Option<V>,
+  node: usize,
+}
+
+impl<K: Ord, V: Ord> Ord for Search<K, V> {
+  fn cmp(&self, other: &Self) -> Ordering {
+    let weight = self.weight.partial_cmp(&other.weight).unwrap_or(Ordering::Equal);
+    weight.then_with(|| (&other.suffix, &other.value).cmp(&(&self.suffix, &self.value)))
+  }
+}
+
+impl<K: Ord, V: Ord> PartialOrd for Search<K, V> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl<K: Ord, V: Ord> PartialEq for Search<K, V> {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl<K: Ord, V: Ord> Eq for Search<K, V> {}
+
+// A lazy depth-first walk of the subgraph below some prefix. The stack holds nodes that
+// we have yet to visit, with their suffixes, and values holds the current node's values.
+
+pub struct Prefix<'a, K: Item, V: Item> {
+  dawg: &'a Dawg<K, V>,
+  stack: Vec<(usize, Vec<K>)>,
+  values: Vec<(Vec<K>, V)>,
+}
+
+impl<'a, K: Item, V: Item> Iterator for Prefix<'a, K, V> {
+  type Item = (Vec<K>, V);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      if let Some(x) = self.values.pop() {
+        return Some(x);
+      }
+      let (i, suffix) = self.stack.pop()?;
+      let node = &self.dawg.data[i];
+      let mut edges: Vec<_> = node.edges.iter().flat_map(|x| x.iter()).collect();
+      edges.sort_by(|a, b| b.0.cmp(a.0));
+      for (k, child) in edges {
+        let mut next = suffix.clone();
+        next.push(k.clone());
+        self.stack.push((*child, next));
+      }
+      let mut values: Vec<_> = node.nodes.iter().flat_map(|x| x.iter().map(|y| &y.0)).collect();
+      values.sort_by(|a, b| b.cmp(a));
+      self.values = values.into_iter().map(|x| (suffix.clone(), x.clone())).collect();
+    }
+  }
+}
+
+// A read-only DAWG that reads the serialized format in place, for example from a
+// memory-mapped file. Nothing is decoded until a lookup touches it.
+
+const MAGIC: &[u8] = b"DAWG";
+
+pub struct DawgView<'a, K: Codec, V: Codec> {
+  bytes: &'a [u8],
+  nodes: usize,
+  phantom: PhantomData<(K, V)>,
+}
+
+impl<'a, K: Codec + Eq, V: Codec> DawgView<'a, K, V> {
+  pub fn new(bytes: &'a [u8]) -> Result<Self> {
+    let error = || "Invalid DAWG: truncated header".to_string();
+    if !bytes.starts_with(MAGIC) {
+      Err("Invalid DAWG: bad magic number")?;
+    }
+    let (nodes, rest) = usize::decode(&bytes[MAGIC.len()..]).ok_or_else(error)?;
+    if nodes == 0 || rest.len() < 4 * nodes {
+      Err(error())?;
+    }
+    Ok(Self { bytes, nodes, phantom: PhantomData })
+  }
+
+  pub fn get(&self, keys: &[K]) -> Vec<V> {
+    let mut prev = self.nodes - 1;
+    for k in keys.iter() {
+      match self.edge(prev, k) {
+        Some(next) => prev = next,
+        None => return vec![],
+      }
+    }
+    self.node(prev).map(|x| x.1.into_iter().map(|y| y.0).collect()).unwrap_or_default()
+  }
+
+  pub fn size(&self) -> usize {
+    self.nodes - 1
+  }
+
+  fn edge(&self, i: usize, key: &K) -> Option<usize> {
+    let (n, mut rest) = usize::decode(self.start(i)?)?;
+    for _ in 0..n {
+      let (k, next) = K::decode(rest)?;
+      let (child, next) = usize::decode(next)?;
+      if k == *key {
+        return if child < self.nodes { Some(child) } else { None };
+      }
+      rest = next;
+    }
+    None
+  }
+
+  fn node(&self, i: usize) -> Option<Record<K, V>> {
+    let (n, mut rest) = usize::decode(self.start(i)?)?;
+    let mut edges = Vec::with_capacity(n);
+    for _ in 0..n {
+      let (k, next) = K::decode(rest)?;
+      let (child, next) = usize::decode(next)?;
+      edges.push((k, child));
+      rest = next;
+    }
+    let (n, mut rest) = usize::decode(rest)?;
+    let mut values = Vec::with_capacity(n);
+    for _ in 0..n {
+      let (v, next) = V::decode(rest)?;
+      let (w, next) = f32::decode(next)?;
+      values.push((v, w));
+      rest = next;
+    }
+    Some((edges, values))
+  }
+
+  fn start(&self, i: usize) -> Option<&'a [u8]> {
+    let offset = MAGIC.len() + 4 * (i + 1);
+    let (start, _) = usize::decode(self.bytes.get(offset..)?)?;
+    self.bytes.get(start..)
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -167,9 +613,9 @@ mod tests {
     keys.iter().for_each(|(k, _)| assert_eq!(dawg.get(k), vec![true]));
     assert_eq!(dawg.entries().len(), 32);
     assert_eq!(dawg.get(b"ac"), vec![true]);
-    assert_eq!(dawg.get(b"ca"), vec![]);
+    assert!(dawg.get(b"ca").is_empty());
     assert_eq!(dawg.get(b"abc"), vec![true]);
-    assert_eq!(dawg.get(b"cab"), vec![]);
+    assert!(dawg.get(b"cab").is_empty());
     assert!(dawg.size() >= 32);
   }
 
@@ -180,9 +626,9 @@ mod tests {
     keys.iter().for_each(|(k, _)| assert_eq!(dawg.get(k), vec![true]));
     assert_eq!(dawg.entries().len(), 32);
     assert_eq!(dawg.get(b"ac"), vec![true]);
-    assert_eq!(dawg.get(b"ca"), vec![]);
+    assert!(dawg.get(b"ca").is_empty());
     assert_eq!(dawg.get(b"abc"), vec![true]);
-    assert_eq!(dawg.get(b"cab"), vec![]);
+    assert!(dawg.get(b"cab").is_empty());
     assert_eq!(dawg.size(), 6);
   }
 
@@ -193,12 +639,134 @@ mod tests {
     keys.iter().for_each(|(k, _)| assert_eq!(dawg.get(k), vec![k.len() % 2]));
     assert_eq!(dawg.entries().len(), 32);
     assert_eq!(dawg.get(b"ac"), vec![0]);
-    assert_eq!(dawg.get(b"ca"), vec![]);
+    assert!(dawg.get(b"ca").is_empty());
     assert_eq!(dawg.get(b"abc"), vec![1]);
-    assert_eq!(dawg.get(b"cab"), vec![]);
+    assert!(dawg.get(b"cab").is_empty());
     assert_eq!(dawg.size(), 10);
   }
 
+  #[test]
+  fn compressed_insertion_stays_minimal() {
+    let keys = subsets(b"abcde").into_iter().map(|x| (x, true)).collect();
+    let mut dawg = dawg(&keys).compress();
+    let size = dawg.size();
+    dawg.add(b"abcdef", &true);
+    dawg.add(b"ea", &true);
+    assert_eq!(dawg.entries().len(), 34);
+    assert_eq!(dawg.get(b"abcdef"), vec![true]);
+    assert_eq!(dawg.get(b"ea"), vec![true]);
+    assert!(dawg.get(b"abcdf").is_empty());
+    assert!(dawg.size() < size + 10);
+    for i in 0..64 {
+      dawg.add(&[b'x', i], &false);
+    }
+    assert_eq!(dawg.entries().len(), 98);
+    assert!(dawg.size() <= 2 * dawg.compress().size());
+  }
+
+  #[test]
+  fn fuzzy_lookup_respects_edit_bound() {
+    let words = ["cat", "cart", "dog", "cot", "at"];
+    let keys = words.iter().map(|x| (x.as_bytes().to_vec(), x.to_string())).collect();
+    let dawg = dawg(&keys).compress();
+    let within = |x: &str, n| dawg.get_within(x.as_bytes(), n);
+    assert_eq!(within("cat", 0), [("cat".to_string(), 0, 0.0)]);
+    let words: Vec<_> = within("cat", 1).into_iter().map(|x| x.0).collect();
+    assert_eq!(words, ["cat", "at", "cart", "cot"]);
+    assert_eq!(within("dgo", 1), []);
+    assert_eq!(within("dgo", 2), [("dog".to_string(), 2, 0.0)]);
+    assert_eq!(within("", 2), [("at".to_string(), 2, 0.0)]);
+  }
+
+  #[test]
+  fn prefix_iteration_is_lazy_and_ordered() {
+    let words = ["cat", "cart", "dog", "cot", "at", "ca"];
+    let keys = words.iter().map(|x| (x.as_bytes().to_vec(), x.to_string())).collect();
+    let dawg = dawg(&keys).compress();
+    let suffixes = |x: &str| {
+      let f = |(k, v): (Vec<u8>, String)| format!("{}/{}", String::from_utf8(k).unwrap(), v);
+      dawg.iter_prefix(x.as_bytes()).map(f).collect::<Vec<_>>()
+    };
+    assert_eq!(suffixes("ca"), ["/ca", "rt/cart", "t/cat"]);
+    assert_eq!(suffixes("").len(), words.len());
+    assert_eq!(suffixes("cat"), ["/cat"]);
+    assert!(suffixes("x").is_empty());
+    assert_eq!(dawg.iter_prefix(b"c").next(), Some((b"a".to_vec(), "ca".to_string())));
+  }
+
+  #[test]
+  fn top_k_ranks_values_by_weight() {
+    let mut dawg = Dawg::new(&[]);
+    let words = [("kaa", 2.0), ("ka", 0.0), ("kA", -1.0), ("kI", -3.0)];
+    words.iter().for_each(|(x, w)| dawg.add_weighted(b"k", &x.to_string(), *w));
+    dawg.add_weighted(b"k", &"kaa".into(), 1.0);
+    let dawg = dawg.compress();
+    let top = |k| dawg.get_top_k(b"k", k).into_iter().map(|x| x.0).collect::<Vec<_>>();
+    assert_eq!(top(2), ["kaa", "ka"]);
+    assert_eq!(top(9), ["kaa", "ka", "kA", "kI"]);
+    assert_eq!(dawg.get_top_k(b"k", 1), [("kaa".to_string(), 1.0)]);
+    assert!(dawg.get_top_k(b"k", 0).is_empty());
+    assert!(dawg.get_top_k(b"x", 1).is_empty());
+    let copy = Dawg::<u8, String>::deserialize(&dawg.serialize()).unwrap();
+    assert_eq!(copy.get_top_k(b"k", 9), dawg.get_top_k(b"k", 9));
+  }
+
+  #[test]
+  fn top_k_keeps_ties() {
+    let words = ["ka", "kA", "kI"];
+    let keys = words.iter().map(|x| (b"k".to_vec(), x.to_string())).collect();
+    let dawg = dawg(&keys);
+    assert_eq!(dawg.get_top_k(b"k", 1).len(), 3);
+    let mut dawg = dawg.compress();
+    dawg.add_weighted(b"k", &"kI".into(), 1.0);
+    assert_eq!(dawg.get_top_k(b"k", 1), [("kI".to_string(), 1.0)]);
+    assert_eq!(dawg.get_top_k(b"k", 2).len(), 3);
+  }
+
+  #[test]
+  fn top_k_prefix_search_is_best_first() {
+    let mut dawg = Dawg::new(&[]);
+    let words = [("cat", -1.0), ("cart", 2.0), ("dog", 3.0), ("cot", -1.0), ("ca", 0.0)];
+    words.iter().for_each(|(x, w)| dawg.add_weighted(x.as_bytes(), &x.to_string(), *w));
+    let dawg = dawg.compress();
+    let top = |x: &str, k| {
+      let f = |(k, v, _): (Vec<u8>, String, f32)| {
+        format!("{}/{}", String::from_utf8(k).unwrap(), v)
+      };
+      dawg.get_top_k_prefix(x.as_bytes(), k).into_iter().map(f).collect::<Vec<_>>()
+    };
+    assert_eq!(top("", 2), ["dog/dog", "cart/cart"]);
+    assert_eq!(top("c", 2), ["art/cart", "a/ca"]);
+    assert_eq!(top("c", 3), ["art/cart", "a/ca", "at/cat", "ot/cot"]);
+    assert_eq!(top("ca", 9), ["rt/cart", "/ca", "t/cat"]);
+    assert!(top("x", 1).is_empty() && top("c", 0).is_empty());
+    assert_eq!(dawg.get_top_k_prefix(b"do", 1), [(b"g".to_vec(), "dog".to_string(), 3.0)]);
+  }
+
+  #[test]
+  fn serialization_round_trips() {
+    let keys = subsets(b"abcde").into_iter().map(|x| (x.clone(), x.len() % 2)).collect();
+    for dawg in [dawg(&keys), dawg(&keys).compress()].iter() {
+      let bytes = dawg.serialize();
+      let copy = Dawg::<u8, usize>::deserialize(&bytes).unwrap();
+      let view = DawgView::<u8, usize>::new(&bytes).unwrap();
+      assert_eq!(copy.size(), dawg.size());
+      assert_eq!(view.size(), dawg.size());
+      keys.iter().for_each(|(k, v)| assert_eq!(copy.get(k), vec![*v]));
+      keys.iter().for_each(|(k, v)| assert_eq!(view.get(k), vec![*v]));
+      assert!(view.get(b"ca").is_empty());
+      assert_eq!(copy.serialize(), bytes);
+    }
+    let (ka, kaa) = (['\u{915}'], ['\u{915}', 'a']);
+    let items: &[(&[char], String)] = &[(&ka, "ka".into()), (&kaa, "kA".into())];
+    let bytes = Dawg::new(items).compress().serialize();
+    let view = DawgView::<char, String>::new(&bytes).unwrap();
+    assert_eq!(view.get(&['\u{915}', 'a']), vec!["kA".to_string()]);
+    assert!(Dawg::<u8, usize>::deserialize(b"DAWG").is_err());
+    assert!(Dawg::<u8, usize>::deserialize(&bytes[..bytes.len() - 1]).is_err());
+    assert!(DawgView::<u8, usize>::new(b"GWAD\x01\x00\x00\x00").is_err());
+  }
+
   #[bench]
   fn insertion_benchmark(b: &mut Bencher) {
     let keys = subsets(b"abcdefghij").into_iter().map(|x| (x, true)).collect();
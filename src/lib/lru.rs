@@ -0,0 +1,153 @@
+// An LRU cache maps keys to values and holds at most "capacity" entries. When it is
+// full, inserting a new key evicts the least-recently-used entry. Entries are stored
+// in a Vec and linked into a list from most to least recently used, so that lookups,
+// insertions, and evictions are all O(1). A cache with capacity 0 stores nothing.
+//
+// The cache counts hits and misses, so that callers can report how well it works.
+
+use super::base::HashMap;
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+const NIL: usize = usize::MAX;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+  pub capacity: usize,
+  pub hits: usize,
+  pub misses: usize,
+  pub size: usize,
+}
+
+struct Node<K, V> {
+  key: K,
+  value: V,
+  prev: usize,
+  next: usize,
+}
+
+pub struct Lru<K, V> {
+  capacity: usize,
+  head: usize,
+  tail: usize,
+  lookup: HashMap<K, usize>,
+  nodes: Vec<Node<K, V>>,
+  stats: Stats,
+}
+
+impl<K: Clone + Eq + Hash, V> Lru<K, V> {
+  pub fn new(capacity: usize) -> Self {
+    let stats = Stats { capacity, ..Stats::default() };
+    Self { capacity, head: NIL, tail: NIL, lookup: HashMap::default(), nodes: vec![], stats }
+  }
+
+  pub fn clear(&mut self) {
+    self.head = NIL;
+    self.tail = NIL;
+    self.lookup.clear();
+    self.nodes.clear();
+  }
+
+  pub fn get<Q: ?Sized + Eq + Hash>(&mut self, key: &Q) -> Option<&V>
+  where
+    K: Borrow<Q>,
+  {
+    let index = match self.lookup.get(key) {
+      Some(x) => *x,
+      None => {
+        self.stats.misses += 1;
+        return None;
+      }
+    };
+    self.stats.hits += 1;
+    self.detach(index);
+    self.attach(index);
+    Some(&self.nodes[index].value)
+  }
+
+  pub fn insert(&mut self, key: K, value: V) {
+    if self.capacity == 0 {
+      return;
+    }
+    if let Some(index) = self.lookup.get(&key).cloned() {
+      self.nodes[index].value = value;
+      self.detach(index);
+      self.attach(index);
+      return;
+    }
+    let index = if self.nodes.len() < self.capacity {
+      self.nodes.push(Node { key: key.clone(), value, prev: NIL, next: NIL });
+      self.nodes.len() - 1
+    } else {
+      let index = self.tail;
+      self.detach(index);
+      let node = &mut self.nodes[index];
+      self.lookup.remove(&node.key);
+      *node = Node { key: key.clone(), value, prev: NIL, next: NIL };
+      index
+    };
+    self.lookup.insert(key, index);
+    self.attach(index);
+  }
+
+  pub fn stats(&self) -> Stats {
+    Stats { size: self.nodes.len(), ..self.stats }
+  }
+
+  fn attach(&mut self, index: usize) {
+    self.nodes[index].next = self.head;
+    if self.head != NIL {
+      self.nodes[self.head].prev = index;
+    }
+    self.head = index;
+    if self.tail == NIL {
+      self.tail = index;
+    }
+  }
+
+  fn detach(&mut self, index: usize) {
+    let (prev, next) = (self.nodes[index].prev, self.nodes[index].next);
+    if prev == NIL {
+      self.head = next;
+    } else {
+      self.nodes[prev].next = next;
+    }
+    if next == NIL {
+      self.tail = prev;
+    } else {
+      self.nodes[next].prev = prev;
+    }
+    self.nodes[index].prev = NIL;
+    self.nodes[index].next = NIL;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn least_recently_used_entries_are_evicted() {
+    let mut lru = Lru::new(2);
+    lru.insert("a".to_string(), 1);
+    lru.insert("b".to_string(), 2);
+    assert_eq!(lru.get("a"), Some(&1));
+    lru.insert("c".to_string(), 3);
+    assert_eq!(lru.get("b"), None);
+    assert_eq!(lru.get("a"), Some(&1));
+    assert_eq!(lru.get("c"), Some(&3));
+    lru.insert("c".to_string(), 4);
+    lru.insert("d".to_string(), 5);
+    assert_eq!(lru.get("a"), None);
+    assert_eq!(lru.get("c"), Some(&4));
+    assert_eq!(lru.stats(), Stats { capacity: 2, hits: 4, misses: 2, size: 2 });
+
+    lru.clear();
+    assert_eq!(lru.get("c"), None);
+    assert_eq!(lru.stats().size, 0);
+
+    let mut empty = Lru::new(0);
+    empty.insert("a", 1);
+    assert_eq!(empty.get("a"), None);
+  }
+}
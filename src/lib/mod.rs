@@ -4,3 +4,5 @@ pub mod arena;
 pub mod base;
 pub mod combine;
 pub mod dawg;
+pub mod lru;
+pub mod morphology;
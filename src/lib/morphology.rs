@@ -0,0 +1,148 @@
+use super::super::nlu::base::{Tense, TenseSchema};
+use super::base::{HashMap, Result};
+
+// Tools for building a lexer's vocabulary out of tables of words. A language configures
+// a Morphology with its grammar's features and with a check for its native script.
+// Paradigms then expand each stem into a list of Cases, its inflected forms, and rollup
+// turns a list of related forms into lexer entries.
+//
+// Native text is in whatever transliteration the language's lexer uses, such as WX for
+// Hindi. For historical reasons, the field holding it is called "hindi".
+
+pub struct Entry {
+  pub head: String,
+  pub hindi: String,
+  pub latin: String,
+  pub scores: HashMap<String, f32>,
+  pub tenses: Vec<Tense>,
+  pub value: String,
+}
+
+pub struct Case {
+  pub hindi: String,
+  pub latin: String,
+  pub tense: Tense,
+}
+
+// A paradigm is a list of suffix rules. Each rule is a native suffix, a Latin suffix,
+// and the tense code of the form that we get by appending those suffixes to a stem.
+
+pub type Paradigm = [(&'static str, &'static str, &'static str)];
+
+pub struct Morphology {
+  categories: Vec<(String, Vec<(u8, String)>)>,
+  check: fn(&str) -> Result<()>,
+}
+
+impl Morphology {
+  pub fn new(features: &TenseSchema, check: fn(&str) -> Result<()>) -> Self {
+    Self { categories: features.codes(), check }
+  }
+
+  pub fn inflect(&self, paradigm: &Paradigm, hstem: &str, lstem: &str) -> Result<Vec<Case>> {
+    let hindis = paradigm.iter().map(|x| format!("{}{}", hstem, x.0)).collect();
+    let latins = paradigm.iter().map(|x| format!("{}{}", lstem, x.1)).collect();
+    let tenses = paradigm.iter().map(|x| self.tense(x.2)).collect::<Result<_>>()?;
+    Ok(zip(hindis, latins, tenses))
+  }
+
+  // Call rollup to construct the entries for a list of related forms of one word. Each
+  // entry can be looked up by the Latin text of any of the forms, at a small penalty.
+  pub fn rollup(&self, cases: &[Case], class: &str, value: &str) -> Result<Vec<Entry>> {
+    assert!(!cases.is_empty());
+    let head = format!("{}-{}", class, cases[0].hindi);
+    let mut result: Vec<Entry> = vec![];
+
+    for case in cases {
+      if result.iter().any(|x| x.hindi == case.hindi) {
+        continue;
+      } else if case.latin != case.latin.to_lowercase() {
+        Err(format!("Invalid Latin: {}", case.latin))?;
+      }
+      (self.check)(&case.hindi)?;
+      let (hindi, latin) = (case.hindi.clone(), case.latin.clone());
+      let scores = std::iter::once((format!("%{}", class), 0.0))
+        .chain(cases.iter().map(|x| (x.latin.clone(), if x.hindi == hindi { 0.0 } else { -1.0 })))
+        .collect();
+      let tenses = cases.iter().filter(|x| x.hindi == hindi).map(|x| x.tense.clone()).collect();
+      result.push(Entry { head: head.clone(), hindi, latin, scores, tenses, value: value.into() });
+    }
+    Ok(result)
+  }
+
+  // Call tense to build a Tense object with interned (statically-allocated) string keys
+  // and values. A tense code has one character for each declared feature, in order, with
+  // "." for unspecified categories. Codes may omit trailing categories entirely.
+  pub fn tense(&self, code: &str) -> Result<Tense> {
+    if code.len() > self.categories.len() {
+      Err(format!("Invalid tense code: {}", code))?
+    }
+    let mut result = HashMap::default();
+    for (i, ch) in code.as_bytes().iter().cloned().enumerate().filter(|x| x.1 != b'.') {
+      let (category, values) = &self.categories[i];
+      let maybe = values.iter().find(|x| x.0 == ch);
+      let value = maybe.ok_or_else(|| format!("Invalid tense code: {}", code))?;
+      result.insert(category.as_str(), value.1.as_str());
+    }
+    Tense::new(&result)
+  }
+}
+
+// Helpers for parsing table cells and for working with stems.
+
+pub fn split(word: &str) -> Result<(String, String)> {
+  let index = word.find('/').ok_or_else(|| format!("Invalid word (missing slash): {}", word))?;
+  let (hindi, latin) = (&word[index + 1..], &word[..index]);
+  Ok((hindi.to_string(), latin.to_string()))
+}
+
+pub fn stem<'a>(hindi: &'a str, latin: &'a str, suffix: (&str, &str)) -> Option<(&'a str, &'a str)>
+{
+  if hindi.ends_with(suffix.0) && latin.ends_with(suffix.1) {
+    Some((&hindi[..hindi.len() - suffix.0.len()], &latin[..latin.len() - suffix.1.len()]))
+  } else {
+    None
+  }
+}
+
+pub fn zip(hindis: Vec<String>, latins: Vec<String>, tenses: Vec<Tense>) -> Vec<Case> {
+  assert!(hindis.len() == latins.len() && latins.len() == tenses.len());
+  let iter = hindis.into_iter().zip(latins.into_iter()).zip(tenses.into_iter());
+  iter.map(|x| Case { hindi: (x.0).0, latin: (x.0).1, tense: x.1 }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn morphology() -> Morphology {
+    let count = ("count", vec!["plural/p", "singular/s"]);
+    let features = TenseSchema::new(&[count, ("gender", vec!["female/f", "male/m"])]).unwrap();
+    Morphology::new(&features, |x| if x.is_ascii() { Ok(()) } else { Err(x.into()) })
+  }
+
+  #[test]
+  fn test_tense_codes() {
+    let m = morphology();
+    let tense = m.tense("pf").unwrap();
+    assert_eq!(tense.get("count"), Some("plural".into()));
+    assert_eq!(tense.get("gender"), Some("female".into()));
+    assert_eq!(m.tense("s").unwrap().get("gender"), None);
+    assert!(m.tense("x.").is_err());
+    assert!(m.tense("sm.").is_err());
+  }
+
+  #[test]
+  fn test_paradigms() {
+    let m = morphology();
+    let paradigm: &Paradigm = &[("o", "o", "sm"), ("os", "os", "pm"), ("a", "a", "sf")];
+    let (hstem, lstem) = stem("gato", "gato", ("o", "o")).unwrap();
+    let entries = m.rollup(&m.inflect(paradigm, hstem, lstem).unwrap(), "noun", "cat").unwrap();
+    let latins: Vec<_> = entries.iter().map(|x| x.latin.as_str()).collect();
+    assert_eq!(latins, ["gato", "gatos", "gata"]);
+    assert!(entries.iter().all(|x| x.head == "noun-gato" && x.value == "cat"));
+    assert_eq!(entries[1].scores["gato"], -1.0);
+    assert_eq!(stem("gato", "gato", ("a", "a")), None);
+    assert!(m.rollup(&m.inflect(paradigm, "\u{e9}", "e").unwrap(), "noun", "x").is_err());
+  }
+}
@@ -1,15 +1,37 @@
-use super::base::Result;
+use super::base::{Error, Result};
+use std::borrow::Cow;
+use std::path::PathBuf;
 
 #[macro_export]
 macro_rules! for_each_row {
   ($a:expr, [$($b:ident),+], $c:block) => {
     let helper = super::super::lib::table::parse_rows;
-    for ($($b),*) in helper(&[$(stringify!($b)),*], $a)?.into_iter().map(|x| {
-      match &x.as_slice() { &[$($b),*] => ($(*$b),*), _ => panic!() }
+    let rows = helper(&[$(stringify!($b)),*], $a)?;
+    for ($($b),*) in rows.iter().map(|x| {
+      match x.as_slice() { [$($b),*] => ($(&**$b),*), _ => panic!() }
     }) $c
   }
 }
 
+// Like for_each_row, but each column has a type, which must implement FromCell. We
+// convert every cell before running the block and report all invalid cells together.
+
+#[macro_export]
+macro_rules! for_each_typed_row {
+  ($a:expr, [$($b:ident: $t:ty),+], $c:block) => {
+    let helper = $crate::lib::table::parse_rows;
+    let (rows, mut errors) = (helper(&[$(stringify!($b)),*], $a)?, vec![]);
+    let typed: Vec<_> = rows.iter().enumerate().map(|(i, x)| match x.as_slice() {
+      [$($b),*] => ($($crate::lib::table::cell::<$t>((i, stringify!($b)), $b, &mut errors)),*),
+      _ => panic!(),
+    }).collect();
+    if !errors.is_empty() {
+      Err($crate::lib::base::Error::Table(errors.join("\n")))?
+    }
+    for ($($b),*) in typed.into_iter().map(|($($b),*)| ($($b.unwrap()),*)) $c
+  }
+}
+
 #[macro_export]
 macro_rules! for_each_table {
   ($a:expr, [$($b:ident),+], $c:block) => {
@@ -20,32 +42,210 @@ macro_rules! for_each_table {
   }
 }
 
-pub fn parse_rows<'a>(columns: &[&str], table: &'a str) -> Result<Vec<Vec<&'a str>>> {
+// A backslash escapes the next character in a cell: "\|" is a literal pipe, and "\^" and
+// "\<" are literal carets and angle brackets instead of shorthands. A row starting with
+// ">" continues the row above it. Its non-empty cells are added to that row's cells as
+// new lines, so that a long gloss can span several lines:
+//
+//   meaning         | word
+//   ----------------|-----------
+//   to eat, to have | KAnA/khana
+//   > (of meals)    |
+
+pub fn parse_rows<'a>(columns: &[&str], table: &'a str) -> Result<Vec<Vec<Cow<'a, str>>>> {
   let lines: Vec<_> = table.split('\n').map(|x| x.trim()).collect();
   let valid: Vec<_> = lines.into_iter().filter(|x| !(x.is_empty() || x.starts_with('#'))).collect();
   if valid.len() < 3 {
-    Err(format!("Invalid table. Tables must have at least one row:\n{}", table))?
+    fail(format!("Invalid table. Tables must have at least one row:\n{}", table))?
   }
-  let (mut prev, mut result) = (vec![], vec![]);
-  let actual: Vec<_> = valid[0].split('|').map(|x| x.trim()).collect();
-  if actual != columns {
-    let (actual, columns) = (actual.join(", "), columns.join(", "));
-    Err(format!("Invalid table. Got columns: {}; expected: {}:\n{}", actual, columns, table))?
+  let header = cells(valid[0]);
+  let mut rows: Vec<Vec<Cow<'a, str>>> = vec![];
+  for line in valid[2..].iter() {
+    let next = cells(line);
+    if !next[0].starts_with('>') {
+      rows.push(next.into_iter().map(Cow::Borrowed).collect());
+      continue;
+    }
+    let (i, n) = (rows.len(), next.len());
+    let error = || Error::Table(format!("Invalid row 1: nothing to continue\n{}", table));
+    let prev = rows.last_mut().ok_or_else(error)?;
+    if n != prev.len() {
+      fail(format!("Invalid row {}: got {} fields; expected: {}\n{}", i, n, prev.len(), table))?;
+    }
+    for (j, cell) in next.into_iter().enumerate() {
+      let cell = if j == 0 { cell[1..].trim() } else { cell };
+      if !cell.is_empty() {
+        let text = prev[j].to_mut();
+        text.push('\n');
+        text.push_str(cell);
+      }
+    }
   }
-  let n = columns.len();
-  for (i, row) in valid[2..].iter().enumerate() {
-    let mut next: Vec<_> = row.split('|').map(|x| x.trim()).collect();
+  let mut result = validate(columns, header, rows, true, table)?;
+  for (i, row) in result.iter_mut().enumerate() {
+    for (j, cell) in row.iter_mut().enumerate().filter(|x| x.1.contains('\\')) {
+      let error = || format!("Invalid cell row {}, column {}: bad escape\n{}", i + 1, j + 1, table);
+      *cell = Cow::Owned(unescape(cell).ok_or_else(|| Error::Table(error()))?);
+    }
+  }
+  Ok(result)
+}
+
+// Typed cells. Implement FromCell for a type to use it as a column in for_each_typed_row.
+// For a column with a fixed set of values, such as an enum, use choice.
+
+pub trait FromCell<'a>: Sized {
+  fn from_cell(cell: &'a str) -> Result<Self>;
+}
+
+impl<'a> FromCell<'a> for &'a str {
+  fn from_cell(cell: &'a str) -> Result<Self> {
+    Ok(cell)
+  }
+}
+
+impl<'a> FromCell<'a> for String {
+  fn from_cell(cell: &'a str) -> Result<Self> {
+    Ok(cell.to_string())
+  }
+}
+
+impl<'a> FromCell<'a> for usize {
+  fn from_cell(cell: &'a str) -> Result<Self> {
+    Ok(cell.parse::<usize>().map_err(|_| format!("expected an integer; got: {}", cell))?)
+  }
+}
+
+impl<'a> FromCell<'a> for f32 {
+  fn from_cell(cell: &'a str) -> Result<Self> {
+    Ok(cell.parse::<f32>().map_err(|_| format!("expected a number; got: {}", cell))?)
+  }
+}
+
+impl<'a> FromCell<'a> for bool {
+  fn from_cell(cell: &'a str) -> Result<Self> {
+    choice(cell, &[("n", false), ("y", true)])
+  }
+}
+
+pub fn choice<T: Clone>(cell: &str, options: &[(&str, T)]) -> Result<T> {
+  if let Some(x) = options.iter().find(|x| x.0 == cell) {
+    return Ok(x.1.clone());
+  }
+  let names: Vec<_> = options.iter().map(|x| x.0).collect();
+  Err(format!("expected one of: {}; got: {}", names.join(", "), cell))?
+}
+
+// Used by for_each_typed_row to convert one cell, recording an error if it is invalid.
+// The location is a row index and a column name.
+
+pub fn cell<'a, T: FromCell<'a>>(
+  location: (usize, &str),
+  text: &'a str,
+  errors: &mut Vec<String>,
+) -> Option<T> {
+  let (i, column) = location;
+  let error = |x: Error| format!("Invalid cell row {}, column {}: {:?}", i + 1, column, x);
+  T::from_cell(text).map_err(|x| errors.push(error(x))).ok()
+}
+
+// Errors in a table's structure, as opposed to in one of its cells, are Table errors.
+
+fn fail<T>(message: String) -> Result<T> {
+  Err(Error::Table(message))
+}
+
+// Split a row of a pipe table into trimmed cells, skipping escaped pipes. Cells are
+// still escaped; we unescape them after expanding shorthands.
+
+fn cells(line: &str) -> Vec<&str> {
+  let (mut result, mut start, mut escaped) = (vec![], 0, false);
+  for (i, ch) in line.char_indices() {
+    if ch == '|' && !escaped {
+      result.push(line[start..i].trim());
+      start = i + 1;
+    }
+    escaped = ch == '\\' && !escaped;
+  }
+  result.push(line[start..].trim());
+  result
+}
+
+fn unescape(cell: &str) -> Option<String> {
+  let mut result = String::with_capacity(cell.len());
+  let mut chars = cell.chars();
+  while let Some(ch) = chars.next() {
+    if ch != '\\' {
+      result.push(ch);
+      continue;
+    }
+    match chars.next() {
+      Some(x) if "\\|^<>#".contains(x) => result.push(x),
+      _ => return None,
+    }
+  }
+  Some(result)
+}
+
+fn escape(cell: &str) -> String {
+  let mut result = String::with_capacity(cell.len());
+  for ch in cell.chars() {
+    if ch == '\\' || ch == '|' || ((ch == '>' || ch == '#') && result.is_empty()) {
+      result.push('\\');
+    }
+    result.push(ch);
+  }
+  result
+}
+
+// Parse a table exported from a spreadsheet: a header row of column names, then rows of
+// cells split by the delimiter, which is usually ',' or '\t'. Cells may be quoted, as
+// in "to go, to walk", with "" for a literal quote. Quoted cells may span lines.
+//
+// The ^ and < shorthands are only expanded if requested, since spreadsheets can fill
+// cells themselves. Columns and row lengths are checked just as for pipe tables.
+
+pub fn parse_csv(
+  columns: &[&str],
+  text: &str,
+  delimiter: char,
+  shorthands: bool,
+) -> Result<Vec<Vec<String>>> {
+  let mut records = records(text, delimiter)?;
+  if records.len() < 2 {
+    fail(format!("Invalid table. Tables must have at least one row:\n{}", text))?
+  }
+  let rows = records.split_off(1);
+  let header = records.pop().unwrap();
+  validate(columns, header.iter().map(|x| x.as_str()).collect(), rows, shorthands, text)
+}
+
+fn validate<T: AsRef<str> + Clone>(
+  columns: &[&str],
+  header: Vec<&str>,
+  rows: Vec<Vec<T>>,
+  shorthands: bool,
+  table: &str,
+) -> Result<Vec<Vec<T>>> {
+  if header != columns {
+    let (actual, columns) = (header.join(", "), columns.join(", "));
+    fail(format!("Invalid table. Got columns: {}; expected: {}:\n{}", actual, columns, table))?
+  }
+  let (n, mut prev, mut result): (_, Vec<T>, _) = (columns.len(), vec![], vec![]);
+  for (i, mut next) in rows.into_iter().enumerate() {
     if next.len() != n {
-      Err(format!("Invalid row {}: got {} fields; expected: {}\n{}", i + 1, next.len(), n, table))?;
+      let m = next.len();
+      fail(format!("Invalid row {}: got {} fields; expected: {}\n{}", i + 1, m, n, table))?;
     }
     for j in 0..next.len() {
-      let mut cell = Some(next[j]);
-      if next[j] == "<" {
-        cell = if j == 0 { None } else { Some(next[j - 1]) };
-      } else if next[j] == "^" {
-        cell = if i == 0 { None } else { Some(prev[j]) };
+      let mut cell = Some(next[j].clone());
+      if shorthands && next[j].as_ref() == "<" {
+        cell = if j == 0 { None } else { Some(next[j - 1].clone()) };
+      } else if shorthands && next[j].as_ref() == "^" {
+        cell = if i == 0 { None } else { Some(prev[j].clone()) };
       }
-      next[j] = cell.ok_or(format!("Invalid cell row {}, column {}:\n{}", i + 1, j + 1, table))?;
+      let error = || format!("Invalid cell row {}, column {}:\n{}", i + 1, j + 1, table);
+      next[j] = cell.ok_or_else(|| Error::Table(error()))?;
     }
     result.push(next.clone());
     prev = next;
@@ -53,31 +253,210 @@ pub fn parse_rows<'a>(columns: &[&str], table: &'a str) -> Result<Vec<Vec<&'a st
   Ok(result)
 }
 
+// Split delimited text into records of cells. We skip blank lines and lines starting
+// with '#', and we trim whitespace around unquoted cells.
+
+fn records(text: &str, delimiter: char) -> Result<Vec<Vec<String>>> {
+  let (mut result, mut record, mut cell) = (vec![], vec![], String::new());
+  let (mut quoted, mut line) = (false, 1);
+  let mut chars = text.chars().peekable();
+  while let Some(ch) = chars.next() {
+    if ch == '\n' {
+      line += 1;
+    }
+    if quoted {
+      if ch == '"' && chars.peek() == Some(&'"') {
+        chars.next();
+        cell.push('"');
+      } else if ch == '"' {
+        quoted = false;
+      } else {
+        cell.push(ch);
+      }
+    } else if ch == '"' && cell.trim().is_empty() {
+      quoted = true;
+      cell.clear();
+    } else if ch == delimiter {
+      record.push(std::mem::take(&mut cell).trim().to_string());
+    } else if ch == '\n' {
+      record.push(std::mem::take(&mut cell).trim().to_string());
+      let record = std::mem::take(&mut record);
+      let blank = record.len() == 1 && record[0].is_empty();
+      if !(blank || record[0].starts_with('#')) {
+        result.push(record);
+      }
+    } else {
+      cell.push(ch);
+    }
+  }
+  if quoted {
+    fail(format!("Invalid table. Unterminated quote at line {}:\n{}", line, text))?
+  }
+  record.push(cell.trim().to_string());
+  if !(record.len() == 1 && record[0].is_empty() || record[0].starts_with('#')) {
+    result.push(record);
+  }
+  Ok(result)
+}
+
+// Render rows as an aligned pipe table that parse_rows reads back, in the style of our
+// vocabulary: the first column is right-aligned and the others are left-aligned. Cells
+// that span lines become continuation rows. If compress is set, we use ^ for a cell that
+// repeats the one above it and < for a cell that repeats the one to its left.
+
+pub fn format_rows<T: AsRef<str>>(
+  columns: &[&str],
+  rows: &[Vec<T>],
+  compress: bool,
+) -> Result<String> {
+  let n = columns.len();
+  let mut lines = vec![columns.iter().map(|x| vec![x.to_string()]).collect::<Vec<_>>()];
+  for (i, row) in rows.iter().enumerate() {
+    if row.len() != n {
+      fail(format!("Invalid row {}: got {} fields; expected: {}", i + 1, row.len(), n))?;
+    }
+    let line = row.iter().enumerate().map(|(j, x)| {
+      let cell = x.as_ref();
+      if cell.contains('\n') {
+        let mut fragments: Vec<_> = cell.split('\n').map(escape).collect();
+        fragments.iter_mut().skip(1).filter(|_| j == 0).for_each(|x| x.insert_str(0, "> "));
+        return fragments;
+      }
+      let above = i > 0 && rows[i - 1][j].as_ref() == cell;
+      let left = j > 0 && row[j - 1].as_ref() == cell;
+      let text = match cell {
+        _ if compress && above => "^".to_string(),
+        _ if compress && left => "<".to_string(),
+        "^" | "<" => format!("\\{}", cell),
+        _ => escape(cell),
+      };
+      vec![text]
+    });
+    lines.push(line.collect());
+  }
+  let width = |j: usize| {
+    let cells = lines.iter().flat_map(|x| x[j].iter());
+    cells.map(|x| x.chars().count()).max().unwrap_or(0)
+  };
+  let widths: Vec<_> = (0..n).map(width).collect();
+  let render = |cells: Vec<&str>| {
+    let cells = cells.iter().enumerate().map(|(j, x)| match j {
+      0 => format!("{:>1$} ", x, widths[j]),
+      _ if j + 1 == n => format!(" {}", x),
+      _ => format!(" {:<1$} ", x, widths[j]),
+    });
+    cells.collect::<Vec<_>>().join("|").trim_end().to_string()
+  };
+  let mut result = vec![];
+  for (i, line) in lines.iter().enumerate() {
+    let height = line.iter().map(|x| x.len()).max().unwrap_or(1);
+    for k in 0..height {
+      let first = if k == 0 { "" } else { ">" };
+      let cells = line.iter().enumerate().map(|(j, x)| {
+        x.get(k).map(|y| y.as_str()).unwrap_or(if j == 0 { first } else { "" })
+      });
+      result.push(render(cells.collect()));
+    }
+    if i == 0 {
+      let dashes = |j: usize| "-".repeat(widths[j] + if j == 0 || j + 1 == n { 1 } else { 2 });
+      result.push((0..n).map(dashes).collect::<Vec<_>>().join("|"));
+    }
+  }
+  Ok(result.join("\n") + "\n")
+}
+
+// Tables may appear in any order, so that vocabulary can be split across several files.
+// We return them in the order given by names.
+
 pub fn parse_tables<'a>(names: &[&str], text: &'a str) -> Result<Vec<&'a str>> {
   let blocks: Vec<_> = text.split('$').map(|x| x.trim()).collect();
   if blocks.is_empty() || !blocks[0].is_empty() {
-    Err(format!("Invalid table list: no $ found!\n{}", text))?
+    fail(format!("Invalid table list: no $ found!\n{}", text))?
   }
   let (mut actual, mut result) = (vec![], vec![]);
   for block in blocks.iter().skip(1) {
     let first = block.split('\n').next().unwrap().trim();
-    let index = first.find(':').ok_or_else(|| format!("Block must start with NAME: {}", first))?;
+    let error = || Error::Table(format!("Block must start with NAME: {}", first));
+    let index = first.find(':').ok_or_else(error)?;
     actual.push(block[..index].to_lowercase());
     result.push(&block[index + 1..]);
   }
-  if actual != names {
+  let (mut sorted_actual, mut sorted_names) = (actual.clone(), names.to_vec());
+  sorted_actual.sort();
+  sorted_names.sort();
+  if sorted_actual != sorted_names {
     let (actual, names) = (actual.join(", "), names.join(", "));
-    Err(format!("Invalid tables. Got columns: {}; expected: {}", actual, names))?
+    fail(format!("Invalid tables. Got columns: {}; expected: {}", actual, names))?
+  }
+  Ok(names.iter().map(|x| result[actual.iter().position(|y| y == x).unwrap()]).collect())
+}
+
+// Read tables from files. A path may be a file or a directory, whose files we read in
+// sorted order. CSV and TSV files hold a single table, named after the file: the rows
+// of "verbs.tsv" become "$VERBS". Other files hold "$NAME:" blocks in the format above.
+
+pub fn read_tables(paths: &[&str]) -> Result<String> {
+  let error = |x: &dyn std::fmt::Display, y: std::io::Error| {
+    Error::Table(format!("Failed to read {}: {}", x, y))
+  };
+  let mut files = vec![];
+  for path in paths {
+    let metadata = std::fs::metadata(path).map_err(|x| error(path, x))?;
+    if metadata.is_dir() {
+      let entries = std::fs::read_dir(path).map_err(|x| error(path, x))?;
+      let entries = entries.map(|x| x.map(|y| y.path())).collect::<std::io::Result<Vec<_>>>();
+      let mut entries = entries.map_err(|x| error(path, x))?;
+      entries.sort();
+      files.extend(entries.into_iter().filter(|x| x.is_file()));
+    } else {
+      files.push(PathBuf::from(path));
+    }
+  }
+  let mut result = String::new();
+  for file in files {
+    let text = std::fs::read_to_string(&file).map_err(|x| error(&file.display(), x))?;
+    let delimiter = match file.extension().and_then(|x| x.to_str()) {
+      Some("csv") => Some(','),
+      Some("tsv") => Some('\t'),
+      _ => None,
+    };
+    if let Some(delimiter) = delimiter {
+      let name = file.file_stem().and_then(|x| x.to_str()).unwrap_or_default().to_uppercase();
+      result.push_str(&format!("${}:\n\n{}\n", name, delimited(&text, delimiter)?));
+    } else {
+      result.push_str(&format!("{}\n", text));
+    }
   }
   Ok(result)
 }
 
+// Convert delimited text to a pipe table. Cells that span lines become continuations.
+
+fn delimited(text: &str, delimiter: char) -> Result<String> {
+  let mut result = vec![];
+  for (i, cells) in records(text, delimiter)?.iter().enumerate() {
+    let lines: Vec<Vec<_>> = cells.iter().map(|x| x.split('\n').map(escape).collect()).collect();
+    let height = lines.iter().map(|x| x.len()).max().unwrap_or(1);
+    for k in 0..height {
+      let row: Vec<_> = lines.iter().map(|x| x.get(k).map(|y| y.as_str()).unwrap_or("")).collect();
+      let prefix = if k == 0 { "" } else { "> " };
+      result.push(format!("{}{}", prefix, row.join(" | ")));
+    }
+    if i == 0 {
+      result.push(cells.iter().map(|_| "--").collect::<Vec<_>>().join("|"));
+    }
+  }
+  Ok(result.join("\n"))
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
 
   fn test_error<T: std::fmt::Debug>(result: Result<T>, prefix: &str) {
-    let error = format!("{:?}", result.unwrap_err());
+    let error = result.unwrap_err();
+    assert!(matches!(error, Error::Table(_)), "Not a table error: {:?}", error);
+    let error = format!("{:?}", error);
     if !error.starts_with(prefix) {
       let error = error.split('\n').next().unwrap_or("");
       panic!("Error does not match prefix:\nexpected: {:?}\n  actual: {:?}", prefix, error);
@@ -141,6 +520,111 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_tables_in_any_order() {
+    let text = "$B:\nb\n$A:\na\n";
+    assert_eq!(parse_tables(&["a", "b"], text).unwrap(), ["\na", "\nb"]);
+    let error = "Invalid tables. Got columns: b, a; expected: a, c";
+    test_error(parse_tables(&["a", "c"], text), error);
+  }
+
+  #[test]
+  fn test_escapes_and_continuations() {
+    let table = r"
+      key    | value
+      -------|------
+      a\|b   | \^
+      > more | c
+      >      | d\\
+      ^      | \<
+    ";
+    let rows = parse_rows(&["key", "value"], table).unwrap();
+    assert_eq!(rows, [["a|b\nmore", "^\nc\nd\\"], ["a|b\nmore", "<"]]);
+    let table = "key | value\n--|--\n> k1 | v1";
+    test_error(parse_rows(&["key", "value"], table), "Invalid row 1: nothing to continue");
+    let table = "key | value\n--|--\nk1 | v1\n> k2";
+    test_error(parse_rows(&["key", "value"], table), "Invalid row 1: got 1 fields; expected: 2");
+    let table = "key | value\n--|--\nk1 | v1\nk2 | \\x";
+    test_error(parse_rows(&["key", "value"], table), "Invalid cell row 2, column 2: bad escape");
+  }
+
+  #[test]
+  fn test_typed_rows() {
+    fn helper(table: &str) -> Result<Vec<(usize, f32, bool, String)>> {
+      let mut result = vec![];
+      for_each_typed_row!(table, [count: usize, score: f32, flag: bool, name: &str], {
+        result.push((count, score, flag, name.to_string()));
+      });
+      Ok(result)
+    }
+    let table = "count | score | flag | name\n--|--|--|--\n2 | -0.5 | y | a\n^ | 1 | n | <";
+    let expected = [(2, -0.5, true, "a".to_string()), (2, 1.0, false, "n".to_string())];
+    assert_eq!(helper(table).unwrap(), expected);
+    let table = "count | score | flag | name\n--|--|--|--\nx | 0 | y | a\n1 | 0 | maybe | b";
+    assert_eq!(
+      helper(table).unwrap_err(),
+      Error::Table(
+        "Invalid cell row 1, column count: expected an integer; got: x\n\
+         Invalid cell row 2, column flag: expected one of: n, y; got: maybe"
+          .into()
+      ),
+    );
+  }
+
+  #[test]
+  fn test_format_rows() {
+    let rows = vec![
+      vec!["food", "type.apple", "seb/seb"],
+      vec!["food", "type.bread", "<"],
+      vec!["person", "a|b", "a|b"],
+      vec!["#x", ">y", "two\nlines"],
+    ];
+    let table = format_rows(&["category", "meaning", "word"], &rows, true).unwrap();
+    let expected = [
+      "category | meaning    | word",
+      "---------|------------|--------",
+      "    food | type.apple | seb/seb",
+      "       ^ | type.bread | \\<",
+      "  person | a\\|b       | <",
+      "     \\#x | \\>y        | two",
+      "       > |            | lines",
+    ];
+    assert_eq!(table, format!("{}\n", expected.join("\n")));
+    assert_eq!(parse_rows(&["category", "meaning", "word"], &table).unwrap(), rows);
+    let table = format_rows(&["category", "meaning", "word"], &rows, false).unwrap();
+    assert_eq!(parse_rows(&["category", "meaning", "word"], &table).unwrap(), rows);
+    test_error(format_rows(&["a", "b"], &[vec!["x"]], false), "Invalid row 1: got 1 fields");
+  }
+
+  #[test]
+  fn test_csv_table() {
+    let text = "key,value\n\n# A comment.\nk1, \"v1, or \"\"v2\"\"\"\n^,\"multiple\nlines\"\n";
+    let rows = parse_csv(&["key", "value"], text, ',', true).unwrap();
+    assert_eq!(rows, [["k1", "v1, or \"v2\""], ["k1", "multiple\nlines"]]);
+    let rows = parse_csv(&["key", "value"], "key\tvalue\n^\t<", '\t', false).unwrap();
+    assert_eq!(rows, [["^", "<"]]);
+    test_error(parse_csv(&["key", "value"], "key,value\n^,<", ',', true), "Invalid cell row 1");
+    test_error(parse_csv(&["key"], "key,value\nk1,v1", ',', true), "Invalid table. Got columns:");
+    test_error(parse_csv(&["key", "value"], "key,value\nk1", ',', true), "Invalid row 1: got 1");
+    test_error(parse_csv(&["key"], "key\n\"k1", ',', true), "Invalid table. Unterminated quote");
+  }
+
+  #[test]
+  fn test_read_tables() {
+    let dir = std::env::temp_dir().join(format!("fantasy-tables-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.csv"), "key,value\nk1,v1\n# comment\n^,\"v2|\nv3\"\n").unwrap();
+    std::fs::write(dir.join("b.tsv"), "key\tvalue\nk2\tv3\n").unwrap();
+    std::fs::write(dir.join("c.txt"), "$C:\n\n  key | value\n  --|--\n  k3 | v4\n").unwrap();
+    let text = read_tables(&[dir.to_str().unwrap()]).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let tables = parse_tables(&["a", "b", "c"], &text).unwrap();
+    let rows: Vec<_> = tables.iter().map(|x| parse_rows(&["key", "value"], x).unwrap()).collect();
+    let expected = [vec![["k1", "v1"], ["k1", "v2|\nv3"]], vec![["k2", "v3"]], vec![["k3", "v4"]]];
+    assert_eq!(rows, expected);
+    test_error(read_tables(&["missing/path"]), "Failed to read missing/path:");
+  }
+
   #[test]
   fn test_invalid_redirect() {
     let table = "
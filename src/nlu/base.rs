@@ -1,6 +1,9 @@
 use super::super::lib::base::HashMap;
-pub use super::tense::Tense;
+pub use super::tense::{Tense, TenseSchema};
+use smallvec::SmallVec;
+use std::fmt::Display;
 use std::rc::Rc;
+use std::sync::{Arc, OnceLock, RwLock};
 
 // Parsing, generation, and correction all return derivations. These methods
 // may fail, and may take additional arguments, but the overall structure is:
@@ -13,13 +16,19 @@ use std::rc::Rc;
 // Derivations expose a top-level value of type T that represents the overall
 // semantics of an utterance. Generation takes a value of type S as input.
 
+// Most rules have at most four terms, so we store terms and children inline up to that.
+
+pub type Children<'a, S, T> = SmallVec<[Child<'a, S, T>; 4]>;
+
+pub type Terms = SmallVec<[Term; 4]>;
+
 pub enum Child<'a, S, T> {
-  Leaf(Rc<Match<T>>),
+  Leaf(Arc<Match<T>>),
   Node(Rc<Derivation<'a, S, T>>),
 }
 
 pub struct Derivation<'a, S, T> {
-  pub children: Vec<Child<'a, S, T>>,
+  pub children: Children<'a, S, T>,
   pub rule: &'a Rule<S, T>,
   pub value: T,
 }
@@ -28,38 +37,53 @@ pub struct Derivation<'a, S, T> {
 // with leaf semantics. Call unlex to generate a token for some leaf semantics.
 //
 // When we lex a token, we store it in a matches dictionary keyed by headword
-// (e.g. "to be" for "am" in English). The match value stores information about
-// the actual lexed word, like its tense. A tense has grammatical categories
-// as keys, like "count", "gender", and "person". For example, "am" could have
-// {count: "singular", person: "1st"} as one of its tense.
+// (e.g. "to be" for "am" in English). Headwords are terminals, interned as ids so
+// that the parser can find a token's matches without hashing strings. The match
+// value stores information about the actual lexed word, like its tense. A tense
+// has grammatical categories as keys, like "count", "gender", and "person". For
+// example, "am" could have {count: "singular", person: "1st"} as one of its tense.
 //
 // We allow a token to be associated with multiple tenses because of words like
 // "hai" in Hindi, the copula for both the 2nd person singular intimate tense
 // and the 3d person plural tense.
 
-pub type Entry<T> = (f32, Rc<Match<T>>);
+pub type Entry<T> = (f32, Arc<Match<T>>);
 
-pub trait Lexer<S, T> {
-  fn fix(&self, _: &Match<T>, _: &Tense) -> Vec<Rc<Match<T>>>;
+pub trait Lexer<S, T>: Send + Sync {
+  fn fix(&self, _: &Match<T>, _: &Tense) -> Vec<Arc<Match<T>>>;
   fn lex<'a: 'b, 'b>(&'a self, _: &'b str) -> Vec<Token<'b, T>>;
-  fn unlex(&self, _: &str, _: &S) -> Vec<Rc<Match<T>>>;
+  fn unlex(&self, _: &str, _: &S) -> Vec<Arc<Match<T>>>;
+
+  // Lists every entry that the lexer can produce, for exporters that need the whole
+  // vocabulary up front. Lexers for open classes, like numbers, may leave them out.
+  fn vocabulary(&self) -> Vec<(Terminal, Entry<T>)> {
+    vec![]
+  }
+
+  // Lists every terminal that the lexer can match. Lexers that leave open classes out
+  // of their vocabulary should override this method to add them.
+  fn terminals(&self) -> Vec<Terminal> {
+    self.vocabulary().into_iter().map(|x| x.0).collect()
+  }
 }
 
 pub struct Match<T> {
   pub tenses: Vec<Tense>,
-  pub texts: HashMap<&'static str, String>,
+  pub texts: HashMap<&'static str, Arc<str>>,
   pub value: T,
 }
 
 pub struct Token<'a, T> {
-  pub matches: HashMap<&'a str, Entry<T>>,
+  pub matches: HashMap<Terminal, Entry<T>>,
   pub text: &'a str,
 }
 
 // The core grammar type. A grammar has a lexer along with a list of rules.
 // Each term on a rule's right-hand-side is either a symbol or a token match.
 // Rules also have "merge" and "split" callbacks for handling semantics during
-// parsing and generation, respectively.
+// parsing and generation, respectively. The split callback passes candidates to
+// a visitor one at a time and stops as soon as the visitor returns false, so that
+// generation can stop without enumerating every split.
 //
 // Each rule also has correction data in its "precedence" and "tense" fields.
 // The tense is a base tense for that rule, implied solely by using the rule.
@@ -74,9 +98,16 @@ pub struct Token<'a, T> {
 //
 // Terms that are missing from precedence are still corrected, but their tense
 // information is not propagated to other terms. For example, a sentence's
-// subject and verb must agree, but its object is only checked internally.
+// subject and verb must agree, but its object is only checked internally. In
+// dative-subject constructions like "mujhe roti chahie" and ergative ones like
+// "maine roti khayi", the roles swap: the verb agrees with its object, and the
+// subject is only checked internally.
+//
+// A grammar may declare the features (grammatical categories and their values)
+// that take part in agreement, as a TenseSchema. If it declares none, all do.
 
 pub struct Grammar<S, T> {
+  pub features: TenseSchema,
   pub lexer: Box<dyn Lexer<S, T>>,
   pub names: Vec<String>,
   pub rules: Vec<Rule<S, T>>,
@@ -85,11 +116,28 @@ pub struct Grammar<S, T> {
 
 pub struct Rule<S, T> {
   pub lhs: usize,
-  pub rhs: Vec<Term>,
-  pub merge: Semantics<dyn Fn(&[T]) -> T>,
-  pub split: Semantics<dyn Fn(&S) -> Vec<Vec<S>>>,
+  pub rhs: Terms,
+  pub merge: Semantics<dyn Fn(&[T]) -> T + Send + Sync>,
+  pub split: Semantics<dyn Fn(&S, &mut dyn FnMut(Vec<S>) -> bool) + Send + Sync>,
   pub precedence: Vec<usize>,
   pub tense: Tense,
+  pub metadata: Metadata,
+}
+
+// Rules compiled from a grammar file keep the source-level data that the fields
+// above can't represent, so that we can render them back to grammar text. Each
+// item is an RHS term's optional template index and its mark ('*', '^', '!', or ' ').
+// The line is the rule's 1-indexed line in the grammar file, used in warnings.
+// The id is the rule's index in its grammar's rules, used to refer to the rule
+// from outside the grammar. Rules built by hand leave this data empty.
+
+#[derive(Clone, Default)]
+pub struct Metadata {
+  pub id: Option<usize>,
+  pub items: Vec<(Option<usize>, char)>,
+  pub line: Option<usize>,
+  pub template: Option<String>,
+  pub tense: Vec<(String, String)>,
 }
 
 pub struct Semantics<F: ?Sized> {
@@ -103,24 +151,161 @@ pub enum Term {
   Terminal(String),
 }
 
+// An interned terminal name. Ids are shared by every grammar and lexer in the process,
+// so a lexer can key its matches by id without knowing which grammar will parse them.
+// Names are never freed, but there are only as many as there are distinct headwords.
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Terminal(u32);
+
+#[derive(Default)]
+struct Interner {
+  ids: HashMap<&'static str, u32>,
+  names: Vec<&'static str>,
+}
+
+fn interner() -> &'static RwLock<Interner> {
+  static INTERNER: OnceLock<RwLock<Interner>> = OnceLock::new();
+  INTERNER.get_or_init(RwLock::default)
+}
+
+impl Terminal {
+  pub fn new(name: &str) -> Self {
+    if let Some(x) = interner().read().unwrap().ids.get(name) {
+      return Terminal(*x);
+    }
+    let mut interner = interner().write().unwrap();
+    if let Some(x) = interner.ids.get(name) {
+      return Terminal(*x);
+    }
+    let (id, name) = (interner.names.len() as u32, Box::leak(name.to_string().into_boxed_str()));
+    interner.ids.insert(name, id);
+    interner.names.push(name);
+    Terminal(id)
+  }
+
+  pub fn name(self) -> &'static str {
+    interner().read().unwrap().names[self.0 as usize]
+  }
+}
+
+// Traversals over a derivation visit its nodes and leaves in order, each node before
+// its children. Each item comes with its depth, which is 0 for the root, and with its
+// index among its parent's children. Implement Visitor to handle items with walk, or
+// use the iterators directly.
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Context {
+  pub depth: usize,
+  pub index: usize,
+}
+
+pub enum Visit<'b, 'a: 'b, S: 'b, T: 'b> {
+  Leaf(&'b Arc<Match<T>>),
+  Node(&'b Derivation<'a, S, T>),
+}
+
+pub trait Visitor<'a, S, T> {
+  fn leaf(&mut self, _: &Arc<Match<T>>, _: Context) {}
+  fn node(&mut self, _: &Derivation<'a, S, T>, _: Context) {}
+}
+
+pub struct Iter<'b, 'a: 'b, S: 'b, T: 'b> {
+  stack: Vec<(Visit<'b, 'a, S, T>, Context)>,
+}
+
+impl<'b, 'a, S, T> Iterator for Iter<'b, 'a, S, T> {
+  type Item = (Visit<'b, 'a, S, T>, Context);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let (visit, context) = self.stack.pop()?;
+    if let Visit::Node(x) = visit {
+      let depth = context.depth + 1;
+      for (index, child) in x.children.iter().enumerate().rev() {
+        let item = match child {
+          Child::Leaf(y) => Visit::Leaf(y),
+          Child::Node(y) => Visit::Node(&**y),
+        };
+        self.stack.push((item, Context { depth, index }));
+      }
+    }
+    Some((visit, context))
+  }
+}
+
+// A zipper for editing a derivation in place. It focuses on one node, starting at the
+// root, and moves down to a node's children and back up. Replacing a child of the focus
+// recomputes the focus's value, and moving up recomputes each ancestor's value, so the
+// finished tree's values reflect the edit.
+
+pub struct Zipper<'a, S, T> {
+  focus: Derivation<'a, S, T>,
+  path: Vec<(Derivation<'a, S, T>, usize)>,
+}
+
+impl<'a, S, T: Clone> Zipper<'a, S, T> {
+  pub fn new(tree: Derivation<'a, S, T>) -> Self {
+    Self { focus: tree, path: vec![] }
+  }
+
+  pub fn focus(&self) -> &Derivation<'a, S, T> {
+    &self.focus
+  }
+
+  // Moves to the focus's i-th child. Returns false, without moving, if it's a leaf.
+
+  pub fn down(&mut self, i: usize) -> bool {
+    let child = match &self.focus.children[i] {
+      Child::Leaf(_) => return false,
+      Child::Node(x) => x,
+    };
+    let (children, rule, value) = (child.children.clone(), child.rule, child.value.clone());
+    let child = Derivation { children, rule, value };
+    let parent = std::mem::replace(&mut self.focus, child);
+    self.path.push((parent, i));
+    true
+  }
+
+  // Moves to the focus's parent. Returns false if the focus is the root.
+
+  pub fn up(&mut self) -> bool {
+    let (parent, i) = match self.path.pop() {
+      Some(x) => x,
+      None => return false,
+    };
+    let child = std::mem::replace(&mut self.focus, parent);
+    self.focus = self.focus.with_child(i, Child::Node(Rc::new(child)));
+    true
+  }
+
+  pub fn replace(&mut self, i: usize, child: Child<'a, S, T>) {
+    self.focus = self.focus.with_child(i, child);
+  }
+
+  pub fn finish(mut self) -> Derivation<'a, S, T> {
+    while self.up() {}
+    self.focus
+  }
+}
+
 // Some utilities implemented on the types above. We avoid deriving them
 // because we must take care to avoid deep copies of grammar structures.
 
 impl<'a, S, T> Clone for Child<'a, S, T> {
   fn clone(&self) -> Self {
     match self {
-      Child::Leaf(x) => Child::Leaf(Rc::clone(x)),
+      Child::Leaf(x) => Child::Leaf(Arc::clone(x)),
       Child::Node(x) => Child::Node(Rc::clone(x)),
     }
   }
 }
 
 impl<'a, S, T> Derivation<'a, S, T> {
-  pub fn new(children: Vec<Child<'a, S, T>>, rule: &'a Rule<S, T>) -> Self {
+  pub fn new(children: Children<'a, S, T>, rule: &'a Rule<S, T>) -> Self {
     let value = {
       let n = rule.rhs.len();
       assert!(children.len() == n);
-      let mut values: Vec<T> = Vec::with_capacity(n);
+      let mut values: SmallVec<[T; 4]> = SmallVec::with_capacity(n);
       let target = values.as_mut_ptr();
       for i in 0..n {
         let source = match &children[i] {
@@ -135,12 +320,165 @@ impl<'a, S, T> Derivation<'a, S, T> {
     Derivation { children, rule, value }
   }
 
-  pub fn matches(&self) -> Vec<Rc<Match<T>>> {
-    let mut result = vec![];
-    self.children.iter().for_each(|x| match x {
-      Child::Leaf(x) => result.push(Rc::clone(x)),
-      Child::Node(x) => result.append(&mut x.matches()),
+  pub fn matches(&self) -> Vec<Arc<Match<T>>> {
+    self.leaves().map(|(x, _)| Arc::clone(x)).collect()
+  }
+
+  pub fn iter(&self) -> Iter<'_, 'a, S, T> {
+    Iter { stack: vec![(Visit::Node(self), Context { depth: 0, index: 0 })] }
+  }
+
+  pub fn leaves(&self) -> impl Iterator<Item = (&Arc<Match<T>>, Context)> + use<'_, 'a, S, T> {
+    self.iter().filter_map(|(x, y)| if let Visit::Leaf(z) = x { Some((z, y)) } else { None })
+  }
+
+  pub fn nodes(&self) -> impl Iterator<Item = (&Derivation<'a, S, T>, Context)> {
+    self.iter().filter_map(|(x, y)| if let Visit::Node(z) = x { Some((z, y)) } else { None })
+  }
+
+  pub fn walk<V: Visitor<'a, S, T>>(&self, visitor: &mut V) {
+    self.iter().for_each(|(x, y)| match x {
+      Visit::Leaf(z) => visitor.leaf(z, y),
+      Visit::Node(z) => visitor.node(z, y),
     });
-    result
+  }
+
+  // Returns a copy of this node with its i-th child replaced, recomputing its value
+  // with its rule's merge callback. Use a Zipper to propagate the change upward.
+
+  pub fn with_child(&self, i: usize, child: Child<'a, S, T>) -> Self {
+    assert!(i < self.children.len());
+    let mut children = self.children.clone();
+    children[i] = child;
+    Derivation::new(children, self.rule)
+  }
+
+  // Renders the derivation as an indented tree, for debugging. Each node shows its
+  // rule's LHS, line, and tense, and each leaf shows its terminal, its texts in every
+  // scheme, and the tenses that it may take. Both show their values.
+
+  pub fn pretty(&self, grammar: &Grammar<S, T>) -> String
+  where
+    T: Display,
+  {
+    let mut lines = vec![];
+    self.pretty_lines(grammar, "", &mut lines);
+    lines.join("\n")
+  }
+
+  fn pretty_lines(&self, grammar: &Grammar<S, T>, indent: &str, lines: &mut Vec<String>)
+  where
+    T: Display,
+  {
+    let rule = self.rule;
+    let line = rule.metadata.line.map(|x| format!(" (line {})", x)).unwrap_or_default();
+    let tense = Some(&rule.tense).filter(|x| !x.entries().is_empty());
+    let tense = tense.map(|x| format!(" {}", x)).unwrap_or_default();
+    let lhs = &grammar.names[rule.lhs];
+    lines.push(format!("{}{}{}{} = {}", indent, lhs, line, tense, self.value));
+    let indent = format!("{}  ", indent);
+    for (term, child) in rule.rhs.iter().zip(&self.children) {
+      let x = match child {
+        Child::Leaf(x) => x,
+        Child::Node(x) => {
+          x.pretty_lines(grammar, &indent, lines);
+          continue;
+        }
+      };
+      let name = match term {
+        Term::Symbol(y) => &grammar.names[*y],
+        Term::Terminal(y) => y,
+      };
+      let mut texts: Vec<_> = x.texts.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+      texts.sort();
+      let tenses = x.tenses.iter().filter(|y| !y.entries().is_empty());
+      let tenses: Vec<_> = tenses.map(|y| y.to_string()).collect();
+      let tenses = if tenses.is_empty() { "".into() } else { format!(" {}", tenses.join(" | ")) };
+      lines.push(format!("{}{} [{}]{} = {}", indent, name, texts.join(", "), tenses, x.value));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::super::hindi::lexer::HindiLexer;
+  use super::super::super::payload::lambda::Lambda;
+  use super::super::fantasy::compile;
+  use super::super::parser::Parser;
+  use super::*;
+
+  struct Texts(Vec<String>);
+
+  impl<'a, S, T> Visitor<'a, S, T> for Texts {
+    fn leaf(&mut self, x: &Arc<Match<T>>, context: Context) {
+      self.0.push(format!("{}:{}:{}", x.texts["latin"], context.depth, context.index));
+    }
+  }
+
+  #[test]
+  fn terminals_are_interned() {
+    let noun = Terminal::new("%noun");
+    assert_eq!(Terminal::new("%noun"), noun);
+    assert_ne!(Terminal::new("%verb"), noun);
+    assert_eq!(noun.name(), "%noun");
+    assert_eq!(std::thread::spawn(|| Terminal::new("%noun")).join().unwrap(), noun);
+  }
+
+  #[test]
+  fn derivations_are_traversed() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar: Grammar<Option<Lambda>, Lambda> = compile(&data, HindiLexer::new).unwrap();
+    let tree = Parser::new(&grammar).parse("mujhe pani chahie").unwrap();
+    let mut texts = Texts(vec![]);
+    tree.walk(&mut texts);
+    assert_eq!(texts.0, ["mujhe:4:0", "pani:5:3", "chahie:3:0"]);
+
+    let nodes = tree.nodes().map(|(x, y)| (grammar.names[x.rule.lhs].as_str(), y));
+    let nodes: Vec<_> = nodes.collect();
+    assert_eq!(nodes.len(), 12);
+    assert_eq!(nodes[0], ("$ROOT", Context { depth: 0, index: 0 }));
+    assert_eq!(nodes[1], ("$TellWant", Context { depth: 1, index: 0 }));
+    assert_eq!(nodes[6], ("NOUN[%noun]", Context { depth: 4, index: 0 }));
+    assert_eq!(nodes[10], ("$WantPassive", Context { depth: 2, index: 2 }));
+    assert_eq!(nodes[11], ("$Stop?", Context { depth: 2, index: 3 }));
+    assert_eq!(tree.leaves().count(), tree.matches().len());
+  }
+
+  #[test]
+  fn edits_are_propagated_upward() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar: Grammar<Option<Lambda>, Lambda> = compile(&data, HindiLexer::new).unwrap();
+    let tree = Parser::new(&grammar).parse("mujhe pani chahie").unwrap();
+    let tokens = grammar.lexer.lex("roti");
+    let roti = Arc::clone(&tokens[0].matches[&Terminal::new("%noun")].1);
+
+    let mut zipper = Zipper::new(tree);
+    assert!(!zipper.up());
+    assert!([0, 1, 0, 0].iter().all(|x| zipper.down(*x)));
+    assert_eq!(grammar.names[zipper.focus().rule.lhs], "NOUN[%noun]");
+    assert!(!zipper.down(3));
+    zipper.replace(3, Child::Leaf(roti));
+    assert_eq!(zipper.focus().value.repr(), "type.bread");
+    let tree = zipper.finish();
+    assert_eq!(tree.value.repr(), "Tell(I, want.type.bread)");
+    let texts: Vec<_> = tree.matches().iter().map(|x| x.texts["latin"].to_string()).collect();
+    assert_eq!(texts, ["mujhe", "roti", "chahie"]);
+  }
+
+  #[test]
+  fn derivations_are_pretty_printed() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar: Grammar<Option<Lambda>, Lambda> = compile(&data, HindiLexer::new).unwrap();
+    let tree = Parser::new(&grammar).parse("mujhe pani chahie").unwrap();
+    let pretty = tree.pretty(&grammar);
+    let lines: Vec<_> = pretty.lines().collect();
+    assert_eq!(lines[0], "$ROOT = Tell(I, want.type.water)");
+    assert_eq!(lines[1], "  $TellWant (line 58) = Tell(I, want.type.water)");
+    assert!(lines[4].starts_with("        %dative [head: dative-muJko, hindi: muJe, hk: mujhe,"));
+    assert!(lines[4].ends_with("] {count: singular, person: first} = I"));
+    let chahie = lines[lines.len() - 2];
+    assert!(chahie.starts_with("      chahie [head: verb-cAhIe, hindi: cAhIe,"));
+    assert!(chahie.ends_with("] = want"));
+    assert_eq!(lines.last(), Some(&"    $Stop? = -"));
   }
 }
@@ -0,0 +1,145 @@
+use super::base::{Child, Derivation, Grammar, Match, Term};
+
+// Converts a derivation to CoNLL-U rows, so that parses can be read by treebank tools and
+// visualizers. Each leaf of the derivation is a token. Its form is its text in the given
+// scheme, its lemma is its headword, its UPOS is the symbol whose rule produced it, and
+// its XPOS is its terminal. Its features are the tense entries that all of its tenses
+// share, like "Count=Singular".
+//
+// We read dependencies off of the tree. Hindi is head-final, so a node's head token is the
+// head of its last child that has any tokens. The heads of its other children depend on
+// that token, with the name of the child's term as the relation. Agreement marks are not
+// used here, since the term that controls agreement, like a count, is often not the head.
+// The root's head token has head 0 and the relation "root".
+
+pub struct Row {
+  pub id: usize,
+  pub form: String,
+  pub lemma: String,
+  pub upos: String,
+  pub xpos: String,
+  pub feats: Vec<(String, String)>,
+  pub head: usize,
+  pub deprel: String,
+}
+
+impl Row {
+  pub fn to_conllu(&self) -> String {
+    let field = |x: &str| if x.is_empty() { "_".to_string() } else { x.to_string() };
+    let feats: Vec<_> = self.feats.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    let fields = vec![
+      self.id.to_string(),
+      field(&self.form),
+      field(&self.lemma),
+      field(&self.upos),
+      field(&self.xpos),
+      field(&feats.join("|")),
+      self.head.to_string(),
+      field(&self.deprel),
+      "_".into(),
+      "_".into(),
+    ];
+    fields.join("\t")
+  }
+}
+
+pub fn rows<S, T>(grammar: &Grammar<S, T>, tree: &Derivation<S, T>, scheme: &str) -> Vec<Row> {
+  let mut result = vec![];
+  if let Some(x) = build(grammar, tree, scheme, &mut result) {
+    result[x - 1].deprel = "root".into();
+  }
+  result
+}
+
+pub fn export<S, T>(grammar: &Grammar<S, T>, tree: &Derivation<S, T>, scheme: &str) -> String {
+  let rows = rows(grammar, tree, scheme);
+  let text: Vec<_> = rows.iter().map(|x| x.form.as_str()).collect();
+  let mut result = vec![format!("# text = {}", text.join(" "))];
+  rows.iter().for_each(|x| result.push(x.to_conllu()));
+  format!("{}\n\n", result.join("\n"))
+}
+
+fn build<S, T>(
+  grammar: &Grammar<S, T>,
+  tree: &Derivation<S, T>,
+  scheme: &str,
+  rows: &mut Vec<Row>,
+) -> Option<usize> {
+  let rule = tree.rule;
+  let label = |x: &Term| match x {
+    Term::Symbol(y) => clean(&grammar.names[*y]),
+    Term::Terminal(y) => clean(y),
+  };
+  let mut heads = vec![];
+  for (term, child) in rule.rhs.iter().zip(&tree.children) {
+    heads.push(match child {
+      Child::Leaf(x) => {
+        let (upos, xpos) = (clean(&grammar.names[rule.lhs]), label(term));
+        rows.push(get_row(x, rows.len() + 1, scheme, upos, xpos));
+        Some(rows.len())
+      }
+      Child::Node(x) => build(grammar, x, scheme, rows),
+    });
+  }
+  let head = heads.iter().rev().find_map(|x| *x);
+  for (i, x) in heads.iter().enumerate() {
+    if let (Some(x), Some(y)) = (x, head) {
+      if *x != y {
+        rows[x - 1].head = y;
+        rows[x - 1].deprel = label(&rule.rhs[i]);
+      }
+    }
+  }
+  head
+}
+
+fn clean(name: &str) -> String {
+  let name = name.trim_end_matches('?').chars();
+  name.filter(|x| *x != '$' && *x != '%' && !x.is_whitespace()).collect()
+}
+
+fn get_row<T>(x: &Match<T>, id: usize, scheme: &str, upos: String, xpos: String) -> Row {
+  let text = |k: &str| x.texts.get(k).map(|y| y.to_string());
+  let form = text(scheme).unwrap_or_default();
+  let lemma = text("head").unwrap_or_else(|| xpos.clone());
+  let capitalize = |y: &str| {
+    let mut chars = y.chars();
+    chars.next().map(|z| z.to_uppercase().chain(chars).collect()).unwrap_or_default()
+  };
+  let mut feats: Vec<_> = x.tenses.first().map(|y| y.entries()).unwrap_or_default();
+  feats.retain(|y| x.tenses.iter().all(|z| z.get(&y.0).as_ref() == Some(&y.1)));
+  let feats = feats.into_iter().map(|(k, v)| (capitalize(&k), capitalize(&v))).collect();
+  Row { id, form, lemma, upos, xpos, feats, head: 0, deprel: "".into() }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::super::hindi::lexer::HindiLexer;
+  use super::super::super::payload::lambda::Lambda;
+  use super::super::fantasy::compile;
+  use super::super::parser::Parser;
+  use super::*;
+
+  #[test]
+  fn derivations_are_exported_to_conllu() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar: Grammar<Option<Lambda>, Lambda> = compile(&data, HindiLexer::new).unwrap();
+    let tree = Parser::new(&grammar).parse("mujhe do roti chahie").unwrap();
+    let text = export(&grammar, &tree, "latin");
+    let lines: Vec<_> = text.lines().map(|x| x.split('\t').collect::<Vec<_>>().join(" ")).collect();
+    assert_eq!(lines, [
+      "# text = mujhe do roti chahie",
+      "1 mujhe dative-muJko PersonKo dative Count=Singular|Person=First 4 I[PersonKo] _ _",
+      "2 do number-xo Count number Count=Plural 3 Count _ _",
+      "3 roti noun-rotI NOUN[noun] noun Gender=Female|Person=Third 4 Noun _ _",
+      "4 chahie verb-cAhIe WantPassive chahie _ 0 root _ _",
+      "",
+    ]);
+    assert!(text.ends_with("\n\n"));
+
+    let tree = Parser::new(&grammar).parse("aap kaun hain").unwrap();
+    let rows = rows(&grammar, &tree, "iso");
+    let heads: Vec<_> = rows.iter().map(|x| (x.form.as_str(), x.head, x.deprel.as_str())).collect();
+    assert_eq!(heads, [("āp", 3, "Person"), ("kaun", 3, "kaun"), ("haiṁ", 0, "root")]);
+  }
+}
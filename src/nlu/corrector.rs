@@ -1,9 +1,15 @@
+use super::super::lib::base::Result;
 use super::super::payload::base::Payload;
 use super::base::Child::{Leaf, Node};
 use super::base::{Match, Tense};
+use super::tense::Mismatch;
+use super::observer::{Event, Observer};
+use super::owned::OwnedDerivation;
 use rand::Rng as RngTrait;
 use std::borrow::Borrow;
+use std::fmt::{Display, Formatter};
 use std::rc::Rc;
+use std::sync::Arc;
 
 // Types that exist while the corrector is executing.
 
@@ -19,9 +25,11 @@ type Lexer<T> = dyn super::base::Lexer<Option<T>, T>;
 type Rule<T> = super::base::Rule<Option<T>, T>;
 
 struct State<'a, 'b, T: Payload> {
+  deterministic: bool,
   diff: Vec<Diff<T>>,
   generator: &'b Generator<'a, T>,
   grammar: &'a Grammar<T>,
+  observer: Option<&'b dyn Observer>,
   rng: &'b mut Rng,
   tense: Tense,
 }
@@ -48,21 +56,37 @@ impl<'a, 'b, T: Payload> State<'a, 'b, T> {
 
   // The tree rebuilding logic: first, memoize all subtrees; then, call the generator.
 
-  fn check_rules(&self, rule: &Rule<T>) -> Vec<String> {
-    let ok = rule.split.score != std::f32::NEG_INFINITY;
-    if ok { self.tense.check(&rule.tense) } else { vec!["Invalid phrasing.".to_string()] }
+  fn check_rules(&self, rule: &Rule<T>) -> (Category, Vec<String>) {
+    if rule.split.score == std::f32::NEG_INFINITY {
+      return (Category::Phrasing, vec![INVALID_PHRASING.to_string()]);
+    }
+    self.categorize(self.tense.check(&self.restrict(&rule.tense)))
+  }
+
+  fn categorize(&self, mismatches: Vec<Mismatch>) -> (Category, Vec<String>) {
+    let politeness = mismatches.iter().any(|x| self.grammar.features.politeness(&x.category()));
+    let category = if politeness { Category::Politeness } else { Category::Agreement };
+    (category, mismatches.iter().map(|x| x.to_string()).collect())
+  }
+
+  fn restrict(&self, tense: &Tense) -> Tense {
+    self.grammar.features.restrict(tense)
   }
 
-  fn rebuild(&mut self, old: Rc<Derivation<'a, T>>) -> Rc<Derivation<'a, T>> {
+  fn rebuild(&mut self, old: Rc<Derivation<'a, T>>, errors: &[String]) -> Rc<Derivation<'a, T>> {
     let mut memo = Memo::default();
     State::fill_memo(&old, &mut memo);
     let rules: Vec<_> = {
       let lhs = old.rule.lhs;
-      let valid = |x: &&Rule<T>| x.lhs == lhs && self.check_rules(*x).is_empty();
+      let valid = |x: &&Rule<T>| x.lhs == lhs && self.check_rules(*x).1.is_empty();
       self.grammar.rules.iter().filter(valid).collect()
     };
     let value = Some(old.value.clone());
     let new = self.generator.generate_from_rules(memo, self.rng, &rules, &value);
+    if let Some(observer) = self.observer {
+      let (lhs, rebuilt) = (self.grammar.names[old.rule.lhs].as_str(), new.is_some());
+      observer.observe(&Event::RuleRebuilt { lhs, errors, rebuilt });
+    }
     new.map(Rc::new).unwrap_or(old)
   }
 
@@ -75,8 +99,10 @@ impl<'a, 'b, T: Payload> State<'a, 'b, T> {
     }
   }
 
-  fn see_leaf(&mut self, old: Rc<Match<T>>) -> Rc<Match<T>> {
-    let errors = self.tense.union_checked(&old.tenses);
+  fn see_leaf(&mut self, old: Arc<Match<T>>) -> Arc<Match<T>> {
+    let tenses: Vec<_> = old.tenses.iter().map(|x| self.restrict(x)).collect();
+    let mismatches = self.tense.union_checked(&tenses);
+    let (category, errors) = self.categorize(mismatches);
     if errors.is_empty() {
       self.diff.push(Diff::Right(old.clone()));
       return old;
@@ -84,19 +110,20 @@ impl<'a, 'b, T: Payload> State<'a, 'b, T> {
     let mut new = old.clone();
     let options = self.grammar.lexer.fix(&*old, &self.tense);
     if !options.is_empty() {
-      new = options[self.rng.gen::<usize>() % options.len()].clone();
+      let index = if self.deterministic { 0 } else { self.rng.gen::<usize>() % options.len() };
+      new = options[index].clone();
       debug_assert!(self.tense.union_checked(&new.tenses).is_empty());
     }
     let (old_matches, new_matches) = (vec![old.clone()], vec![new.clone()]);
-    self.diff.push(Diff::Wrong(Wrong { errors, old_matches, new_matches }));
+    self.diff.push(Diff::Wrong(Wrong { category, errors, old_matches, new_matches }));
     new
   }
 
   fn see_node(&mut self, old: Rc<Derivation<'a, T>>) -> Rc<Derivation<'a, T>> {
     // Correct top-level issues by regenerating the whole subtree.
-    let errors = self.check_rules(old.rule);
-    let new = if errors.is_empty() { old.clone() } else { self.rebuild(old.clone()) };
-    self.tense.union(&new.rule.tense);
+    let (category, errors) = self.check_rules(old.rule);
+    let new = if errors.is_empty() { old.clone() } else { self.rebuild(old.clone(), &errors) };
+    self.tense.union(&self.restrict(&new.rule.tense));
 
     // Correct tense errors in each of the tree's children.
     let Derivation { children, rule, value } = new.borrow();
@@ -126,7 +153,7 @@ impl<'a, 'b, T: Payload> State<'a, 'b, T> {
       child_diffs.into_iter().for_each(|mut x| self.diff.append(&mut x));
     } else {
       let (old_matches, new_matches) = (old.matches(), new.matches());
-      self.diff.push(Diff::Wrong(Wrong { errors, old_matches, new_matches }));
+      self.diff.push(Diff::Wrong(Wrong { category, errors, old_matches, new_matches }));
     }
     new
   }
@@ -141,39 +168,94 @@ pub struct Correction<'a, T> {
 }
 
 pub enum Diff<T> {
-  Right(Rc<Match<T>>),
+  Right(Arc<Match<T>>),
   Wrong(Wrong<T>),
 }
 
 pub struct Wrong<T> {
+  pub category: Category,
   pub errors: Vec<String>,
-  pub old_matches: Vec<Rc<Match<T>>>,
-  pub new_matches: Vec<Rc<Match<T>>>,
+  pub old_matches: Vec<Arc<Match<T>>>,
+  pub new_matches: Vec<Arc<Match<T>>>,
+}
+
+// Each Wrong diff has a category. Errors in the grammar's politeness features, such as
+// using intimate forms with the honorific "ap", are politeness errors rather than
+// agreement errors.
+
+const INVALID_PHRASING: &str = "Invalid phrasing.";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Category {
+  Agreement,
+  Phrasing,
+  Politeness,
+}
+
+impl Display for Category {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    let name = match self {
+      Category::Agreement => "agreement",
+      Category::Phrasing => "phrasing",
+      Category::Politeness => "politeness",
+    };
+    write!(f, "{}", name)
+  }
 }
 
+// A deterministic corrector takes the lexer's best fix for each wrong word and
+// regenerates wrong subtrees with a deterministic generator.
+
 pub struct Corrector<'a, T: Payload> {
+  deterministic: bool,
   generator: Generator<'a, T>,
   grammar: &'a Grammar<T>,
+  observer: Option<Arc<dyn Observer>>,
 }
 
 impl<'a, T: Payload> Corrector<'a, T> {
   pub fn new(grammar: &'a Grammar<T>) -> Self {
-    Self { generator: Generator::new(grammar), grammar }
+    Self { deterministic: false, generator: Generator::new(grammar), grammar, observer: None }
+  }
+
+  pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+    self.deterministic = deterministic;
+    self.generator = self.generator.with_deterministic(deterministic);
+    self
   }
 
-  pub fn correct(&self, rng: &mut Rng, tree: &'a Derivation<'a, T>) -> Correction<'a, T> {
-    let Self { generator, grammar } = self;
-    let mut state = State { diff: vec![], generator, grammar, rng, tense: Tense::default() };
+  // The observer sees the corrector's events and those of the generator it rebuilds with.
+  pub fn with_observer(mut self, observer: Option<Arc<dyn Observer>>) -> Self {
+    self.generator = self.generator.with_observer(observer.clone());
+    self.observer = observer;
+    self
+  }
+
+  pub fn correct(&self, rng: &mut Rng, tree: &Derivation<'a, T>) -> Correction<'a, T> {
+    let Self { deterministic, generator, grammar, observer } = self;
+    let (deterministic, diff, tense) = (*deterministic, vec![], Tense::default());
+    let observer = observer.as_deref();
+    let mut state = State { deterministic, diff, generator, grammar, observer, rng, tense };
     let new = state.see_node(Rc::new(State::clone_tree(tree)));
     Correction { diff: state.diff, tree: State::clone_tree(&new) }
   }
+
+  // Corrects an owned derivation, looking up its rules by id in this grammar.
+
+  pub fn correct_owned(
+    &self,
+    rng: &mut Rng,
+    tree: &OwnedDerivation<T>,
+  ) -> Result<Correction<'a, T>> {
+    Ok(self.correct(rng, &tree.to_derivation(self.grammar)?))
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::super::super::lib::base::HashMap;
   use super::super::super::payload::json::Json;
-  use super::super::base::{Lexer, Semantics, Term, Token};
+  use super::super::base::{Lexer, Metadata, Semantics, TenseSchema, Term, Terminal, Terms, Token};
   use super::super::parser::Parser;
   use super::*;
   use test::Bencher;
@@ -181,7 +263,7 @@ mod tests {
   struct WordLexer();
 
   impl Lexer<Option<Json>, Json> for WordLexer {
-    fn fix(&self, _: &Match<Json>, _: &Tense) -> Vec<Rc<Match<Json>>> {
+    fn fix(&self, _: &Match<Json>, _: &Tense) -> Vec<Arc<Match<Json>>> {
       unimplemented!()
     }
 
@@ -189,16 +271,17 @@ mod tests {
       let iter = input.split(' ').into_iter().map(|x| {
         let mut matches = HashMap::default();
         let texts = vec![("latin", x.into())].into_iter().collect::<HashMap<_, _>>();
-        matches.insert(x, (0.0, Rc::new(Match { tenses: vec![], texts, value: Json::default() })));
+        let value = Json::default();
+        matches.insert(Terminal::new(x), (0.0, Arc::new(Match { tenses: vec![], texts, value })));
         Token { matches, text: x }
       });
       iter.collect()
     }
 
-    fn unlex(&self, name: &str, value: &Option<Json>) -> Vec<Rc<Match<Json>>> {
+    fn unlex(&self, name: &str, value: &Option<Json>) -> Vec<Arc<Match<Json>>> {
       if value.as_ref().map(|x| x.empty()).unwrap_or(true) {
         let texts = vec![("latin", name.into())].into_iter().collect::<HashMap<_, _>>();
-        vec![Rc::new(Match { tenses: vec![], texts, value: Json::default() })]
+        vec![Arc::new(Match { tenses: vec![], texts, value: Json::default() })]
       } else {
         vec![]
       }
@@ -206,28 +289,29 @@ mod tests {
   }
 
   fn make_rule(lhs: usize, rhs: &str, template: &str, is: &[usize], tense: Tense) -> Rule<Json> {
-    let rhs: Vec<_> = rhs.split(' ').filter(|x| !x.is_empty()).map(make_term).collect();
+    let rhs: Terms = rhs.split(' ').filter(|x| !x.is_empty()).map(make_term).collect();
     let n = rhs.len();
-    let template = Rc::new(Json::template(template).unwrap());
+    let template = Arc::new(Json::template(template).unwrap());
     let (merge, split) = (template.clone(), template.clone());
-    let merge: Semantics<dyn Fn(&[Json]) -> Json> = Semantics {
+    let merge: Semantics<dyn Fn(&[Json]) -> Json + Send + Sync> = Semantics {
       callback: Box::new(move |x| merge.merge(&x.iter().cloned().enumerate().collect())),
       score: 0.0,
     };
-    let split: Semantics<dyn Fn(&Option<Json>) -> Vec<Vec<Option<Json>>>> = Semantics {
-      callback: Box::new(move |x| {
-        let mut result = vec![];
+    type Callback = dyn Fn(&Option<Json>, &mut dyn FnMut(Vec<Option<Json>>) -> bool) + Send + Sync;
+    let split: Semantics<Callback> = Semantics {
+      callback: Box::new(move |x, f| {
         for option in x.as_ref().map(|y| split.split(y)).unwrap_or(vec![vec![]]) {
           let mut entry = vec![None; n];
           option.into_iter().filter(|(i, _)| *i < n).for_each(|(i, y)| entry[i] = Some(y));
-          result.push(entry);
+          if !f(entry) {
+            break;
+          }
         }
-        result
       }),
       score: 0.0,
     };
     let precedence = if is.is_empty() { (0..n).into_iter().collect() } else { is.to_owned() };
-    Rule { lhs, rhs, merge, split, precedence, tense }
+    Rule { lhs, rhs, merge, split, precedence, tense, metadata: Metadata::default() }
   }
 
   fn make_term(term: &str) -> Term {
@@ -238,8 +322,8 @@ mod tests {
     }
   }
 
-  fn render<T>(matches: &[Rc<Match<T>>]) -> String {
-    let texts = matches.iter().map(|x| x.texts.get("latin").map(|y| y.as_str()).unwrap_or("?"));
+  fn render<T>(matches: &[Arc<Match<T>>]) -> String {
+    let texts = matches.iter().map(|x| x.texts.get("latin").map(|y| &**y).unwrap_or("?"));
     texts.collect::<Vec<_>>().join(" ")
   }
 
@@ -263,6 +347,7 @@ mod tests {
 
   fn make_grammar() -> Grammar<Json> {
     Grammar {
+      features: TenseSchema::default(),
       lexer: Box::new(WordLexer {}),
       names: "$Root $Num $Adjs $Noun $Adj $Extra".split(' ').map(|x| x.into()).collect(),
       rules: vec![
@@ -316,6 +401,49 @@ mod tests {
     }
   }
 
+  #[test]
+  fn correction_uses_declared_features() {
+    let mut grammar = make_grammar();
+    grammar.features = TenseSchema::new(&[("count", vec!["plural", "singular"])]).unwrap();
+    let tree = Parser::new(&grammar).parse("do chota bari admi huh").unwrap();
+    let corrector = Corrector::new(&grammar);
+    let mut rng = rand::SeedableRng::from_seed([17; 32]);
+    let correction = corrector.correct(&mut rng, &tree);
+    assert!(render(&correction.tree.matches()).ends_with(" bari admiyo huh"));
+    let iter = correction.diff.into_iter().map(|x| match x {
+      Diff::Right(_) => vec![],
+      Diff::Wrong(x) => x.errors,
+    });
+    assert_eq!(
+      iter.collect::<Vec<_>>(),
+      vec![
+        vec![],
+        vec!["count should be plural (was: singular)"],
+        vec![],
+        vec!["count should be plural (was: singular)"],
+      ]
+    );
+  }
+
+  #[test]
+  fn errors_are_categorized() {
+    let mut grammar = make_grammar();
+    let features = [("count", vec!["plural", "singular"]), ("gender", vec!["female", "male"])];
+    grammar.features = TenseSchema::new(&features).unwrap();
+    grammar.features.set_politeness("gender").unwrap();
+    assert!(grammar.features.set_politeness("tone").is_err());
+    let tree = Parser::new(&grammar).parse("do chota bari admi huh").unwrap();
+    let mut rng = rand::SeedableRng::from_seed([17; 32]);
+    let correction = Corrector::new(&grammar).correct(&mut rng, &tree);
+    let iter = correction.diff.into_iter().filter_map(|x| match x {
+      Diff::Right(_) => None,
+      Diff::Wrong(x) => Some(x.category),
+    });
+    let categories = [Category::Agreement, Category::Politeness, Category::Agreement];
+    assert_eq!(iter.collect::<Vec<_>>(), categories);
+    assert_eq!(Category::Politeness.to_string(), "politeness");
+  }
+
   #[bench]
   fn correction_benchmark(b: &mut Bencher) {
     let grammar = make_grammar();
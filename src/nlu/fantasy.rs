@@ -1,7 +1,9 @@
-use super::super::lib::base::{HashMap, HashSet, Result};
+use super::super::lib::base::{Error, HashMap, HashSet, Result};
+use super::super::lib::table::read_tables;
 use super::super::payload::base::{DefaultTemplate, Payload, SlotTemplate, Template, UnitTemplate};
-use super::base::{Tense, Term};
+use super::base::{Entry, Match, Metadata, Tense, TenseSchema, Term, Terminal, Terms, Token};
 use std::rc::Rc;
+use std::sync::Arc;
 
 // We parse our grammar files into this AST, rooted at a list of RootNodes.
 
@@ -20,6 +22,7 @@ struct MacroNode {
 
 #[derive(Default)]
 struct RuleNode {
+  line: usize,
   merge: f32,
   split: f32,
   rhs: Vec<ItemNode>,
@@ -41,13 +44,17 @@ enum ExprNode {
 
 #[derive(PartialEq)]
 enum MarkNode {
+  Exclude,
   Max,
   Min,
   Skip,
 }
 
 enum RootNode {
-  Lexer(String),
+  Alias(String, String),
+  Feature(String, bool, Vec<String>),
+  Lexer(Option<String>, String),
+  LexerFile(Option<String>, String),
   Macro(MacroNode),
   Rules(SymbolNode),
 }
@@ -63,18 +70,35 @@ fn get_precedence(rhs: &[ItemNode]) -> Vec<usize> {
   let mut result = vec![];
   rhs.iter().enumerate().filter(|(_, x)| x.mark == MarkNode::Max).for_each(|(i, _)| result.push(i));
   rhs.iter().enumerate().filter(|(_, x)| x.mark == MarkNode::Min).for_each(|(i, _)| result.push(i));
-  if result.is_empty() { (0..rhs.len()).collect() } else { result }
+  if !result.is_empty() {
+    return result;
+  }
+  rhs.iter().enumerate().filter(|(_, x)| x.mark != MarkNode::Exclude).map(|(i, _)| i).collect()
 }
 
-fn get_rule<T: Payload>(lhs: usize, rhs: Vec<Term>) -> Rule<T> {
+fn get_rule<T: Payload>(lhs: usize, rhs: Terms) -> Rule<T> {
   let n = rhs.len();
-  let template: Rc<dyn Template<T>> =
-    if n == 1 { Rc::new(UnitTemplate {}) } else { Rc::new(DefaultTemplate {}) };
+  let template: Arc<dyn Template<T>> =
+    if n == 1 { Arc::new(UnitTemplate {}) } else { Arc::new(DefaultTemplate {}) };
   let (merge, split) = get_semantics(n, &RuleNode::default(), template);
-  Rule { lhs, rhs, merge, split, precedence: (0..n).collect(), tense: Tense::default() }
+  let (precedence, tense, metadata) = ((0..n).collect(), Tense::default(), Metadata::default());
+  Rule { lhs, rhs, merge, split, precedence, tense, metadata }
 }
 
-fn get_semantics<T: Payload>(n: usize, rule: &RuleNode, template: Rc<dyn Template<T>>) -> Pair<T> {
+fn get_metadata(rule: &RuleNode) -> Metadata {
+  let mark = |x: &MarkNode| match x {
+    MarkNode::Exclude => '!',
+    MarkNode::Max => '*',
+    MarkNode::Min => '^',
+    MarkNode::Skip => ' ',
+  };
+  let items = rule.rhs.iter().map(|x| (x.index, mark(&x.mark))).collect();
+  let mut tense: Vec<_> = rule.tense.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+  tense.sort();
+  Metadata { id: None, items, line: Some(rule.line), template: rule.template.clone(), tense }
+}
+
+fn get_semantics<T: Payload>(n: usize, rule: &RuleNode, template: Arc<dyn Template<T>>) -> Pair<T> {
   let (merge, split) = (template.clone(), template.clone());
   (
     Merge {
@@ -82,14 +106,18 @@ fn get_semantics<T: Payload>(n: usize, rule: &RuleNode, template: Rc<dyn Templat
       score: rule.merge,
     },
     Split {
-      callback: Box::new(move |x| {
-        let mut result = vec![];
-        for option in x.as_ref().map(|y| split.split(y)).unwrap_or(vec![vec![]]) {
+      callback: Box::new(move |x, f| {
+        let options = match x {
+          Some(y) => split.split_iter(y),
+          None => Box::new(std::iter::once(vec![])),
+        };
+        for option in options {
           let mut entry = vec![None; n];
           option.into_iter().filter(|(i, _)| *i < n).for_each(|(i, y)| entry[i] = Some(y));
-          result.push(entry);
+          if !f(entry) {
+            break;
+          }
         }
-        result
       }),
       score: rule.split,
     },
@@ -103,10 +131,10 @@ fn get_semantics<T: Payload>(n: usize, rule: &RuleNode, template: Rc<dyn Templat
 // This required assumption fails in the case of symbols that can expand to an empty
 // RHS without provided rule semantics. However, the optimization is critical, as we
 // need a way to stop generation in the default case where it works.
-fn get_template<T: Payload>(n: usize, rule: &RuleNode) -> Result<Rc<dyn Template<T>>> {
+fn get_template<T: Payload>(n: usize, rule: &RuleNode) -> Result<Arc<dyn Template<T>>> {
   let template = match &rule.template {
-    Some(x) => T::template(x)?,
-    None => return Ok(Rc::new(DefaultTemplate {})),
+    Some(x) => T::template(x).map_err(|y| Error::Template(y.to_string()))?,
+    None => return Ok(Arc::new(DefaultTemplate {})),
   };
   let terms = rule.rhs.iter().enumerate();
   let limit = rule.rhs.iter().filter_map(|x| x.index).max();
@@ -117,7 +145,7 @@ fn get_template<T: Payload>(n: usize, rule: &RuleNode) -> Result<Rc<dyn Template
   } else {
     terms.map(|(i, x)| Some((i, x.optional))).collect()
   };
-  Ok(Rc::new(SlotTemplate::new(n, slots, template)))
+  Ok(Arc::new(SlotTemplate::new(n, slots, template)))
 }
 
 fn get_warning(mut xs: Vec<String>, message: &str) -> Result<()> {
@@ -131,11 +159,13 @@ type Grammar<T> = super::base::Grammar<Option<T>, T>;
 type Lexer<T> = dyn super::base::Lexer<Option<T>, T>;
 type Rule<T> = super::base::Rule<Option<T>, T>;
 
-type Merge<T> = super::base::Semantics<dyn Fn(&[T]) -> T>;
-type Split<T> = super::base::Semantics<dyn Fn(&Option<T>) -> Vec<Vec<Option<T>>>>;
+type Merge<T> = super::base::Semantics<dyn Fn(&[T]) -> T + Send + Sync>;
+type Split<T> =
+  super::base::Semantics<dyn Fn(&Option<T>, &mut dyn FnMut(Vec<Option<T>>) -> bool) + Send + Sync>;
 type Pair<T> = (Merge<T>, Split<T>);
 
 struct State<T: Payload> {
+  alias: HashMap<String, String>,
   binding: HashMap<String, Term>,
   grammar: Grammar<T>,
   macros: HashMap<String, Rc<MacroNode>>,
@@ -181,7 +211,7 @@ impl<T: Payload> State<T> {
     let name = format!("{}?", self.get_name(&term));
     if !self.symbol.contains_key(&name) {
       let symbol = self.get_symbol(&name);
-      for rhs in vec![vec![], vec![term]] {
+      for rhs in vec![Terms::new(), vec![term].into()] {
         self.grammar.rules.push(get_rule(symbol, rhs));
       }
     }
@@ -201,6 +231,7 @@ impl<T: Payload> State<T> {
   }
 
   fn get_symbol(&mut self, name: &str) -> usize {
+    let name = self.alias.get(name).map(|x| x.as_str()).unwrap_or(name);
     let names = &mut self.grammar.names;
     *self.symbol.entry(name.to_string()).or_insert_with(|| {
       names.push(name.to_string());
@@ -208,6 +239,37 @@ impl<T: Payload> State<T> {
     })
   }
 
+  fn process_aliases(&mut self, aliases: &[(String, String)], symbol: &[SymbolNode]) -> Result<()> {
+    let defined: HashSet<_> = symbol.iter().map(|x| x.lhs.as_str()).collect();
+    for (alias, target) in aliases {
+      if defined.contains(alias.as_str()) || self.alias.contains_key(alias) {
+        Err(format!("Duplicate alias: {}", alias))?;
+      }
+      std::mem::drop(self.alias.insert(alias.clone(), target.clone()));
+    }
+
+    // Resolve chains of aliases, so that every alias maps directly to a defined symbol.
+    let mut dangling = vec![];
+    let mut resolved = HashMap::default();
+    for (alias, target) in aliases {
+      let mut target = target;
+      let mut seen = HashSet::default();
+      while let Some(x) = self.alias.get(target) {
+        if !seen.insert(target) {
+          Err(format!("Cyclic alias: {}", alias))?;
+        }
+        target = x;
+      }
+      if !defined.contains(target.as_str()) {
+        dangling.push(format!("{} -> {}", alias, target));
+      }
+      std::mem::drop(resolved.insert(alias.clone(), target.clone()));
+    }
+    get_warning(dangling, "Dangling aliases")?;
+    self.alias = resolved;
+    Ok(())
+  }
+
   fn process_macro(&mut self, x: MacroNode) -> Result<()> {
     match self.macros.insert(x.name.clone(), Rc::new(x)) {
       Some(x) => Err(format!("Duplicate macro: {}", x.name))?,
@@ -218,19 +280,21 @@ impl<T: Payload> State<T> {
   fn process_rules(&mut self, lhs: &str, rules: &[RuleNode]) -> Result<()> {
     let lhs = self.get_symbol(lhs);
     rules.iter().try_for_each(|y| {
+      let tense = Tense::new(&y.tense)?;
+      self.grammar.features.validate(&tense)?;
       let n = y.rhs.len();
       let precedence = get_precedence(&y.rhs);
       let (merge, split) = get_semantics(n, y, get_template(n, y)?);
-      let rhs = y.rhs.iter().map(|z| self.build_term(z)).collect::<Result<Vec<_>>>()?;
-      let tense = Tense::new(&y.tense)?;
-      self.grammar.rules.push(Rule { lhs, rhs, merge, split, precedence, tense });
+      let rhs = y.rhs.iter().map(|z| self.build_term(z)).collect::<Result<Terms>>()?;
+      let metadata = get_metadata(y);
+      self.grammar.rules.push(Rule { lhs, rhs, merge, split, precedence, tense, metadata });
       Ok(())
     })
   }
 
   fn process_start(&mut self, x: &str) {
     let lhs = self.get_symbol(x);
-    self.grammar.rules.push(get_rule(0, vec![Term::Symbol(lhs)]));
+    self.grammar.rules.push(get_rule(0, vec![Term::Symbol(lhs)].into()));
   }
 
   fn validate(self) -> Result<Grammar<T>> {
@@ -248,13 +312,24 @@ impl<T: Payload> State<T> {
     });
 
     // Throw if a symbol is LHS- or RHS-only, or if a terminal is unknown to the lexer.
+    // Each item names the line of the first rule that defines or uses it.
     {
-      let Grammar { lexer, names, .. } = &self.grammar;
+      let Grammar { lexer, names, rules, .. } = &self.grammar;
       let dummy = Some(T::base_lex("dummy"));
       let check = |x: &str| lexer.unlex(x, &None).is_empty() && lexer.unlex(x, &dummy).is_empty();
-      let dead_end = rhs.iter().filter(|x| !lhs.contains(*x)).map(|x| names[*x].clone());
-      let unreachable = lhs.iter().filter(|x| !rhs.contains(*x)).map(|x| names[*x].clone());
+      let locate = |x: String, f: &dyn Fn(&Rule<T>) -> bool| {
+        match rules.iter().find(|y| f(y)).and_then(|y| y.metadata.line) {
+          Some(line) => format!("{} (line {})", x, line),
+          None => x,
+        }
+      };
+      let used = |x: String, term: Term| locate(x, &|y| y.rhs.contains(&term));
+      let dead_end = rhs.iter().filter(|x| !lhs.contains(*x));
+      let dead_end = dead_end.map(|x| used(names[*x].clone(), Term::Symbol(*x)));
+      let unreachable = lhs.iter().filter(|x| !rhs.contains(*x));
+      let unreachable = unreachable.map(|x| locate(names[*x].clone(), &|y| y.lhs == *x));
       let unknown = terminals.into_iter().filter(|x| check(x));
+      let unknown = unknown.map(|x| used(x.clone(), Term::Terminal(x)));
       get_warning(dead_end.collect(), "Dead-end symbols")?;
       get_warning(unreachable.collect(), "Unreachable symbols")?;
       get_warning(unknown.collect(), "Unknown terminals")?;
@@ -263,6 +338,71 @@ impl<T: Payload> State<T> {
   }
 }
 
+// A grammar may have named lexer blocks in addition to its default one. Terminals of the
+// form %name_* are routed to the lexer built from the block with that name. The default
+// lexer determines tokenization; routed matches are attached to tokens with the same span.
+
+type Factory<T> = dyn Fn(&str, &TenseSchema) -> Result<Box<Lexer<T>>>;
+
+struct RoutedLexer<S, T> {
+  base: Box<dyn super::base::Lexer<S, T>>,
+  routes: Vec<(String, Box<dyn super::base::Lexer<S, T>>)>,
+}
+
+impl<S, T> RoutedLexer<S, T> {
+  fn routed(&self, name: &str) -> bool {
+    self.routes.iter().any(|x| name.starts_with(&x.0))
+  }
+}
+
+impl<S, T> super::base::Lexer<S, T> for RoutedLexer<S, T> {
+  fn fix(&self, m: &Match<T>, t: &Tense) -> Vec<Arc<Match<T>>> {
+    let lexers = std::iter::once(&self.base).chain(self.routes.iter().map(|x| &x.1));
+    lexers.map(|x| x.fix(m, t)).find(|x| !x.is_empty()).unwrap_or_default()
+  }
+
+  fn lex<'a: 'b, 'b>(&'a self, input: &'b str) -> Vec<Token<'b, T>> {
+    let mut tokens = self.base.lex(input);
+    tokens.iter_mut().for_each(|x| x.matches.retain(|k, _| !self.routed(k.name())));
+    for (prefix, lexer) in &self.routes {
+      for token in lexer.lex(input) {
+        let span = (token.text.as_ptr(), token.text.len());
+        if let Some(x) = tokens.iter_mut().find(|x| (x.text.as_ptr(), x.text.len()) == span) {
+          let routed = |k: &Terminal| k.name().starts_with(prefix.as_str());
+          let matches = token.matches.into_iter().filter(|(k, _)| routed(k));
+          matches.for_each(|(k, v)| std::mem::drop(x.matches.insert(k, v)));
+        }
+      }
+    }
+    tokens
+  }
+
+  fn unlex(&self, name: &str, value: &S) -> Vec<Arc<Match<T>>> {
+    let route = self.routes.iter().find(|x| name.starts_with(&x.0));
+    route.map(|x| &x.1).unwrap_or(&self.base).unlex(name, value)
+  }
+
+  fn vocabulary(&self) -> Vec<(Terminal, Entry<T>)> {
+    let mut result = self.base.vocabulary();
+    result.retain(|x| !self.routed(x.0.name()));
+    for (prefix, lexer) in &self.routes {
+      let entries = lexer.vocabulary().into_iter();
+      result.extend(entries.filter(|x| x.0.name().starts_with(prefix.as_str())));
+    }
+    result
+  }
+
+  fn terminals(&self) -> Vec<Terminal> {
+    let mut result = self.base.terminals();
+    result.retain(|x| !self.routed(x.name()));
+    for (prefix, lexer) in &self.routes {
+      let terminals = lexer.terminals().into_iter();
+      result.extend(terminals.filter(|x| x.name().starts_with(prefix.as_str())));
+    }
+    result
+  }
+}
+
 // A parser that builds up the AST above.
 
 fn parse(input: &str) -> Result<Vec<RootNode>> {
@@ -277,58 +417,62 @@ fn parse(input: &str) -> Result<Vec<RootNode>> {
 
   thread_local! {
     static PARSER: Parser<Vec<RootNode>> = {
-      let comment = regexp(r#"#.*"#, |_| ());
-      let ws = separate(regexp(r#"\s*"#, |_| ()), comment, 0);
-      let id = regexp("[a-zA-Z_]+", |x| x.to_string());
+      let comment = seq((string("#", |_| ()), take_while(|x| x != '\n', |_| ())), |_| ());
+      let comment = label(comment, "comment");
+      let ws = separate(whitespace(), comment, 0);
+      let id = take_while1(|x| x.is_ascii_alphabetic() || x == '_', |x| x.to_string());
+      let id = label(id, "identifier");
       let st = |x| string(x, |_| ());
-      let prefix = |x: &'static str| seq2((st(x), &id), move |y| format!("{}{}", x, y.1));
+      let prefix = |x: &'static str| seq((st(x), &id), move |y| format!("{}{}", x, y.1));
       let (binding, symbol, terminal) = (prefix("@"), prefix("$"), prefix("%"));
 
       // Parser for various primitive types.
-      let index = regexp("(0|[1-9][0-9]*)", |x| x.parse::<usize>().unwrap());
-      let number = any(&[
+      let index = label(regexp("(0|[1-9][0-9]*)", |x| x.parse::<usize>().unwrap()), "index");
+      let number = label(any(&[
         regexp(r#"-?(?:[0-9]|[1-9][0-9]+)?(?:\.[0-9]+)\b"#, |x| x.parse::<f32>().unwrap()),
         regexp(r#"-?(?:[0-9]|[1-9][0-9]+)\b"#, |x| x.parse::<f32>().unwrap()),
-      ]);
-      let string = any(&[
+      ]), "number");
+      let string = label(any(&[
         regexp(r#""[^"]*""#, |x| x[1..x.len()-1].to_string()),
         regexp(r#"'[^']*'"#, |x| x[1..x.len()-1].to_string()),
-      ]);
+      ]), "string");
 
       // Parsers for term and expr expressions. An expr can be a binding, macro, or term.
-      let commas = seq3((&ws, st(","), &ws), |_| ());
+      let commas = seq((&ws, st(","), &ws), |_| ());
       let term = any(&[
         map(&symbol, TermNode::Symbol),
         map(&id, TermNode::Terminal),
         map(terminal, TermNode::Terminal),
       ]);
-      let (cell, expr) = lazy();
+      let (cell, expr) = lazy("expr");
       cell.replace(any(&[
         map(&binding, ExprNode::Binding),
-        seq4((&id, st("["), separate(&expr, &commas, 1), st("]")), |x| ExprNode::Macro(x.0, x.2)),
+        seq((&id, st("["), separate(&expr, &commas, 1), st("]")), |x| ExprNode::Macro(x.0, x.2)),
         map(term, ExprNode::Term),
       ]));
 
-      // A parser for an RHS item, which is a marked-up expr.
+      // A parser for an RHS item, which is a marked-up expr. Terms marked with "^" or "*"
+      // agree with each other; if no term is marked, all terms except those marked "!"
+      // agree. Use "!" for a dative subject, so that its verb agrees with the object.
       let mark = any(&[
+        map(st("!"), |_| MarkNode::Exclude),
         map(st("*"), |_| MarkNode::Max),
         map(st("^"), |_| MarkNode::Min),
         succeed(|| MarkNode::Skip),
       ]);
-      let item = seq4(
-        (expr, opt(seq2((st(":"), index), |x| x.1)), opt(st("?")), mark),
+      let item = seq(
+        (expr, opt(seq((st(":"), index), |x| x.1)), opt(st("?")), mark),
         |x| ItemNode { expr: x.0, index: x.1, mark: x.3, optional: x.2.is_some() }
       );
 
       // A parser for a rule's associated metadata.
-      let tense = seq3((&id, &ws, &id), |x| x);
       let entry = any(&[
-        seq3((st("<"), &ws, &number), |x| DataNode::Merge(x.2)),
-        seq3((st(">"), &ws, &number), |x| DataNode::Split(x.2)),
-        seq3((st("="), &ws, &string), |x| DataNode::Template(x.2)),
-        seq3((st("?"), &ws, tense), |x| DataNode::Tense((x.2).0, (x.2).2)),
+        seq((st("<"), &ws, &number), |x| DataNode::Merge(x.2)),
+        seq((st(">"), &ws, &number), |x| DataNode::Split(x.2)),
+        seq((st("="), &ws, &string), |x| DataNode::Template(x.2)),
+        seq((st("?"), &ws, &id, &ws, &id), |x| DataNode::Tense(x.2, x.4)),
       ]);
-      let tuple = seq3((st("("), entry, st(")")), |x| x.1);
+      let tuple = seq((st("("), entry, st(")")), |x| x.1);
       let metas = separate(tuple, &ws, 0);
 
       // A parser for a complete list of RHS options for a macro or a rule.
@@ -339,11 +483,10 @@ fn parse(input: &str) -> Result<Vec<RootNode>> {
         map(st(">"), |_| Some(DataNode::Merge(std::f32::NEG_INFINITY))),
         map(st("="), |_| None),
       ]);
-      let once = seq3((list, &ws, &metas), |x| x);
-      let side = seq3((sign, &ws, once), |x| x);
-      let rule = seq3((&metas, &ws, separate(side, &ws, 1)), |(rule_data, _, sides)| {
-        let rules = sides.into_iter().map(|(sign_data, _, (rhs, _, side_data))| {
-          let mut rule = RuleNode { rhs, ..RuleNode::default() };
+      let side = spanned(seq((sign, &ws, list, &ws, &metas), |x| x));
+      let rule = seq((&metas, &ws, separate(side, &ws, 1)), |(rule_data, _, sides)| {
+        let rules = sides.into_iter().map(|((sign_data, _, rhs, _, side_data), span)| {
+          let mut rule = RuleNode { line: span.start, rhs, ..RuleNode::default() };
           let data = rule_data.iter().chain(sign_data.iter()).chain(side_data.iter());
           data.for_each(|z| match z {
             DataNode::Merge(x) => rule.merge = *x,
@@ -356,54 +499,333 @@ fn parse(input: &str) -> Result<Vec<RootNode>> {
         rules.collect::<Vec<_>>()
       });
 
-      // Our top-level grammar parser.
-      let args = seq3((st("["), separate(binding, commas, 1), st("]")), |x| x.1);
+      // Our top-level grammar parser. Lexer blocks may be named: "lexer(name): ```...```".
+      // They may also be read from a file or directory of tables: "lexer: file("vocab/")".
+      // Aliases let a grammar keep an old symbol name alive: "alias $Old = $New".
+      // Features declare the categories used in agreement: "feature count: plural singular".
+      // Their values may have codes for the lexer's tense codes: "feature count: plural/p".
+      // Politeness features are marked: "feature tone (politeness): casual formal".
+      let args = separate(binding, commas, 1);
+      let name = opt(seq((st("("), &id, st(")")), |x| x.1));
+      let block = regexp(r#": ```[\s\S]*?```"#, |x| x[5..x.len() - 3].to_string());
+      let file = regexp(r#": file\("[^"]*"\)"#, |x| x[8..x.len() - 2].to_string());
+      let code = opt(regexp(r#"/[^\s.]"#, |x| x.to_string()));
+      let value = seq((&id, code), |x| format!("{}{}", x.0, x.1.unwrap_or_default()));
+      let values = separate(value, st(" "), 1);
+      let politeness = opt(st(" (politeness)"));
       let update = any(&[
-        regexp(r#"lexer: ```[\s\S]*```"#, |x| RootNode::Lexer(x[10..x.len() - 3].to_string())),
-        seq4((&id, args, &ws, &rule), |x| RootNode::Macro(MacroNode { name: x.0, args: x.1, rules: x.3 })),
-        seq4((&symbol, opt(st("!")), &ws, &rule), |x| RootNode::Rules(SymbolNode { lhs: x.0, root: x.1.is_some(), rules: x.3 })),
+        seq((st("feature "), &ws, &id, politeness, st(":"), &ws, values), |x| {
+          RootNode::Feature(x.2, x.3.is_some(), x.6)
+        }),
+        seq((st("alias "), &ws, &symbol, &ws, st("="), &ws, &symbol), |x| {
+          RootNode::Alias(x.2, x.6)
+        }),
+        seq((st("lexer"), &name, block), |x| RootNode::Lexer(x.1, x.2)),
+        seq((st("lexer"), &name, file), |x| RootNode::LexerFile(x.1, x.2)),
+        seq((&id, st("["), args, st("]"), &ws, &rule), |x| {
+          RootNode::Macro(MacroNode { name: x.0, args: x.2, rules: x.5 })
+        }),
+        seq((&symbol, opt(st("!")), &ws, &rule), |x| {
+          RootNode::Rules(SymbolNode { lhs: x.0, root: x.1.is_some(), rules: x.3 })
+        }),
       ]);
-      seq3((&ws, separate(update, &ws, 1), &ws), |x| x.1)
+
+      // After an error, we skip to the next line that starts a new top-level item, so we
+      // can report errors in later items, too.
+      let sync = followed_by(regexp(r#"\n[ \t]*"#, |_| ()), regexp("[$a-zA-Z_]", |_| ()));
+      let updates = separate(recover(update, sync), &ws, 1);
+      seq((&ws, updates, &ws), |x| x.1.into_iter().flatten().collect())
     };
   }
 
-  PARSER.with(|x| x.parse(input))
+  // The parser records each rule's byte offset. Convert those offsets to line numbers.
+  let result = PARSER.with(|x| x.parse_all(input));
+  let errors = |x: Vec<Error>| {
+    let message = x.iter().map(|y| format!("{:?}", y)).collect::<Vec<_>>().join("\n");
+    Error::GrammarCompile(message, x.first().and_then(|y| y.location()))
+  };
+  let mut result = result.map_err(errors)?;
+  let newlines: Vec<_> = input.match_indices('\n').map(|x| x.0).collect();
+  let line = |x: usize| match newlines.binary_search(&x) {
+    Ok(i) | Err(i) => i + 1,
+  };
+  result.iter_mut().for_each(|x| match x {
+    RootNode::Macro(x) => x.rules.iter_mut().for_each(|y| y.line = line(y.line)),
+    RootNode::Rules(x) => x.rules.iter_mut().for_each(|y| y.line = line(y.line)),
+    _ => (),
+  });
+  Ok(result)
 }
 
-// Our public API is a simple function.
+// Our public API is a simple function. Use compile_routed to supply lexers for named blocks.
+// Errors from the grammar itself are GrammarCompile errors; syntax errors have a location.
 
-pub fn compile<F: Fn(&str) -> Result<Box<Lexer<T>>>, T: Payload>(
+pub fn compile<F: Fn(&str, &TenseSchema) -> Result<Box<Lexer<T>>>, T: Payload>(
   input: &str,
   lexer: F,
 ) -> Result<Grammar<T>> {
-  let (mut lexers, mut macros, mut symbol) = (vec![], vec![], vec![]);
-  parse(input)?.into_iter().for_each(|x| match x {
-    RootNode::Lexer(x) => lexers.push(x),
-    RootNode::Macro(x) => macros.push(x),
-    RootNode::Rules(x) => symbol.push(x),
-  });
+  compile_routed(input, lexer, &[])
+}
+
+pub fn compile_routed<F: Fn(&str, &TenseSchema) -> Result<Box<Lexer<T>>>, T: Payload>(
+  input: &str,
+  lexer: F,
+  routes: &[(&str, &Factory<T>)],
+) -> Result<Grammar<T>> {
+  let result = build(input, lexer, routes);
+  result.map_err(|x| x.tag(|y| Error::GrammarCompile(y, None)))
+}
+
+fn build<F: Fn(&str, &TenseSchema) -> Result<Box<Lexer<T>>>, T: Payload>(
+  input: &str,
+  lexer: F,
+  routes: &[(&str, &Factory<T>)],
+) -> Result<Grammar<T>> {
+  let (mut aliases, mut features, mut lexers, mut macros) = (vec![], vec![], vec![], vec![]);
+  let (mut politeness, mut routed, mut symbol) = (vec![], vec![], vec![]);
+  for root in parse(input)? {
+    match root {
+      RootNode::Alias(x, y) => aliases.push((x, y)),
+      RootNode::Feature(x, y, z) => {
+        if y {
+          politeness.push(x.clone());
+        }
+        features.push((x, z));
+      }
+      RootNode::Lexer(None, x) => lexers.push(x),
+      RootNode::Lexer(Some(name), x) => routed.push((name, x)),
+      RootNode::LexerFile(None, x) => lexers.push(read_tables(&[&x])?),
+      RootNode::LexerFile(Some(name), x) => routed.push((name, read_tables(&[&x])?)),
+      RootNode::Macro(x) => macros.push(x),
+      RootNode::Rules(x) => symbol.push(x),
+    }
+  }
   if lexers.len() != 1 {
     Err(format!("Expected: 1 lexer block; got: {}", lexers.len()))?;
   }
 
+  let mut features = TenseSchema::new(&features)?;
+  politeness.iter().try_for_each(|x| features.set_politeness(x))?;
+  let mut base = lexer(&lexers[0], &features).map_err(|x| x.tag(Error::Lexer))?;
+  if !routed.is_empty() {
+    let mut lexers = vec![];
+    for (name, text) in routed {
+      let prefix = format!("%{}_", name);
+      if lexers.iter().any(|(x, _)| *x == prefix) {
+        Err(format!("Duplicate lexer block: {}", name))?;
+      }
+      let route = routes.iter().find(|x| x.0 == name);
+      let factory = route.ok_or_else(|| format!("Unbound lexer block: {}", name))?.1;
+      lexers.push((prefix, factory(&text, &features).map_err(|x| x.tag(Error::Lexer))?));
+    }
+    base = Box::new(RoutedLexer { base, routes: lexers });
+  }
+
   let mut state: State<T> = State {
+    alias: HashMap::default(),
     binding: HashMap::default(),
-    grammar: Grammar { lexer: lexer(&lexers[0])?, names: vec![], rules: vec![], start: 0 },
+    grammar: Grammar {
+      features,
+      lexer: base,
+      names: vec![],
+      rules: vec![],
+      start: 0,
+    },
     macros: HashMap::default(),
     symbol: HashMap::default(),
   };
 
   state.get_symbol("$ROOT");
+  state.process_aliases(&aliases, &symbol)?;
   macros.into_iter().try_for_each(|x| state.process_macro(x))?;
   symbol.iter().try_for_each(|x| state.process_rules(&x.lhs, &x.rules))?;
   symbol.iter().filter(|x| x.root).for_each(|x| state.process_start(&x.lhs));
-  state.validate()
+  let mut grammar = state.validate()?;
+  grammar.rules.iter_mut().enumerate().for_each(|(i, x)| x.metadata.id = Some(i));
+  Ok(grammar)
+}
+
+// An opt-in lint pass over a compiled grammar. It warns about rules for a symbol with identical
+// RHS terms, left-recursive symbols without a base case, which stall generation, and epsilon
+// cycles, which let a symbol derive itself without consuming any input.
+
+fn cyclic(graph: &[Vec<usize>], x: usize) -> bool {
+  let (mut seen, mut stack) = (HashSet::default(), graph[x].clone());
+  while let Some(y) = stack.pop() {
+    if y == x {
+      return true;
+    } else if seen.insert(y) {
+      stack.extend(graph[y].iter().cloned());
+    }
+  }
+  false
+}
+
+fn fixpoint<T>(rules: &[Rule<T>], n: usize, f: impl Fn(&[bool], &Term) -> bool) -> Vec<bool> {
+  let (mut changed, mut result) = (true, vec![false; n]);
+  while changed {
+    changed = false;
+    for rule in rules {
+      if !result[rule.lhs] && rule.rhs.iter().all(|x| f(&result, x)) {
+        result[rule.lhs] = true;
+        changed = true;
+      }
+    }
+  }
+  result
+}
+
+pub fn lint<T>(grammar: &Grammar<T>) -> Vec<String> {
+  let Grammar { names, rules, .. } = grammar;
+  let n = names.len();
+  let describe = |x: &Rule<T>| match x.metadata.line {
+    Some(line) => format!("{} (line {})", names[x.lhs], line),
+    None => names[x.lhs].clone(),
+  };
+  let symbol = |x: usize| rules.iter().find(|y| y.lhs == x).map(describe).unwrap();
+  let mut result = vec![];
+
+  // Check for pairs of rules with the same LHS and RHS.
+  let mut seen: HashMap<(usize, &[Term]), &Rule<T>> = HashMap::default();
+  for rule in rules {
+    if let Some(x) = seen.insert((rule.lhs, &rule.rhs), rule) {
+      result.push(format!("Ambiguous rules: {} and {}", describe(x), describe(rule)));
+    }
+  }
+
+  // Build graphs of the symbols that a symbol may start with or derive without input.
+  let nullable = fixpoint(rules, n, |x, y| if let Term::Symbol(z) = y { x[*z] } else { false });
+  let productive = fixpoint(rules, n, |x, y| if let Term::Symbol(z) = y { x[*z] } else { true });
+  let null = |x: &Term| if let Term::Symbol(y) = x { nullable[*y] } else { false };
+  let (mut left, mut epsilon) = (vec![vec![]; n], vec![vec![]; n]);
+  for rule in rules {
+    for (i, x) in rule.rhs.iter().enumerate() {
+      if let Term::Symbol(y) = x {
+        left[rule.lhs].push(*y);
+        if rule.rhs.iter().enumerate().all(|(j, z)| i == j || null(z)) {
+          epsilon[rule.lhs].push(*y);
+        }
+      }
+      if !null(x) {
+        break;
+      }
+    }
+  }
+
+  // Report symbols involved in a cycle in either graph.
+  let stalls = (0..n).filter(|x| !productive[*x] && cyclic(&left, *x));
+  stalls.for_each(|x| result.push(format!("Left recursion without a base case: {}", symbol(x))));
+  let cycles = (0..n).filter(|x| cyclic(&epsilon, *x));
+  cycles.for_each(|x| result.push(format!("Epsilon cycle: {}", symbol(x))));
+  result
+}
+
+// We can also render a compiled grammar back to canonical grammar text. Macros have been
+// expanded by this point, so instantiations like "NOUN[%drink]" become plain symbols.
+// Optional symbols and the $ROOT symbol are implied by "?" and "!" markers.
+
+pub fn render<T>(grammar: &Grammar<T>, lexer: &str) -> String {
+  let Grammar { features, names, rules, start, .. } = grammar;
+  let symbol: HashMap<_, _> = names.iter().enumerate().map(|(i, x)| (x.as_str(), i)).collect();
+  let valid = |x: &str| x.len() > 1 && x[1..].chars().all(|y| y.is_ascii_alphabetic() || y == '_');
+  let mut used: HashSet<_> = names.iter().filter(|x| valid(x)).cloned().collect();
+  let mangled: Vec<_> = names.iter().map(|x| {
+    if valid(x) || x.ends_with('?') {
+      return x.clone();
+    }
+    let base: String = x.chars().map(|y| if y.is_ascii_alphabetic() { y } else { '_' }).collect();
+    let mut result = format!("${}", base);
+    while !used.insert(result.clone()) {
+      result.push('_');
+    }
+    result
+  }).collect();
+  let name = |x: &str| -> String {
+    if !x.ends_with('?') {
+      return symbol.get(x).map(|y| mangled[*y].clone()).unwrap_or_else(|| x.to_string());
+    }
+    let base = &x[..x.len() - 1];
+    format!("{}?", symbol.get(base).map(|y| mangled[*y].as_str()).unwrap_or(base))
+  };
+
+  let roots: HashSet<_> = rules.iter().filter(|x| x.lhs == *start).map(|x| &x.rhs[..]).collect();
+  let item = |rule: &Rule<T>, i: usize| {
+    let term = match &rule.rhs[i] {
+      Term::Symbol(x) => name(&names[*x]),
+      Term::Terminal(x) => x.clone(),
+    };
+    let (index, mark) = rule.metadata.items.get(i).cloned().unwrap_or((None, ' '));
+    let optional = if term.ends_with('?') { "?" } else { "" };
+    let base = &term[..term.len() - optional.len()];
+    let index = index.map(|x| format!(":{}", x)).unwrap_or_default();
+    format!("{}{}{}{}", base, index, optional, mark.to_string().trim())
+  };
+  let line = |rule: &Rule<T>| {
+    let (merge, split) = (rule.merge.score, rule.split.score);
+    let sign = if merge == f32::NEG_INFINITY {
+      ">"
+    } else if split == f32::NEG_INFINITY {
+      "<"
+    } else {
+      "="
+    };
+    let mut result = vec![sign.to_string()];
+    let items: Vec<_> = (0..rule.rhs.len()).map(|i| item(rule, i)).collect();
+    result.push(if items.is_empty() { "NONE".into() } else { items.join(" ") });
+    [("<", merge), (">", split)].iter().filter(|x| x.1 != 0.0 && x.1.is_finite()).for_each(|x| {
+      result.push(format!("({} {})", x.0, x.1));
+    });
+    rule.metadata.template.iter().for_each(|x| {
+      let quote = if x.contains('\'') { '"' } else { '\'' };
+      result.push(format!("(= {}{}{})", quote, x, quote));
+    });
+    rule.metadata.tense.iter().for_each(|x| result.push(format!("(? {} {})", x.0, x.1)));
+    result.join(" ")
+  };
+
+  // Order symbols by a depth-first traversal from the start symbol, so that the output does
+  // not depend on the order in which symbols were assigned ids.
+  let (mut order, mut seen, mut stack) = (vec![], HashSet::default(), vec![*start]);
+  while let Some(x) = stack.pop() {
+    if !seen.insert(x) {
+      continue;
+    }
+    order.push(x);
+    let rhs = rules.iter().filter(|y| y.lhs == x).flat_map(|y| y.rhs.iter());
+    let children = rhs.filter_map(|y| if let Term::Symbol(z) = y { Some(*z) } else { None });
+    let mut children: Vec<_> = children.collect();
+    children.reverse();
+    stack.append(&mut children);
+  }
+
+  let mut result = vec![];
+  if !features.is_empty() {
+    let mark = |x: &str| if features.politeness(x) { " (politeness)" } else { "" };
+    let lines = features.features().into_iter().map(|x| {
+      format!("feature {}{}: {}\n", x.0, mark(&x.0), x.1.join(" "))
+    });
+    result.push(lines.collect::<Vec<_>>().join(""));
+  }
+  for i in order {
+    let x = &names[i];
+    let block: Vec<_> = rules.iter().filter(|y| y.lhs == i).map(&line).collect();
+    if i == *start || x.ends_with('?') || block.is_empty() {
+      continue;
+    }
+    let root = roots.contains(&[Term::Symbol(i)][..]);
+    result.push(format!("{}{}\n{}\n", mangled[i], if root { "!" } else { "" }, block.join("\n")));
+  }
+  result.push(format!("lexer: ```{}```\n", lexer));
+  result.join("\n")
 }
 
 #[cfg(test)]
 mod tests {
-  use super::super::super::hindi::lexer::HindiLexer;
-  use super::super::super::nlu::corrector::Corrector;
+  use super::super::super::hindi::normalize::{Lowercase, Pipeline};
+  use super::super::super::hindi::lexer::{HindiLexer, Usage};
+  use super::super::super::hindi::vocabulary::Entry;
+  use super::super::super::nlu::corrector::Category::{Agreement, Politeness};
+  use super::super::super::nlu::corrector::{Corrector, Diff};
   use super::super::super::nlu::generator::Generator;
   use super::super::super::nlu::parser::Parser;
   use super::super::super::payload::lambda::Lambda;
@@ -417,11 +839,599 @@ mod tests {
     Ok(grammar.map_err(|x| format!("Failed to compile grammar: {}\n\n{:?}", file, x))?)
   }
 
+  struct WordLexer(Vec<String>, fn(&str) -> bool);
+
+  impl WordLexer {
+    fn boxed(text: &str, valid: fn(&str) -> bool) -> Result<Box<Lexer<Lambda>>> {
+      Ok(Box::new(WordLexer(text.split_whitespace().map(|x| x.to_string()).collect(), valid)))
+    }
+  }
+
+  impl super::super::base::Lexer<Option<Lambda>, Lambda> for WordLexer {
+    fn fix(&self, _: &Match<Lambda>, _: &Tense) -> Vec<Arc<Match<Lambda>>> {
+      vec![]
+    }
+
+    fn lex<'a: 'b, 'b>(&'a self, input: &'b str) -> Vec<Token<'b, Lambda>> {
+      let iter = input.split(' ').map(|x| {
+        let mut matches = HashMap::default();
+        let value = Lambda::base_lex(x);
+        let entry = (0.0, Arc::new(Match { tenses: vec![], texts: HashMap::default(), value }));
+        self.0.iter().filter(|y| self.1(x) || *y == x).for_each(|y| {
+          std::mem::drop(matches.insert(Terminal::new(y), entry.clone()));
+        });
+        Token { matches, text: x }
+      });
+      iter.collect()
+    }
+
+    fn unlex(&self, name: &str, value: &Option<Lambda>) -> Vec<Arc<Match<Lambda>>> {
+      let value = value.clone().unwrap_or_default();
+      let valid = value.base_unlex().map(|x| self.1(x) || x == name).unwrap_or(true);
+      if !self.0.iter().any(|x| x == name) || !valid {
+        return vec![];
+      }
+      vec![Arc::new(Match { tenses: vec![], texts: HashMap::default(), value })]
+    }
+  }
+
+  fn digits(x: &str) -> bool {
+    !x.is_empty() && x.chars().all(|y| y.is_ascii_digit())
+  }
+
+  fn words(_: &str) -> bool {
+    false
+  }
+
+  fn hindi_lexer(data: &str) -> HindiLexer<Lambda> {
+    let base = regex::Regex::new(r#"lexer: ```[\s\S]*```"#).unwrap().find(data).unwrap();
+    let features = parse(data).unwrap().into_iter().filter_map(|x| match x {
+      RootNode::Feature(x, _, y) => Some((x, y)),
+      _ => None,
+    });
+    let features = TenseSchema::new(&features.collect::<Vec<_>>()).unwrap();
+    HindiLexer::create(&data[base.start() + 10..base.end() - 3], &features).unwrap()
+  }
+
   #[test]
   fn smoke_test() {
     make_grammar().unwrap();
   }
 
+  #[test]
+  fn lexer_blocks_are_routed_by_prefix() {
+    let data = "
+      $Order! (= 'order.$0')
+      = %num_digit roti
+      lexer: ```roti %num_digit```
+      lexer(num): ```%num_digit```
+    ";
+    let default = |x: &str, _: &TenseSchema| WordLexer::boxed(x, words);
+    let routed = |x: &str, _: &TenseSchema| WordLexer::boxed(x, digits);
+    let grammar = compile_routed(data, default, &[("num", &routed)]).unwrap();
+    let parser = Parser::new(&grammar);
+    assert_eq!(parser.value("3 roti").map(|x| x.repr().to_string()), Some("order.3".into()));
+    assert!(parser.value("roti roti").is_none());
+  }
+
+  #[test]
+  fn lexer_blocks_must_be_bound() {
+    let data = "
+      $Order! = roti
+      lexer: ```roti```
+      lexer(num): ```%num_digit```
+    ";
+    let result = compile(data, |x, _| WordLexer::boxed(x, words));
+    assert_eq!(result.err(), Some(Error::GrammarCompile("Unbound lexer block: num".into(), None)));
+  }
+
+  #[test]
+  fn aliases_share_a_symbol() {
+    let data = "
+      alias $Bread = $Food
+      alias $Roti = $Bread
+      $Order! (= 'order.$0') = $Roti $Food?
+      $Food (= 'roti') = roti
+      lexer: ```roti```
+    ";
+    let grammar = compile(data, |x, _| WordLexer::boxed(x, words)).unwrap();
+    assert!(!grammar.names.iter().any(|x| x == "$Roti" || x == "$Bread"));
+    assert!(grammar.names.iter().any(|x| x == "$Food?"));
+    let parser = Parser::new(&grammar);
+    assert_eq!(parser.value("roti").map(|x| x.repr().to_string()), Some("order.roti".into()));
+  }
+
+  #[test]
+  fn aliases_must_resolve() {
+    let lexer = |x: &str, _: &TenseSchema| WordLexer::boxed(x, words);
+    let base = "$Order! = roti\nlexer: ```roti```\n";
+    let check = |x: &str| compile(&format!("{}{}", base, x), lexer).err();
+    let error = |x: &str| Some(Error::GrammarCompile(x.into(), None));
+    assert_eq!(check("alias $Roti = $Food"), error("Dangling aliases: $Roti -> $Food"));
+    assert_eq!(check("alias $A = $B\nalias $B = $A"), error("Cyclic alias: $A"));
+    assert_eq!(check("alias $Order = $Order"), error("Duplicate alias: $Order"));
+  }
+
+  #[test]
+  fn syntax_errors_are_all_reported() {
+    let data = "
+      $Order! (= 'order.$0') = $Food pani)
+      $Food (= 'roti') = roti (< -x)
+      $Drink = pani
+      $Sweet (? count) = laddu
+      lexer: ```roti pani laddu```
+    ";
+    let error = format!("{:?}", compile(data, |x, _| WordLexer::boxed(x, words)).err().unwrap());
+    let lines: Vec<_> = error.lines().filter(|x| x.starts_with("At line")).collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("At line 2, column 42:"));
+    assert_eq!(lines[1], "At line 3, column 34: expected: comment | number");
+    assert!(lines[2].starts_with("At line 5, column 22:"));
+  }
+
+  #[test]
+  fn errors_have_kinds() {
+    let lexer = |x: &str, _: &TenseSchema| WordLexer::boxed(x, words);
+    let kind = |x: &str| match compile(&format!("lexer: ```roti```\n{}", x), lexer).err() {
+      Some(Error::GrammarCompile(_, y)) => format!("GrammarCompile {:?}", y.map(|z| z.line)),
+      Some(Error::Template(_)) => "Template".into(),
+      Some(y) => format!("{:?}", y),
+      None => "Ok".into(),
+    };
+    assert_eq!(kind("$Order! = roti"), "Ok");
+    assert_eq!(kind("$Order! = roti\n$Order = )"), "GrammarCompile Some(3)");
+    assert_eq!(kind("$Order! = $Food"), "GrammarCompile None");
+    assert_eq!(kind("$Order! (= 'order.(') = roti"), "Template");
+    let error = compile::<_, Lambda>("lexer: ```roti```", |_, _| Err("Invalid lexer".into()));
+    assert!(matches!(error.err(), Some(Error::Lexer(_))));
+  }
+
+  #[test]
+  fn features_must_be_declared() {
+    let lexer = |x: &str, _: &TenseSchema| WordLexer::boxed(x, words);
+    let base = "feature count: plural singular\nlexer: ```roti```\n";
+    let check = |x: &str| compile(&format!("{}{}", base, x), lexer).err();
+    let error = |x: &str| Some(Error::GrammarCompile(x.into(), None));
+    let grammar = compile(&format!("{}$Order! = roti (? count plural)", base), lexer).unwrap();
+    assert!(render(&grammar, "roti").starts_with("feature count: plural singular\n"));
+    let invalid = "Invalid value for feature count: many";
+    assert_eq!(check("$Order! = roti (? count many)"), error(invalid));
+    assert_eq!(check("$Order! = roti (? case direct)"), error("Undeclared feature: case"));
+    let duplicate = "feature count: plural\n$Order! = roti";
+    assert_eq!(check(duplicate), error("Duplicate feature: count"));
+    let coded = "feature case: direct/d oblique/o\n$Order! = roti";
+    let grammar = compile(&format!("{}{}", base, coded), lexer).unwrap();
+    assert!(render(&grammar, "roti").contains("feature case: direct/d oblique/o\n"));
+    let duplicate = "feature case: direct/d oblique/d\n$Order! = roti";
+    assert_eq!(check(duplicate), error("Duplicate code for feature case: oblique/d"));
+    let polite = "feature tone (politeness): casual formal\n$Order! = roti";
+    let grammar = compile(&format!("{}{}", base, polite), lexer).unwrap();
+    assert!(grammar.features.politeness("tone") && !grammar.features.politeness("count"));
+    assert!(render(&grammar, "roti").contains("feature tone (politeness): casual formal\n"));
+
+    // The Hindi lexer writes tenses with its features' codes. Grammars may leave them
+    // out, but if they declare them, they must use the lexer's layout.
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let lines = || data.lines().filter(|x| !x.starts_with("feature "));
+    let undeclared = lines().collect::<Vec<_>>().join("\n");
+    assert!(compile(&undeclared, HindiLexer::<Lambda>::new).is_ok());
+    let features = data.lines().filter(|x| x.starts_with("feature ")).collect::<Vec<_>>();
+    let reordered = features.iter().rev().cloned().chain(lines()).collect::<Vec<_>>();
+    let error = compile(&reordered.join("\n"), HindiLexer::<Lambda>::new).err().unwrap();
+    assert!(format!("{:?}", error).contains("Feature count must be declared at 1, not 7"));
+  }
+
+  #[test]
+  fn dative_subjects_are_excluded_from_agreement() {
+    let data = "
+      $Want! = $Person! roti chahie = $Person! roti^ chahie^ = $Person roti chahie
+      $Person = mujhe
+      lexer: ```roti chahie mujhe```
+    ";
+    let grammar = compile(data, |x, _| WordLexer::boxed(x, words)).unwrap();
+    let want = grammar.names.iter().position(|x| x == "$Want").unwrap();
+    let rules: Vec<_> = grammar.rules.iter().filter(|x| x.lhs == want).collect();
+    let precedence: Vec<_> = rules.iter().map(|x| x.precedence.clone()).collect();
+    assert_eq!(precedence, [vec![1, 2], vec![1, 2], vec![0, 1, 2]]);
+    assert!(render(&grammar, "roti chahie mujhe").contains("= $Person! roti chahie\n"));
+  }
+
+  #[test]
+  fn postpositions_take_oblique_nouns() {
+    let grammar = make_grammar().unwrap();
+    let tree = Parser::new(&grammar).parse("accha larka ko pani chahie").unwrap();
+    let mut rng = rand::SeedableRng::from_seed([17; 32]);
+    let correction = Corrector::new(&grammar).correct(&mut rng, &tree);
+    let matches = correction.tree.matches();
+    let texts: Vec<_> = matches.iter().map(|x| &*x.texts["latin"]).collect();
+    assert_eq!(texts, ["acche", "larke", "ko", "pani", "chahie"]);
+  }
+
+  #[test]
+  fn compound_verbs_work() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    assert_eq!(value("main pani pi lunga"), Some("Tell(I, want.type.water)".into()));
+    assert_eq!(value("ap kya kha lenge"), Some("Ask((R[want]).you)".into()));
+  }
+
+  #[test]
+  fn compound_numerals_work() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    let want = |x| Some(format!("Tell(I, want.(count.{} & type.bread))", x));
+    assert_eq!(value("mujhe pachas roti chahie"), want(50));
+    assert_eq!(value("mujhe do sau roti chahie"), want(200));
+    assert_eq!(value("mujhe ek lakh roti chahie"), want(100000));
+    let generator = Generator::new(&grammar);
+    let mut rng = rand::SeedableRng::from_seed([17; 32]);
+    let semantics = Some(Lambda::parse("Tell(I, want.(count.300 & type.bread))").unwrap());
+    let tree = generator.generate(&mut rng, &semantics).unwrap();
+    let matches = tree.matches();
+    let texts: Vec<_> = matches.iter().map(|x| &*x.texts["latin"]).collect();
+    assert!(texts.windows(2).any(|x| x == ["tin", "sau"]), "{:?}", texts);
+  }
+
+  #[test]
+  fn digits_lex_as_numbers() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    let want = |x: &str| Some(format!("Tell(I, want.(count.{} & type.bread))", x));
+    assert_eq!(value("mujhe 2 roti chahie"), want("2"));
+    assert_eq!(value("mujhe 250 roti chahie"), want("250"));
+    assert_eq!(value("mujhe \u{968}\u{966} roti chahie"), want("20"));
+    assert_eq!(value("mujhe 2x roti chahie"), None);
+    assert_eq!(value("mujhe 2-3 roti chahie"), want("Range(2, 3)"));
+    assert_eq!(value("mujhe \u{968}\u{2013}\u{969}\u{966} roti chahie"), want("Range(2, 30)"));
+    assert_eq!(value("mujhe 3-2 roti chahie"), None);
+    let tokens = grammar.lexer.lex("2-3");
+    let (_, m) = &tokens[0].matches[&Terminal::new("%number")];
+    assert_eq!(m.tenses[0].get("count"), Some("plural".into()));
+    assert_eq!(&*m.texts["hindi"], "\u{968}-\u{969}");
+  }
+
+  #[test]
+  fn honorific_pronouns_take_formal_agreement() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let correct = |x: &str| {
+      let tree = parser.parse(x).unwrap();
+      let mut rng = rand::SeedableRng::from_seed([17; 32]);
+      let correction = Corrector::new(&grammar).correct(&mut rng, &tree);
+      let matches = correction.tree.matches();
+      let texts: Vec<_> = matches.iter().map(|x| x.texts["latin"].to_string()).collect();
+      let categories = correction.diff.iter().filter_map(|x| match x {
+        Diff::Right(_) => None,
+        Diff::Wrong(y) => Some(y.category),
+      });
+      (texts.join(" "), categories.collect::<Vec<_>>())
+    };
+    assert_eq!(correct("ap kya chahte hain"), ("ap kya chahte hain".into(), vec![]));
+    assert_eq!(correct("ap kya chahte ho"), ("ap kya chahte hain".into(), vec![Politeness]));
+    assert_eq!(correct("ap kya loge"), ("ap kya lenge".into(), vec![Politeness]));
+    assert_eq!(correct("ap kya khaega").1, [Politeness]);
+    assert_eq!(correct("tum kya chahte hain").1, [Agreement]);
+  }
+
+  #[test]
+  fn progressive_verbs_agree_with_subjects() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    assert_eq!(value("main pani pi raha hun"), Some("Tell(I, want.type.water)".into()));
+    let tree = parser.parse("larki pani pi raha hai").unwrap();
+    let mut rng = rand::SeedableRng::from_seed([17; 32]);
+    let correction = Corrector::new(&grammar).correct(&mut rng, &tree);
+    let matches = correction.tree.matches();
+    let texts: Vec<_> = matches.iter().map(|x| &*x.texts["latin"]).collect();
+    assert_eq!(texts, ["larki", "pani", "pi", "rahi", "hai"]);
+  }
+
+  #[test]
+  fn ergative_verbs_agree_with_objects() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    assert_eq!(value("maine roti khayi"), Some("Tell(I, want.type.bread)".into()));
+    for (input, expected) in &[("maine roti khaya", "khayi"), ("larke ne roti khaya", "khayi")] {
+      let tree = parser.parse(input).unwrap();
+      let mut rng = rand::SeedableRng::from_seed([17; 32]);
+      let correction = Corrector::new(&grammar).correct(&mut rng, &tree);
+      let matches = correction.tree.matches();
+      assert_eq!(&*matches.last().unwrap().texts["latin"], *expected);
+    }
+  }
+
+  #[test]
+  fn lexer_blocks_can_be_read_from_files() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let (start, end) = (data.find("lexer: ```").unwrap(), data.rfind("```").unwrap());
+    let file = std::env::temp_dir().join(format!("fantasy-lexer-{}.txt", std::process::id()));
+    std::fs::write(&file, &data[start + 10..end]).unwrap();
+    let data = format!("{}lexer: file(\"{}\")", &data[..start], file.display());
+    let grammar: Result<Grammar<Lambda>> = compile(&data, HindiLexer::new);
+    std::fs::remove_file(&file).unwrap();
+    let value = Parser::new(&grammar.unwrap()).value("meri bacche ko pani chahie");
+    assert_eq!(value.unwrap().repr(), "Tell(parent.I, want.type.water)");
+  }
+
+  #[test]
+  fn lexer_entries_can_be_added_at_runtime() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let lexer = |x: &str, y: &TenseSchema| -> Result<Box<Lexer<Lambda>>> {
+      let mut lexer = HindiLexer::create(x, y)?;
+      let names = ["%food", "%noun", "%noun_singular", "samosa"];
+      lexer.add_entry(Entry {
+        head: "noun-samosA".into(),
+        hindi: "samosA".into(),
+        latin: "samosa".into(),
+        scores: names.iter().map(|x| (x.to_string(), 0.0)).collect(),
+        tenses: vec![],
+        value: "type.samosa".into(),
+      })?;
+      Ok(Box::new(lexer))
+    };
+    let grammar = compile(&data, lexer).unwrap();
+    let value = Parser::new(&grammar).value("mujhe samosa chahie");
+    assert_eq!(value.unwrap().repr(), "Tell(I, want.type.samosa)");
+  }
+
+  #[test]
+  fn lexer_scores_reflect_transliteration() {
+    let grammar = make_grammar().unwrap();
+    let tokens = grammar.lexer.lex("hain");
+    let score = |name: &str| tokens[0].matches.get(&Terminal::new(name)).map(|x| x.0);
+    assert_eq!(score("%copula"), Some(0.0));
+    assert!(score("%verb").unwrap() < -10.0);
+  }
+
+  #[test]
+  fn proper_nouns_are_scored_by_plausibility() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let mut lexer = hindi_lexer(&data);
+    lexer.add_name("Pani");
+    let lexer: Box<Lexer<Lambda>> = Box::new(lexer);
+    let tokens = lexer.lex("Rahul rahul x7 pani");
+    let scores = tokens.iter().map(|x| x.matches.get(&Terminal::new("%proper_noun")).map(|y| y.0));
+    assert_eq!(scores.collect::<Vec<_>>(), [Some(-0.5), Some(-2.0), None, Some(0.0)]);
+
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    assert_eq!(value("mera nam Rahul hai"), Some("Tell(I, name.Rahul)".into()));
+    assert_eq!(value("mera nam x7 hai"), Some("Tell(I, name.x7)".into()));
+  }
+
+  #[test]
+  fn lexer_scores_can_be_learned() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let mut lexer = hindi_lexer(&data);
+    let scores = |x: &HindiLexer<Lambda>, names: &[&str]| {
+      let tokens = super::super::base::Lexer::lex(x, "hai mera");
+      let score = |y: &str| tokens.iter().find_map(|z| z.matches.get(&Terminal::new(y)));
+      names.iter().map(|y| score(y).map(|z| (z.0 * 100.0).round() / 100.0)).collect::<Vec<_>>()
+    };
+    let names = ["%copula", "hai", "hain", "hun", "mere"];
+    assert_eq!(scores(&lexer, &names), [Some(0.0), Some(0.0), Some(-1.0), Some(-1.0), Some(-1.0)]);
+
+    let grammar = make_grammar().unwrap();
+    let mut usage = Usage::default();
+    usage.add_tree(&Parser::new(&grammar).parse("mujhe pani chahie").unwrap());
+    assert_eq!((usage.count("pAnI", "%noun"), usage.count("pAnI", "%drink")), (1, 0));
+    (0..4).for_each(|_| usage.add("hE", "%copula"));
+    (0..3).for_each(|_| usage.add("hE", "hain"));
+    usage.add("hE", "hai");
+
+    // The learned scores are log(5 / 5), log(2 / 5), log(4 / 5), and log(1 / 5). Words
+    // without usage data, like "mera", keep their scores.
+    lexer.learn_scores(&usage);
+    let learned = [Some(0.0), Some(-0.92), Some(-0.22), Some(-1.61), Some(-1.0)];
+    assert_eq!(scores(&lexer, &names), learned);
+  }
+
+  #[test]
+  fn transliterations_are_cached() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let lexer = hindi_lexer(&data);
+    let tokens = |x: &HindiLexer<Lambda>| super::super::base::Lexer::lex(x, "hai kya Hai").len();
+    assert_eq!(tokens(&lexer), 3);
+    let stats = lexer.cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.size), (1, 2, 2));
+
+    let lexer = lexer.set_cache_size(0);
+    assert_eq!(tokens(&lexer), 3);
+    let stats = lexer.cache_stats();
+    assert_eq!((stats.capacity, stats.hits, stats.misses, stats.size), (0, 0, 3, 0));
+  }
+
+  #[test]
+  fn echo_words_work() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    assert_eq!(value("mujhe pani-vani chahie"), Some("Tell(I, want.type.water)".into()));
+    assert_eq!(value("mujhe khana-wana chahie"), Some("Tell(I, want.type.food)".into()));
+    assert_eq!(value("mujhe pani-pani chahie"), None);
+    let tree = parser.parse("mujhe pani-vani chahie").unwrap();
+    let matches = tree.matches();
+    assert_eq!(&*matches[1].texts["approximative"], "vani");
+    assert_eq!(&*matches[1].texts["hindi"], "pAnI-vAnI");
+
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let lexer = hindi_lexer(&data);
+    let lexer: Box<Lexer<Lambda>> = Box::new(lexer.set_echo_words(true));
+    let value = Some(Lambda::parse("type.food").unwrap());
+    let matches = lexer.unlex("%noun", &value);
+    let texts: Vec<_> = matches.iter().map(|x| &*x.texts["latin"]).collect();
+    assert_eq!(texts, ["khana", "khana-vana"]);
+  }
+
+  #[test]
+  fn merged_tokens_are_split() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    assert_eq!(value("mujhepani chahie"), Some("Tell(I, want.type.water)".into()));
+    assert_eq!(value("mujhe panichahie"), Some("Tell(I, want.type.water)".into()));
+    let tokens = grammar.lexer.lex("mujhepani");
+    let texts: Vec<_> = tokens.iter().map(|x| x.text).collect();
+    assert_eq!(texts, ["mujhe", "pani"]);
+    assert!(tokens.iter().all(|x| x.matches.iter().all(|y| (y.1).0 <= -1.0)));
+    assert_eq!(value("mera nam Mohan hai"), Some("Tell(I, name.Mohan)".into()));
+    assert_eq!(value("mera nam Sohan hai"), Some("Tell(I, name.Sohan)".into()));
+  }
+
+  #[test]
+  fn punctuation_is_lexed() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    let ask = value("aap kaun hain");
+    assert!(ask.is_some());
+    assert_eq!(value("aap kaun hain?"), ask);
+    assert_eq!(value("aap kaun hain \u{61f}"), ask);
+    assert_eq!(value("aap kaun hain??"), None);
+    let list = "Tell(I, want.(type.apple | type.bread | type.water))";
+    assert_eq!(value("mujhe roti, seb aur pani chahie"), Some(list.into()));
+
+    let texts = |x: &str| {
+      grammar.lexer.lex(x).iter().map(|y| y.text.to_string()).collect::<Vec<_>>()
+    };
+    assert_eq!(texts("hain?! 1.5,"), ["hain", "?", "!", "1.5", ","]);
+    assert_eq!(texts("... ?"), [".", ".", ".", "?"]);
+    let tokens = grammar.lexer.lex("hain?");
+    let names: Vec<_> = tokens[1].matches.keys().map(|x| x.name()).collect();
+    assert_eq!(names, ["%punct_question"]);
+    let matches = grammar.lexer.unlex("%punct_comma", &None);
+    assert_eq!(matches.iter().map(|x| &*x.texts["latin"]).collect::<Vec<_>>(), [","]);
+  }
+
+  #[test]
+  fn statements_may_end_in_punctuation() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    let water = Some("Tell(I, want.type.water)".into());
+    assert_eq!(value("mujhe pani chahie."), water);
+    assert_eq!(value("mujhe pani chahie\u{964}"), water);
+    assert_eq!(value("mujhe pani chahie!"), water);
+    assert_eq!(value("namaste!"), Some("Hello()".into()));
+    assert_eq!(value("mera nam Rahul hai."), Some("Tell(I, name.Rahul)".into()));
+    assert_eq!(value("mujhe pani chahie?"), None);
+  }
+
+  #[test]
+  fn words_are_normalized() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    let water = Some("Tell(I, want.type.water)".into());
+    assert_eq!(value("mujhhhe pa\u{200c}ni chahiiie"), water);
+    let tokens = grammar.lexer.lex("mujhhhe");
+    assert_eq!(tokens[0].matches[&Terminal::new("mujhe")].0, 0.0);
+    assert_eq!(value("mujhe\u{200b}pani chahie"), water);
+    assert_eq!(value("mujhe pani\u{200c}chahie"), water);
+
+    // Each lexer has its own normalizer. This one doesn't strip zero-width characters.
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let lexer = hindi_lexer(&data);
+    let lexer = lexer.set_normalizer(Box::new(Pipeline::new(vec![Box::new(Lowercase)])));
+    let lexer: Box<Lexer<Lambda>> = Box::new(lexer);
+    let noun = |x: &str| lexer.lex(x)[0].matches.contains_key(&Terminal::new("%noun"));
+    assert!(noun("PANI"));
+    assert!(!noun("pa\u{200c}ni"));
+  }
+
+  #[test]
+  fn unknown_nouns_get_provisional_genders() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let correct = |x: &str| {
+      let tree = parser.parse(x).unwrap();
+      let mut rng = rand::SeedableRng::from_seed([17; 32]);
+      let correction = Corrector::new(&grammar).correct(&mut rng, &tree);
+      let matches = correction.tree.matches();
+      let texts: Vec<_> = matches.iter().map(|x| x.texts["latin"].to_string()).collect();
+      texts.join(" ")
+    };
+    assert_eq!(correct("mujhe bara taxi chahie"), "mujhe bari taxi chahie");
+    assert_eq!(correct("mujhe bari pizza chahie"), "mujhe bara pizza chahie");
+    assert_eq!(correct("mujhe bari coffee chahie"), "mujhe bari coffee chahie");
+  }
+
+  #[test]
+  fn urdu_script_works() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    let urdu = "\u{645}\u{62c}\u{6be}\u{6d2} \u{67e}\u{627}\u{646}\u{6cc} \
+                \u{686}\u{627}\u{6c1}\u{6cc}\u{6d2}";
+    assert_eq!(value(urdu), Some("Tell(I, want.type.water)".into()));
+    // "chahie" is also spelled with a hamza. It is not in our vocabulary, so we read it
+    // back into Latin and transliterate it.
+    let hamza = urdu.replace("\u{6c1}\u{6cc}\u{6d2}", "\u{6c1}\u{626}\u{6d2}");
+    assert_eq!(value(&hamza), Some("Tell(I, want.type.water)".into()));
+    let tree = parser.parse("mujhe pani chahie").unwrap();
+    let texts: Vec<_> = tree.matches().iter().map(|x| x.texts["urdu"].clone()).collect();
+    assert_eq!(texts.join(" "), urdu);
+  }
+
+  #[test]
+  fn english_nouns_pass_through() {
+    let grammar = make_grammar().unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    assert_eq!(value("mujhe coffee chahie"), Some("Tell(I, want.coffee)".into()));
+    assert_eq!(value("mujhe pani chahie"), Some("Tell(I, want.type.water)".into()));
+    let tokens = grammar.lexer.lex("coffee pani");
+    let english = Terminal::new("%english");
+    assert!(tokens[0].matches.contains_key(&english));
+    assert!(!tokens[1].matches.contains_key(&english));
+  }
+
+  #[test]
+  fn lints_find_problem_rules() {
+    let data = "
+      $Root! = $A = $B = $C
+      $A = roti
+      $A = roti
+      $B = $B roti
+      $C = $D
+      $D = $C = roti
+      lexer: ```roti```
+    ";
+    let grammar = compile(data, |x, _| WordLexer::boxed(x, words)).unwrap();
+    assert_eq!(
+      lint(&grammar),
+      [
+        "Ambiguous rules: $A (line 3) and $A (line 4)",
+        "Left recursion without a base case: $B (line 5)",
+        "Epsilon cycle: $C (line 6)",
+        "Epsilon cycle: $D (line 7)",
+      ]
+    );
+    assert!(lint(&make_grammar().unwrap()).is_empty());
+  }
+
+  #[test]
+  fn rendering_round_trips() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let lexer = &data[data.find("lexer: ```").unwrap() + 10..data.rfind("```").unwrap()];
+    let grammar = make_grammar().unwrap();
+    let text = render(&grammar, lexer);
+    let other = compile(&text, HindiLexer::new).unwrap();
+    assert_eq!(render(&other, lexer), text);
+    assert_eq!(other.rules.len(), grammar.rules.len());
+    let (a, b) = (Parser::new(&grammar), Parser::new(&other));
+    for input in &["meri bacche ko pani chahie", "do accha acche larki ko pani chahie"] {
+      let value = |x: &Parser<_, Lambda>| x.value(input).map(|y| y.repr().to_string());
+      assert_eq!(value(&a), value(&b));
+    }
+  }
+
   #[bench]
   fn correction_benchmark(b: &mut Bencher) {
     let grammar = make_grammar().unwrap();
@@ -440,6 +1450,30 @@ mod tests {
     b.iter(|| generator.generate(&mut rng, &semantics).unwrap());
   }
 
+  #[bench]
+  fn generation_with_duplicates_benchmark(b: &mut Bencher) {
+    let data = "
+      $List!
+      = $Item $List (= '$0 & $1')
+      = $Item (= '$0')
+      $Item
+      = roti (= 'roti')
+      = dal (= 'dal')
+      lexer: ```roti dal```
+    ";
+    let grammar = compile(data, |x, _| WordLexer::boxed(x, words)).unwrap();
+    let generator = Generator::new(&grammar);
+    let mut rng = rand::SeedableRng::from_seed([17; 32]);
+    let semantics = Some(Lambda::parse("roti & roti & roti & dal & dal & roti & dal").unwrap());
+    b.iter(|| generator.generate(&mut rng, &semantics).unwrap());
+  }
+
+  #[bench]
+  fn grammar_parsing_benchmark(b: &mut Bencher) {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    b.iter(|| parse(&data).unwrap());
+  }
+
   #[bench]
   fn parsing_benchmark(b: &mut Bencher) {
     let grammar = make_grammar().unwrap();
@@ -0,0 +1,290 @@
+use super::super::lib::base::{HashMap, HashSet};
+use super::base::{Grammar, Rule, Term};
+use std::collections::hash_map::Entry;
+
+// Exports a compiled grammar and its lexer's vocabulary as weighted FSTs in OpenFST's text
+// format, for embedded keyword spotting. The FSTs accept the sentences of the grammar, with
+// words written in the given scheme. Their weights are costs in the tropical semiring: each
+// rule's merge score is negated on an epsilon arc at the start of the rule, and each word's
+// lexical score is negated on its arc. Generation-only rules are left out.
+//
+// We emit one FST per symbol and one per terminal with listed vocabulary, for fstreplace:
+// a use of a symbol or terminal is a single arc labeled with its name in angle brackets,
+// like "<$Food>" or "<%noun>", and fstreplace substitutes the FST with that name on demand.
+// Each rule and each word is stored once, however often it's used. Terminals with no listed
+// vocabulary, like %number, stay as plain arcs labeled with the terminal, as word classes.
+//
+// fstreplace can't handle recursion, so we number the symbols in depth-first order from the
+// start symbol. Using a symbol that can reach the current one, and that's numbered no later
+// than it, nests one level deeper; every cycle includes such a use. We allow at most "depth"
+// levels. A symbol's FST at level k > 0 is named like "<$Foods:k>", and uses that would nest
+// deeper are cut, leaving dead states behind. Run the results through fstconnect.
+
+pub struct Fst {
+  pub arcs: Vec<(usize, usize, String, f32)>,
+  pub finals: Vec<usize>,
+  pub states: usize,
+}
+
+impl Fst {
+  fn new() -> Self {
+    Self { arcs: vec![], finals: vec![1], states: 2 }
+  }
+
+  fn add_arc(&mut self, source: usize, target: usize, label: &str, score: f32) {
+    let cost = if score == 0.0 { 0.0 } else { -score };
+    self.arcs.push((source, target, label.to_string(), cost));
+  }
+
+  fn add_state(&mut self) -> usize {
+    self.states += 1;
+    self.states - 1
+  }
+
+  // Adds a path from the start state to the final state through the given labels.
+  fn add_path(&mut self, labels: &[String], score: f32) {
+    let mut state = 0;
+    for (i, label) in labels.iter().enumerate() {
+      let next = if i + 1 == labels.len() { 1 } else { self.add_state() };
+      self.add_arc(state, next, label, if i == 0 { score } else { 0.0 });
+      state = next;
+    }
+  }
+
+  // One arc per line, as "source target input output weight", then one final state per
+  // line. Input and output labels are the same, so the FST is an acceptor.
+  pub fn to_text(&self) -> String {
+    let mut result = vec![];
+    for (source, target, label, weight) in &self.arcs {
+      result.push(format!("{} {} {} {} {}\n", source, target, label, label, weight));
+    }
+    self.finals.iter().for_each(|x| result.push(format!("{}\n", x)));
+    result.concat()
+  }
+}
+
+// The FSTs for a grammar, sorted by name. The root names the start symbol's FST.
+pub struct Fsts {
+  pub fsts: Vec<(String, Fst)>,
+  pub root: String,
+}
+
+impl Fsts {
+  // A symbol table for all of the FSTs' labels, including the FSTs' own names, which are
+  // passed to fstreplace. Use it with fstcompile's --isymbols flag.
+  pub fn symbols(&self) -> String {
+    let mut labels = vec![];
+    for (name, fst) in &self.fsts {
+      labels.push(name.as_str());
+      labels.extend(fst.arcs.iter().map(|x| x.2.as_str()).filter(|x| *x != EPSILON));
+    }
+    labels.sort();
+    labels.dedup();
+    let labels = std::iter::once(EPSILON).chain(labels);
+    labels.enumerate().map(|(i, x)| format!("{} {}\n", x, i)).collect()
+  }
+}
+
+const EPSILON: &str = "<eps>";
+
+struct Builder<'a, S, T> {
+  depth: usize,
+  names: &'a [String],
+  order: Vec<Option<usize>>,
+  reach: Vec<HashSet<usize>>,
+  rules: Vec<Vec<&'a Rule<S, T>>>,
+  wanted: Vec<(usize, usize)>,
+  words: HashMap<String, Vec<(Vec<String>, f32)>>,
+}
+
+impl<'a, S, T> Builder<'a, S, T> {
+  fn children(&self, symbol: usize) -> impl Iterator<Item = usize> + '_ {
+    let terms = self.rules[symbol].iter().flat_map(|x| x.rhs.iter());
+    terms.filter_map(|x| if let Term::Symbol(y) = x { Some(*y) } else { None })
+  }
+
+  fn name(&self, symbol: usize, level: usize) -> String {
+    match level {
+      0 => format!("<{}>", self.names[symbol]),
+      _ => format!("<{}:{}>", self.names[symbol], level),
+    }
+  }
+
+  fn number(&mut self, symbol: usize, next: &mut usize) {
+    if self.order[symbol].is_some() {
+      return;
+    }
+    self.order[symbol] = Some(*next);
+    *next += 1;
+    let children: Vec<_> = self.children(symbol).collect();
+    children.into_iter().for_each(|x| self.number(x, next));
+  }
+
+  fn search(&mut self, symbol: usize) {
+    let mut stack: Vec<_> = self.children(symbol).collect();
+    while let Some(next) = stack.pop() {
+      if self.reach[symbol].insert(next) {
+        stack.extend(self.children(next));
+      }
+    }
+  }
+
+  fn build(&mut self, symbol: usize, level: usize) -> Fst {
+    let mut fst = Fst::new();
+    for rule in self.rules[symbol].clone() {
+      let score = rule.merge.score;
+      if rule.rhs.is_empty() {
+        fst.add_arc(0, 1, EPSILON, score);
+        continue;
+      }
+      let mut state = fst.add_state();
+      fst.add_arc(0, state, EPSILON, score);
+      for (i, term) in rule.rhs.iter().enumerate() {
+        let next = if i + 1 == rule.rhs.len() { 1 } else { fst.add_state() };
+        let label = match term {
+          Term::Symbol(x) => {
+            let cycle = self.reach[*x].contains(&symbol);
+            let level = match (cycle, self.order[*x] <= self.order[symbol]) {
+              (true, true) => level + 1,
+              (true, false) => level,
+              (false, _) => 0,
+            };
+            if level > self.depth {
+              break;
+            }
+            self.wanted.push((*x, level));
+            self.name(*x, level)
+          }
+          Term::Terminal(x) if self.words.contains_key(x) => format!("<{}>", x),
+          Term::Terminal(x) => x.to_string(),
+        };
+        fst.add_arc(state, next, &label, 0.0);
+        state = next;
+      }
+    }
+    fst
+  }
+}
+
+pub fn export<S, T>(grammar: &Grammar<S, T>, scheme: &str, depth: usize) -> Fsts {
+  let Grammar { lexer, names, rules, start, .. } = grammar;
+  let mut by_lhs = vec![vec![]; names.len()];
+  rules.iter().filter(|x| x.merge.score.is_finite()).for_each(|x| by_lhs[x.lhs].push(x));
+
+  // Collect each terminal's words, keeping the best score for each distinct text.
+  let mut best: HashMap<(String, Vec<String>), f32> = HashMap::default();
+  for (terminal, (score, m)) in lexer.vocabulary() {
+    let text = match m.texts.get(scheme) {
+      Some(x) if score.is_finite() => x,
+      _ => continue,
+    };
+    let labels: Vec<_> = text.split_whitespace().map(|x| x.to_string()).collect();
+    if labels.is_empty() {
+      continue;
+    }
+    let entry = best.entry((terminal.name().to_string(), labels)).or_insert(score);
+    *entry = entry.max(score);
+  }
+  let mut best: Vec<_> = best.into_iter().collect();
+  best.sort_by(|a, b| a.0.cmp(&b.0));
+  let mut words: HashMap<String, Vec<_>> = HashMap::default();
+  best.into_iter().for_each(|((k, v), x)| words.entry(k).or_default().push((v, x)));
+
+  let (order, reach) = (vec![None; names.len()], vec![HashSet::default(); names.len()]);
+  let rules = by_lhs;
+  let mut builder = Builder { depth, names, order, reach, rules, wanted: vec![], words };
+  builder.number(*start, &mut 0);
+  (0..names.len()).for_each(|x| builder.search(x));
+
+  // Build an FST for each symbol at each level that's used, starting from the root.
+  let mut fsts = HashMap::default();
+  builder.wanted.push((*start, 0));
+  while let Some((symbol, level)) = builder.wanted.pop() {
+    if let Entry::Vacant(x) = fsts.entry(builder.name(symbol, level)) {
+      x.insert(builder.build(symbol, level));
+    }
+  }
+
+  // Only the terminals that those FSTs use need FSTs of their own.
+  let used: HashSet<_> = fsts.values().flat_map(|x| x.arcs.iter().map(|y| y.2.clone())).collect();
+  for (terminal, words) in &builder.words {
+    let name = format!("<{}>", terminal);
+    if used.contains(&name) {
+      let mut fst = Fst::new();
+      words.iter().for_each(|(labels, score)| fst.add_path(labels, *score));
+      fsts.insert(name, fst);
+    }
+  }
+  let mut fsts: Vec<_> = fsts.into_iter().collect();
+  fsts.sort_by(|a, b| a.0.cmp(&b.0));
+  Fsts { fsts, root: builder.name(*start, 0) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::super::hindi::lexer::HindiLexer;
+  use super::super::super::payload::lambda::Lambda;
+  use super::super::fantasy::compile;
+  use super::*;
+
+  #[test]
+  fn grammars_are_exported_to_fsts() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let base = regex::Regex::new(r#"lexer: ```[\s\S]*```"#).unwrap().find(&data).unwrap();
+    let features = regex::Regex::new(r#"(?m)^feature .*$"#).unwrap();
+    let features: Vec<_> = features.find_iter(&data).map(|x| x.as_str()).collect();
+    let data = format!("
+      {}
+      $Want! = $Foods chahie (< -0.5)
+      $Foods = $Foods? $Food
+      $Food
+      = %food
+      = %proper_noun (< -1)
+      {}
+    ", features.join("\n"), base.as_str());
+    let grammar: Grammar<Option<Lambda>, Lambda> = compile(&data, HindiLexer::new).unwrap();
+    let fsts = export(&grammar, "latin", 0);
+    let text = |x: &Fsts, name: &str| {
+      let fst = &x.fsts.iter().find(|y| y.0 == name).unwrap().1;
+      fst.to_text().lines().map(|y| y.to_string()).collect::<Vec<_>>()
+    };
+    assert_eq!(fsts.root, "<$ROOT>");
+    assert_eq!(text(&fsts, "<$Want>"), [
+      "0 2 <eps> <eps> 0.5",
+      "2 3 <$Foods> <$Foods> 0",
+      "3 1 <chahie> <chahie> 0",
+      "1",
+    ]);
+    assert_eq!(text(&fsts, "<$Food>"), [
+      "0 2 <eps> <eps> 0",
+      "2 1 <%food> <%food> 0",
+      "0 3 <eps> <eps> 1",
+      "3 1 %proper_noun %proper_noun 0",
+      "1",
+    ]);
+    let words = ["0 1 khana khana 0", "0 1 roti roti 0", "0 1 seb seb 0", "1"];
+    assert_eq!(text(&fsts, "<%food>"), words);
+    let names: Vec<_> = fsts.fsts.iter().map(|x| x.0.as_str()).collect();
+    let symbols = ["<$Food>", "<$Foods>", "<$Foods?>", "<$ROOT>", "<$Want>"];
+    assert_eq!(names, [&symbols[..], &["<%food>", "<chahie>"]].concat());
+    let symbols = fsts.symbols();
+    assert!(symbols.starts_with("<eps> 0\n%proper_noun 1\n<$Food> 2\n"));
+    assert!(symbols.ends_with("<chahie> 8\nchahie 9\nkhana 10\nroti 11\nseb 12\n"));
+
+    // Each level of recursion allows one more food, with one more copy of $Foods.
+    let fsts = export(&grammar, "latin", 2);
+    assert_eq!(text(&fsts, "<$Foods?:1>")[1], "0 2 <eps> <eps> 0");
+    assert_eq!(text(&fsts, "<$Foods:1>")[1], "2 3 <$Foods?:1> <$Foods?:1> 0");
+    assert_eq!(text(&fsts, "<$Foods:2>")[1], "2 3 <$Foods?:2> <$Foods?:2> 0");
+    assert_eq!(text(&fsts, "<$Foods?:2>"), ["0 1 <eps> <eps> 0", "0 2 <eps> <eps> 0", "1"]);
+
+    // The full grammar exports to a few hundred small FSTs at any depth.
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar: Grammar<Option<Lambda>, Lambda> = compile(&data, HindiLexer::new).unwrap();
+    for depth in 0..4 {
+      let fsts = export(&grammar, "latin", depth);
+      let arcs: usize = fsts.fsts.iter().map(|x| x.1.arcs.len()).sum();
+      assert!(fsts.fsts.len() < 200 && arcs < 1000, "depth {}: {} arcs", depth, arcs);
+    }
+  }
+}
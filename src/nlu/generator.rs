@@ -1,9 +1,11 @@
 use super::super::lib::base::HashMap;
-use super::base::{Child, Derivation, Grammar, Rule, Term};
+use super::base::{Child, Children, Derivation, Grammar, Rule, Term};
+use super::observer::{Event, Observer};
 use rand::Rng as RngTrait;
 use std::collections::hash_map::Entry;
 use std::hash::Hash;
 use std::rc::Rc;
+use std::sync::Arc;
 
 // We only support generation for types implementing three utility traits.
 
@@ -34,6 +36,10 @@ impl<'a, 'b, S: Split, T> State<'a, 'b, S, T> {
       };
       rules.iter().filter_map(f).collect()
     };
+    if self.generator.deterministic {
+      let best = scores.iter().map(|x| x.0).fold(f32::NEG_INFINITY, f32::max);
+      return scores.into_iter().find(|x| x.0 == best).map(|x| x.1);
+    }
     let length = scores.len();
     let mut left = self.rng.gen::<f32>() * scores.iter().fold(0.0, |acc, x| acc + x.0);
     for (i, (score, derivation)) in scores.into_iter().enumerate() {
@@ -57,19 +63,19 @@ impl<'a, 'b, S: Split, T> State<'a, 'b, S, T> {
   }
 
   fn generate_from_rule(&mut self, rule: &'a Rule<S, T>, value: &S) -> Tree<'a, S, T> {
-    let candidates = (rule.split.callback)(value);
-    let mut options = Vec::with_capacity(candidates.len());
-    'outer: for candidate in candidates.iter() {
-      let mut children = Vec::with_capacity(rule.rhs.len());
-      for i in 0..rule.rhs.len() {
-        if let Some(derivation) = self.generate_from_memo(&rule.rhs[i], &candidate[i]) {
-          children.push(derivation);
-        } else {
-          continue 'outer;
+    let limit = self.generator.limit;
+    let mut options = vec![];
+    (rule.split.callback)(value, &mut |candidate| {
+      let mut children = Children::with_capacity(rule.rhs.len());
+      for (term, value) in rule.rhs.iter().zip(candidate.iter()) {
+        match self.generate_from_memo(term, value) {
+          Some(derivation) => children.push(derivation),
+          None => return true,
         }
       }
       options.push(children);
-    }
+      options.len() < limit
+    });
     self.sample(options).map(|x| Child::Node(Rc::new(Derivation::new(x, rule))))
   }
 
@@ -77,7 +83,13 @@ impl<'a, 'b, S: Split, T> State<'a, 'b, S, T> {
     let lexer = &self.generator.grammar.lexer;
     match term {
       Term::Symbol(x) => self.generate_from_list(&self.generator.by_name[*x], value),
-      Term::Terminal(x) => self.sample(lexer.unlex(x, value)).map(|y| Child::Leaf(y)),
+      Term::Terminal(x) => {
+        let matches = lexer.unlex(x, value);
+        if let Some(observer) = &self.generator.observer {
+          observer.observe(&Event::Unlexed { terminal: x, matches: matches.len() });
+        }
+        self.sample(matches).map(|y| Child::Leaf(y))
+      }
     }
   }
 
@@ -85,24 +97,49 @@ impl<'a, 'b, S: Split, T> State<'a, 'b, S, T> {
     if xs.is_empty() {
       return None;
     }
-    let index = self.rng.gen::<usize>() % xs.len();
+    let index = if self.generator.deterministic { 0 } else { self.rng.gen::<usize>() % xs.len() };
     Some(xs.swap_remove(index))
   }
 }
 
 // Our public interface has a simple "generate" entry point, but also supports
 // generation from a list of rules, which is useful for correction.
+//
+// By default, we sample from all viable splits of a value for each rule. Setting
+// a limit stops the search after that many viable splits are found, which trades
+// generation diversity for speed on payloads with many possible splits.
+//
+// A deterministic generator ignores its RNG: it takes the highest-scoring rule at
+// each step, breaking ties by rule order, and the first viable split or lexer match.
 
 pub struct Generator<'a, S: Split, T> {
   by_name: Vec<Vec<&'a Rule<S, T>>>,
+  deterministic: bool,
   grammar: &'a Grammar<S, T>,
+  limit: usize,
+  observer: Option<Arc<dyn Observer>>,
 }
 
 impl<'a, S: Split, T> Generator<'a, S, T> {
   pub fn new(grammar: &'a Grammar<S, T>) -> Self {
     let mut by_name: Vec<_> = grammar.names.iter().map(|_| vec![]).collect();
     grammar.rules.iter().for_each(|x| by_name[x.lhs].push(x));
-    Self { by_name, grammar }
+    Self { by_name, deterministic: false, grammar, limit: usize::MAX, observer: None }
+  }
+
+  pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+    self.deterministic = deterministic;
+    self
+  }
+
+  pub fn with_limit(mut self, limit: usize) -> Self {
+    self.limit = std::cmp::max(limit, 1);
+    self
+  }
+
+  pub fn with_observer(mut self, observer: Option<Arc<dyn Observer>>) -> Self {
+    self.observer = observer;
+    self
   }
 
   pub fn generate(&self, rng: &mut Rng, value: &S) -> Option<Derivation<'a, S, T>> {
@@ -129,20 +166,21 @@ impl<'a, S: Split, T> Generator<'a, S, T> {
 
 #[cfg(test)]
 mod tests {
-  use super::super::base::{Lexer, Match, Semantics, Tense, Token};
+  use super::super::base::{Lexer, Match, Metadata, Semantics, Tense, TenseSchema, Token};
   use super::*;
   use std::marker::PhantomData;
+  use std::sync::Arc;
   use test::Bencher;
 
-  type Split<S> = Box<dyn Fn(&S) -> Vec<Vec<S>>>;
+  type Split<S> = Box<dyn Fn(&S) -> Vec<Vec<S>> + Send + Sync>;
 
   #[derive(Default)]
   struct CharacterLexer<T: Default> {
     mark: PhantomData<T>,
   }
 
-  impl<T: Default + PartialEq> Lexer<T, String> for CharacterLexer<T> {
-    fn fix(&self, _: &Match<String>, _: &Tense) -> Vec<Rc<Match<String>>> {
+  impl<T: Default + PartialEq + Send + Sync> Lexer<T, String> for CharacterLexer<T> {
+    fn fix(&self, _: &Match<String>, _: &Tense) -> Vec<Arc<Match<String>>> {
       unimplemented!()
     }
 
@@ -150,10 +188,10 @@ mod tests {
       unimplemented!()
     }
 
-    fn unlex(&self, name: &str, value: &T) -> Vec<Rc<Match<String>>> {
+    fn unlex(&self, name: &str, value: &T) -> Vec<Arc<Match<String>>> {
       if name.len() == 1 && *value == T::default() {
         let (tenses, texts, value) = (vec![], HashMap::default(), name.into());
-        vec![Rc::new(Match { tenses, texts, value })]
+        vec![Arc::new(Match { tenses, texts, value })]
       } else {
         vec![]
       }
@@ -171,12 +209,24 @@ mod tests {
     }
   }
 
-  fn make_rule<S: Clone>(lhs: usize, rhs: &str, f: Split<S>) -> Rule<S, String> {
-    let merge: Semantics<dyn Fn(&[String]) -> String> =
+  fn make_rule<S>(lhs: usize, rhs: &str, f: Split<S>) -> Rule<S, String>
+  where
+    S: Clone + Send + Sync + 'static,
+  {
+    let merge: Semantics<dyn Fn(&[String]) -> String + Send + Sync> =
       Semantics { callback: Box::new(|x| x.join("")), score: 0.0 };
-    let split: Semantics<dyn Fn(&S) -> Vec<Vec<S>>> = Semantics { callback: f, score: 0.0 };
+    let callback = move |x: &S, g: &mut dyn FnMut(Vec<S>) -> bool| {
+      for y in f(x) {
+        if !g(y) {
+          break;
+        }
+      }
+    };
+    let split: Semantics<dyn Fn(&S, &mut dyn FnMut(Vec<S>) -> bool) + Send + Sync> =
+      Semantics { callback: Box::new(callback), score: 0.0 };
     let rhs = rhs.split(' ').filter(|x| !x.is_empty()).map(make_term).collect();
-    Rule { lhs, rhs, merge, split, precedence: vec![], tense: Tense::default() }
+    let (precedence, tense, metadata) = (vec![], Tense::default(), Metadata::default());
+    Rule { lhs, rhs, merge, split, precedence, tense, metadata }
   }
 
   fn make_term(term: &str) -> Term {
@@ -191,7 +241,7 @@ mod tests {
     Box::new(move |x| if *x == n { vec![vec![0]] } else { vec![] })
   }
 
-  fn split_operator(f: Box<dyn Fn(f32, f32) -> f32>) -> Split<i32> {
+  fn split_operator(f: Box<dyn Fn(f32, f32) -> f32 + Send + Sync>) -> Split<i32> {
     Box::new(move |x| {
       let mut result = vec![];
       for a in 0..10 {
@@ -208,6 +258,7 @@ mod tests {
 
   fn make_grammar(deepness: f32) -> Grammar<i32, String> {
     Grammar {
+      features: TenseSchema::default(),
       lexer: Box::new(CharacterLexer::default()),
       names: "$Root $Add $Mul $Num".split(' ').map(|x| x.into()).collect(),
       rules: vec![
@@ -247,6 +298,25 @@ mod tests {
     }
   }
 
+  #[test]
+  fn generation_with_limit_works() {
+    let grammar = make_grammar(0.0);
+    let generator = Generator::new(&grammar).with_limit(1);
+    let tests = vec![
+      (0, "8/2/2"),
+      (2, "8-5-2-2/2+2"),
+      (3, "8/2/2-3*0"),
+      (5, "4/(5-3)/(5-3)*(5-3)"),
+      (6, "4/(4-3+3/3)/(4-3)"),
+    ];
+    for (index, expected) in tests {
+      let rules = [&grammar.rules[index]];
+      let mut rng = rand::SeedableRng::from_seed([17; 32]);
+      let result = generator.generate_from_rules(Memo::default(), &mut rng, &rules, &2).unwrap();
+      assert_eq!(result.value, expected);
+    }
+  }
+
   #[test]
   fn scoring_works() {
     let tests = vec![(6.0, "6-6+8/2/2"), (3.0, "8/2/2"), (-3.0, "4/2"), (-6.0, "2")];
@@ -259,6 +329,20 @@ mod tests {
     }
   }
 
+  #[test]
+  fn deterministic_generation_ignores_the_rng() {
+    let tests = vec![(0.0, "2"), (-3.0, "2"), (3.0, "7-8/2-6/2+6/2/(7-8/2)*2")];
+    for (deepness, expected) in tests {
+      let grammar = make_grammar(deepness);
+      let generator = Generator::new(&grammar).with_deterministic(true);
+      for seed in 0..4 {
+        let mut rng = rand::SeedableRng::from_seed([seed; 32]);
+        let result = generator.generate(&mut rng, &2).unwrap();
+        assert_eq!(result.value, expected);
+      }
+    }
+  }
+
   #[bench]
   fn generation_benchmark(b: &mut Bencher) {
     let grammar = make_grammar(0.0);
@@ -0,0 +1,490 @@
+use super::super::lib::base::{HashMap, HashSet, Result};
+use super::super::lib::combine::*;
+use super::super::payload::base::Payload;
+use super::base::{Lexer, Match, Tense, TenseSchema, Terminal, Token};
+use std::sync::Arc;
+
+// Imports speech grammars written in JSGF or in the ABNF form of SRGS by converting them
+// to the text of an equivalent fantasy grammar, which can then be passed to compile.
+//
+// Each alternative of a rule becomes one fantasy rule. Groups, optional items, and
+// repeats ("*", "+", and SRGS's "<m-n>") become helper symbols named after their rule.
+// Public rules are roots, unless an SRGS "root $rule;" declaration names the only one.
+// A weight "/w/" becomes a merge score of ln(w); alternatives of weight 0 are dropped.
+//
+// A tag at the end of an alternative becomes its rule's template, and a tag anywhere
+// else becomes the template of the item before it. We accept SISR literal tags, like
+// {out = "order";}, as the template "order". Template slots count the items of the
+// alternative, not counting tags or NULLs; a group or a repeat is a single item.
+// Alternatives without tags use the default template, as do the helper symbols for
+// repeats, so tag a repeated item to give it a value. The result ends with a lexer
+// block listing the grammar's words, which WordLexer reads: compile it with that lexer.
+
+#[derive(Clone)]
+enum Unit {
+  Group(Vec<Alternative>),
+  Null,
+  Ref(String),
+  Token(String),
+  Void,
+}
+
+#[derive(Clone)]
+struct Item {
+  unit: Unit,
+  repeats: Vec<(usize, Option<usize>)>,
+}
+
+#[derive(Clone)]
+enum Piece {
+  Item(Item),
+  Tag(String),
+}
+
+#[derive(Clone)]
+struct Alternative {
+  weight: Option<f32>,
+  pieces: Vec<Piece>,
+}
+
+enum Declaration {
+  Root(String),
+  Rule(bool, String, Vec<Alternative>),
+  Skip,
+}
+
+fn parse(input: &str) -> Result<Vec<Declaration>> {
+  thread_local! {
+    static PARSER: Parser<Vec<Declaration>> = {
+      let comment = any(&[
+        regexp(r#"//[^\n]*"#, |_| ()),
+        regexp(r#"/\*[\s\S]*?\*/"#, |_| ()),
+      ]);
+      let ws = separate(whitespace(), label(comment, "comment"), 0);
+      let st = |x| string(x, |_| ());
+      let name = label(any(&[
+        regexp(r#"<[^<>\s]+>"#, |x| x[1..x.len() - 1].to_string()),
+        regexp(r#"\$[\w.\-]+"#, |x| x[1..].to_string()),
+      ]), "rule name");
+      let number = regexp(r#"[0-9]*\.?[0-9]+"#, |x| x.parse::<f32>().unwrap());
+      let weight = seq((st("/"), &ws, number, &ws, st("/")), |x| x.2);
+      let tag = label(any(&[
+        regexp(r#"\{!\{[\s\S]*?\}!\}"#, |x| x[3..x.len() - 3].trim().to_string()),
+        regexp(r#"\{[^}]*\}"#, |x| x[1..x.len() - 1].trim().to_string()),
+      ]), "tag");
+      let token = label(any(&[
+        regexp(r#""[^"]*""#, |x| x[1..x.len() - 1].to_string()),
+        regexp(r#"[^\s;|/*+()\[\]{}<>=$"]+"#, |x| x.to_string()),
+      ]), "token");
+      let quantifier = any(&[
+        map(st("*"), |_| (0, None)),
+        map(st("+"), |_| (1, None)),
+        regexp(r#"<[0-9]+(-[0-9]*)?>"#, |x| {
+          let mut bounds = x[1..x.len() - 1].split('-').map(|y| y.parse::<usize>().ok());
+          let min = bounds.next().and_then(|y| y).unwrap_or_default();
+          (min, bounds.next().map_or(Some(min), |y| y))
+        }),
+      ]);
+
+      // An expansion is a list of alternatives, each of which is a sequence of items
+      // and tags. Items may be nested expansions, with optional repeat operators.
+      let (cell, expansion) = lazy("expansion");
+      let unit = any(&[
+        seq((st("("), &ws, &expansion, &ws, st(")")), |x| (Unit::Group(x.2), vec![])),
+        seq((st("["), &ws, &expansion, &ws, st("]")), |x| (Unit::Group(x.2), vec![(0, Some(1))])),
+        map(&name, |x| match x.as_str() {
+          "NULL" => (Unit::Null, vec![]),
+          "VOID" => (Unit::Void, vec![]),
+          _ => (Unit::Ref(x), vec![]),
+        }),
+        map(token, |x| (Unit::Token(x), vec![])),
+      ]);
+      let item = seq((unit, repeat(seq((&ws, quantifier), |x| x.1), 0)), |mut x| {
+        (x.0).1.append(&mut x.1);
+        Item { unit: (x.0).0, repeats: (x.0).1 }
+      });
+      let piece = any(&[map(tag, Piece::Tag), map(item, Piece::Item)]);
+      let alternative = seq((opt(seq((weight, &ws), |x| x.0)), separate(piece, &ws, 1)), |x| {
+        Alternative { weight: x.0, pieces: x.1 }
+      });
+      cell.replace(separate(alternative, seq((&ws, st("|"), &ws), |_| ()), 1));
+
+      // A grammar is a header followed by declarations and rules, each ending in ";".
+      let header = regexp(r#"#(JSGF|ABNF)[^;]*;"#, |_| ());
+      let keyword = "base|grammar|import|language|lexicon|meta|mode|tag-format";
+      let end = seq((&ws, st(";")), |_| ());
+      let declaration = any(&[
+        seq((st("root"), &ws, &name, &end), |x| Declaration::Root(x.2)),
+        regexp(&format!(r#"({})\b[^;]*;"#, keyword), |_| Declaration::Skip),
+        seq(
+          (opt(st("public")), &ws, &name, &ws, st("="), &ws, &expansion, &end),
+          |x| Declaration::Rule(x.0.is_some(), x.2, x.6),
+        ),
+      ]);
+      seq((&ws, opt(header), &ws, separate(declaration, &ws, 0), &ws), |x| x.3)
+    };
+  }
+  PARSER.with(|x| x.parse(input))
+}
+
+// Logic for converting the parsed grammar to fantasy grammar text.
+
+struct State {
+  helpers: Vec<(String, Vec<String>)>,
+  names: HashMap<String, String>,
+  terminals: HashSet<String>,
+  undefined: HashSet<String>,
+  unsupported: HashSet<String>,
+  used: HashSet<String>,
+}
+
+impl State {
+  fn build_alternative(&mut self, lhs: &str, x: &Alternative) -> Result<Option<String>> {
+    let weight = x.weight.unwrap_or(1.0);
+    if weight == 0.0 {
+      return Ok(None);
+    }
+    let mut pieces = x.pieces.clone();
+    let template = match pieces.last().cloned() {
+      Some(Piece::Tag(y)) => {
+        pieces.pop();
+        Some(y)
+      }
+      _ => None,
+    };
+    let mut items: Vec<String> = vec![];
+    for piece in pieces {
+      match piece {
+        Piece::Item(y) => match self.build_item(lhs, &y)? {
+          Some(z) => items.push(z),
+          None if self.is_void(&y) => return Ok(None),
+          None => (),
+        },
+        Piece::Tag(y) => {
+          let item = items.pop().ok_or(format!("Unattached tag in {}: {{{}}}", lhs, y))?;
+          let rule = get_rule(vec![item], 1.0, Some(&y))?;
+          items.push(self.build_symbol(lhs, "tag", vec![rule]));
+        }
+      }
+    }
+    get_rule(items, weight, template.as_deref()).map(Some)
+  }
+
+  fn build_item(&mut self, lhs: &str, x: &Item) -> Result<Option<String>> {
+    let mut base = match &x.unit {
+      Unit::Group(y) => {
+        let mut rules = vec![];
+        for alternative in y {
+          rules.extend(self.build_alternative(lhs, alternative)?);
+        }
+        if rules.len() == 1 && rules[0] == "= NONE" {
+          return Ok(None);
+        }
+        match rules.as_slice() {
+          [rule] if !rule.contains('(') && !rule[2..].contains(' ') => rule[2..].to_string(),
+          _ => self.build_symbol(lhs, "group", rules),
+        }
+      }
+      Unit::Null | Unit::Void => return Ok(None),
+      Unit::Ref(y) => match self.names.get(y) {
+        Some(z) => z.clone(),
+        None => {
+          self.undefined.insert(y.clone());
+          return Ok(Some(format!("${}", y)));
+        }
+      },
+      Unit::Token(y) => {
+        let words: Vec<_> = y.split_whitespace().map(|z| z.to_string()).collect();
+        for word in &words {
+          let valid = word.chars().all(|z| z.is_ascii_alphabetic() || z == '_');
+          let set = if valid { &mut self.terminals } else { &mut self.unsupported };
+          set.insert(word.clone());
+        }
+        match words.len() {
+          0 => return Ok(None),
+          1 => words[0].clone(),
+          _ => self.build_symbol(lhs, "words", vec![get_rule(words, 1.0, None)?]),
+        }
+      }
+    };
+    // We expand "<m-n>" to m required copies of the item, followed by an optional
+    // symbol that matches up to n - m more copies, or any number of them if n is unset.
+    for (min, max) in &x.repeats {
+      let (min, max) = (*min, *max);
+      let mut items = vec![base.clone(); min];
+      match max {
+        Some(max) if max < min => Err(format!("Invalid repeat in {}: <{}-{}>", lhs, min, max))?,
+        Some(max) if max > min => {
+          items.push(format!("{}?", self.build_upto(lhs, &base, max - min)));
+        }
+        Some(_) => (),
+        None if min == 0 => items.push(format!("{}?", self.build_plus(lhs, &base))),
+        None => items[min - 1] = self.build_plus(lhs, &base),
+      }
+      base = match items.len() {
+        0 => return Ok(None),
+        1 => items.pop().unwrap(),
+        _ => self.build_symbol(lhs, "repeat", vec![get_rule(items, 1.0, None)?]),
+      };
+      if base.ends_with("??") {
+        base.pop();
+      }
+    }
+    Ok(Some(base))
+  }
+
+  fn build_plus(&mut self, lhs: &str, base: &str) -> String {
+    let symbol = self.get_symbol(&format!("{}_repeat", lhs));
+    let rules = vec![format!("= {}", base), format!("= {} {}", symbol, base)];
+    self.helpers.push((symbol.clone(), rules));
+    symbol
+  }
+
+  fn build_symbol(&mut self, lhs: &str, kind: &str, rules: Vec<String>) -> String {
+    let symbol = self.get_symbol(&format!("{}_{}", lhs, kind));
+    self.helpers.push((symbol.clone(), rules));
+    symbol
+  }
+
+  fn build_upto(&mut self, lhs: &str, base: &str, n: usize) -> String {
+    let mut result = base.to_string();
+    for _ in 1..n {
+      let rules = vec![format!("= {}", base), format!("= {} {}?", base, result)];
+      result = self.build_symbol(lhs, "repeat", rules);
+    }
+    result
+  }
+
+  fn get_symbol(&mut self, name: &str) -> String {
+    let name = name.trim_start_matches('$');
+    let base: String =
+      name.chars().map(|x| if x.is_ascii_alphabetic() { x } else { '_' }).collect();
+    let mut result = format!("${}", base);
+    while !self.used.insert(result.clone()) {
+      result.push('_');
+    }
+    result
+  }
+
+  fn is_void(&self, x: &Item) -> bool {
+    matches!(x.unit, Unit::Void) && x.repeats.iter().all(|y| y.0 > 0)
+  }
+}
+
+fn get_rule(items: Vec<String>, weight: f32, template: Option<&str>) -> Result<String> {
+  let mut result = vec!["=".to_string()];
+  result.push(if items.is_empty() { "NONE".into() } else { items.join(" ") });
+  if weight != 1.0 {
+    result.push(format!("(< {})", weight.ln()));
+  }
+  if let Some(x) = template {
+    let x = get_template(x);
+    if x.contains('\'') && x.contains('"') {
+      Err(format!("Unsupported tag: {{{}}}", x))?;
+    }
+    let quote = if x.contains('\'') { '"' } else { '\'' };
+    result.push(format!("(= {}{}{})", quote, x, quote));
+  }
+  Ok(result.join(" "))
+}
+
+fn get_template(tag: &str) -> &str {
+  let mut result = tag.trim().trim_end_matches(';').trim();
+  if result.starts_with("out") && result[3..].trim_start().starts_with('=') {
+    result = result[3..].trim_start()[1..].trim();
+  }
+  let quoted = |x: char| result.len() > 1 && result.starts_with(x) && result.ends_with(x);
+  if quoted('"') || quoted('\'') {
+    &result[1..result.len() - 1]
+  } else {
+    result
+  }
+}
+
+fn get_warning(xs: HashSet<String>, message: &str) -> Result<()> {
+  let mut xs: Vec<_> = xs.into_iter().collect();
+  xs.sort();
+  if xs.is_empty() { Ok(()) } else { Err(format!("{}: {}", message, xs.join(", ")))? }
+}
+
+// Our public API: converts JSGF or SRGS ABNF text to fantasy grammar text.
+
+pub fn import(input: &str) -> Result<String> {
+  let declarations = parse(input)?;
+  let mut state = State {
+    helpers: vec![],
+    names: HashMap::default(),
+    terminals: HashSet::default(),
+    undefined: HashSet::default(),
+    unsupported: HashSet::default(),
+    used: HashSet::default(),
+  };
+  let mut root = None;
+  let mut rules = vec![];
+  for declaration in declarations {
+    match declaration {
+      Declaration::Root(x) => root = Some(x),
+      Declaration::Rule(public, name, alternatives) => {
+        if state.names.contains_key(&name) {
+          Err(format!("Duplicate rule: {}", name))?;
+        }
+        let symbol = state.get_symbol(&name);
+        state.names.insert(name.clone(), symbol);
+        rules.push((public, name, alternatives));
+      }
+      Declaration::Skip => (),
+    }
+  }
+  if let Some(x) = &root {
+    if !state.names.contains_key(x) {
+      Err(format!("Undefined root: {}", x))?;
+    }
+  } else if !rules.iter().any(|x| x.0) {
+    Err("No public rules")?;
+  }
+
+  // Rules come first, in order, followed by their helper symbols.
+  let mut result = vec![];
+  for (public, name, alternatives) in &rules {
+    let symbol = state.names[name].clone();
+    let mut block = vec![];
+    for alternative in alternatives {
+      block.extend(state.build_alternative(&symbol, alternative)?);
+    }
+    let root = if root.as_ref().map_or(*public, |x| x == name) { "!" } else { "" };
+    result.push(format!("{}{}\n{}\n", symbol, root, block.join("\n")));
+  }
+  get_warning(state.undefined, "Undefined rules")?;
+  get_warning(state.unsupported, "Unsupported tokens")?;
+  for (symbol, block) in &state.helpers {
+    result.push(format!("{}\n{}\n", symbol, block.join("\n")));
+  }
+  let mut terminals: Vec<_> = state.terminals.into_iter().collect();
+  terminals.sort();
+  result.push(format!("lexer: ```{}```\n", terminals.join(" ")));
+  Ok(result.join("\n"))
+}
+
+// A lexer for imported grammars. Its block is a whitespace-separated list of words. It
+// splits input on whitespace and matches each listed word as the terminal of that name,
+// with the word as its Latin text and its base value.
+
+pub struct WordLexer<T: Payload>(HashMap<String, Arc<Match<T>>>);
+
+impl<T: Payload> WordLexer<T> {
+  pub fn new(text: &str, _: &TenseSchema) -> Result<Box<dyn Lexer<Option<T>, T>>> {
+    let entry = |x: &str| {
+      let texts = std::iter::once(("latin", Arc::from(x))).collect();
+      Arc::new(Match { tenses: vec![], texts, value: T::base_lex(x) })
+    };
+    let words = text.split_whitespace().map(|x| (x.to_string(), entry(x))).collect();
+    Ok(Box::new(WordLexer(words)))
+  }
+}
+
+impl<T: Payload> Lexer<Option<T>, T> for WordLexer<T> {
+  fn fix(&self, _: &Match<T>, _: &Tense) -> Vec<Arc<Match<T>>> {
+    vec![]
+  }
+
+  fn lex<'a: 'b, 'b>(&'a self, input: &'b str) -> Vec<Token<'b, T>> {
+    let iter = input.split_whitespace().map(|x| {
+      let mut matches = HashMap::default();
+      if let Some(y) = self.0.get(x) {
+        matches.insert(Terminal::new(x), (0.0, y.clone()));
+      }
+      Token { matches, text: x }
+    });
+    iter.collect()
+  }
+
+  fn unlex(&self, name: &str, value: &Option<T>) -> Vec<Arc<Match<T>>> {
+    let matches = self.0.get(name).map(|x| {
+      let value = value.clone().unwrap_or_else(|| x.value.clone());
+      Arc::new(Match { tenses: vec![], texts: x.texts.clone(), value })
+    });
+    matches.into_iter().collect()
+  }
+
+  fn vocabulary(&self) -> Vec<(Terminal, (f32, Arc<Match<T>>))> {
+    self.0.iter().map(|(k, v)| (Terminal::new(k), (0.0, v.clone()))).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::super::lib::base::Error;
+  use super::super::super::payload::lambda::Lambda;
+  use super::super::base::Grammar;
+  use super::super::fantasy::compile;
+  use super::super::parser::Parser;
+  use super::*;
+
+  #[test]
+  fn jsgf_grammars_are_imported() {
+    let data = "
+      #JSGF V1.0;
+      grammar order;
+      // Weights and tags become scores and templates.
+      public <order> = [please] (bring | get) <count> <food> {count.$2 & food.$3};
+      <count> = /3/ one {one} | /1/ two {two} | <NULL>;
+      <food> = roti+ {roti} | /* unused */ <VOID> pani | (dal chawal) {dal};
+    ";
+    let text = import(data).unwrap();
+    assert_eq!(text.lines().take(8).collect::<Vec<_>>(), [
+      "$order!",
+      "= please? $order_group $count $food (= 'count.$2 & food.$3')",
+      "",
+      "$count",
+      "= one (< 1.0986123) (= 'one')",
+      "= two (= 'two')",
+      "= NONE",
+      "",
+    ]);
+    assert!(text.ends_with("lexer: ```bring chawal dal get one please roti two```\n"));
+
+    let grammar: Grammar<Option<Lambda>, Lambda> = compile(&text, WordLexer::new).unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    assert_eq!(value("please bring one roti roti"), Some("count.one & food.roti".into()));
+    assert_eq!(value("get dal chawal"), Some("food.dal".into()));
+    assert_eq!(value("bring roti pani"), None);
+  }
+
+  #[test]
+  fn srgs_grammars_are_imported() {
+    let data = "
+      #ABNF 1.0 UTF-8;
+      language en-US;
+      root $order;
+      public $order = $food [$NULL \"right now\"] {!{ order.$0 }!} | $food<2-3> {many};
+      public $food = roti {out = \"roti\";} | dal {out = 'dal';};
+    ";
+    let text = import(data).unwrap();
+    let lines: Vec<_> = text.lines().take(7).collect();
+    assert_eq!(lines, [
+      "$order!",
+      "= $food $order_words? (= 'order.$0')",
+      "= $order_repeat (= 'many')",
+      "",
+      "$food",
+      "= roti (= 'roti')",
+      "= dal (= 'dal')",
+    ]);
+    let grammar: Grammar<Option<Lambda>, Lambda> = compile(&text, WordLexer::new).unwrap();
+    let parser = Parser::new(&grammar);
+    let value = |x: &str| parser.value(x).map(|y| y.repr().to_string());
+    assert_eq!(value("roti right now"), Some("order.roti".into()));
+    assert_eq!(value("dal"), Some("order.dal".into()));
+    assert_eq!(value("roti dal dal"), Some("many".into()));
+    assert_eq!(value("roti roti roti roti"), None);
+
+    let error = |x: &str| import(x).err().unwrap();
+    assert_eq!(error("<a> = b;"), "No public rules".into());
+    assert_eq!(error("public <a> = <b> | <c>;"), "Undefined rules: b, c".into());
+    assert_eq!(error("public <a> = don't;"), "Unsupported tokens: don't".into());
+    assert_eq!(error("public $a = b; $a = c;"), "Duplicate rule: a".into());
+    assert_eq!(error("root $b; public $a = b;"), "Undefined root: b".into());
+    assert!(matches!(error("public <a> = (b;"), Error::ParseFailure(_, Some(_))));
+  }
+}
@@ -1,6 +1,16 @@
 pub mod base;
+pub mod conllu;
 pub mod corrector;
 pub mod fantasy;
+pub mod fst;
 pub mod generator;
+pub mod jsgf;
+pub mod nearley;
+pub mod observer;
+pub mod owned;
 pub mod parser;
+pub mod report;
+pub mod segmenter;
+#[cfg(feature = "serde")]
+pub mod serialize;
 pub mod tense;
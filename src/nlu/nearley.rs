@@ -0,0 +1,129 @@
+use super::super::lib::base::{HashMap, HashSet};
+use super::base::{Grammar, Rule, Term};
+
+// Exports a compiled grammar as an equivalent context-free grammar in Nearley syntax, so
+// that it can be used from a JavaScript toolchain. This crate remains the source of truth:
+// Nearley has no notion of our semantics, so each rule's template and scores are kept in a
+// comment after it, in the same "(< score) (= 'template')" syntax as our grammar files.
+//
+// Symbols are renamed to valid Nearley identifiers, and terminals become token types, such
+// as %roti or %num_digit. The output includes a minimal lexer that splits the input on
+// whitespace and uses each word as its own type. Grammars with category terminals, like
+// %num_digit, should replace it with a lexer that assigns those types.
+
+const LEXER: &str = "@{%
+const lexer = {
+  has: (type) => true,
+  next() {
+    const text = this.words[this.index++];
+    return text === undefined ? undefined : {type: text, text, value: text};
+  },
+  reset(data) {
+    this.words = data.split(/\\s+/).filter((x) => x);
+    this.index = 0;
+  },
+  save: () => ({}),
+  formatError: (token) => `Unexpected token: ${token.text}`,
+};
+%}
+
+@lexer lexer
+";
+
+pub fn export<S, T>(grammar: &Grammar<S, T>) -> String {
+  let Grammar { names, rules, start, .. } = grammar;
+  let valid = |x: char| x.is_ascii_alphanumeric() || x == '_';
+  let mut used = HashSet::default();
+  let mangled: Vec<_> = names
+    .iter()
+    .map(|x| {
+      let base: String = x.chars().map(|y| if valid(y) { y } else { '_' }).collect();
+      let mut result = base.trim_matches('_').to_string();
+      if result.is_empty() || result.starts_with(|y: char| y.is_ascii_digit()) {
+        result = format!("s_{}", result);
+      }
+      while !used.insert(result.clone()) {
+        result.push('_');
+      }
+      result
+    })
+    .collect();
+
+  let line = |rule: &Rule<S, T>| {
+    let items = rule.rhs.iter().map(|x| match x {
+      Term::Symbol(y) => mangled[*y].clone(),
+      Term::Terminal(y) => format!("%{}", y.trim_start_matches('%')),
+    });
+    let items: Vec<_> = items.collect();
+    let mut result = if items.is_empty() { "null".into() } else { items.join(" ") };
+    let mut comment = vec![];
+    let (merge, split) = (rule.merge.score, rule.split.score);
+    [("<", merge), (">", split)].iter().filter(|x| x.1 != 0.0).for_each(|x| {
+      comment.push(format!("({} {})", x.0, x.1));
+    });
+    rule.metadata.template.iter().for_each(|x| comment.push(format!("(= '{}')", x)));
+    if !comment.is_empty() {
+      result = format!("{}  # {}", result, comment.join(" "));
+    }
+    result
+  };
+
+  // Nearley starts parsing at the first rule, so we order symbols by a depth-first
+  // traversal from the start symbol, as we do when rendering our own grammar text.
+  let (mut order, mut seen, mut stack) = (vec![], HashSet::default(), vec![*start]);
+  while let Some(x) = stack.pop() {
+    if !seen.insert(x) {
+      continue;
+    }
+    order.push(x);
+    let rhs = rules.iter().filter(|y| y.lhs == x).flat_map(|y| y.rhs.iter());
+    let children = rhs.filter_map(|y| if let Term::Symbol(z) = y { Some(*z) } else { None });
+    let mut children: Vec<_> = children.collect();
+    children.reverse();
+    stack.append(&mut children);
+  }
+
+  let mut blocks: HashMap<usize, Vec<String>> = HashMap::default();
+  rules.iter().for_each(|x| blocks.entry(x.lhs).or_default().push(line(x)));
+  let mut result = vec![LEXER.to_string()];
+  for i in order {
+    if let Some(block) = blocks.get(&i) {
+      let name = &mangled[i];
+      let padding = " ".repeat(name.len() + 1);
+      result.push(format!("{} -> {}\n", name, block.join(&format!("\n{}| ", padding))));
+    }
+  }
+  result.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::super::hindi::lexer::HindiLexer;
+  use super::super::super::payload::lambda::Lambda;
+  use super::super::fantasy::compile;
+  use super::*;
+
+  #[test]
+  fn grammars_are_exported_to_nearley() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar = compile::<_, Lambda>(&data, HindiLexer::new).unwrap();
+    let text = export(&grammar);
+    assert!(text.starts_with("@{%\nconst lexer = {\n"));
+    assert!(text.contains("\n@lexer lexer\n\nROOT -> AskFood\n     | "));
+    let ask = "\nAskFood -> YOU__Person %kya Khana_ %chahte %hain punct_question  \
+               # (= 'Ask(R[want].$0)')\n";
+    assert!(text.contains(ask));
+    assert!(text.contains("\nDeterminer_ -> null\n            | Determiner\n"));
+    assert!(text.contains("\nMention -> Noun Stop_  # (< -10) (= 'Mention($0)')\n"));
+
+    // Every symbol that appears on the right-hand side of a rule is defined.
+    let defined: HashSet<_> =
+      text.lines().filter_map(|x| x.split(" -> ").nth(1).and(x.split(' ').next())).collect();
+    for line in text.lines().filter(|x| x.contains(" -> ") || x.trim_start().starts_with("| ")) {
+      let rhs = line.split(" -> ").last().unwrap().split("  #").next().unwrap();
+      for item in rhs.trim_start_matches(&[' ', '|'][..]).split(' ') {
+        assert!(item.starts_with('%') || item == "null" || defined.contains(item), "{}", item);
+      }
+    }
+  }
+}
@@ -0,0 +1,77 @@
+// Parsing, generation, and correction report structured events to an optional observer,
+// so that services can collect telemetry without patching the algorithms. Events borrow
+// from the running algorithm; an observer that keeps an event must copy what it needs.
+//
+// A column event can render the whole column as text, which is what the parser's debug
+// output prints. Rendering formats every state in the column, so telemetry should only
+// call it when someone will read the result.
+
+pub enum Event<'a> {
+  // The parser filled the column after the given number of tokens.
+  ColumnFilled { index: usize, states: usize, render: &'a dyn Fn() -> String },
+  // The generator unlexed a terminal and got the given number of matches.
+  Unlexed { terminal: &'a str, matches: usize },
+  // The corrector tried to regenerate a subtree because of the given errors.
+  RuleRebuilt { lhs: &'a str, errors: &'a [String], rebuilt: bool },
+}
+
+pub trait Observer: Send + Sync {
+  fn observe(&self, event: &Event);
+}
+
+// Prints the parser's columns to stdout. Parser::set_debug installs this observer.
+
+pub struct Printer;
+
+impl Observer for Printer {
+  fn observe(&self, event: &Event) {
+    if let Event::ColumnFilled { render, .. } = event {
+      println!("{}", render());
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::super::hindi::lexer::HindiLexer;
+  use super::super::super::payload::lambda::Lambda;
+  use super::super::corrector::Corrector;
+  use super::super::fantasy::compile;
+  use super::super::parser::Parser;
+  use super::*;
+  use rand::SeedableRng;
+  use std::sync::{Arc, Mutex};
+
+  #[derive(Default)]
+  struct Recorder(Mutex<Vec<String>>);
+
+  impl Observer for Recorder {
+    fn observe(&self, event: &Event) {
+      let line = match event {
+        Event::ColumnFilled { index, states, .. } => format!("column {}: {}", index, states > &0),
+        Event::Unlexed { terminal, matches } => format!("unlex {}: {}", terminal, matches > &0),
+        Event::RuleRebuilt { lhs, rebuilt, .. } => format!("rebuild {}: {}", lhs, rebuilt),
+      };
+      self.0.lock().unwrap().push(line);
+    }
+  }
+
+  #[test]
+  fn events_are_observed() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar = compile::<_, Lambda>(&data, HindiLexer::new).unwrap();
+    let recorder = Arc::new(Recorder::default());
+    let parser = Parser::new(&grammar).set_observer(Some(recorder.clone()));
+    let tree = parser.parse("main ko pani chahie").unwrap();
+    let events = std::mem::take(&mut *recorder.0.lock().unwrap());
+    let columns: Vec<_> = (0..5).map(|x| format!("column {}: true", x)).collect();
+    assert_eq!(events, columns);
+
+    // "main ko" is a parse-only phrasing, so the corrector regenerates that subtree.
+    let corrector = Corrector::new(&grammar).with_observer(Some(recorder.clone()));
+    corrector.correct(&mut rand::rngs::StdRng::seed_from_u64(17), &tree);
+    let events = std::mem::take(&mut *recorder.0.lock().unwrap());
+    let expected = ["unlex %person: false", "unlex %dative: true", "rebuild $PersonKo: true"];
+    assert_eq!(events, expected);
+  }
+}
@@ -0,0 +1,103 @@
+use super::super::lib::base::Result;
+use super::base::{Child, Children, Derivation, Grammar, Match, Metadata};
+use std::rc::Rc;
+use std::sync::Arc;
+
+// Derivations borrow their grammar's rules, so they can't be cached, sent to another
+// thread, or kept across a grammar reload. An owned derivation refers to each rule by
+// its id, its index in the grammar's rules, and keeps a snapshot of the rule's metadata.
+//
+// Converting an owned derivation back looks up each rule by id in the given grammar and
+// recomputes each node's value with the rule's merge callback, so the grammar must have
+// the same rules as the one that produced the tree.
+
+#[derive(Clone)]
+pub enum OwnedChild<T> {
+  Leaf(Arc<Match<T>>),
+  Node(OwnedDerivation<T>),
+}
+
+#[derive(Clone)]
+pub struct OwnedDerivation<T> {
+  pub children: Vec<OwnedChild<T>>,
+  pub metadata: Metadata,
+  pub rule: usize,
+  pub value: T,
+}
+
+impl<T: Clone> OwnedDerivation<T> {
+  pub fn new<S>(grammar: &Grammar<S, T>, tree: &Derivation<S, T>) -> Result<Self> {
+    let rule = tree.rule;
+    let check = |x: usize| grammar.rules.get(x).filter(|y| std::ptr::eq(*y, rule)).map(|_| x);
+    let id = rule.metadata.id.and_then(check);
+    let id = id.or_else(|| grammar.rules.iter().position(|x| std::ptr::eq(x, rule)));
+    let id = id.ok_or_else(|| format!("Rule not in grammar: {}", grammar.names[rule.lhs]))?;
+    let children = tree.children.iter().map(|x| match x {
+      Child::Leaf(y) => Ok(OwnedChild::Leaf(Arc::clone(y))),
+      Child::Node(y) => Ok(OwnedChild::Node(OwnedDerivation::new(grammar, y)?)),
+    });
+    let children = children.collect::<Result<Vec<_>>>()?;
+    Ok(Self { children, metadata: rule.metadata.clone(), rule: id, value: tree.value.clone() })
+  }
+
+  pub fn to_derivation<'a, S>(&self, grammar: &'a Grammar<S, T>) -> Result<Derivation<'a, S, T>> {
+    let rule = grammar.rules.get(self.rule).ok_or_else(|| format!("Unknown rule: {}", self.rule))?;
+    let (m, n) = (rule.rhs.len(), self.children.len());
+    if m != n {
+      Err(format!("Rule {} has {} terms; got: {} children", self.rule, m, n))?;
+    }
+    let children = self.children.iter().map(|x| match x {
+      OwnedChild::Leaf(y) => Ok(Child::Leaf(Arc::clone(y))),
+      OwnedChild::Node(y) => Ok(Child::Node(Rc::new(y.to_derivation(grammar)?))),
+    });
+    Ok(Derivation::new(children.collect::<Result<Children<_, _>>>()?, rule))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::super::hindi::lexer::HindiLexer;
+  use super::super::super::payload::lambda::Lambda;
+  use super::super::corrector::Corrector;
+  use super::super::fantasy::compile;
+  use super::super::parser::Parser;
+  use super::*;
+  use rand::SeedableRng;
+
+  fn make_grammar() -> Grammar<Option<Lambda>, Lambda> {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    compile(&data, HindiLexer::new).unwrap()
+  }
+
+  #[test]
+  fn owned_derivations_survive_a_grammar_reload() {
+    let owned = {
+      let grammar = make_grammar();
+      let tree = Parser::new(&grammar).parse("do accha acche larki ko pani chahie").unwrap();
+      let owned = OwnedDerivation::new(&grammar, &tree).unwrap();
+      assert_eq!(owned.rule, tree.rule.metadata.id.unwrap());
+      std::thread::spawn(move || owned).join().unwrap()
+    };
+
+    let grammar = make_grammar();
+    let tree = owned.to_derivation(&grammar).unwrap();
+    assert_eq!(tree.value.repr(), owned.value.repr());
+    assert_eq!(tree.matches().len(), 7);
+    let corrector = Corrector::new(&grammar);
+    let text = |x: Derivation<_, Lambda>| {
+      let matches = x.matches();
+      matches.iter().map(|y| y.texts["latin"].clone()).collect::<Vec<_>>().join(" ")
+    };
+    let correct = |x: &OwnedDerivation<Lambda>| {
+      let mut rng = rand::rngs::StdRng::seed_from_u64(17);
+      corrector.correct_owned(&mut rng, x).map(|y| text(y.tree))
+    };
+    let expected = corrector.correct(&mut rand::rngs::StdRng::seed_from_u64(17), &tree);
+    assert_eq!(correct(&owned), Ok(text(expected.tree)));
+
+    let mut invalid = owned.clone();
+    invalid.rule = grammar.rules.len();
+    let error = format!("Unknown rule: {}", grammar.rules.len());
+    assert_eq!(correct(&invalid), Err(error.into()));
+  }
+}
@@ -1,7 +1,11 @@
 use super::super::lib::base::HashMap;
-use super::base::{Child, Derivation, Entry, Grammar, Rule, Term, Token};
+use super::base::{Child, Children, Derivation, Entry, Grammar, Rule, Term, Terminal, Token};
+use super::observer::{Event, Observer, Printer};
+use super::segmenter::{DefaultSegmenter, Segmenter};
 use lib::arena::Arena;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 // A State is a rule along with a "cursor" and a "start", where the cursor is
 // the position in the rule up to which we have a match and the start is the
@@ -19,6 +23,9 @@ use std::rc::Rc;
 //
 //   next: This field lists other states that predict the same next symbol as
 //         this one. (Their end index and next term must match this state's.)
+//
+// When the parser skips tokens, it copies states and adds a penalty to the copies'
+// scores. A copy's "skipped" field records how many tokens were skipped to reach it.
 
 struct Candidate<'a, 'b, T> {
   down: *const u8,
@@ -37,6 +44,7 @@ struct State<'a, 'b, T> {
   next: *const State<'a, 'b, T>,
   rule: &'a IndexedRule<'b, T>,
   score: f32,
+  skipped: u16,
   start: u16,
 }
 
@@ -46,7 +54,7 @@ impl<'a, 'b, T> State<'a, 'b, T> {
     assert!(cursor <= max && start <= max);
     let (cursor, start) = (cursor as u16, start as u16);
     let (candidate, next) = (std::ptr::null(), std::ptr::null());
-    Self { candidate, cursor, next, rule, score: std::f32::NEG_INFINITY, start }
+    Self { candidate, cursor, next, rule, score: std::f32::NEG_INFINITY, skipped: 0, start }
   }
 
   fn cursor(&self) -> usize {
@@ -63,12 +71,12 @@ impl<'a, 'b, T> State<'a, 'b, T> {
 
   fn evaluate<S>(&self) -> Derivation<'b, S, T> {
     assert!(self.cursor() == self.rule.base.rhs.len());
-    let mut children = Vec::with_capacity(self.cursor());
+    let mut children = Children::with_capacity(self.cursor());
     let mut current = self;
     for _ in 0..self.cursor {
       let Candidate { down, prev, .. } = unsafe { &*current.candidate };
       children.push(match current.down(*down) {
-        Down::Leaf(x) => Child::Leaf(Rc::clone(&x.1)),
+        Down::Leaf(x) => Child::Leaf(Arc::clone(&x.1)),
         Down::Node(x) => Child::Node(Rc::new(x.evaluate())),
       });
       current = unsafe { &**prev };
@@ -78,11 +86,63 @@ impl<'a, 'b, T> State<'a, 'b, T> {
     Derivation::new(children, rule)
   }
 
+  fn explain(&self, skip_penalty: f32) -> Score {
+    assert!(self.cursor() == self.rule.base.rhs.len());
+    let (mut children, mut skipped) = (Vec::with_capacity(self.cursor()), 0);
+    let mut current = self;
+    for _ in 0..self.cursor {
+      let Candidate { down, prev, .. } = unsafe { &*current.candidate };
+      skipped += current.skipped as usize;
+      children.push(match current.down(*down) {
+        Down::Leaf(x) => Score::Leaf(x.0),
+        Down::Node(x) => x.explain(skip_penalty),
+      });
+      current = unsafe { &**prev };
+    }
+    skipped += current.skipped as usize;
+    children.reverse();
+    let penalty = if skipped > 0 { skipped as f32 * skip_penalty } else { 0.0 };
+    let merge = self.rule.base.merge.score;
+    Score::Node { children, merge, skipped, penalty, total: self.score }
+  }
+
+  // Returns the log of the sum of exp(score) over all of this state's derivations. As in
+  // a Ranker, a copy made while skipping tokens adds its penalty to its original's sum.
+  fn inside(&self, skip_penalty: f32, memo: &mut HashMap<*const Self, f32>) -> f32 {
+    if self.cursor == 0 {
+      return self.score;
+    } else if let Some(x) = memo.get(&(self as *const Self)) {
+      return *x;
+    }
+    let mut scores = vec![];
+    let mut candidate = self.candidate;
+    while !candidate.is_null() {
+      let Candidate { down, next, prev } = unsafe { &*candidate };
+      let down = match self.down(*down) {
+        Down::Leaf(x) => x.0,
+        Down::Node(x) => x.inside(skip_penalty, memo),
+      };
+      scores.push(unsafe { &**prev }.inside(skip_penalty, memo) + down);
+      candidate = *next;
+    }
+    let result = log_sum_exp(&scores) + self.skipped as f32 * skip_penalty;
+    memo.insert(self, result);
+    result
+  }
+
   fn start(&self) -> usize {
     self.start as usize
   }
 }
 
+fn log_sum_exp(xs: &[f32]) -> f32 {
+  let max = xs.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+  if max == f32::NEG_INFINITY {
+    return max;
+  }
+  max + xs.iter().map(|x| (x - max).exp()).sum::<f32>().ln()
+}
+
 // A Chart is a set of Earley parser states and candidate derivation lists,
 // stored in an arena during the parse. It also includes a "wanted" hashmap:
 // (Here, C = number of cursors, N = number of tokens, S = number of symbols.)
@@ -108,8 +168,9 @@ impl<'a, 'b, T> State<'a, 'b, T> {
 struct Chart<'a, 'b, T> {
   candidates: Arena<Candidate<'a, 'b, T>>,
   column: Column<'a, 'b, T>,
-  debug: bool,
   grammar: &'a IndexedGrammar<'b, T>,
+  max_column: usize,
+  observer: Option<&'a dyn Observer>,
   skipped: Option<Skipped<'a, 'b, T>>,
   states: Arena<State<'a, 'b, T>>,
   wanted: HashMap<usize, *const State<'a, 'b, T>>,
@@ -126,8 +187,12 @@ struct Column<'a, 'b, T> {
 }
 
 impl<'a, 'b, T> Chart<'a, 'b, T> {
-  fn new<S>(grammar: &'a IndexedGrammar<'b, T>, options: &Parser<'a, S, T>) -> Self {
-    let (arena, lists) = (256, 64);
+  fn new<S>(
+    grammar: &'a IndexedGrammar<'b, T>,
+    options: &'a Parser<'_, S, T>,
+    scratch: Scratch<'b, T>,
+  ) -> Self {
+    let lists = 64;
     let column = Column {
       completed: Vec::with_capacity(lists),
       scannable: Vec::with_capacity(lists),
@@ -137,10 +202,11 @@ impl<'a, 'b, T> Chart<'a, 'b, T> {
       token: None,
       token_index: 0,
     };
-    let (candidates, states) = (Arena::with_capacity(arena), Arena::with_capacity(arena));
+    let Scratch { candidates, states } = scratch;
     let skipped = if options.skip_count > 0 { Some(Skipped::new(options)) } else { None };
-    let (debug, wanted) = (options.debug, HashMap::default());
-    let mut result = Self { candidates, column, debug, grammar, skipped, states, wanted };
+    let (observer, wanted) = (options.observer.as_deref(), HashMap::default());
+    let mut result =
+      Self { candidates, column, grammar, max_column: 0, observer, skipped, states, wanted };
     for rule in &result.grammar.by_name[grammar.start] {
       result.column.states.push(result.states.alloc(State::new(0, rule, 0)));
     }
@@ -214,21 +280,40 @@ impl<'a, 'b, T> Chart<'a, 'b, T> {
     self.column.states.iter().for_each(|x| {
       self.score_state(*x);
     });
-    if self.debug {
-      println!("{}", self.print_column());
+    self.max_column = std::cmp::max(self.max_column, self.column.states.len());
+    if let Some(observer) = self.observer {
+      let (index, states) = (self.column.token_index, self.column.states.len());
+      observer.observe(&Event::ColumnFilled { index, states, render: &|| self.print_column() });
     }
   }
 
-  fn get_result<S>(mut self) -> Option<Derivation<'b, S, T>> {
-    let mut _temp = None;
-    let completed = if let Some(skipped) = self.skipped.as_mut() {
+  fn get_completed(&mut self) -> States<'a, 'b, T> {
+    if let Some(skipped) = self.skipped.as_mut() {
       skipped.push_column(&mut self.column);
-      let completed = skipped.get_completed(&mut self.states);
-      _temp = Some(completed);
-      _temp.as_ref().unwrap()
+      skipped.get_completed(&mut self.states)
     } else {
-      &self.column.completed
-    };
+      std::mem::take(&mut self.column.completed)
+    }
+  }
+
+  fn get_n_best<S>(&mut self, n: usize) -> Vec<(Derivation<'b, S, T>, f32)> {
+    let completed = self.get_completed();
+    let skip_penalty = self.skipped.as_ref().map(|x| x.skip_penalty).unwrap_or_default();
+    let mut ranker = Ranker { memo: HashMap::default(), n, skip_penalty };
+    let mut roots = vec![];
+    for state in completed {
+      let state = unsafe { &*state };
+      if state.rule.base.lhs == self.grammar.start {
+        let ranks = ranker.rank(state).len();
+        (0..ranks).for_each(|i| roots.push((ranker.rank(state)[i].0, state, i)));
+      }
+    }
+    roots.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    roots.truncate(n);
+    roots.into_iter().map(|(score, state, i)| (ranker.evaluate(state, i), score)).collect()
+  }
+
+  fn get_best(&self, completed: &States<'a, 'b, T>) -> Option<&'a State<'a, 'b, T>> {
     let mut best_score = std::f32::NEG_INFINITY;
     let mut best_state = None;
     for state in completed {
@@ -238,12 +323,47 @@ impl<'a, 'b, T> Chart<'a, 'b, T> {
         best_state = Some(state);
       }
     }
-    best_state.map(|x| x.evaluate())
+    best_state
+  }
+
+  // The best derivation's confidence is its probability, if we treat the exponentiated
+  // scores of all of the chart's complete derivations as an unnormalized distribution.
+  fn get_confidence<S>(&mut self) -> Option<(Derivation<'b, S, T>, f32)> {
+    let completed = self.get_completed();
+    let best = self.get_best(&completed)?;
+    let skip_penalty = self.skipped.as_ref().map(|x| x.skip_penalty).unwrap_or_default();
+    let mut memo = HashMap::default();
+    let roots = completed.iter().map(|x| unsafe { &**x });
+    let roots = roots.filter(|x| x.rule.base.lhs == self.grammar.start);
+    let scores: Vec<_> = roots.map(|x| x.inside(skip_penalty, &mut memo)).collect();
+    let confidence = (best.score - log_sum_exp(&scores)).exp();
+    Some((best.evaluate(), confidence.min(1.0)))
+  }
+
+  fn get_result<S>(&mut self, explain: bool) -> Option<Explained<'b, S, T>> {
+    let completed = self.get_completed();
+    let best = self.get_best(&completed);
+    let skip_penalty = self.skipped.as_ref().map(|x| x.skip_penalty).unwrap_or_default();
+    best.map(|x| (x.evaluate(), if explain { Some(x.explain(skip_penalty)) } else { None }))
+  }
+
+  // Resets the chart's arenas so that the parser can reuse them. The arenas are empty,
+  // so no state outlives the chart, and we can extend their lifetimes to the parser's.
+  fn recycle<'c>(mut self) -> Scratch<'c, T> {
+    self.candidates.reset();
+    self.states.reset();
+    type Candidates<'a, 'b, T> = Arena<Candidate<'a, 'b, T>>;
+    type Entries<'a, 'b, T> = Arena<State<'a, 'b, T>>;
+    let (candidates, states) = (self.candidates, self.states);
+    let candidates =
+      unsafe { std::mem::transmute::<Candidates<'a, 'b, T>, Candidates<'c, 'c, T>>(candidates) };
+    let states = unsafe { std::mem::transmute::<Entries<'a, 'b, T>, Entries<'c, 'c, T>>(states) };
+    Scratch { candidates, states }
   }
 
   fn print_column(&self) -> String {
     let header = self.column.token.map(|x| {
-      let mut xs: Vec<_> = x.matches.iter().collect();
+      let mut xs: Vec<_> = x.matches.iter().map(|(k, v)| (k.name(), v)).collect();
       xs.sort_by(|(a, _), (b, _)| a.cmp(b));
       let xs: Vec<_> = xs.iter().map(|(k, v)| format!("  {} (score: {})", k, v.0)).collect();
       format!(": {:?}\n{}", x.text, xs.join("\n"))
@@ -283,8 +403,8 @@ impl<'a, 'b, T> Chart<'a, 'b, T> {
 
     scannable.iter().for_each(|x| {
       let state = unsafe { &**x };
-      if let Term::Terminal(t) = &state.rule.base.rhs[state.cursor()] {
-        if let Some(m) = token.matches.get(t.as_str()) {
+      if let Some(t) = &state.rule.terminals[state.cursor()] {
+        if let Some(m) = token.matches.get(t) {
           self.advance_state(Down::Leaf(m), state);
         }
       }
@@ -301,7 +421,7 @@ impl<'a, 'b, T> Chart<'a, 'b, T> {
       state.score = state.rule.base.merge.score;
       return state.score;
     }
-    let mut best_candidate = std::ptr::null();
+    let mut best_candidate: *const Candidate<'a, 'b, T> = std::ptr::null();
     let mut best_score = std::f32::NEG_INFINITY;
     let mut candidate = state.candidate;
     while !candidate.is_null() {
@@ -319,12 +439,90 @@ impl<'a, 'b, T> Chart<'a, 'b, T> {
     }
     assert!(!best_candidate.is_null());
     assert!(best_score > std::f32::NEG_INFINITY);
-    state.candidate = best_candidate;
+    // Move the winning derivation to the head of the list. We keep the rest of the list,
+    // so that a Ranker can find the state's other derivations.
+    if best_candidate != state.candidate {
+      let head = unsafe { &mut *(state.candidate as *mut Candidate<'a, 'b, T>) };
+      let best = unsafe { &mut *(best_candidate as *mut Candidate<'a, 'b, T>) };
+      std::mem::swap(&mut head.down, &mut best.down);
+      std::mem::swap(&mut head.prev, &mut best.prev);
+    }
     state.score = best_score;
     state.score
   }
 }
 
+// A Ranker finds the n best derivations of a scored state. A state's ranked derivations
+// combine each of its candidates with ranked derivations of the candidate's prev state
+// and down node. Each entry is a derivation's score, its candidate, and the ranks of the
+// prev and down derivations that it uses. A state without candidates has one derivation.
+
+type Ranked<'a, 'b, T> = (f32, *const Candidate<'a, 'b, T>, usize, usize);
+
+struct Ranker<'a, 'b, T> {
+  memo: HashMap<*const State<'a, 'b, T>, Vec<Ranked<'a, 'b, T>>>,
+  n: usize,
+  skip_penalty: f32,
+}
+
+impl<'a, 'b, T> Ranker<'a, 'b, T> {
+  fn evaluate<S>(&mut self, state: &State<'a, 'b, T>, rank: usize) -> Derivation<'b, S, T> {
+    assert!(state.cursor() == state.rule.base.rhs.len());
+    let mut children = Children::with_capacity(state.cursor());
+    let (mut current, mut rank) = (state, rank);
+    for _ in 0..state.cursor {
+      let (_, candidate, prev_rank, down_rank) = self.rank(current)[rank];
+      let Candidate { down, prev, .. } = unsafe { &*candidate };
+      children.push(match current.down(*down) {
+        Down::Leaf(x) => Child::Leaf(Arc::clone(&x.1)),
+        Down::Node(x) => Child::Node(Rc::new(self.evaluate(x, down_rank))),
+      });
+      current = unsafe { &**prev };
+      rank = prev_rank;
+    }
+    children.reverse();
+    let rule = unsafe { std::mem::transmute::<&Rule<(), T>, &Rule<S, T>>(state.rule.base) };
+    Derivation::new(children, rule)
+  }
+
+  fn rank(&mut self, state: &State<'a, 'b, T>) -> &[Ranked<'a, 'b, T>] {
+    let key = state as *const State<'a, 'b, T>;
+    if !self.memo.contains_key(&key) {
+      let ranked = self.rank_candidates(state);
+      self.memo.insert(key, ranked);
+    }
+    &self.memo[&key]
+  }
+
+  // A copy of a state made while skipping tokens shares the original's candidates, so we
+  // add the copy's skip penalty to each of their scores.
+  fn rank_candidates(&mut self, state: &State<'a, 'b, T>) -> Vec<Ranked<'a, 'b, T>> {
+    if state.cursor == 0 {
+      return vec![(state.score, std::ptr::null(), 0, 0)];
+    }
+    let penalty = state.skipped as f32 * self.skip_penalty;
+    let mut result = vec![];
+    let mut candidate = state.candidate;
+    while !candidate.is_null() {
+      let Candidate { down, next, prev } = unsafe { &*candidate };
+      let prevs: Vec<_> = self.rank(unsafe { &**prev }).iter().map(|x| x.0).collect();
+      let downs: Vec<_> = match state.down(*down) {
+        Down::Leaf(x) => vec![x.0],
+        Down::Node(x) => self.rank(x).iter().map(|y| y.0).collect(),
+      };
+      for (i, prev) in prevs.iter().enumerate() {
+        for (j, down) in downs.iter().enumerate() {
+          result.push((prev + down + penalty, candidate, i, j));
+        }
+      }
+      candidate = *next;
+    }
+    result.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    result.truncate(self.n);
+    result
+  }
+}
+
 // A Skipped structure keeps a small rolling window of past column's states.
 // We use it to support parsing that ignores some tokens at a given penalty.
 
@@ -339,7 +537,7 @@ struct Skipped<'a, 'b, T> {
 }
 
 impl<'a, 'b, T> Skipped<'a, 'b, T> {
-  fn new<S>(options: &Parser<'a, S, T>) -> Self {
+  fn new<S>(options: &Parser<'_, S, T>) -> Self {
     let Parser { skip_count: n, skip_penalty, .. } = *options;
     let completed = (0..=n).map(|_| vec![]).collect();
     let scannable = (0..=n).map(|_| vec![]).collect();
@@ -361,6 +559,7 @@ impl<'a, 'b, T> Skipped<'a, 'b, T> {
         columns[j].iter().for_each(|y| {
           let mut state = unsafe { std::ptr::read(*y) };
           state.score += i as f32 * self.skip_penalty;
+          state.skipped = i as u16;
           result.push(arena.alloc(state));
         });
       }
@@ -383,8 +582,28 @@ impl<'a, 'b, T> Skipped<'a, 'b, T> {
   }
 }
 
+// Arenas from earlier parses, reset for reuse. A parser keeps a pool of them, so that
+// concurrent parses on different threads each get their own.
+
+struct Scratch<'a, T> {
+  candidates: Arena<Candidate<'a, 'a, T>>,
+  states: Arena<State<'a, 'a, T>>,
+}
+
+// Scratch arenas are always empty, so they don't hold any pointers into other threads.
+unsafe impl<'a, T> Send for Scratch<'a, T> {}
+
+impl<'a, T> Default for Scratch<'a, T> {
+  fn default() -> Self {
+    let arena = 256;
+    Self { candidates: Arena::with_capacity(arena), states: Arena::with_capacity(arena) }
+  }
+}
+
 // An IndexedGrammar is a parsing-only grammar that includes an extra "index"
 // field on each rule, which is the cursor position at the start of that rule.
+// Each rule also has its terminals' interned ids, which scanning uses to find
+// a token's matches.
 
 struct IndexedGrammar<'a, T> {
   by_name: Vec<Vec<IndexedRule<'a, T>>>,
@@ -396,53 +615,221 @@ struct IndexedGrammar<'a, T> {
 struct IndexedRule<'a, T> {
   base: &'a Rule<(), T>,
   index: usize,
+  terminals: Vec<Option<Terminal>>,
 }
 
 fn index<S, T>(grammar: &Grammar<S, T>) -> IndexedGrammar<T> {
   let mut index = 0;
   let mut by_name: Vec<_> = grammar.names.iter().map(|_| vec![]).collect();
   for rule in grammar.rules.iter().filter(|x| x.merge.score > std::f32::NEG_INFINITY) {
-    by_name[rule.lhs].push(IndexedRule { base: unsafe { std::mem::transmute(rule) }, index });
+    let terminals = rule.rhs.iter().map(|x| match x {
+      Term::Symbol(_) => None,
+      Term::Terminal(y) => Some(Terminal::new(y)),
+    });
+    let (base, terminals) = (unsafe { std::mem::transmute(rule) }, terminals.collect());
+    by_name[rule.lhs].push(IndexedRule { base, index, terminals });
     index += rule.rhs.len() + 1;
   }
   IndexedGrammar { by_name, max_index: index, names: &grammar.names, start: grammar.start }
 }
 
+// A breakdown of a derivation's score, in the same shape as the derivation. A node's
+// total is its rule's merge score, plus its children's totals, plus a penalty for each
+// token that the parser skipped while building it. A leaf's score is its lexer score.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Score {
+  Leaf(f32),
+  Node { children: Vec<Score>, merge: f32, skipped: usize, penalty: f32, total: f32 },
+}
+
+impl Score {
+  pub fn total(&self) -> f32 {
+    match self {
+      Score::Leaf(x) => *x,
+      Score::Node { total, .. } => *total,
+    }
+  }
+
+  // Renders the breakdown as an indented tree labeled with the derivation's terms.
+  pub fn explain<S, T>(&self, grammar: &Grammar<S, T>, tree: &Derivation<S, T>) -> String {
+    let mut lines = vec![];
+    self.explain_lines(grammar, &grammar.names[tree.rule.lhs], tree, "", &mut lines);
+    lines.join("\n")
+  }
+
+  fn explain_lines<S, T>(
+    &self,
+    grammar: &Grammar<S, T>,
+    name: &str,
+    tree: &Derivation<S, T>,
+    indent: &str,
+    lines: &mut Vec<String>,
+  ) {
+    let (children, merge, skipped, penalty, total) = match self {
+      Score::Leaf(x) => return lines.push(format!("{}{}: {}", indent, name, x)),
+      Score::Node { children, merge, skipped, penalty, total } => {
+        (children, merge, skipped, penalty, total)
+      }
+    };
+    let line = tree.rule.metadata.line.map(|x| format!(" (line {})", x)).unwrap_or_default();
+    let skips = Some(skipped).filter(|x| **x > 0).map(|x| format!(", skipped {}: {}", x, penalty));
+    let skips = skips.unwrap_or_default();
+    lines.push(format!("{}{}{}: {} (merge: {}{})", indent, name, line, total, merge, skips));
+    let indent = format!("{}  ", indent);
+    for ((term, child), score) in tree.rule.rhs.iter().zip(&tree.children).zip(children) {
+      let name = match term {
+        Term::Symbol(x) => &grammar.names[*x],
+        Term::Terminal(x) => x,
+      };
+      let tree = if let Child::Node(x) = child { x } else { tree };
+      score.explain_lines(grammar, name, tree, &indent, lines);
+    }
+  }
+}
+
+type Explained<'a, S, T> = (Derivation<'a, S, T>, Option<Score>);
+
+// Statistics about one parse, for tuning the capacities that the parser preallocates.
+// States and candidates count every arena allocation, including the penalized copies
+// made when skipping tokens. The parser only reads the clock when stats are enabled,
+// since std::time::Instant is not available on every target (e.g. wasm).
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParseStats {
+  pub candidates: usize,
+  pub columns: usize,
+  pub max_column: usize,
+  pub states: usize,
+  pub lex_time: Duration,
+  pub parse_time: Duration,
+  pub result_time: Duration,
+}
+
 // Our public interface: use a builder interface to set a Parser's options,
 // then call parse(). We may want to make index() public later for performance.
 
 pub struct Parser<'a, S, T> {
-  debug: bool,
   grammar: &'a Grammar<S, T>,
   indexed: IndexedGrammar<'a, T>,
+  observer: Option<Arc<dyn Observer>>,
+  scratch: Mutex<Vec<Scratch<'a, T>>>,
+  segmenter: Arc<dyn Segmenter>,
   skip_count: usize,
   skip_penalty: f32,
+  stats: bool,
 }
 
 impl<'a, S, T> Parser<'a, S, T> {
   pub fn new(grammar: &'a Grammar<S, T>) -> Self {
     let indexed = index(grammar);
-    Self { debug: false, grammar, indexed, skip_count: 0, skip_penalty: 0.0 }
+    let (scratch, segmenter) = (Mutex::default(), Arc::new(DefaultSegmenter::default()));
+    let (skip_count, skip_penalty, stats) = (0, 0.0, false);
+    Self { grammar, indexed, observer: None, scratch, segmenter, skip_count, skip_penalty, stats }
   }
 
   pub fn parse<'b>(&self, input: &'b str) -> Option<Derivation<'b, S, T>>
   where
     'a: 'b,
   {
+    self.run(input, |x| x.get_result(false)).0.map(|x| x.0)
+  }
+
+  // Splits the input into utterances with the parser's segmenter, then parses each one.
+  // Returns each segment with its derivation, which is None if the segment failed to parse.
+  pub fn parse_all<'b>(&self, input: &'b str) -> Vec<(&'b str, Option<Derivation<'b, S, T>>)>
+  where
+    'a: 'b,
+  {
+    self.segmenter.segment(input).into_iter().map(|x| (x, self.parse(x))).collect()
+  }
+
+  // Returns up to n of the input's highest-scoring derivations, best first, with scores.
+  pub fn parse_n_best<'b>(&self, input: &'b str, n: usize) -> Vec<(Derivation<'b, S, T>, f32)>
+  where
+    'a: 'b,
+  {
+    self.run(input, |x| x.get_n_best(n)).0
+  }
+
+  // Like parse, but also returns the parser's confidence in the derivation, from 0 to 1,
+  // which is comparable across inputs of different lengths, unlike its score.
+  pub fn parse_with_confidence<'b>(&self, input: &'b str) -> Option<(Derivation<'b, S, T>, f32)>
+  where
+    'a: 'b,
+  {
+    self.run(input, |x| x.get_confidence()).0
+  }
+
+  // Like parse, but also returns the parse's stats if they're enabled with set_stats.
+  pub fn parse_with_stats<'b>(
+    &self,
+    input: &'b str,
+  ) -> (Option<Derivation<'b, S, T>>, Option<ParseStats>)
+  where
+    'a: 'b,
+  {
+    let (result, stats) = self.run(input, |x| x.get_result(false));
+    (result.map(|x| x.0), stats)
+  }
+
+  // Like parse, but also returns a breakdown of the derivation's score.
+  pub fn explain<'b>(&self, input: &'b str) -> Option<(Derivation<'b, S, T>, Score)>
+  where
+    'a: 'b,
+  {
+    self.run(input, |x| x.get_result(true)).0.and_then(|(x, y)| Some((x, y?)))
+  }
+
+  fn run<'b, F, R>(&self, input: &'b str, f: F) -> (R, Option<ParseStats>)
+  where
+    'a: 'b,
+    F: for<'c> FnOnce(&mut Chart<'c, 'b, T>) -> R,
+  {
+    let now = || if self.stats { Some(Instant::now()) } else { None };
+    let start = now();
     let tokens = self.grammar.lexer.lex(input);
-    let mut chart = Chart::new(&self.indexed, self);
+    let lexed = now();
+    let scratch = self.scratch.lock().unwrap().pop().unwrap_or_default();
+    let mut chart = Chart::new(&self.indexed, self, scratch);
     for token in tokens.iter() {
       chart.process_token(token);
     }
-    chart.get_result()
+    let parsed = now();
+    let result = f(&mut chart);
+    let done = now();
+    let since = |a: Option<Instant>, b: Option<Instant>| {
+      a.zip(b).map(|(a, b)| b - a).unwrap_or_default()
+    };
+    let stats = done.map(|_| ParseStats {
+      candidates: chart.candidates.len(),
+      columns: tokens.len() + 1,
+      max_column: chart.max_column,
+      states: chart.states.len(),
+      lex_time: since(start, lexed),
+      parse_time: since(lexed, parsed),
+      result_time: since(parsed, done),
+    });
+    self.scratch.lock().unwrap().push(chart.recycle());
+    (result, stats)
   }
 
   pub fn value(&self, input: &str) -> Option<T> {
     self.parse(input).map(|x| x.value)
   }
 
-  pub fn set_debug(mut self, debug: bool) -> Self {
-    self.debug = debug;
+  // Debugging prints each column of the chart. It replaces any other observer.
+  pub fn set_debug(self, debug: bool) -> Self {
+    self.set_observer(if debug { Some(Arc::new(Printer)) } else { None })
+  }
+
+  pub fn set_observer(mut self, observer: Option<Arc<dyn Observer>>) -> Self {
+    self.observer = observer;
+    self
+  }
+
+  pub fn set_segmenter(mut self, segmenter: Arc<dyn Segmenter>) -> Self {
+    self.segmenter = segmenter;
     self
   }
 
@@ -455,29 +842,34 @@ impl<'a, S, T> Parser<'a, S, T> {
     self.skip_penalty = skip_penalty;
     self
   }
+
+  pub fn set_stats(mut self, stats: bool) -> Self {
+    self.stats = stats;
+    self
+  }
 }
 
 #[cfg(test)]
 mod tests {
-  use super::super::base::{Lexer, Match, Semantics, Tense};
+  use super::super::base::{Lexer, Match, Metadata, Semantics, Tense, TenseSchema};
   use super::*;
   use std::marker::PhantomData;
   use test::Bencher;
 
   struct CharacterLexer<T: Default> {
-    base: Rc<Match<T>>,
+    base: Arc<Match<T>>,
     mark: PhantomData<T>,
   }
 
   impl<T: Default> Default for CharacterLexer<T> {
     fn default() -> Self {
       let (tenses, texts, value) = (vec![], HashMap::default(), T::default());
-      Self { base: Rc::new(Match { tenses, texts, value }), mark: PhantomData }
+      Self { base: Arc::new(Match { tenses, texts, value }), mark: PhantomData }
     }
   }
 
-  impl<T: Default> Lexer<(), T> for CharacterLexer<T> {
-    fn fix(&self, _: &Match<T>, _: &Tense) -> Vec<Rc<Match<T>>> {
+  impl<T: Default + Send + Sync> Lexer<(), T> for CharacterLexer<T> {
+    fn fix(&self, _: &Match<T>, _: &Tense) -> Vec<Arc<Match<T>>> {
       unimplemented!()
     }
 
@@ -485,14 +877,14 @@ mod tests {
       let map = input.char_indices().map(|(i, x)| {
         let text = &input[i..i + x.len_utf8()];
         let mut matches = HashMap::default();
-        matches.insert(text, (0.0, Rc::clone(&self.base)));
-        matches.insert("%ch", (0.0, Rc::clone(&self.base)));
+        matches.insert(Terminal::new(text), (0.0, Arc::clone(&self.base)));
+        matches.insert(Terminal::new("%ch"), (0.0, Arc::clone(&self.base)));
         Token { matches, text }
       });
       map.collect()
     }
 
-    fn unlex(&self, _: &str, _: &()) -> Vec<Rc<Match<T>>> {
+    fn unlex(&self, _: &str, _: &()) -> Vec<Arc<Match<T>>> {
       unimplemented!()
     }
   }
@@ -508,12 +900,17 @@ mod tests {
     }
   }
 
-  fn make_rule<F: Fn(&[T]) -> T + 'static, T>(lhs: usize, rhs: &str, f: F) -> Rule<(), T> {
-    let merge: Semantics<dyn Fn(&[T]) -> T> = Semantics { callback: Box::new(f), score: 0.0 };
-    let split: Semantics<dyn Fn(&()) -> Vec<Vec<()>>> =
-      Semantics { callback: Box::new(|_| unimplemented!()), score: 0.0 };
+  fn make_rule<F, T>(lhs: usize, rhs: &str, f: F) -> Rule<(), T>
+  where
+    F: Fn(&[T]) -> T + Send + Sync + 'static,
+  {
+    let merge: Semantics<dyn Fn(&[T]) -> T + Send + Sync> =
+      Semantics { callback: Box::new(f), score: 0.0 };
+    let split: Semantics<dyn Fn(&(), &mut dyn FnMut(Vec<()>) -> bool) + Send + Sync> =
+      Semantics { callback: Box::new(|_, _| unimplemented!()), score: 0.0 };
     let rhs = rhs.split(' ').filter(|x| !x.is_empty()).map(make_term).collect();
-    Rule { lhs, rhs, merge, split, precedence: vec![], tense: Tense::default() }
+    let (precedence, tense, metadata) = (vec![], Tense::default(), Metadata::default());
+    Rule { lhs, rhs, merge, split, precedence, tense, metadata }
   }
 
   fn make_term(term: &str) -> Term {
@@ -529,6 +926,7 @@ mod tests {
   #[test]
   fn scoring_works() {
     let grammar = Grammar {
+      features: TenseSchema::default(),
       lexer: Box::new(CharacterLexer::default()),
       names: "$Root $As $Bs $Neither $A $B".split(' ').map(|x| x.into()).collect(),
       rules: vec![
@@ -555,11 +953,30 @@ mod tests {
     assert_eq!(parser.value("bab").unwrap(), "bb");
     assert_eq!(parser.value("b?b").unwrap(), "bb");
     assert_eq!(parser.value("b??").unwrap(), "");
+
+    // The n best parses are ordered by score. Here, each "a" can be scored as an "a" or
+    // as an arbitrary character, but the "b" can only be an arbitrary character in $As.
+    let n_best = parser.parse_n_best("aab", 10);
+    let scores: Vec<_> = n_best.iter().map(|x| x.1).collect();
+    assert_eq!(scores, [1.0, 0.0, -1.0, -1.0, -1.0, -3.0, -3.0]);
+    assert_eq!((n_best[0].0.value.as_str(), n_best[1].0.value.as_str()), ("aa", ""));
+    let mut values: Vec<_> = n_best[2..5].iter().map(|x| x.0.value.as_str()).collect();
+    values.sort();
+    assert_eq!(values, ["a", "a", "b"]);
+    assert_eq!(parser.parse_n_best("aab", 2).len(), 2);
+
+    // The best parse's confidence is its share of the total probability of all parses.
+    let total: f32 = n_best.iter().map(|x| x.1.exp()).sum();
+    let (tree, confidence) = parser.parse_with_confidence("aab").unwrap();
+    assert_eq!(tree.value, "aa");
+    assert!((confidence - 1f32.exp() / total).abs() < 1e-6);
+    assert!(parser.parse_with_confidence("aaa").unwrap().1 > confidence);
   }
 
   #[test]
   fn skipping_works() {
     let grammar = Grammar {
+      features: TenseSchema::default(),
       lexer: Box::new(CharacterLexer::default()),
       names: "$Root $Add $Num $Whitespace".split(' ').map(|x| x.into()).collect(),
       rules: vec![
@@ -586,11 +1003,45 @@ mod tests {
     assert_eq!(skip(1).value("1+2+3 ??"), None);
     assert_eq!(skip(2).value("1+2??+3 "), Some(6));
     assert_eq!(skip(2).value("1+2+3 ??"), Some(6));
+
+
+    // Each skipped token is charged to the node that skipped it.
+    let (tree, score) = skip(1).explain("1+2?+3 ?").unwrap();
+    let explanation = score.explain(&grammar, &tree);
+    let lines: Vec<_> = explanation.lines().take(3).collect();
+    assert_eq!(lines, [
+      "$Root: -2 (merge: 0, skipped 1: -1)",
+      "  $Add: -1 (merge: 0, skipped 1: -1)",
+      "    $Add: 0 (merge: 0)",
+    ]);
+    fn check(score: &Score) {
+      if let Score::Node { children, merge, penalty, total, .. } = score {
+        assert_eq!(*total, merge + penalty + children.iter().map(|x| x.total()).sum::<f32>());
+        children.iter().for_each(check);
+      }
+    }
+    check(&score);
+    assert_eq!(Some(tree.value), skip(1).value("1+2?+3 ?"));
+    let n_best = skip(1).parse_n_best("1+2?+3 ?", 4);
+    assert_eq!((n_best[0].0.value, n_best[0].1), (6, -2.0));
+    assert!(n_best.windows(2).all(|x| x[0].1 >= x[1].1));
+    let total: f32 = skip(1).parse_n_best("1+2?+3 ?", 1000).iter().map(|x| x.1.exp()).sum();
+    let confidence = skip(1).parse_with_confidence("1+2?+3 ?").unwrap().1;
+    assert!((confidence - (-2f32).exp() / total).abs() < 1e-6);
+
+    // Stats are only collected when they're enabled. Skipping allocates copies of states.
+    assert_eq!(skip(1).parse_with_stats("1+2+3   ").1, None);
+    let stats = |x| skip(x).set_stats(true).parse_with_stats("1+2+3   ").1.unwrap();
+    let (base, skipping) = (stats(0), stats(1));
+    assert_eq!((base.columns, skipping.columns), (9, 9));
+    assert!(0 < base.max_column && base.max_column <= base.states);
+    assert!(0 < base.candidates && base.states < skipping.states);
   }
 
   #[bench]
   fn parsing_benchmark(b: &mut Bencher) {
     let grammar = Grammar {
+      features: TenseSchema::default(),
       lexer: Box::new(CharacterLexer::default()),
       names: "$Root $Add $Mul $Num".split(' ').map(|x| x.into()).collect(),
       rules: vec![
@@ -0,0 +1,111 @@
+use super::super::lib::base::{HashMap, HashSet};
+use super::super::payload::base::Payload;
+use super::base::{Grammar, Term};
+
+// A GrammarReport summarizes the structure of a compiled grammar. Symbols are
+// referred to by their index into the grammar's names list.
+//
+//   - rules: the number of rules with each symbol as their LHS
+//   - graph: the sorted list of symbols on the RHS of each symbol's rules
+//   - reachable: whether each symbol can be reached from the start symbol
+//   - nullable: whether each symbol can expand to an empty sequence of tokens
+//   - unknown: terminals for which the lexer has no entries
+//   - macros: for each macro, the number of distinct instantiations of it
+//
+// Compiled grammars name macro instantiations "name[arg, ...]", so we can
+// recover macro counts from the names list without access to the AST.
+
+pub struct GrammarReport {
+  pub rules: Vec<usize>,
+  pub graph: Vec<Vec<usize>>,
+  pub reachable: Vec<bool>,
+  pub nullable: Vec<bool>,
+  pub unknown: Vec<String>,
+  pub macros: Vec<(String, usize)>,
+}
+
+impl GrammarReport {
+  pub fn new<T: Payload>(grammar: &Grammar<Option<T>, T>) -> Self {
+    let n = grammar.names.len();
+    let mut rules = vec![0; n];
+    let mut graph = vec![vec![]; n];
+    let mut terminals = HashSet::default();
+    grammar.rules.iter().for_each(|x| {
+      rules[x.lhs] += 1;
+      x.rhs.iter().for_each(|y| match y {
+        Term::Symbol(z) => graph[x.lhs].push(*z),
+        Term::Terminal(z) => std::mem::drop(terminals.insert(z.as_str())),
+      });
+    });
+    graph.iter_mut().for_each(|x| {
+      x.sort();
+      x.dedup();
+    });
+
+    // Compute reachability with a depth-first search from the start symbol.
+    let mut reachable = vec![false; n];
+    let mut stack = vec![grammar.start];
+    while let Some(x) = stack.pop() {
+      if !reachable[x] {
+        reachable[x] = true;
+        stack.extend(graph[x].iter().filter(|y| !reachable[**y]));
+      }
+    }
+
+    // Compute nullability by iterating to a fixed point.
+    let mut nullable = vec![false; n];
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for rule in &grammar.rules {
+        if nullable[rule.lhs] {
+          continue;
+        }
+        if rule.rhs.iter().all(|x| if let Term::Symbol(y) = x { nullable[*y] } else { false }) {
+          nullable[rule.lhs] = true;
+          changed = true;
+        }
+      }
+    }
+
+    // Collect terminals that the lexer can't match and count macro instantiations.
+    let known: HashSet<_> = grammar.lexer.terminals().into_iter().map(|x| x.name()).collect();
+    let check = |x: &&str| !known.contains(x);
+    let mut unknown: Vec<_> = terminals.into_iter().filter(check).map(|x| x.to_string()).collect();
+    unknown.sort();
+
+    let mut counts: HashMap<&str, usize> = HashMap::default();
+    grammar.names.iter().filter(|x| !x.starts_with('$') && x.ends_with(']')).for_each(|x| {
+      x.find('[').iter().for_each(|i| *counts.entry(&x[..*i]).or_insert(0) += 1);
+    });
+    let mut macros: Vec<_> = counts.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+    macros.sort();
+
+    Self { rules, graph, reachable, nullable, unknown, macros }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::super::hindi::lexer::HindiLexer;
+  use super::super::super::payload::lambda::Lambda;
+  use super::super::fantasy::compile;
+  use super::*;
+
+  #[test]
+  fn hindi_grammar_report() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar = compile(&data, HindiLexer::<Lambda>::new).unwrap();
+    let report = GrammarReport::new(&grammar);
+    let index = |x: &str| grammar.names.iter().position(|y| y == x).unwrap();
+    let count = |x: &str| report.macros.iter().find(|y| y.0 == x).map(|y| y.1);
+    assert!(report.reachable.iter().all(|x| *x));
+    assert!(report.unknown.is_empty());
+    assert!(report.nullable[index("$Main?")]);
+    assert!(!report.nullable[index("$Main")]);
+    assert!(report.graph[index("$AskFood")].contains(&index("$Khana?")));
+    assert_eq!(report.rules[index("$AskFood")], 5);
+    assert_eq!(count("YOU"), Some(2));
+    assert!(count("NOUN").unwrap() > 1);
+  }
+}
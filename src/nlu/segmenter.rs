@@ -0,0 +1,128 @@
+// A segmenter splits input with several utterances, like "namaste. mujhe pani chahie",
+// into segments that the parser handles one at a time. Parser::parse_all runs one; other
+// entry points parse the whole input as a single utterance.
+//
+// The default segmenter ends a segment at sentence-final punctuation, which it drops, and
+// starts one at a discourse marker, which it keeps. Punctuation only counts when it ends
+// a word, so "1.5" is one segment. Markers are matched case-insensitively and only as
+// whole words, so the "magar" marker doesn't split "magarmach".
+
+pub trait Segmenter: Send + Sync {
+  fn segment<'a>(&self, input: &'a str) -> Vec<&'a str>;
+}
+
+pub struct DefaultSegmenter {
+  markers: Vec<String>,
+  stops: Vec<char>,
+}
+
+impl Default for DefaultSegmenter {
+  fn default() -> Self {
+    let markers = vec!["lekin".into(), "magar".into()];
+    Self { markers, stops: ".?!।॥".chars().collect() }
+  }
+}
+
+impl DefaultSegmenter {
+  pub fn set_markers(mut self, markers: &[&str]) -> Self {
+    self.markers = markers.iter().map(|x| x.to_string()).collect();
+    self
+  }
+
+  pub fn set_stops(mut self, stops: &str) -> Self {
+    self.stops = stops.chars().collect();
+    self
+  }
+}
+
+impl Segmenter for DefaultSegmenter {
+  fn segment<'a>(&self, input: &'a str) -> Vec<&'a str> {
+    let ends = |i: usize| {
+      let next = input[i..].chars().next();
+      next.map(|x| x.is_whitespace() || self.stops.contains(&x)).unwrap_or(true)
+    };
+    let marker = |i: usize| {
+      self.markers.iter().any(|x| {
+        let text = input.get(i..i + x.len());
+        text.map(|y| y.eq_ignore_ascii_case(x) && ends(i + x.len())).unwrap_or_default()
+      })
+    };
+    let (mut result, mut start, mut prev) = (vec![], 0, ' ');
+    for (i, x) in input.char_indices() {
+      if self.stops.contains(&x) && ends(i + x.len_utf8()) {
+        result.push(&input[start..i]);
+        start = i + x.len_utf8();
+      } else if prev.is_whitespace() && marker(i) {
+        result.push(&input[start..i]);
+        start = i;
+      }
+      prev = x;
+    }
+    result.push(&input[start..]);
+    result.into_iter().map(|x| x.trim()).filter(|x| !x.is_empty()).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::super::hindi::lexer::HindiLexer;
+  use super::super::super::payload::lambda::Lambda;
+  use super::super::fantasy::compile;
+  use super::super::parser::Parser;
+  use super::*;
+  use std::sync::Arc;
+
+  struct Commas;
+
+  impl Segmenter for Commas {
+    fn segment<'a>(&self, input: &'a str) -> Vec<&'a str> {
+      input.split(',').map(|x| x.trim()).collect()
+    }
+  }
+
+  #[test]
+  fn inputs_are_segmented() {
+    let segmenter = DefaultSegmenter::default();
+    assert_eq!(segmenter.segment("namaste. mujhe pani chahie"), ["namaste", "mujhe pani chahie"]);
+    assert_eq!(
+      segmenter.segment("aap kaun hain? ... main Ram hun।"),
+      ["aap kaun hain", "main Ram hun"]
+    );
+    assert_eq!(
+      segmenter.segment("mujhe pani chahie Lekin chai nahin"),
+      ["mujhe pani chahie", "Lekin chai nahin"]
+    );
+    assert_eq!(segmenter.segment("mujhe 1.5 magarmach chahie!"), ["mujhe 1.5 magarmach chahie"]);
+    assert_eq!(segmenter.segment(" ?! "), Vec::<&str>::new());
+
+    let segmenter = segmenter.set_markers(&["aur phir"]).set_stops(";");
+    assert_eq!(
+      segmenter.segment("namaste; aur phir. aur phirni"),
+      ["namaste", "aur phir. aur phirni"]
+    );
+    assert_eq!(segmenter.segment("chai aur phir pani"), ["chai", "aur phir pani"]);
+  }
+
+  #[test]
+  fn segments_are_parsed() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar = compile::<_, Lambda>(&data, HindiLexer::new).unwrap();
+    let parser = Parser::new(&grammar);
+    let input = "namaste. mujhe pani chahie! mujhe mujhe";
+    assert!(parser.parse(input).is_none());
+    let values = parser.parse_all(input).into_iter();
+    let values: Vec<_> = values.map(|(x, y)| (x, y.map(|z| z.value.repr().to_string()))).collect();
+    assert_eq!(values, [
+      ("namaste", Some("Hello()".into())),
+      ("mujhe pani chahie", Some("Tell(I, want.type.water)".into())),
+      ("mujhe mujhe", None),
+    ]);
+
+    let parser = parser.set_segmenter(Arc::new(Commas));
+    let segments = parser.parse_all("namaste, aap kaun hain").into_iter();
+    assert_eq!(segments.map(|x| (x.0, x.1.is_some())).collect::<Vec<_>>(), [
+      ("namaste", true),
+      ("aap kaun hain", true),
+    ]);
+  }
+}
@@ -0,0 +1,289 @@
+use super::super::lib::base::HashMap;
+use super::base::{Child, Derivation, Match, Metadata, Tense, Token};
+use super::owned::{OwnedChild, OwnedDerivation};
+use serde::de::{Deserializer, Error};
+use serde::Deserialize;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::sync::Arc;
+
+// Serialization for parse trees, so that they can be logged, cached, and inspected by
+// external tools. Payload values serialize however their type does: Cached payloads,
+// like Lambda, serialize as their repr. The shapes are:
+//
+//   Tense:      {"category": "value", ...}
+//   Match:      {"tenses": [Tense], "texts": {"scheme": "text", ...}, "value": T}
+//   Token:      {"matches": {"terminal": {"match": Match, "score": f32}, ...}, "text": ...}
+//   Derivation: {"children": [Child], "line": ?, "rule": ?, "span": [start, end], "value": T}
+//   Child:      {"Leaf": {"match": Match, "span": [i, i + 1]}} or {"Node": Derivation}
+//
+// A derivation's "rule" is the rule's id, its index in the grammar's rules, and its
+// "line" is the rule's line in the grammar file. Both are null for rules built by hand.
+// Spans are ranges of indices into the derivation's matches. Maps are sorted by key.
+//
+// Owned derivations serialize in the same shape, and any serialized derivation whose
+// rules all have ids deserializes to an owned derivation. Spans are ignored on input.
+
+type Result<S> = std::result::Result<<S as Serializer>::Ok, <S as Serializer>::Error>;
+
+impl Serialize for Tense {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S> {
+    Sorted(self.entries()).serialize(serializer)
+  }
+}
+
+impl<T: Serialize> Serialize for Match<T> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S> {
+    let mut map = serializer.serialize_map(Some(3))?;
+    map.serialize_entry("tenses", &self.tenses)?;
+    map.serialize_entry("texts", &Sorted(self.texts.iter().map(|(k, v)| (k, &**v)).collect()))?;
+    map.serialize_entry("value", &self.value)?;
+    map.end()
+  }
+}
+
+impl<'a, T: Serialize> Serialize for Token<'a, T> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S> {
+    let matches = self.matches.iter().map(|(k, v)| (k.name(), Scored(v.0, &v.1)));
+    let mut map = serializer.serialize_map(Some(2))?;
+    map.serialize_entry("matches", &Sorted(matches.collect()))?;
+    map.serialize_entry("text", self.text)?;
+    map.end()
+  }
+}
+
+impl<'a, S, T: Serialize> Serialize for Derivation<'a, S, T> {
+  fn serialize<R: Serializer>(&self, serializer: R) -> Result<R> {
+    Item(Part::Node(self), 0).serialize(serializer)
+  }
+}
+
+impl<T: Serialize> Serialize for OwnedDerivation<T> {
+  fn serialize<R: Serializer>(&self, serializer: R) -> Result<R> {
+    Item(Part::Node(self), 0).serialize(serializer)
+  }
+}
+
+impl<'de, T: Clone + Deserialize<'de>> Deserialize<'de> for OwnedDerivation<T> {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    RawTree::deserialize(deserializer)?.build().map_err(D::Error::custom)
+  }
+}
+
+// Helpers for serialization. Both kinds of derivations implement Node, and an Item is
+// a leaf or a node along with the index of its first match.
+
+trait Node<T> {
+  fn metadata(&self) -> &Metadata;
+  fn parts(&self) -> Vec<Part<'_, T>>;
+  fn value(&self) -> &T;
+}
+
+enum Part<'b, T: 'b> {
+  Leaf(&'b Match<T>),
+  Node(&'b dyn Node<T>),
+}
+
+struct Item<'b, T: 'b>(Part<'b, T>, usize);
+
+struct Scored<'b, T: 'b>(f32, &'b Match<T>);
+
+struct Sorted<K: Ord, V>(Vec<(K, V)>);
+
+impl<'a, S, T> Node<T> for Derivation<'a, S, T> {
+  fn metadata(&self) -> &Metadata {
+    &self.rule.metadata
+  }
+  fn parts(&self) -> Vec<Part<'_, T>> {
+    let parts = self.children.iter().map(|x| match x {
+      Child::Leaf(y) => Part::Leaf(&**y),
+      Child::Node(y) => Part::Node(&**y),
+    });
+    parts.collect()
+  }
+  fn value(&self) -> &T {
+    &self.value
+  }
+}
+
+impl<T> Node<T> for OwnedDerivation<T> {
+  fn metadata(&self) -> &Metadata {
+    &self.metadata
+  }
+  fn parts(&self) -> Vec<Part<'_, T>> {
+    let parts = self.children.iter().map(|x| match x {
+      OwnedChild::Leaf(y) => Part::Leaf(&**y),
+      OwnedChild::Node(y) => Part::Node(y),
+    });
+    parts.collect()
+  }
+  fn value(&self) -> &T {
+    &self.value
+  }
+}
+
+fn size<T>(part: &Part<T>) -> usize {
+  match part {
+    Part::Leaf(_) => 1,
+    Part::Node(x) => x.parts().iter().map(size).sum(),
+  }
+}
+
+impl<'b, T: Serialize> Serialize for Item<'b, T> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S> {
+    let (start, mut map) = (self.1, serializer.serialize_map(None)?);
+    let node = match self.0 {
+      Part::Leaf(x) => {
+        map.serialize_entry("match", x)?;
+        map.serialize_entry("span", &[start, start + 1])?;
+        return map.end();
+      }
+      Part::Node(x) => x,
+    };
+    let (mut children, mut end) = (vec![], start);
+    for part in node.parts() {
+      let n = size(&part);
+      let key = if let Part::Leaf(_) = part { "Leaf" } else { "Node" };
+      children.push(Sorted(vec![(key, Item(part, end))]));
+      end += n;
+    }
+    let metadata = node.metadata();
+    map.serialize_entry("children", &children)?;
+    map.serialize_entry("line", &metadata.line)?;
+    map.serialize_entry("rule", &metadata.id)?;
+    map.serialize_entry("span", &[start, end])?;
+    map.serialize_entry("value", node.value())?;
+    map.end()
+  }
+}
+
+impl<'b, T: Serialize> Serialize for Scored<'b, T> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S> {
+    let mut map = serializer.serialize_map(Some(2))?;
+    map.serialize_entry("match", self.1)?;
+    map.serialize_entry("score", &self.0)?;
+    map.end()
+  }
+}
+
+impl<K: Ord + Serialize, V: Serialize> Serialize for Sorted<K, V> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S> {
+    let mut entries: Vec<_> = self.0.iter().collect();
+    entries.sort_by(|x, y| x.0.cmp(&y.0));
+    let mut map = serializer.serialize_map(Some(entries.len()))?;
+    entries.into_iter().try_for_each(|(k, v)| map.serialize_entry(k, v))?;
+    map.end()
+  }
+}
+
+// Helpers for deserialization. Match texts are keyed by static scheme names, so we
+// map each key to one of the names that our lexers write and reject any other key.
+
+const SCHEMES: [&str; 8] = ["approximative", "head", "hindi", "hk", "iast", "iso", "latin", "urdu"];
+
+#[derive(Deserialize)]
+struct RawMatch<T> {
+  tenses: Vec<HashMap<String, String>>,
+  texts: HashMap<String, String>,
+  value: T,
+}
+
+#[derive(Deserialize)]
+struct RawLeaf<T> {
+  #[serde(rename = "match")]
+  item: RawMatch<T>,
+}
+
+#[derive(Deserialize)]
+enum RawChild<T> {
+  Leaf(RawLeaf<T>),
+  Node(RawTree<T>),
+}
+
+#[derive(Deserialize)]
+struct RawTree<T> {
+  children: Vec<RawChild<T>>,
+  line: Option<usize>,
+  rule: usize,
+  value: T,
+}
+
+fn scheme(name: &str) -> std::result::Result<&'static str, String> {
+  let result = SCHEMES.iter().find(|x| **x == name).cloned();
+  result.ok_or_else(|| format!("Unknown text scheme: {:?}", name))
+}
+
+impl<T: Clone> RawTree<T> {
+  fn build(self) -> std::result::Result<OwnedDerivation<T>, String> {
+    let leaf = |x: RawMatch<T>| {
+      let tenses = x.tenses.iter().map(Tense::new).collect::<super::super::lib::base::Result<_>>();
+      let tenses = tenses.map_err(|y| format!("{:?}", y))?;
+      let texts = x.texts.into_iter().map(|(k, v)| Ok((scheme(&k)?, v.into())));
+      let texts = texts.collect::<std::result::Result<_, String>>()?;
+      Ok(OwnedChild::Leaf(Arc::new(Match { tenses, texts, value: x.value })))
+    };
+    let children = self.children.into_iter().map(|x| match x {
+      RawChild::Leaf(y) => leaf(y.item),
+      RawChild::Node(y) => Ok(OwnedChild::Node(y.build()?)),
+    });
+    let children = children.collect::<std::result::Result<_, String>>()?;
+    let metadata = Metadata { id: Some(self.rule), line: self.line, ..Metadata::default() };
+    Ok(OwnedDerivation { children, metadata, rule: self.rule, value: self.value })
+  }
+}
+
+#[cfg(all(test, feature = "serde_json"))]
+mod tests {
+  use super::super::super::hindi::lexer::HindiLexer;
+  use super::super::super::payload::lambda::Lambda;
+  use super::super::base::Grammar;
+  use super::super::fantasy::compile;
+  use super::super::owned::OwnedDerivation;
+  use super::super::parser::Parser;
+  use serde_json::{json, to_value, Value};
+
+  fn leaves(value: &Value) -> Vec<Value> {
+    let children = value["children"].as_array().unwrap().iter();
+    let leaf = |x: &Value| match &x["Leaf"] {
+      Value::Null => leaves(&x["Node"]),
+      y => vec![y.clone()],
+    };
+    children.flat_map(leaf).collect()
+  }
+
+  #[test]
+  fn derivations_are_serialized() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar: Grammar<Option<Lambda>, Lambda> = compile(&data, HindiLexer::new).unwrap();
+    let tree = Parser::new(&grammar).parse("mujhe pani chahie").unwrap();
+    let value = to_value(&tree).unwrap();
+    let id = value["rule"].as_u64().unwrap() as usize;
+    assert_eq!(grammar.rules[id].lhs, tree.rule.lhs);
+    assert_eq!(value["span"], json!([0, 3]));
+    assert_eq!(value["value"], "Tell(I, want.type.water)");
+
+    let leaves = leaves(&value);
+    let spans: Vec<_> = leaves.iter().map(|x| x["span"].clone()).collect();
+    assert_eq!(spans, [json!([0, 1]), json!([1, 2]), json!([2, 3])]);
+    assert_eq!(leaves[1]["match"]["texts"]["latin"], "pani");
+    assert_eq!(leaves[1]["match"]["value"], "type.water");
+
+    let owned = OwnedDerivation::new(&grammar, &tree).unwrap();
+    assert_eq!(to_value(&owned).unwrap(), value);
+    let owned: OwnedDerivation<Lambda> = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(to_value(&owned).unwrap(), value);
+    let tree = owned.to_derivation(&grammar).unwrap();
+    assert_eq!(&*tree.matches()[1].texts["hindi"], "pAnI");
+    assert_eq!(tree.matches()[1].tenses[0].get("gender"), Some("male".into()));
+    let mut invalid = value.clone();
+    invalid["rule"] = Value::Null;
+    assert!(serde_json::from_value::<OwnedDerivation<Lambda>>(invalid).is_err());
+    let unknown = value.to_string().replace(r#""latin":"pani""#, r#""klingon":"pani""#);
+    let error = serde_json::from_str::<OwnedDerivation<Lambda>>(&unknown).err().unwrap();
+    assert!(error.to_string().contains(r#"Unknown text scheme: "klingon""#));
+
+    let tokens = grammar.lexer.lex("pani");
+    let value = to_value(&tokens[0]).unwrap();
+    assert_eq!(value["text"], "pani");
+    assert!(value["matches"].as_object().unwrap().values().all(|x| x["score"].is_number()));
+  }
+}
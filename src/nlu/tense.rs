@@ -1,7 +1,7 @@
 use super::super::lib::base::{HashMap, Result};
 use std::borrow::Borrow;
-use std::cell::{RefCell, RefMut};
 use std::fmt::{Display, Formatter};
+use std::sync::{OnceLock, RwLock};
 
 // Our tense type is a mapping from interned string -> interned string. The keys represent
 // grammatical categories, such as "count", "gender", or "person". The values represent
@@ -25,9 +25,17 @@ impl Tense {
     self.0.iter().all(|(k, v)| other.0.get(k).map(|x| x == v).unwrap_or(true))
   }
 
-  pub fn check(&self, other: &Tense) -> Vec<String> {
-    let base = self.check_base(other);
-    base.iter().map(|x| format!("{} should be {} (was: {})", x.0, x.1, x.2)).collect()
+  pub fn check(&self, other: &Tense) -> Vec<Mismatch> {
+    let f = |(k, v): (&Interned, &Interned)| {
+      other.0.get(k).map(|x| if x == v { None } else { Some(Mismatch(*k, *v, *x)) })?
+    };
+    self.0.iter().filter_map(f).collect()
+  }
+
+  pub fn entries(&self) -> Vec<(String, String)> {
+    let mut result: Vec<_> = self.0.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    result.sort();
+    result
   }
 
   pub fn get(&self, category: &str) -> Option<String> {
@@ -38,16 +46,16 @@ impl Tense {
     others.0.iter().for_each(|(k, v)| std::mem::drop(self.0.insert(*k, *v)))
   }
 
-  pub fn union_checked(&mut self, others: &[Tense]) -> Vec<String> {
+  pub fn union_checked(&mut self, others: &[Tense]) -> Vec<Mismatch> {
     if others.is_empty() {
       return vec![];
     }
-    let checks: Vec<_> = others.iter().map(|x| (x, self.check_base(x))).collect();
+    let checks: Vec<_> = others.iter().map(|x| (x, self.check(x))).collect();
     let agrees: Vec<_> = checks.iter().filter(|x| x.1.is_empty()).map(|x| x.0).collect();
     if agrees.is_empty() {
       let min = checks.iter().map(|x| x.1.len()).min().unwrap();
-      let min_errors = checks.iter().find(|x| x.1.len() == min).unwrap();
-      min_errors.1.iter().map(|x| format!("{} should be {} (was: {})", x.0, x.1, x.2)).collect()
+      let min_errors = checks.into_iter().find(|x| x.1.len() == min).unwrap();
+      min_errors.1
     } else if agrees.len() == 1 {
       self.union(agrees[0]);
       vec![]
@@ -58,13 +66,6 @@ impl Tense {
     }
   }
 
-  fn check_base(&self, other: &Tense) -> Vec<(Interned, Interned, Interned)> {
-    let f = |(k, v): (&Interned, &Interned)| {
-      other.0.get(k).map(|x| if x == v { None } else { Some((*k, *v, *x)) })?
-    };
-    self.0.iter().filter_map(f).collect()
-  }
-
   fn intersect(&self, other: &Tense) -> Tense {
     let f = |(k, v): (&Interned, &Interned)| {
       other.0.get(k).map(|x| if x == v { Some((*k, *v)) } else { None })?
@@ -73,12 +74,146 @@ impl Tense {
   }
 }
 
+// A failed tense check: the category, the value that we wanted, and the value we got.
+// Mismatches display as errors, such as "count should be plural (was: singular)".
+
+pub struct Mismatch(Interned, Interned, Interned);
+
+impl Mismatch {
+  pub fn category(&self) -> String {
+    self.0.to_string()
+  }
+}
+
+impl Display for Mismatch {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    write!(f, "{} should be {} (was: {})", self.0, self.1, self.2)
+  }
+}
+
+// A grammar's tense schema declares the grammatical categories that take part in
+// agreement and the values that each one may take. We validate rules' tenses against
+// it at compile time, so that a typo like "(? count singlar)" is a compile error. An
+// empty schema accepts any tense. The schema stores interned ids, so restricting a
+// tense to its categories doesn't touch the interning table.
+//
+// Values may also declare a one-character code, as in "plural/p". A lexer can use these
+// codes to write compact tenses for the forms of a word: see Morphology::tense.
+//
+// A grammar may mark some features as politeness features, as in "feature tone
+// (politeness): ...". The corrector reports errors in them as politeness errors.
+
+type Feature = (Interned, Vec<(Interned, Option<u8>)>);
+
+#[derive(Clone, Default)]
+pub struct TenseSchema(Vec<Feature>, Vec<Interned>);
+
+impl TenseSchema {
+  pub fn new<T: Borrow<str>>(features: &[(T, Vec<T>)]) -> Result<TenseSchema> {
+    let mut result = TenseSchema::default();
+    features.iter().try_for_each(|(k, v)| result.add(k.borrow(), v))?;
+    Ok(result)
+  }
+
+  pub fn add<T: Borrow<str>>(&mut self, category: &str, values: &[T]) -> Result<()> {
+    let id = Interned::new(category)?;
+    if self.0.iter().any(|x| x.0 == id) {
+      Err(format!("Duplicate feature: {}", category))?;
+    }
+    let mut result: Vec<(Interned, Option<u8>)> = vec![];
+    for value in values.iter().map(|x| x.borrow()) {
+      let (name, code) = match value.find('/') {
+        Some(i) if value.len() == i + 2 && value.as_bytes()[i + 1] != b'.' => {
+          (&value[..i], Some(value.as_bytes()[i + 1]))
+        }
+        Some(_) => Err(format!("Invalid code for feature {}: {}", category, value))?,
+        None => (value, None),
+      };
+      if code.is_some() && result.iter().any(|x| x.1 == code) {
+        Err(format!("Duplicate code for feature {}: {}", category, value))?;
+      }
+      result.push((Interned::new(name)?, code));
+    }
+    self.0.push((id, result));
+    Ok(())
+  }
+
+  pub fn codes(&self) -> Vec<(String, Vec<(u8, String)>)> {
+    let values = |x: &[(Interned, Option<u8>)]| {
+      x.iter().filter_map(|y| Some((y.1?, y.0.to_string()))).collect()
+    };
+    self.0.iter().map(|(k, v)| (k.to_string(), values(v))).collect()
+  }
+
+  pub fn features(&self) -> Vec<(String, Vec<String>)> {
+    let value = |x: &(Interned, Option<u8>)| match x.1 {
+      Some(code) => format!("{}/{}", x.0, code as char),
+      None => x.0.to_string(),
+    };
+    self.0.iter().map(|(k, v)| (k.to_string(), v.iter().map(value).collect())).collect()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  pub fn politeness(&self, category: &str) -> bool {
+    self.1.iter().any(|x| x.to_string() == category)
+  }
+
+  pub fn set_politeness(&mut self, category: &str) -> Result<()> {
+    let id = Interned::new(category)?;
+    if !self.0.iter().any(|x| x.0 == id) {
+      Err(format!("Undeclared feature: {}", category))?;
+    }
+    self.1.push(id);
+    Ok(())
+  }
+
+  pub fn restrict(&self, tense: &Tense) -> Tense {
+    if self.is_empty() {
+      return tense.clone();
+    }
+    let known = |x: &Interned| self.0.iter().any(|y| y.0 == *x);
+    Tense(tense.0.iter().filter(|(k, _)| known(k)).map(|(k, v)| (*k, *v)).collect())
+  }
+
+  pub fn validate(&self, tense: &Tense) -> Result<()> {
+    if self.is_empty() {
+      return Ok(());
+    }
+    let mut entries: Vec<_> = tense.0.iter().map(|(k, v)| (k.to_string(), *k, *v)).collect();
+    entries.sort_by(|x, y| x.0.cmp(&y.0));
+    for (category, k, v) in entries {
+      let feature = self.0.iter().find(|x| x.0 == k);
+      let values = &feature.ok_or_else(|| format!("Undeclared feature: {}", category))?.1;
+      if !values.iter().any(|x| x.0 == v) {
+        Err(format!("Invalid value for feature {}: {}", category, v))?;
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Display for Tense {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    let entries: Vec<_> = self.entries().iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+    write!(f, "{{{}}}", entries.join(", "))
+  }
+}
+
 // The Interned helper type allows us to intern strings, checks whether a given string is
 // already interned, and checks when we run out of interned string space. If we need more
 // space we just need to bump the size on the type below.
+//
+// The table is shared by all threads, since a grammar compiled on one thread may be used
+// to parse and correct on others.
 
-thread_local! {
-  static MAP: RefCell<(Vec<String>, HashMap<String, Interned>)> = RefCell::default();
+type Table = RwLock<(Vec<String>, HashMap<String, Interned>)>;
+
+fn table() -> &'static Table {
+  static TABLE: OnceLock<Table> = OnceLock::new();
+  TABLE.get_or_init(Table::default)
 }
 
 type InternedId = u16;
@@ -88,25 +223,26 @@ pub struct Interned(InternedId);
 
 impl Interned {
   pub fn new(value: &str) -> Result<Interned> {
-    MAP.with(|x| {
-      let pair = RefMut::map_split(x.borrow_mut(), |x| (&mut x.0, &mut x.1));
-      let (mut id_to_str, mut str_to_id) = pair;
-      if let Some(x) = str_to_id.get(value) {
-        return Ok(*x);
-      }
-      let len = id_to_str.len();
-      if len > InternedId::max_value() as usize {
-        Err(format!("Hit string interning limit: {}", len))?
-      }
-      id_to_str.push(value.to_string());
-      str_to_id.insert(value.to_string(), Interned(len as InternedId));
-      Ok(Interned(len as InternedId))
-    })
+    if let Some(x) = table().read().unwrap().1.get(value) {
+      return Ok(*x);
+    }
+    let mut guard = table().write().unwrap();
+    let (id_to_str, str_to_id) = &mut *guard;
+    if let Some(x) = str_to_id.get(value) {
+      return Ok(*x);
+    }
+    let len = id_to_str.len();
+    if len > InternedId::max_value() as usize {
+      Err(format!("Hit string interning limit: {}", len))?
+    }
+    id_to_str.push(value.to_string());
+    str_to_id.insert(value.to_string(), Interned(len as InternedId));
+    Ok(Interned(len as InternedId))
   }
 }
 
 impl Display for Interned {
   fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-    MAP.with(|x| write!(f, "{}", x.borrow().0[self.0 as usize]))
+    write!(f, "{}", table().read().unwrap().0[self.0 as usize])
   }
 }
@@ -1,9 +1,11 @@
-use super::super::lib::base::Result;
+use super::super::lib::base::{HashSet, Result};
 use std::hash::Hash;
 
 pub type Args<T> = Vec<(usize, T)>;
 
-pub trait Payload: 'static + Clone + Default + Eq + Hash {
+pub type SplitIter<'a, T> = Box<dyn Iterator<Item = Args<T>> + 'a>;
+
+pub trait Payload: 'static + Clone + Default + Eq + Hash + Send + Sync {
   fn base_lex(_: &str) -> Self;
   fn base_unlex(&self) -> Option<&str>;
   fn empty(&self) -> bool;
@@ -11,9 +13,22 @@ pub trait Payload: 'static + Clone + Default + Eq + Hash {
   fn template(_: &str) -> Result<Box<dyn Template<Self>>>;
 }
 
-pub trait Template<T> {
+// Templates may override split_iter to yield splits lazily, so that callers that
+// only need a few candidates don't pay for the full (often exponential) set.
+//
+// Templates that combine two parts check whether either part is a wildcard. A wildcard
+// only takes what the other part can't, so that a variable next to one binds the value
+// that is there rather than also being split as absent.
+
+pub trait Template<T: 'static>: Send + Sync {
   fn merge(&self, xs: &Args<T>) -> T;
   fn split(&self, x: &T) -> Vec<Args<T>>;
+  fn split_iter<'a>(&'a self, x: &T) -> SplitIter<'a, T> {
+    Box::new(self.split(x).into_iter())
+  }
+  fn wildcard(&self) -> bool {
+    false
+  }
 }
 
 // Helpers used by types that implement the Payload trait.
@@ -32,6 +47,133 @@ pub fn cross<T: Clone>(a: Vec<Args<T>>, b: Vec<Args<T>>) -> Vec<Args<T>> {
   result
 }
 
+// Canonicalizes each split by sorting its arguments by index, then drops splits
+// that we've already yielded. Templates over commutative operators can produce
+// the same split many times, and each duplicate costs the generator a search.
+
+pub fn unique<'a, T: Payload>(xs: SplitIter<'a, T>) -> SplitIter<'a, T> {
+  let mut seen = HashSet::default();
+  Box::new(xs.filter_map(move |mut x| {
+    x.sort_by_key(|(i, _)| *i);
+    if seen.insert(x.clone()) { Some(x) } else { None }
+  }))
+}
+
+// Combinators used to build templates programmatically, without template syntax.
+//
+//   - ComposeTemplate substitutes the inner template for variable $i of the outer one.
+//     The two templates share an argument namespace, so their other variables should
+//     be disjoint.
+//   - RemapTemplate renames variables: variable $i of the template becomes $map[i].
+//     Variables past the end of the map are never merged, so splits that bind them to
+//     non-empty values are dropped.
+//   - WrapTemplate post-processes merged values and pre-processes values to split.
+//     If the pre-processing function returns None, the value can't be split.
+
+pub struct ComposeTemplate<T: Payload> {
+  index: usize,
+  inner: Box<dyn Template<T>>,
+  outer: Box<dyn Template<T>>,
+}
+
+impl<T: Payload> ComposeTemplate<T> {
+  pub fn new(outer: Box<dyn Template<T>>, index: usize, inner: Box<dyn Template<T>>) -> Self {
+    Self { index, inner, outer }
+  }
+}
+
+impl<T: Payload> Template<T> for ComposeTemplate<T> {
+  fn merge(&self, xs: &Args<T>) -> T {
+    let mut args: Args<T> = xs.iter().filter(|(k, _)| *k != self.index).cloned().collect();
+    args.push((self.index, self.inner.merge(xs)));
+    self.outer.merge(&args)
+  }
+  fn split(&self, x: &T) -> Vec<Args<T>> {
+    self.split_iter(x).collect()
+  }
+  fn split_iter<'a>(&'a self, x: &T) -> SplitIter<'a, T> {
+    Box::new(self.outer.split_iter(x).flat_map(move |xs| {
+      let (mut inner, outer): (Args<T>, Args<T>) =
+        xs.into_iter().partition(|(k, _)| *k == self.index);
+      let value = inner.pop().map(|(_, v)| v).unwrap_or_default();
+      cross(vec![outer], self.inner.split(&value))
+    }))
+  }
+}
+
+pub struct RemapTemplate<T: Payload> {
+  map: Vec<usize>,
+  template: Box<dyn Template<T>>,
+}
+
+impl<T: Payload> RemapTemplate<T> {
+  pub fn new(map: Vec<usize>, template: Box<dyn Template<T>>) -> Self {
+    Self { map, template }
+  }
+}
+
+impl<T: Payload> Template<T> for RemapTemplate<T> {
+  fn merge(&self, xs: &Args<T>) -> T {
+    let mut args: Args<T> = vec![];
+    for (i, j) in self.map.iter().enumerate() {
+      xs.iter().filter(|(k, _)| k == j).for_each(|(_, v)| args.push((i, v.clone())));
+    }
+    self.template.merge(&args)
+  }
+  fn split(&self, x: &T) -> Vec<Args<T>> {
+    self.split_iter(x).collect()
+  }
+  fn split_iter<'a>(&'a self, x: &T) -> SplitIter<'a, T> {
+    let remap = move |xs: Args<T>| {
+      let mut result = Vec::with_capacity(xs.len());
+      for (k, v) in xs {
+        match self.map.get(k) {
+          Some(j) => result.push((*j, v)),
+          None if v.empty() => (),
+          None => return None,
+        }
+      }
+      Some(result)
+    };
+    Box::new(self.template.split_iter(x).filter_map(remap))
+  }
+}
+
+type Unwrap<T> = Box<dyn Fn(&T) -> Option<T> + Send + Sync>;
+
+pub struct WrapTemplate<T: Payload> {
+  merge: Box<dyn Fn(T) -> T + Send + Sync>,
+  split: Unwrap<T>,
+  template: Box<dyn Template<T>>,
+}
+
+impl<T: Payload> WrapTemplate<T> {
+  pub fn new(
+    template: Box<dyn Template<T>>,
+    merge: impl Fn(T) -> T + Send + Sync + 'static,
+    split: impl Fn(&T) -> Option<T> + Send + Sync + 'static,
+  ) -> Self {
+    Self { merge: Box::new(merge), split: Box::new(split), template }
+  }
+}
+
+impl<T: Payload> Template<T> for WrapTemplate<T> {
+  fn merge(&self, xs: &Args<T>) -> T {
+    (self.merge)(self.template.merge(xs))
+  }
+  fn split(&self, x: &T) -> Vec<Args<T>> {
+    self.split_iter(x).collect()
+  }
+  fn split_iter<'a>(&'a self, x: &T) -> SplitIter<'a, T> {
+    match (self.split)(x) {
+      Some(y) => self.template.split_iter(&y),
+      None => Box::new(std::iter::empty()),
+    }
+  }
+}
+
+// Simple templates used directly by the grammar compiler.
+
 pub struct DefaultTemplate {}
 
 impl<T: Payload> Template<T> for DefaultTemplate {
@@ -64,7 +206,10 @@ impl<T: Payload> Template<T> for SlotTemplate<T> {
     self.template.merge(&args)
   }
   fn split(&self, x: &T) -> Vec<Args<T>> {
-    let result = self.template.split(x).into_iter().filter_map(|xs| {
+    self.split_iter(x).collect()
+  }
+  fn split_iter<'a>(&'a self, x: &T) -> SplitIter<'a, T> {
+    Box::new(self.template.split_iter(x).filter_map(move |xs| {
       let mut result: Args<T> = vec![];
       for (k, v) in xs.into_iter() {
         if let Some(slot) = self.slots[k] {
@@ -77,8 +222,7 @@ impl<T: Payload> Template<T> for SlotTemplate<T> {
         }
       }
       Some(result)
-    });
-    result.collect()
+    }))
   }
 }
 
@@ -95,7 +239,7 @@ impl<T: Payload> Template<T> for UnitTemplate {
 
 pub struct VariableTemplate(pub usize);
 
-impl<T: Clone + Default> Template<T> for VariableTemplate {
+impl<T: 'static + Clone + Default> Template<T> for VariableTemplate {
   fn merge(&self, xs: &Args<T>) -> T {
     let mut x = xs.iter().filter_map(|(i, x)| if *i == self.0 { Some(x.clone()) } else { None });
     x.next().unwrap_or_default()
@@ -105,9 +249,26 @@ impl<T: Clone + Default> Template<T> for VariableTemplate {
   }
 }
 
+// A wildcard matches any value. It merges to the default value, which templates
+// treat as absent, and it splits any value without binding any variables.
+
+pub struct WildcardTemplate {}
+
+impl<T: 'static + Default> Template<T> for WildcardTemplate {
+  fn merge(&self, _: &Args<T>) -> T {
+    T::default()
+  }
+  fn split(&self, _: &T) -> Vec<Args<T>> {
+    vec![vec![]]
+  }
+  fn wildcard(&self) -> bool {
+    true
+  }
+}
+
 #[cfg(test)]
 mod tests {
-  use super::super::json::Json;
+  use super::super::json::{Expr, Json};
   use super::*;
 
   fn j(input: &str) -> Json {
@@ -126,6 +287,48 @@ mod tests {
     template.merge(&args.into_iter().enumerate().collect())
   }
 
+  #[test]
+  fn compose_template_works() {
+    let t = ComposeTemplate::new(t("{a: $0, b: $1}"), 0, t("[$2, ...$3]"));
+    assert_eq!(merge(&t, vec![j("null"), j("5"), j("1"), j("[2]")]), j("{a: [1, 2], b: 5}"));
+    assert_eq!(merge(&t, vec![j("7"), j("5"), j("1"), j("null")]), j("{a: [1], b: 5}"));
+    assert_eq!(
+      t.split(&j("{a: [1, 2], b: 5}")),
+      [
+        [(1, j("5")), (2, j("null")), (3, j("[1, 2]"))],
+        [(1, j("5")), (2, j("1")), (3, j("[2]"))],
+      ]
+    );
+    assert_eq!(t.split(&j("{a: 1, b: 5}")), empty());
+  }
+
+  #[test]
+  fn remap_template_works() {
+    let short = RemapTemplate::new(vec![4], t("[$0, ...$1]"));
+    assert_eq!(merge(&short, vec![j("0"), j("1"), j("[2]"), j("3"), j("4")]), j("[4]"));
+    assert_eq!(short.split(&j("[3, 5]")), empty());
+    assert_eq!(short.split(&j("[3]")), [[(4, j("3"))]]);
+    let t = RemapTemplate::new(vec![4, 2], t("[$0, ...$1]"));
+    assert_eq!(merge(&t, vec![j("0"), j("1"), j("[2]"), j("3"), j("4")]), j("[4, 2]"));
+    assert_eq!(
+      t.split(&j("[3, 5]")),
+      [[(4, j("null")), (2, j("[3, 5]"))], [(4, j("3")), (2, j("[5]"))]]
+    );
+  }
+
+  #[test]
+  fn wrap_template_works() {
+    let wrap = |x| Json::new(Expr::List(vec![x]));
+    let unwrap = |x: &Json| match x.expr() {
+      Expr::List(xs) if xs.len() == 1 => Some(xs[0].clone()),
+      _ => None,
+    };
+    let t = WrapTemplate::new(t("{a: $0}"), wrap, unwrap);
+    assert_eq!(merge(&t, vec![j("3")]), j("[{a: 3}]"));
+    assert_eq!(t.split(&j("[{a: 3}]")), [[(0, j("3"))]]);
+    assert_eq!(t.split(&j("{a: 3}")), empty());
+  }
+
   #[test]
   fn slot_template_works() {
     let slots = vec![Some((3, false)), Some((4, false)), Some((5, true))];
@@ -1,9 +1,11 @@
 use super::super::lib::base::Result;
-use super::base::{Payload, Template};
-use std::cell::UnsafeCell;
+use super::super::lib::lru::Lru;
+use super::base::{Args, Payload, SplitIter, Template};
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
 use std::fmt::Display;
 use std::hash::{Hash, Hasher};
-use std::rc::Rc;
+use std::sync::{Arc, OnceLock};
 
 // A helper trait used to implement the Payload trait. Implement Base for T, and then
 // use Cached<T> as your Payload type. You can also use Cached<T> as a field of T and
@@ -16,8 +18,11 @@ use std::rc::Rc;
 //
 // The PartialEq method on Base does *not* need to canonicalize. The Display result will
 // be used for all equality checks and hashing on the Cached<T> type for a Base type T.
+//
+// Cached values are shared across threads, so the cached repr is computed at most once
+// per value, by whichever thread gets to it first.
 
-pub trait Base: 'static + Default + Display + PartialEq {
+pub trait Base: 'static + Default + Display + PartialEq + Send + Sync {
   fn base_lex(_: &str) -> Self;
   fn base_unlex(&self) -> Option<&str>;
   fn default_static() -> Cached<Self>;
@@ -25,11 +30,11 @@ pub trait Base: 'static + Default + Display + PartialEq {
 }
 
 #[derive(Debug)]
-pub struct Cached<T>(Rc<(T, UnsafeCell<String>)>);
+pub struct Cached<T>(Arc<(T, OnceLock<String>)>);
 
 impl<T: Base> Cached<T> {
   pub fn new(base: T) -> Self {
-    Self(Rc::new((base, UnsafeCell::default())))
+    Self(Arc::new((base, OnceLock::new())))
   }
 
   pub fn expr(&self) -> &T {
@@ -37,17 +42,13 @@ impl<T: Base> Cached<T> {
   }
 
   pub fn repr(&self) -> &str {
-    let x = unsafe { &mut *(self.0).1.get() };
-    if x.is_empty() {
-      *x = self.expr().to_string();
-    }
-    x
+    (self.0).1.get_or_init(|| self.expr().to_string())
   }
 }
 
 impl<T: Base> Clone for Cached<T> {
   fn clone(&self) -> Self {
-    Self(Rc::clone(&self.0))
+    Self(Arc::clone(&self.0))
   }
 }
 
@@ -57,6 +58,12 @@ impl<T: Base> Default for Cached<T> {
   }
 }
 
+impl<T: Base> Display for Cached<T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "{}", self.repr())
+  }
+}
+
 impl<T: Base> Eq for Cached<T> {}
 
 impl<T: Base> Hash for Cached<T> {
@@ -71,6 +78,26 @@ impl<T: Base> PartialEq for Cached<T> {
   }
 }
 
+// Cached values serialize as their repr, which is canonical, and deserialize by parsing it.
+
+#[cfg(feature = "serde")]
+impl<T: Base> serde::Serialize for Cached<T> {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.repr())
+  }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: Base> serde::Deserialize<'de> for Cached<T> {
+  fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+  where
+    D: serde::Deserializer<'de>,
+  {
+    let repr = <String as serde::Deserialize>::deserialize(deserializer)?;
+    Self::parse(&repr).map_err(|x| serde::de::Error::custom(format!("{:?}", x)))
+  }
+}
+
 impl<T: Base> Payload for Cached<T> {
   fn base_lex(x: &str) -> Self {
     Self::new(T::base_lex(x))
@@ -89,11 +116,162 @@ impl<T: Base> Payload for Cached<T> {
     if x == default.repr() {
       return Ok(default);
     }
-    let y = Self::template(x)?.merge(&vec![]);
+    let y = T::template(x)?.merge(&vec![]);
     return if y.empty() { Err(format!("Empty payload: {}", x))? } else { Ok(y) };
   }
 
   fn template(x: &str) -> Result<Box<dyn Template<Self>>> {
-    T::template(x)
+    Ok(Box::new(Shared(memoized_template::<T>(x)?)))
+  }
+}
+
+// Grammars repeat many templates, like "'$0'", so we memoize parsed templates on each
+// thread, keyed by payload type and source. Templates are immutable, so every rule with
+// the same template can share one. Parsing payload values skips the cache, since values
+// are rarely repeated.
+
+const TEMPLATE_CACHE_SIZE: usize = 1024;
+
+thread_local! {
+  static TEMPLATES: RefCell<Lru<(TypeId, String), Box<dyn Any>>> =
+    RefCell::new(Lru::new(TEMPLATE_CACHE_SIZE));
+}
+
+fn memoized_template<T: Base>(x: &str) -> Result<Arc<dyn Template<Cached<T>>>> {
+  let key = (TypeId::of::<T>(), x.to_string());
+  let cached = TEMPLATES.with(|y| {
+    let mut templates = y.borrow_mut();
+    let template = templates.get(&key)?.downcast_ref::<Arc<dyn Template<Cached<T>>>>();
+    template.map(Arc::clone)
+  });
+  if let Some(y) = cached {
+    return Ok(y);
+  }
+  let result: Arc<dyn Template<Cached<T>>> = T::template(x)?.into();
+  TEMPLATES.with(|y| y.borrow_mut().insert(key, Box::new(Arc::clone(&result))));
+  Ok(result)
+}
+
+struct Shared<T>(Arc<dyn Template<T>>);
+
+impl<T: 'static> Template<T> for Shared<T> {
+  fn merge(&self, xs: &Args<T>) -> T {
+    self.0.merge(xs)
+  }
+  fn split(&self, x: &T) -> Vec<Args<T>> {
+    self.0.split(x)
+  }
+  fn split_iter<'a>(&'a self, x: &T) -> SplitIter<'a, T> {
+    self.0.split_iter(x)
+  }
+}
+
+// A property check for templates over cached payloads. Each sample x must have at
+// least one split, and every split of x must merge back to x. Samples should be values
+// that rules produce with the template. Conversely, every value that the template merges
+// from those splits, or from those splits with one argument cleared, must itself
+// have some split that merges back to it, unless the merged value is empty. Rule
+// authors can run this check in tests to catch templates that drop information.
+
+pub fn check_template_roundtrip<T: Base>(
+  template: &dyn Template<Cached<T>>,
+  samples: &[Cached<T>],
+) -> Result<()> {
+  let show = |xs: &Args<Cached<T>>| {
+    let xs: Vec<_> = xs.iter().map(|(i, x)| format!("${} = {}", i, x.repr())).collect();
+    format!("[{}]", xs.join(", "))
+  };
+  let mut options = vec![vec![]];
+  for x in samples {
+    let splits = template.split(x);
+    if splits.is_empty() {
+      Err(format!("Round-trip failed for {}: it did not split", x.repr()))?;
+    }
+    for args in splits {
+      let y = template.merge(&args);
+      if y != *x {
+        let (x, y, args) = (x.repr(), y.repr(), show(&args));
+        Err(format!("Round-trip failed for {}: split into {}, which merged to {}", x, args, y))?;
+      }
+      for i in 0..args.len() {
+        let mut option = args.clone();
+        option[i].1 = Cached::default();
+        options.push(option);
+      }
+      options.push(args);
+    }
+  }
+  for args in options {
+    let y = template.merge(&args);
+    if !y.empty() && !template.split(&y).iter().any(|z| template.merge(z) == y) {
+      let (y, args) = (y.repr(), show(&args));
+      Err(format!("Round-trip failed for {}: merged to {}, which did not split", args, y))?;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::base::WrapTemplate;
+  use super::super::json::{Expr, Json};
+  use super::*;
+
+  fn j(input: &str) -> Json {
+    Json::parse(input).unwrap()
+  }
+
+  fn t(input: &str) -> Box<dyn Template<Json>> {
+    Json::template(input).unwrap()
+  }
+
+  #[test]
+  fn templates_are_memoized() {
+    let template = memoized_template::<Expr>("[$0, 'x']").unwrap();
+    assert!(Arc::ptr_eq(&template, &memoized_template::<Expr>("[$0, 'x']").unwrap()));
+    assert_eq!(t("[$0, 'x']").merge(&vec![(0, j("1"))]), j("[1, 'x']"));
+    let lambda = memoized_template::<super::super::lambda::Expr>("$0 & a").unwrap();
+    assert_eq!(lambda.merge(&vec![]).repr(), "a");
+    assert!(memoized_template::<Expr>("$0 & a").is_err());
+  }
+
+  #[test]
+  fn check_template_roundtrip_accepts_valid_templates() {
+    let samples = [j("{a: 1, b: [2, 3]}"), j("{a: 1}"), j("null")];
+    assert_eq!(check_template_roundtrip(&*t("{a: $0, b: [$1, ...$2]}"), &samples), Ok(()));
+    let samples = [j("[1, 2]"), j("[1]"), j("null")];
+    assert_eq!(check_template_roundtrip(&*t("[$0, ...$1]"), &samples), Ok(()));
+  }
+
+  #[test]
+  fn check_template_roundtrip_reports_lossy_splits() {
+    let first = |x: &Json| match x.expr() {
+      Expr::List(xs) => xs.first().cloned(),
+      _ => None,
+    };
+    let template = WrapTemplate::new(t("$0"), |x| Json::new(Expr::List(vec![x])), first);
+    assert_eq!(check_template_roundtrip(&template, &[j("[1]")]), Ok(()));
+    assert_eq!(
+      check_template_roundtrip(&template, &[j("[1, 2]")]),
+      Err("Round-trip failed for [1, 2]: split into [$0 = 1], which merged to [1]".into())
+    );
+  }
+
+  #[test]
+  fn check_template_roundtrip_reports_lossy_merges() {
+    let template = WrapTemplate::new(t("$0"), |x| x, |_| None);
+    assert_eq!(
+      check_template_roundtrip(&template, &[j("1")]),
+      Err("Round-trip failed for 1: it did not split".into())
+    );
+    let pairs = |x: &Json| match x.expr() {
+      Expr::List(xs) if xs.len() == 2 => Some(x.clone()),
+      _ => None,
+    };
+    let template = WrapTemplate::new(t("[1, $0]"), |x| x, pairs);
+    assert_eq!(
+      check_template_roundtrip(&template, &[j("[1, 2]")]),
+      Err("Round-trip failed for []: merged to [1], which did not split".into())
+    );
   }
 }
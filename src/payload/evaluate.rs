@@ -0,0 +1,168 @@
+use super::super::lib::base::{HashMap, HashSet, Result};
+use super::lambda::{Binary, Expr, Lambda, Unary};
+use std::collections::BTreeSet;
+
+// An evaluator for lambda DCS expressions over an in-memory knowledge base of
+// (subject, relation, object) triples. Every expression denotes a set of entities:
+//
+//   - A terminal x denotes the singleton set {x}.
+//   - A join r.u denotes {e : (e, r, v) is a fact for some v in u}.
+//   - A reversed join R[r].u denotes {e : (v, r, e) is a fact for some v in u}.
+//   - Conjunction, disjunction, and negation are intersection, union, and
+//     complement, where complements are taken over all entities in the base.
+//
+// Custom functions must be registered with the knowledge base before use. "Ask"
+// is registered by default and returns the denotation of its single argument.
+
+pub type Denotation = BTreeSet<String>;
+
+pub type Function = Box<dyn Fn(&[Denotation]) -> Result<Denotation>>;
+
+pub struct KnowledgeBase {
+  entities: Denotation,
+  forward: HashMap<String, HashMap<String, Vec<String>>>,
+  backward: HashMap<String, HashMap<String, Vec<String>>>,
+  functions: HashMap<String, Function>,
+}
+
+impl KnowledgeBase {
+  pub fn new(triples: &[(&str, &str, &str)]) -> Self {
+    let mut result = Self {
+      entities: Denotation::default(),
+      forward: HashMap::default(),
+      backward: HashMap::default(),
+      functions: HashMap::default(),
+    };
+    let mut seen = HashSet::default();
+    for (subject, relation, object) in triples.iter().filter(|x| seen.insert(**x)) {
+      let (s, r, o) = (subject.to_string(), relation.to_string(), object.to_string());
+      let forward = result.forward.entry(r.clone()).or_default();
+      forward.entry(o.clone()).or_default().push(s.clone());
+      let backward = result.backward.entry(r).or_default();
+      backward.entry(s.clone()).or_default().push(o.clone());
+      result.entities.insert(s);
+      result.entities.insert(o);
+    }
+    result.define("Ask", |xs| match xs {
+      [x] => Ok(x.clone()),
+      _ => Err(format!("Ask takes 1 argument; got: {}", xs.len()))?,
+    });
+    result
+  }
+
+  pub fn define(&mut self, name: &str, f: impl Fn(&[Denotation]) -> Result<Denotation> + 'static) {
+    std::mem::drop(self.functions.insert(name.to_string(), Box::new(f)));
+  }
+
+  pub fn evaluate(&self, lambda: &Lambda) -> Result<Denotation> {
+    match lambda.expr() {
+      Expr::Binary(Binary::Conjunction, children) => {
+        let mut xs = children.iter().map(|x| self.evaluate(x));
+        let first = xs.next().unwrap_or_else(|| Ok(self.entities.clone()))?;
+        xs.try_fold(first, |acc, x| Ok(acc.intersection(&x?).cloned().collect()))
+      }
+      Expr::Binary(Binary::Disjunction, children) => {
+        children.iter().try_fold(Denotation::default(), |mut acc, x| {
+          acc.extend(self.evaluate(x)?);
+          Ok(acc)
+        })
+      }
+      Expr::Binary(Binary::Join, children) => {
+        let (last, relations) = match children.split_last() {
+          Some(x) => x,
+          None => Err("Unable to evaluate an empty join")?,
+        };
+        relations.iter().rev().try_fold(self.evaluate(last)?, |acc, x| self.join(x, &acc))
+      }
+      Expr::Custom(name, children) => {
+        let f = self.functions.get(name).ok_or_else(|| format!("Unknown function: {}", name))?;
+        let xs = children.iter().map(|x| self.evaluate(x)).collect::<Result<Vec<_>>>()?;
+        f(&xs)
+      }
+      Expr::Date(_) | Expr::Float(_) | Expr::Int(_) | Expr::Terminal(_) => {
+        Ok(std::iter::once(lambda.repr().to_string()).collect())
+      }
+      Expr::Unary(Unary::Not, child) => {
+        let x = self.evaluate(child)?;
+        Ok(self.entities.difference(&x).cloned().collect())
+      }
+      Expr::Unary(Unary::Reverse, _) => Err(format!("Unable to evaluate relation: {}", lambda.repr()))?,
+      Expr::Unknown => Err("Unable to evaluate an empty lambda")?,
+    }
+  }
+
+  fn join(&self, relation: &Lambda, objects: &Denotation) -> Result<Denotation> {
+    let (index, name) = match relation.expr() {
+      Expr::Terminal(x) => (&self.forward, x),
+      Expr::Unary(Unary::Reverse, x) => match x.expr() {
+        Expr::Terminal(y) => (&self.backward, y),
+        _ => Err(format!("Expected a relation: {}", relation.repr()))?,
+      },
+      _ => Err(format!("Expected a relation: {}", relation.repr()))?,
+    };
+    let mut result = Denotation::default();
+    if let Some(index) = index.get(name) {
+      objects.iter().filter_map(|x| index.get(x)).for_each(|x| result.extend(x.iter().cloned()));
+    }
+    Ok(result)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::base::Payload;
+  use super::*;
+
+  fn kb() -> KnowledgeBase {
+    KnowledgeBase::new(&[
+      ("ann", "name", "Ann"),
+      ("bob", "name", "Bob"),
+      ("rex", "name", "Rex"),
+      ("rex", "owner", "me"),
+      ("rex", "type", "dog"),
+      ("tom", "owner", "bob"),
+      ("tom", "type", "cat"),
+      ("ann", "friend", "bob"),
+    ])
+  }
+
+  fn eval(kb: &KnowledgeBase, input: &str) -> Result<Vec<String>> {
+    Ok(kb.evaluate(&Lambda::parse(input)?)?.into_iter().collect())
+  }
+
+  #[test]
+  fn evaluate_joins() {
+    let kb = kb();
+    assert_eq!(eval(&kb, "owner.me").unwrap(), ["rex"]);
+    assert_eq!(eval(&kb, "R[name].owner.me").unwrap(), ["Rex"]);
+    assert_eq!(eval(&kb, "Ask(R[name].owner.me)").unwrap(), ["Rex"]);
+    assert_eq!(eval(&kb, "R[type].owner.friend.ann").unwrap(), Vec::<String>::new());
+    assert_eq!(eval(&kb, "R[type].owner.R[friend].ann").unwrap(), ["cat"]);
+  }
+
+  #[test]
+  fn evaluate_boolean_operators() {
+    let kb = kb();
+    assert_eq!(eval(&kb, "type.dog | type.cat").unwrap(), ["rex", "tom"]);
+    assert_eq!(eval(&kb, "type.dog & owner.me").unwrap(), ["rex"]);
+    assert_eq!(eval(&kb, "type.cat & owner.me").unwrap(), Vec::<String>::new());
+    assert_eq!(eval(&kb, "R[name].(~owner.me)").unwrap(), ["Ann", "Bob"]);
+  }
+
+  #[test]
+  fn evaluate_custom_functions() {
+    let mut kb = kb();
+    assert_eq!(eval(&kb, "Count(type.dog)"), Err("Unknown function: Count".into()));
+    kb.define("Count", |xs| Ok(xs.iter().map(|x| x.len().to_string()).collect()));
+    assert_eq!(eval(&kb, "Count(type.dog | type.cat)").unwrap(), ["2"]);
+    assert_eq!(eval(&kb, "Ask(me, you)"), Err("Ask takes 1 argument; got: 2".into()));
+  }
+
+  #[test]
+  fn evaluate_errors() {
+    let kb = kb();
+    assert_eq!(eval(&kb, "(a | b).c"), Err("Expected a relation: a | b".into()));
+    assert_eq!(eval(&kb, "-"), Err("Unable to evaluate an empty lambda".into()));
+    assert_eq!(eval(&kb, "R[name]"), Err("Unable to evaluate relation: R[name]".into()));
+  }
+}
@@ -0,0 +1,381 @@
+use super::super::lib::base::{HashMap, Result};
+use super::base::{cross, Args, Template, VariableTemplate, WildcardTemplate};
+use std::fmt::{Display, Formatter};
+
+// A frame is an intent plus a set of typed slots, as used by slot-filling dialog
+// systems. Partial frames, which have slots but no intent, are the semantics of
+// the phrases that fill those slots. Frames are written "Order(count=2, item='roti')"
+// and partial frames "(item='roti')". Slots are always kept sorted by name.
+
+pub type Frame = super::cached::Cached<Expr>;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value {
+  Boolean(bool),
+  Integer(i64),
+  String(String),
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub enum Expr {
+  Frame(Option<String>, Vec<(String, Value)>),
+  Value(Value),
+  #[default]
+  Unknown,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Type {
+  Boolean,
+  Integer,
+  String,
+}
+
+impl Frame {
+  pub fn intent(&self) -> Option<&str> {
+    match self.expr() {
+      Expr::Frame(x, _) => x.as_ref().map(|y| y.as_str()),
+      _ => None,
+    }
+  }
+
+  pub fn slot(&self, name: &str) -> Option<&Value> {
+    let slots = if let Expr::Frame(_, x) = self.expr() { x.as_slice() } else { &[] };
+    slots.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+  }
+}
+
+impl Value {
+  fn coerce(&self, kind: Type) -> Option<Value> {
+    match (kind, self) {
+      (Type::Boolean, Value::String(x)) => x.parse().ok().map(Value::Boolean),
+      (Type::Integer, Value::String(x)) => x.parse().ok().map(Value::Integer),
+      (Type::String, Value::Boolean(x)) => Some(Value::String(x.to_string())),
+      (Type::String, Value::Integer(x)) => Some(Value::String(x.to_string())),
+      (Type::Boolean, Value::Boolean(_))
+      | (Type::Integer, Value::Integer(_))
+      | (Type::String, Value::String(_)) => Some(self.clone()),
+      _ => None,
+    }
+  }
+}
+
+// The base Payload implementation for Frame types. Lexed tokens are strings; typed
+// template variables like "$0:int" convert them to other types.
+
+impl Display for Expr {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    write!(f, "{}", stringify(self))
+  }
+}
+
+impl Display for Value {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    match self {
+      Value::Boolean(x) => write!(f, "{}", x),
+      Value::Integer(x) => write!(f, "{}", x),
+      Value::String(x) => write!(f, "'{}'", x.escape_default()),
+    }
+  }
+}
+
+impl super::cached::Base for Expr {
+  fn base_lex(input: &str) -> Self {
+    Expr::Value(Value::String(input.to_string()))
+  }
+
+  fn base_unlex(&self) -> Option<&str> {
+    if let Expr::Value(Value::String(x)) = self { Some(x.as_str()) } else { None }
+  }
+
+  fn default_static() -> Frame {
+    thread_local! { static DEFAULT: Frame = Frame::new(Expr::default()); }
+    DEFAULT.with(|x| x.clone())
+  }
+
+  fn template(input: &str) -> Result<Box<dyn Template<Frame>>> {
+    template(input)
+  }
+}
+
+fn stringify(expr: &Expr) -> String {
+  match expr {
+    Expr::Frame(intent, slots) => {
+      let slots: Vec<_> = slots.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+      format!("{}({})", intent.as_ref().map(|x| x.as_str()).unwrap_or(""), slots.join(", "))
+    }
+    Expr::Value(x) => x.to_string(),
+    Expr::Unknown => "-".to_string(),
+  }
+}
+
+fn template(input: &str) -> Result<Box<dyn Template<Frame>>> {
+  use super::super::lib::combine::*;
+
+  type Node = Box<dyn Template<Frame>>;
+
+  pub fn wrap(x: impl Template<Frame> + 'static) -> Node {
+    Box::new(x)
+  }
+
+  thread_local! {
+    static PARSER: Parser<Node> = {
+      let ws = whitespace();
+      let id = seq2((ident(), &ws), |x| x.0);
+      let st = |x| seq2((string(x, |_| ()), &ws), |x| x.0);
+
+      // Parsers for typed values and variables.
+      let index = seq2((regexp("(0|[1-9][0-9]*)", |x| x.parse::<usize>().unwrap()), &ws), |x| x.0);
+      let index = label(index, "index");
+      let integer = regexp(r#"-?(?:[0-9]|[1-9][0-9]+)\b"#, |x| x.parse::<i64>().ok());
+      let integer = label(integer, "integer");
+      let string = label(any(&[
+        regexp(r#""[^"]*""#, |x| Value::String(x[1..x.len() - 1].to_string())),
+        regexp(r#"'[^']*'"#, |x| Value::String(x[1..x.len() - 1].to_string())),
+      ]), "string");
+      let kind = any(&[
+        map(st(":bool"), |_| Some(Type::Boolean)),
+        map(st(":int"), |_| Some(Type::Integer)),
+        map(st(":str"), |_| Some(Type::String)),
+        succeed(|| None),
+      ]);
+      let variable = seq3((st("$"), &index, kind), |x| match x.2 {
+        Some(y) => wrap(TypedTemplate(y, VariableTemplate(x.1))),
+        None => wrap(VariableTemplate(x.1)),
+      });
+      let literal = seq2((any(&[
+        map(st("true"), |_| Some(Value::Boolean(true))),
+        map(st("false"), |_| Some(Value::Boolean(false))),
+        map(integer, |x| x.map(Value::Integer)),
+        map(string, Some),
+      ]), &ws), |x| x.0);
+      let value = any(&[
+        map(literal, |x| match x {
+          Some(y) => wrap(BaseTemplate(Frame::new(Expr::Value(y)))),
+          None => wrap(BaseTemplate(Frame::default())),
+        }),
+        variable,
+        map(st("*"), |_| wrap(WildcardTemplate {})),
+      ]);
+
+      // Parsers for frames, which may include slots and spreads of partial frames.
+      let slot = seq3((&id, st("="), &value), |x| Item::Slot(x.0, x.2));
+      let spread = seq2((st("...$"), &index), |x| Item::Spread(wrap(VariableTemplate(x.1))));
+      let items = separate(any(&[slot, spread]), st(","), 0);
+      let frame = seq4((opt(&id), st("("), items, st(")")), |x| frame(x.0, x.2));
+      seq2((&ws, any(&[frame, value])), |x| x.1)
+    }
+  }
+
+  PARSER.with(|x| x.parse(input))
+}
+
+// Templates that operate on frames. A FrameTemplate binds each of its named slots
+// to a value template, and distributes any other slots among its spreads. Spreads
+// only take partial frames, and a template's intent must match its value's exactly.
+
+enum Item {
+  Slot(String, Box<dyn Template<Frame>>),
+  Spread(Box<dyn Template<Frame>>),
+}
+
+struct BaseTemplate(Frame);
+
+impl Template<Frame> for BaseTemplate {
+  fn merge(&self, _: &Args<Frame>) -> Frame {
+    self.0.clone()
+  }
+  fn split(&self, x: &Frame) -> Vec<Args<Frame>> {
+    if *x == self.0 { vec![vec![]] } else { vec![] }
+  }
+}
+
+struct FrameTemplate {
+  intent: Option<String>,
+  slots: Vec<(String, Box<dyn Template<Frame>>)>,
+  spreads: Vec<Box<dyn Template<Frame>>>,
+}
+
+impl Template<Frame> for FrameTemplate {
+  fn merge(&self, xs: &Args<Frame>) -> Frame {
+    let mut slots: HashMap<String, Value> = HashMap::default();
+    for spread in &self.spreads {
+      if let Expr::Frame(None, ys) = spread.merge(xs).expr() {
+        ys.iter().for_each(|(k, v)| std::mem::drop(slots.insert(k.clone(), v.clone())));
+      }
+    }
+    for (k, v) in &self.slots {
+      if let Expr::Value(x) = v.merge(xs).expr() {
+        std::mem::drop(slots.insert(k.clone(), x.clone()));
+      }
+    }
+    let mut slots: Vec<_> = slots.into_iter().collect();
+    slots.sort_by(|a, b| a.0.cmp(&b.0));
+    if self.intent.is_none() && slots.is_empty() {
+      return Frame::default();
+    }
+    Frame::new(Expr::Frame(self.intent.clone(), slots))
+  }
+
+  fn split(&self, x: &Frame) -> Vec<Args<Frame>> {
+    let (intent, slots) = match x.expr() {
+      Expr::Frame(x, y) => (x.as_ref(), y.as_slice()),
+      Expr::Unknown => (None, &[] as &[(String, Value)]),
+      Expr::Value(_) => return vec![],
+    };
+    if intent != self.intent.as_ref() {
+      return vec![];
+    }
+    let named = |k: &str| self.slots.iter().any(|(x, _)| x == k);
+    let rest: Vec<_> = slots.iter().filter(|(k, _)| !named(k)).collect();
+    if !rest.is_empty() && self.spreads.is_empty() {
+      return vec![];
+    }
+
+    // Bind each named slot, then try each assignment of the other slots to spreads.
+    let mut result = self.slots.iter().fold(vec![vec![]], |acc, (k, v)| {
+      let value = slots.iter().find(|(x, _)| x == k).map(|(_, y)| y.clone());
+      cross(acc, v.split(&value.map(|y| Frame::new(Expr::Value(y))).unwrap_or_default()))
+    });
+    if self.spreads.is_empty() {
+      return result;
+    }
+    // Give up on frames with too many extra slots to enumerate their assignments.
+    let n = self.spreads.len();
+    let total = match n.checked_pow(rest.len() as u32) {
+      Some(x) => x,
+      None => return vec![],
+    };
+    let options = (0..total).flat_map(|i| {
+      let mut parts = vec![vec![]; n];
+      for (j, x) in rest.iter().enumerate() {
+        parts[(i / n.pow(j as u32)) % n].push((*x).clone());
+      }
+      let mut parts = parts.into_iter().map(|x| {
+        if x.is_empty() { Frame::default() } else { Frame::new(Expr::Frame(None, x)) }
+      });
+      self.spreads.iter().fold(vec![vec![]], |acc, y| cross(acc, y.split(&parts.next().unwrap())))
+    });
+    result = cross(result, options.collect());
+    result
+  }
+}
+
+struct TypedTemplate(Type, VariableTemplate);
+
+impl Template<Frame> for TypedTemplate {
+  fn merge(&self, xs: &Args<Frame>) -> Frame {
+    match self.1.merge(xs).expr() {
+      Expr::Value(x) => x.coerce(self.0).map(|y| Frame::new(Expr::Value(y))).unwrap_or_default(),
+      _ => Frame::default(),
+    }
+  }
+  fn split(&self, x: &Frame) -> Vec<Args<Frame>> {
+    match x.expr() {
+      Expr::Value(y) if y.coerce(self.0).as_ref() == Some(y) => {
+        let value = y.coerce(Type::String).unwrap_or_else(|| y.clone());
+        self.1.split(&Frame::new(Expr::Value(value)))
+      }
+      Expr::Unknown => self.1.split(x),
+      _ => vec![],
+    }
+  }
+}
+
+fn frame(intent: Option<String>, items: Vec<Item>) -> Box<dyn Template<Frame>> {
+  let (mut slots, mut spreads) = (vec![], vec![]);
+  items.into_iter().for_each(|x| match x {
+    Item::Slot(k, v) => slots.push((k, v)),
+    Item::Spread(x) => spreads.push(x),
+  });
+  Box::new(FrameTemplate { intent, slots, spreads })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::base::Payload;
+  use super::*;
+
+  fn f(input: &str) -> Frame {
+    Frame::parse(input).unwrap()
+  }
+
+  fn t(input: &str) -> Box<dyn Template<Frame>> {
+    Frame::template(input).unwrap()
+  }
+
+  fn empty() -> Vec<Args<Frame>> {
+    vec![]
+  }
+
+  fn merge(template: &dyn Template<Frame>, args: Vec<Frame>) -> Frame {
+    template.merge(&args.into_iter().enumerate().collect())
+  }
+
+  fn none() -> Frame {
+    Frame::default()
+  }
+
+  #[test]
+  fn parse_works() {
+    let frame = f("Order(item = 'roti', count = 2, urgent = false)");
+    assert_eq!(frame.repr(), "Order(count=2, item='roti', urgent=false)");
+    assert_eq!(frame.intent(), Some("Order"));
+    assert_eq!(frame.slot("count"), Some(&Value::Integer(2)));
+    assert_eq!(frame.slot("price"), None);
+    assert_eq!(f("(item='roti')").repr(), "(item='roti')");
+    assert_eq!(f("Hello()").repr(), "Hello()");
+    assert_eq!(f("17"), Frame::new(Expr::Value(Value::Integer(17))));
+    assert_eq!(Frame::parse("()"), Err("Empty payload: ()".into()));
+  }
+
+  #[test]
+  fn frame_template_works() {
+    let template = t("Order(item=$0, count=$1:int)");
+    assert_eq!(merge(&*template, vec![f("'roti'"), f("'2'")]), f("Order(item='roti', count=2)"));
+    assert_eq!(merge(&*template, vec![f("'roti'"), f("'two'")]), f("Order(item='roti')"));
+    assert_eq!(merge(&*template, vec![none(), none()]), f("Order()"));
+    assert_eq!(
+      template.split(&f("Order(item='roti', count=2)")),
+      [[(0, f("'roti'")), (1, f("'2'"))]]
+    );
+    assert_eq!(template.split(&f("Order(item='roti')")), [[(0, f("'roti'")), (1, none())]]);
+    assert_eq!(template.split(&f("Order(item='roti', count='2')")), empty());
+    assert_eq!(template.split(&f("Order(item='roti', price=3)")), empty());
+    assert_eq!(template.split(&f("Cancel(item='roti')")), empty());
+  }
+
+  #[test]
+  fn frame_template_handles_spreads() {
+    let template = t("Order(...$0, ...$1, urgent=true)");
+    assert_eq!(
+      merge(&*template, vec![f("(item='roti')"), f("(count=2)")]),
+      f("Order(count=2, item='roti', urgent=true)")
+    );
+    assert_eq!(
+      template.split(&f("Order(count=2, item='roti', urgent=true)")),
+      [
+        [(0, f("(count=2, item='roti')")), (1, none())],
+        [(0, f("(item='roti')")), (1, f("(count=2)"))],
+        [(0, f("(count=2)")), (1, f("(item='roti')"))],
+        [(0, none()), (1, f("(count=2, item='roti')"))],
+      ]
+    );
+    assert_eq!(template.split(&f("Order(item='roti')")), empty());
+    let slots: Vec<_> = (0..64).map(|i| format!("x{}=1", i)).collect();
+    let frame = f(&format!("Order({}, urgent=true)", slots.join(", ")));
+    assert_eq!(template.split(&frame), empty());
+    let template = t("(item=$0)");
+    assert_eq!(merge(&*template, vec![f("'roti'")]), f("(item='roti')"));
+    assert_eq!(template.split(&f("(item='roti')")), [[(0, f("'roti'"))]]);
+    assert_eq!(template.split(&f("Order(item='roti')")), empty());
+  }
+
+  #[test]
+  fn frame_template_handles_wildcards() {
+    let template = t("Order(item=$0, count=*)");
+    assert_eq!(merge(&*template, vec![f("'roti'")]), f("Order(item='roti')"));
+    assert_eq!(template.split(&f("Order(item='roti', count=2)")), [[(0, f("'roti'"))]]);
+    assert_eq!(template.split(&f("Order(item='roti')")), [[(0, f("'roti'"))]]);
+  }
+}
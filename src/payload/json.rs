@@ -1,6 +1,7 @@
 use super::super::lib::base::Result;
 use super::super::lib::base::{HashMap, HashSet};
-use super::base::{append, cross, Args, Payload, Template, VariableTemplate};
+use super::base::{cross, unique, Args, Payload, SplitIter, Template};
+use super::base::{VariableTemplate, WildcardTemplate};
 use std::fmt::{Display, Formatter};
 
 // The core JSON expression type.
@@ -10,6 +11,7 @@ pub type Json = super::cached::Cached<Expr>;
 #[derive(Debug, PartialEq)]
 pub enum Expr {
   Boolean(bool),
+  Integer(i64),
   Number(f32),
   String(String),
   Dict(Vec<(String, Json)>),
@@ -50,13 +52,20 @@ impl super::cached::Base for Expr {
   }
 }
 
-// Helpers used to implement the Payload trait.
+// Helpers used to implement the Payload trait. We always write floats with a decimal point
+// so that they are distinct from integers and so that they parse back as floats.
+
+fn number(x: f32) -> String {
+  let result = x.to_string();
+  if !x.is_finite() || result.contains('.') { result } else { format!("{}.0", result) }
+}
 
 fn stringify(expr: &Expr) -> String {
   match expr {
     Expr::Unknown => "null".to_string(),
     Expr::Boolean(x) => x.to_string(),
-    Expr::Number(x) => x.to_string(),
+    Expr::Integer(x) => x.to_string(),
+    Expr::Number(x) => number(*x),
     Expr::String(x) => format!(r#"'{}'"#, x.escape_default()),
     Expr::Dict(x) => {
       let mut terms: Vec<_> = x.iter().map(|(k, v)| format!("{}: {}", k, v.repr())).collect();
@@ -70,6 +79,171 @@ fn stringify(expr: &Expr) -> String {
   }
 }
 
+// Conversions to standard RFC-8259 JSON. Our repr uses single-quoted strings and unquoted
+// keys, which most JSON APIs reject. Non-finite numbers have no JSON encoding, so we emit null.
+
+fn quote(x: &str) -> String {
+  let mut result = String::with_capacity(x.len() + 2);
+  result.push('"');
+  for ch in x.chars() {
+    match ch {
+      '"' => result.push_str("\\\""),
+      '\\' => result.push_str("\\\\"),
+      '\n' => result.push_str("\\n"),
+      '\r' => result.push_str("\\r"),
+      '\t' => result.push_str("\\t"),
+      x if (x as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", x as u32)),
+      x => result.push(x),
+    }
+  }
+  result.push('"');
+  result
+}
+
+impl Json {
+  pub fn to_string_json(&self) -> String {
+    match self.expr() {
+      Expr::Unknown => "null".to_string(),
+      Expr::Boolean(x) => x.to_string(),
+      Expr::Integer(x) => x.to_string(),
+      Expr::Number(x) => if x.is_finite() { number(*x) } else { "null".to_string() },
+      Expr::String(x) => quote(x),
+      Expr::Dict(x) => {
+        let mut terms: Vec<_> = x.iter().collect();
+        terms.sort_by_key(|(k, _)| k);
+        let terms: Vec<_> =
+          terms.iter().map(|(k, v)| format!("{}:{}", quote(k), v.to_string_json())).collect();
+        format!("{{{}}}", terms.join(","))
+      }
+      Expr::List(x) => {
+        let terms: Vec<_> = x.iter().map(|y| y.to_string_json()).collect();
+        format!("[{}]", terms.join(","))
+      }
+    }
+  }
+}
+
+// Path-based accessors. A path is a sequence of dict keys separated by dots, each
+// optionally followed by list indices, as in "a.b[2].c". The empty path refers to
+// the value itself. Since payloads are immutable, set_path returns a new value.
+// Setting a missing key creates it, setting a key to null removes it, and setting
+// index i of a list of length i appends to it.
+
+enum Step<'a> {
+  Key(&'a str),
+  Index(usize),
+}
+
+fn steps(path: &str) -> Result<Vec<Step<'_>>> {
+  let mut result = vec![];
+  if path.is_empty() {
+    return Ok(result);
+  }
+  let error = || format!("Invalid path: {}", path);
+  for (i, part) in path.split('.').enumerate() {
+    let (key, mut rest) = part.split_at(part.find('[').unwrap_or(part.len()));
+    if !key.is_empty() {
+      result.push(Step::Key(key));
+    } else if i > 0 || rest.is_empty() {
+      Err(error())?;
+    }
+    while !rest.is_empty() {
+      let end = rest.find(']').filter(|x| rest.starts_with('[') && *x > 1).ok_or_else(error)?;
+      result.push(Step::Index(rest[1..end].parse().map_err(|_| error())?));
+      rest = &rest[end + 1..];
+    }
+  }
+  Ok(result)
+}
+
+fn set(json: &Json, steps: &[Step], value: Json, path: &str) -> Result<Json> {
+  let (step, steps) = match steps.split_first() {
+    Some(x) => x,
+    None => return Ok(value),
+  };
+  match step {
+    Step::Key(key) => {
+      if !json.empty() && coerce_dict(json).is_empty() {
+        Err(format!("Expected a dict for {} in path: {}", key, path))?;
+      }
+      let mut xs = coerce_dict(json).to_vec();
+      let index = xs.iter().position(|(k, _)| k == key);
+      let child = set(&index.map(|i| xs[i].1.clone()).unwrap_or_default(), steps, value, path)?;
+      match (index, child.empty()) {
+        (Some(i), true) => std::mem::drop(xs.remove(i)),
+        (Some(i), false) => xs[i].1 = child,
+        (None, true) => (),
+        (None, false) => xs.push((key.to_string(), child)),
+      }
+      Ok(dict_to_null(xs))
+    }
+    Step::Index(index) => {
+      if !json.empty() && coerce_list(json).is_empty() {
+        Err(format!("Expected a list for [{}] in path: {}", index, path))?;
+      }
+      let mut xs = coerce_list(json).to_vec();
+      if *index > xs.len() {
+        Err(format!("Index {} out of range in path: {}", index, path))?;
+      }
+      let child = set(&xs.get(*index).cloned().unwrap_or_default(), steps, value, path)?;
+      if *index == xs.len() { xs.push(child) } else { xs[*index] = child };
+      Ok(list_to_null(xs))
+    }
+  }
+}
+
+impl Json {
+  pub fn get_path(&self, path: &str) -> Option<&Json> {
+    steps(path).ok()?.iter().try_fold(self, |acc, x| match x {
+      Step::Key(key) => coerce_dict(acc).iter().find(|(k, _)| k == key).map(|(_, v)| v),
+      Step::Index(index) => coerce_list(acc).get(*index),
+    })
+  }
+
+  pub fn set_path(&self, path: &str, value: Json) -> Result<Json> {
+    set(self, &steps(path)?, value, path)
+  }
+}
+
+#[cfg(feature = "serde_json")]
+impl From<serde_json::Value> for Json {
+  fn from(value: serde_json::Value) -> Self {
+    use serde_json::Value;
+    match value {
+      Value::Null => Json::default(),
+      Value::Bool(x) => Json::new(Expr::Boolean(x)),
+      Value::Number(x) => match x.as_i64() {
+        Some(x) => Json::new(Expr::Integer(x)),
+        None => Json::new(Expr::Number(x.as_f64().unwrap_or(f64::NAN) as f32)),
+      },
+      Value::String(x) => Json::new(Expr::String(x)),
+      Value::Array(x) => list_to_null(x.into_iter().map(Json::from).collect()),
+      Value::Object(x) => {
+        let items = x.into_iter().map(|(k, v)| (k, Json::from(v))).filter(|x| !x.1.empty());
+        dict_to_null(items.collect())
+      }
+    }
+  }
+}
+
+#[cfg(feature = "serde_json")]
+impl<'a> From<&'a Json> for serde_json::Value {
+  fn from(json: &'a Json) -> Self {
+    use serde_json::Value;
+    match json.expr() {
+      Expr::Unknown => Value::Null,
+      Expr::Boolean(x) => Value::Bool(*x),
+      Expr::Integer(x) => Value::Number((*x).into()),
+      Expr::Number(x) => {
+        serde_json::Number::from_f64(f64::from(*x)).map_or(Value::Null, Value::Number)
+      }
+      Expr::String(x) => Value::String(x.clone()),
+      Expr::Dict(x) => Value::Object(x.iter().map(|(k, v)| (k.clone(), Value::from(v))).collect()),
+      Expr::List(x) => Value::Array(x.iter().map(Value::from).collect()),
+    }
+  }
+}
+
 fn template(input: &str) -> Result<Box<dyn Template<Json>>> {
   use super::super::lib::combine::*;
 
@@ -81,26 +255,36 @@ fn template(input: &str) -> Result<Box<dyn Template<Json>>> {
 
   thread_local! {
     static PARSER: Parser<Node> = {
-      let ws = regexp(r#"\s*"#, |_| ());
-      let id = seq2((regexp("[a-zA-Z_]+", |x| x.to_string()), &ws), |x| x.0);
+      let ws = whitespace();
+      let id = take_while1(|x| x.is_ascii_alphabetic() || x == '_', |x| x.to_string());
+      let id = label(seq2((id, &ws), |x| x.0), "identifier");
       let st = |x| seq2((string(x, |_| ()), &ws), |x| x.0);
 
       // Parser for various primitive types.
       let index = seq2((regexp("(0|[1-9][0-9]*)", |x| x.parse::<usize>().unwrap()), &ws), |x| x.0);
+      let index = label(index, "index");
       let number = seq2((any(&[
-        regexp(r#"-?(?:[0-9]|[1-9][0-9]+)?(?:\.[0-9]+)\b"#, |x| x.parse::<f32>().unwrap()),
-        regexp(r#"-?(?:[0-9]|[1-9][0-9]+)\b"#, |x| x.parse::<f32>().unwrap()),
+        regexp(r#"-?(?:[0-9]|[1-9][0-9]+)?(?:\.[0-9]+)\b"#, |x| Expr::Number(x.parse().unwrap())),
+        regexp(r#"-?(?:[0-9]|[1-9][0-9]+)\b"#, |x| match x.parse::<i64>() {
+          Ok(x) => Expr::Integer(x),
+          Err(_) => Expr::Number(x.parse().unwrap()),
+        }),
       ]), &ws), |x| x.0);
+      let number = label(number, "number");
       let string = seq2((any(&[
         regexp(r#""[^"]*""#, |x| x[1..x.len()-1].to_string()),
         regexp(r#"'[^']*'"#, |x| x[1..x.len()-1].to_string()),
       ]), &ws), |x| x.0);
+      let string = label(string, "string");
 
       // The root parser, a lazily-computed recursive parser.
-      let (cell, root) = lazy();
+      let (cell, root) = lazy("root");
       let result = seq2((&ws, &root), |x| x.1);
       let variable = seq2((st("$"), &index), |x| wrap(VariableTemplate(x.1)));
-      let spread = seq2((st("...$"), index), |x| wrap(VariableTemplate(x.1)));
+      let spread = any(&[
+        seq2((st("...$"), index), |x| wrap(VariableTemplate(x.1))),
+        map(st("..."), |_| wrap(WildcardTemplate {})),
+      ]);
 
       // Helpers needed to parse a dict.
       let key = any(&[&id, &string]);
@@ -121,8 +305,9 @@ fn template(input: &str) -> Result<Box<dyn Template<Json>>> {
         map(st("null"), |_| wrap(BaseTemplate(Json::default()))),
         map(st("true"), |_| wrap(BaseTemplate(Json::new(Expr::Boolean(true))))),
         map(st("false"), |_| wrap(BaseTemplate(Json::new(Expr::Boolean(false))))),
-        map(number, |x| wrap(BaseTemplate(Json::new(Expr::Number(x))))),
+        map(number, |x| wrap(BaseTemplate(Json::new(x)))),
         map(string, |x| wrap(BaseTemplate(Json::new(Expr::String(x))))),
+        map(st("*"), |_| wrap(WildcardTemplate {})),
       ]);
 
       cell.replace(any(&[dict, list, primitive, variable]));
@@ -174,7 +359,14 @@ impl Template<Json> for DictBaseTemplate {
   }
 }
 
-struct DictPairTemplate(Box<dyn Template<Json>>, Box<dyn Template<Json>>);
+// A pair of dict templates, merged by combining their keys. Each side records the keys
+// it accepts, or None if a spread lets it accept any key. When splitting, a key that only
+// one side accepts must go to that side, so we only enumerate assignments of the keys
+// that both sides accept. A wildcard spread takes none of those keys.
+
+type Keys = Option<HashSet<String>>;
+
+struct DictPairTemplate(Box<dyn Template<Json>>, Box<dyn Template<Json>>, Keys, Keys);
 
 impl Template<Json> for DictPairTemplate {
   fn merge(&self, xs: &Args<Json>) -> Json {
@@ -185,23 +377,45 @@ impl Template<Json> for DictPairTemplate {
   }
 
   fn split(&self, x: &Json) -> Vec<Args<Json>> {
-    let mut base = coerce_dict(x).iter().collect::<Vec<_>>();
-    base.sort_by_key(|(k, _)| k);
-    let mut result = vec![];
-    for i in 0..(1 << base.len()) {
-      let mut xs = (vec![], vec![]);
-      for (j, (k, v)) in base.iter().enumerate() {
+    self.split_iter(x).collect()
+  }
+
+  fn split_iter<'a>(&'a self, x: &Json) -> SplitIter<'a, Json> {
+    let accepts = |keys: &Keys, k: &String| keys.as_ref().map(|x| x.contains(k)).unwrap_or(true);
+    let (mut base, mut free) = ((vec![], vec![]), vec![]);
+    let (wild0, wild1) = (self.0.wildcard(), self.1.wildcard());
+    for (k, v) in coerce_dict(x) {
+      let entry = (k.clone(), v.clone());
+      match (accepts(&self.2, k), accepts(&self.3, k)) {
+        (true, true) if wild0 != wild1 => {
+          if wild1 { base.0.push(entry) } else { base.1.push(entry) }
+        }
+        (true, true) => free.push(entry),
+        (true, false) => base.0.push(entry),
+        (false, true) => base.1.push(entry),
+        (false, false) => return Box::new(std::iter::empty()),
+      }
+    }
+    free.sort_by(|a, b| a.0.cmp(&b.0));
+    Box::new((0..(1 << free.len())).flat_map(move |i| {
+      let mut xs = base.clone();
+      for (j, (k, v)) in free.iter().enumerate() {
         if (1 << j) & i > 0 {
           xs.0.push((k.clone(), v.clone()));
         } else {
           xs.1.push((k.clone(), v.clone()));
         }
       }
+      xs.0.sort_by(|a, b| a.0.cmp(&b.0));
+      xs.1.sort_by(|a, b| a.0.cmp(&b.0));
       let x0 = self.0.split(&dict_to_null(xs.0));
       let x1 = self.1.split(&dict_to_null(xs.1));
-      append(&x0, &x1, &mut result);
-    }
-    result
+      cross(x0, x1)
+    }))
+  }
+
+  fn wildcard(&self) -> bool {
+    self.0.wildcard() || self.1.wildcard()
   }
 }
 
@@ -215,6 +429,13 @@ impl Template<Json> for DictWrapTemplate {
   fn split(&self, x: &Json) -> Vec<Args<Json>> {
     return if !x.empty() && coerce_dict(x).is_empty() { vec![] } else { self.0.split(x) };
   }
+
+  fn split_iter<'a>(&'a self, x: &Json) -> SplitIter<'a, Json> {
+    if !x.empty() && coerce_dict(x).is_empty() {
+      return Box::new(std::iter::empty());
+    }
+    self.0.split_iter(x)
+  }
 }
 
 struct ListBaseTemplate(Box<dyn Template<Json>>);
@@ -246,14 +467,28 @@ impl Template<Json> for ListPairTemplate {
   }
 
   fn split(&self, x: &Json) -> Vec<Args<Json>> {
-    let xs = coerce_list(x);
-    let mut result = vec![];
-    for i in 0..=xs.len() {
+    self.split_iter(x).collect()
+  }
+
+  fn split_iter<'a>(&'a self, x: &Json) -> SplitIter<'a, Json> {
+    let xs = coerce_list(x).to_vec();
+    let split = move |i: usize| {
       let x0 = self.0.split(&list_to_null(xs[..i].to_owned()));
       let x1 = self.1.split(&list_to_null(xs[i..].to_owned()));
-      append(&x0, &x1, &mut result);
+      cross(x0, x1)
+    };
+    let (n, wild0, wild1) = (coerce_list(x).len(), self.0.wildcard(), self.1.wildcard());
+    if wild0 == wild1 {
+      return unique(Box::new((0..=n).flat_map(split)));
     }
-    result
+    // A wildcard takes the fewest elements that let the other side split.
+    let order: Box<dyn Iterator<Item = usize>> =
+      if wild1 { Box::new((0..=n).rev()) } else { Box::new(0..=n) };
+    Box::new(order.map(split).find(|x| !x.is_empty()).unwrap_or_default().into_iter())
+  }
+
+  fn wildcard(&self) -> bool {
+    self.0.wildcard() || self.1.wildcard()
   }
 }
 
@@ -267,6 +502,13 @@ impl Template<Json> for ListWrapTemplate {
   fn split(&self, x: &Json) -> Vec<Args<Json>> {
     return if !x.empty() && coerce_list(x).is_empty() { vec![] } else { self.0.split(x) };
   }
+
+  fn split_iter<'a>(&'a self, x: &Json) -> SplitIter<'a, Json> {
+    if !x.empty() && coerce_list(x).is_empty() {
+      return Box::new(std::iter::empty());
+    }
+    self.0.split_iter(x)
+  }
 }
 
 // Specific implementations of the Template interface.
@@ -295,12 +537,19 @@ fn dict(items: Vec<Item>) -> Box<dyn Template<Json>> {
   let mut xs = items.into_iter().map(|x| match x {
     Item::Literals(dict) => {
       let keys = dict.iter().map(|(k, _)| k.clone()).collect::<HashSet<_>>();
-      Box::new(DictBaseTemplate(dict, keys))
+      (Box::new(DictBaseTemplate(dict, keys.clone())) as Box<dyn Template<Json>>, Some(keys))
     }
-    Item::Variable(x) => x,
+    Item::Variable(x) => (x, None),
   });
   let base = xs.next().unwrap();
-  Box::new(DictWrapTemplate(xs.fold(base, |a, x| Box::new(DictPairTemplate(a, x)))))
+  let pair = |a: (Box<dyn Template<Json>>, Keys), b: (Box<dyn Template<Json>>, Keys)| {
+    let keys = match (&a.1, &b.1) {
+      (Some(x), Some(y)) => Some(x.union(y).cloned().collect()),
+      _ => None,
+    };
+    (Box::new(DictPairTemplate(a.0, b.0, a.1, b.1)) as Box<dyn Template<Json>>, keys)
+  };
+  Box::new(DictWrapTemplate(xs.fold(base, pair).0))
 }
 
 fn list(items: Vec<(Box<dyn Template<Json>>, bool)>) -> Box<dyn Template<Json>> {
@@ -350,11 +599,12 @@ mod tests {
     assert_eq!(j("null"), none);
     assert_eq!(j("false"), Json::new(Expr::Boolean(false)));
     assert_eq!(j("17.5"), Json::new(Expr::Number(17.5)));
+    assert_eq!(j("1000000001"), Json::new(Expr::Integer(1_000_000_001)));
     assert_eq!(j("'1000'"), Json::new(Expr::String("1000".into())));
     assert_eq!(
       j("{num: 17, str: 'is', bool: false}"),
       Json::new(Expr::Dict(vec![
-        ("num".into(), Json::new(Expr::Number(17.0))),
+        ("num".into(), Json::new(Expr::Integer(17))),
         ("str".into(), Json::new(Expr::String("is".into()))),
         ("bool".into(), Json::new(Expr::Boolean(false))),
       ]))
@@ -362,7 +612,7 @@ mod tests {
     assert_eq!(
       j("[17, 'is', false]"),
       Json::new(Expr::List(vec![
-        Json::new(Expr::Number(17.0)),
+        Json::new(Expr::Integer(17)),
         Json::new(Expr::String("is".into())),
         Json::new(Expr::Boolean(false)),
       ]))
@@ -376,6 +626,59 @@ mod tests {
     assert_eq!(Json::parse("$0"), Err("Empty payload: $0".into()));
   }
 
+  #[test]
+  fn get_path_works() {
+    let json = j("{a: {b: [1, 2, {c: 'x'}], d: [[3, 4]]}}");
+    assert_eq!(json.get_path("a.b[2].c"), Some(&j("'x'")));
+    assert_eq!(json.get_path("a.b[1]"), Some(&j("2")));
+    assert_eq!(json.get_path("a.d[0][1]"), Some(&j("4")));
+    assert_eq!(json.get_path(""), Some(&json));
+    assert_eq!(j("[5, 6]").get_path("[1]"), Some(&j("6")));
+    assert_eq!(json.get_path("a.b[3]"), None);
+    assert_eq!(json.get_path("a.e"), None);
+    assert_eq!(json.get_path("a.b.c"), None);
+    assert_eq!(json.get_path("a..b"), None);
+    assert_eq!(json.get_path("a.b[x]"), None);
+  }
+
+  #[test]
+  fn set_path_works() {
+    let json = j("{a: {b: [1, 2]}}");
+    assert_eq!(json.set_path("a.b[1]", j("3")).unwrap(), j("{a: {b: [1, 3]}}"));
+    assert_eq!(json.set_path("a.b[2]", j("3")).unwrap(), j("{a: {b: [1, 2, 3]}}"));
+    assert_eq!(json.set_path("a.c.d", j("'x'")).unwrap(), j("{a: {b: [1, 2], c: {d: 'x'}}}"));
+    assert_eq!(json.set_path("a.b", j("null")).unwrap(), j("null"));
+    assert_eq!(json.set_path("", j("5")).unwrap(), j("5"));
+    assert_eq!(j("null").set_path("a[0]", j("5")).unwrap(), j("{a: [5]}"));
+    assert_eq!(json, j("{a: {b: [1, 2]}}"));
+    assert_eq!(
+      json.set_path("a.b[3]", j("3")),
+      Err("Index 3 out of range in path: a.b[3]".into())
+    );
+    assert_eq!(json.set_path("a.b.c", j("3")), Err("Expected a dict for c in path: a.b.c".into()));
+    assert_eq!(json.set_path("a[0]", j("3")), Err("Expected a list for [0] in path: a[0]".into()));
+    assert_eq!(json.set_path("a.", j("3")), Err("Invalid path: a.".into()));
+  }
+
+  #[test]
+  fn to_string_json_works() {
+    assert_eq!(j("null").to_string_json(), "null");
+    assert_eq!(j("[17, 0.5, true]").to_string_json(), "[17,0.5,true]");
+    let json = j(r#"{b: "it's", a: 'say "hi"'}"#);
+    assert_eq!(json.to_string_json(), r#"{"a":"say \"hi\"","b":"it's"}"#);
+    assert_eq!(Json::new(Expr::String("a\nb".into())).to_string_json(), r#""a\nb""#);
+  }
+
+  #[cfg(feature = "serde_json")]
+  #[test]
+  fn serde_json_conversion_works() {
+    let value: serde_json::Value = serde_json::from_str(r#"{"a": [1, "x"], "b": null}"#).unwrap();
+    let json = Json::from(value);
+    assert_eq!(json, j("{a: [1, 'x']}"));
+    let back = serde_json::Value::from(&json);
+    assert_eq!(back.to_string(), r#"{"a":[1,"x"]}"#);
+  }
+
   #[test]
   fn boolean_template_works() {
     let template = t("false");
@@ -394,6 +697,15 @@ mod tests {
     assert_eq!(template.split(&j("null")), empty());
   }
 
+  #[test]
+  fn integer_template_works() {
+    let template = t("17");
+    assert_eq!(merge(&*template, vec![]), j("17"));
+    assert_eq!(template.split(&j("17")), [[]]);
+    assert_eq!(template.split(&j("17.0")), empty());
+    assert_eq!(template.split(&j("null")), empty());
+  }
+
   #[test]
   fn string_template_works() {
     let template = t("'1000'");
@@ -472,6 +784,13 @@ mod tests {
     );
     assert_eq!(t.split(&j("null")), [[(0, j("null")), (1, j("null")), (2, j("null"))]]);
     assert_eq!(t.split(&j("false")), empty());
+
+    // Keys that only the spread accepts always go to the spread.
+    let wide = j("{a: 0, b: 1, c: 2, d: 3, e: 4, f: 5, g: 6, h: 7, num: 8, bool: 9}");
+    let splits = t.split(&wide);
+    assert_eq!(splits.len(), 4);
+    assert!(splits.iter().all(|x| coerce_dict(&x[1].1).len() >= 8));
+    assert_eq!(splits.iter().filter(|x| x[0].1 == j("8") && x[2].1 == j("9")).count(), 1);
   }
 
   #[test]
@@ -527,6 +846,37 @@ mod tests {
     assert_eq!(t.split(&j("false")), empty());
   }
 
+  #[test]
+  fn dict_with_wildcards_works() {
+    let template = t("{type: *, owner: $0}");
+    assert_eq!(merge(&*template, vec![j("'me'")]), j("{owner: 'me'}"));
+    assert_eq!(template.split(&j("{type: 'dog', owner: 'me'}")), [[(0, j("'me'"))]]);
+    assert_eq!(template.split(&j("{owner: 'me'}")), [[(0, j("'me'"))]]);
+    assert_eq!(template.split(&j("{type: 'dog', owner: 'me', name: 'Rex'}")), empty());
+    let template = t("{owner: $0, ...}");
+    assert_eq!(merge(&*template, vec![j("'me'")]), j("{owner: 'me'}"));
+    assert_eq!(template.split(&j("{type: 'dog', owner: 'me', name: 'Rex'}")), [[(0, j("'me'"))]]);
+    assert_eq!(template.split(&j("{owner: 'me'}")), [[(0, j("'me'"))]]);
+    assert_eq!(template.split(&j("{type: 'dog'}")), [[(0, j("null"))]]);
+    let template = t("{..., owner: 'me', name: $0}");
+    assert_eq!(template.split(&j("{type: 'dog', owner: 'me', name: 'Rex'}")), [[(0, j("'Rex'"))]]);
+    assert_eq!(template.split(&j("{type: 'dog', owner: 'you', name: 'Rex'}")), empty());
+  }
+
+  #[test]
+  fn list_with_wildcards_works() {
+    let template = t("[$0, ...]");
+    assert_eq!(merge(&*template, vec![j("3")]), j("[3]"));
+    assert_eq!(template.split(&j("[3, 5, 7]")), [[(0, j("3"))]]);
+    assert_eq!(template.split(&j("null")), [[(0, j("null"))]]);
+    let template = t("[..., $0, $1]");
+    assert_eq!(template.split(&j("[3, 5, 7]")), [[(0, j("5")), (1, j("7"))]]);
+    assert_eq!(template.split(&j("false")), empty());
+    let template = t("[*, $0]");
+    assert_eq!(merge(&*template, vec![j("3")]), j("[3]"));
+    assert_eq!(template.split(&j("[3, 5]")), [[(0, j("5"))]]);
+  }
+
   #[test]
   fn parse_fails_on_unquoted_string_literal() {
     test_error(Json::parse("failed"), "At line 1, column 1");
@@ -553,8 +903,8 @@ mod tests {
 
   #[test]
   fn stringify_sorts_keys() {
-    let json = j("{c: 1.5, b: 5.0, a: '3'}");
-    assert_eq!(json.repr(), "{a: '3', b: 5, c: 1.5}");
+    let json = j("{c: 1.5, b: 5.0, a: '3', d: 5}");
+    assert_eq!(json.repr(), "{a: '3', b: 5.0, c: 1.5, d: 5}");
   }
 
   #[bench]
@@ -596,6 +946,22 @@ mod tests {
     b.iter(|| template.split(&json));
   }
 
+  #[bench]
+  fn template_dict_split_wide_easy_benchmark(b: &mut Bencher) {
+    let json = Json::parse("{a: 0, b: 1, c: 2, d: 3, e: 4, f: 5, g: 6, h: 7, i: 8, j: 9}").unwrap();
+    let template = Json::template("{a: $0, b: $1, c: $2, d: $3, ...$4}").unwrap();
+    assert_eq!(template.split(&json).len(), 16);
+    b.iter(|| template.split(&json));
+  }
+
+  #[bench]
+  fn template_dict_split_wide_hard_benchmark(b: &mut Bencher) {
+    let json = Json::parse("{a: 0, b: 1, c: 2, d: 3, e: 4, f: 5, g: 6, h: 7}").unwrap();
+    let template = Json::template("{a: $0, ...$1, b: $2, ...$3}").unwrap();
+    assert_eq!(template.split(&json).len(), 576);
+    b.iter(|| template.split(&json));
+  }
+
   #[bench]
   fn template_list_split_easy_benchmark(b: &mut Bencher) {
     let json = Json::parse("[3, 4, 5]").unwrap();
@@ -1,5 +1,6 @@
-use super::super::lib::base::Result;
-use super::base::{append, Args, Payload, Template, VariableTemplate};
+use super::super::lib::base::{Error, Result};
+use super::base::{append, cross, unique, Args, Payload, SplitIter, Template};
+use super::base::{VariableTemplate, WildcardTemplate};
 use std::fmt::{Display, Formatter};
 
 // The core lambda DCS expression type.
@@ -19,10 +20,20 @@ pub enum Unary {
   Reverse,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Date {
+  year: i32,
+  month: u32,
+  day: u32,
+}
+
+#[derive(Debug, PartialEq)]
 pub enum Expr {
   Binary(Binary, Vec<Lambda>),
   Custom(String, Vec<Lambda>),
+  Date(Date),
+  Float(f64),
+  Int(i64),
   Terminal(String),
   Unary(Unary, Lambda),
   Unknown,
@@ -61,6 +72,110 @@ impl super::cached::Base for Expr {
   }
 }
 
+// Typed terminals. Integers, floats, and ISO dates print canonically, so a typed
+// terminal is equal to a plain terminal with the same text, and the accessors below
+// coerce plain terminals as well. Dates are proleptic Gregorian and support simple
+// arithmetic via a count of days since 1970-01-01.
+
+impl Date {
+  pub fn new(year: i32, month: u32, day: u32) -> Option<Self> {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = [31, if leap { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    let valid = (1..=12).contains(&month) && 1 <= day && day <= days[month as usize - 1];
+    if valid { Some(Self { year, month, day }) } else { None }
+  }
+
+  pub fn from_days(days: i64) -> Self {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+    Self { year, month, day }
+  }
+
+  pub fn days(&self) -> i64 {
+    let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (self.month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+  }
+
+  pub fn add_days(&self, days: i64) -> Self {
+    Self::from_days(self.days() + days)
+  }
+
+  pub fn year(&self) -> i32 {
+    self.year
+  }
+
+  pub fn month(&self) -> u32 {
+    self.month
+  }
+
+  pub fn day(&self) -> u32 {
+    self.day
+  }
+}
+
+impl Display for Date {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+  }
+}
+
+impl std::str::FromStr for Date {
+  type Err = Error;
+  fn from_str(input: &str) -> Result<Self> {
+    let error = || format!("Invalid date: {}", input);
+    let parts: Vec<_> = input.split('-').collect();
+    if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+      Err(error())?;
+    }
+    let year = parts[0].parse().map_err(|_| error())?;
+    let month = parts[1].parse().map_err(|_| error())?;
+    let day = parts[2].parse().map_err(|_| error())?;
+    Ok(Self::new(year, month, day).ok_or_else(error)?)
+  }
+}
+
+impl Lambda {
+  pub fn int(&self) -> Option<i64> {
+    match self.expr() {
+      Expr::Int(x) => Some(*x),
+      Expr::Float(x) if x.fract() == 0.0 => Some(*x as i64),
+      Expr::Terminal(x) => x.parse().ok().or_else(|| {
+        self.float().filter(|x| x.fract() == 0.0 && x.abs() < i64::MAX as f64).map(|x| x as i64)
+      }),
+      _ => None,
+    }
+  }
+
+  pub fn float(&self) -> Option<f64> {
+    match self.expr() {
+      Expr::Int(x) => Some(*x as f64),
+      Expr::Float(x) => Some(*x),
+      Expr::Terminal(x) => x.parse().ok().filter(|x: &f64| x.is_finite()),
+      _ => None,
+    }
+  }
+
+  pub fn date(&self) -> Option<Date> {
+    match self.expr() {
+      Expr::Date(x) => Some(*x),
+      Expr::Terminal(x) => x.parse().ok(),
+      _ => None,
+    }
+  }
+}
+
 // Helpers used to implement the Payload trait for Lambda.
 
 struct Operator {
@@ -88,6 +203,12 @@ impl Unary {
   }
 }
 
+// Integral floats keep a ".0", so that they don't print, compare, or parse as ints.
+fn float(x: f64) -> String {
+  let result = x.to_string();
+  if !x.is_finite() || result.contains('.') { result } else { format!("{}.0", result) }
+}
+
 fn stringify(lambda: &Expr) -> String {
   match lambda {
     Expr::Binary(op, children) => {
@@ -102,6 +223,9 @@ fn stringify(lambda: &Expr) -> String {
       let base: Vec<_> = children.iter().map(|x| x.repr()).collect();
       format!("{}({})", name, base.join(", "))
     }
+    Expr::Date(x) => x.to_string(),
+    Expr::Float(x) => float(*x),
+    Expr::Int(x) => x.to_string(),
     Expr::Terminal(name) => name.to_string(),
     Expr::Unary(op, child) => {
       let Operator { precedence, text, .. } = op.data();
@@ -135,14 +259,29 @@ fn template(input: &str) -> Result<Box<dyn Template<Lambda>>> {
 
   thread_local! {
     static PARSER: Parser<Node> = {
-      let ws = regexp(r#"\s*"#, |_| ());
+      let ws = whitespace();
       let st = |x| seq2((string(x, |_| ()), &ws), |x| x.0);
-      let id = seq2((regexp("[a-zA-Z0-9_]+", |x| x.to_string()), &ws), |x| x.0);
+      let id = take_while1(|x| x.is_ascii_alphanumeric() || x == '_', |x| x.to_string());
+      let id = seq2((id, &ws), |x| x.0);
+      let id = label(id, "identifier");
       let number = seq2((regexp("(0|[1-9][0-9]*)", |x| x.parse::<usize>().unwrap()), &ws), |x| x.0);
+      let number = label(number, "index");
+      let literal = |re: &str, f: fn(&str) -> Option<Expr>| {
+        let typed = move |x: &str| {
+          let expr = f(x).unwrap_or_else(|| Expr::Terminal(x.to_string()));
+          wrap(TerminalTemplate(x.to_string(), Lambda::new(expr)))
+        };
+        seq2((regexp(&format!(r#"{}\b"#, re), typed), &ws), |x| x.0)
+      };
+      let date = literal("[0-9]{4}-[0-9]{2}-[0-9]{2}", |x| x.parse().ok().map(Expr::Date));
+      let float = literal("-?[0-9]+\\.[0-9]+", |x| x.parse().ok().map(Expr::Float));
+      let int = literal("-?[0-9]+", |x| x.parse().ok().map(Expr::Int));
+      let typed = label(any(&[date, float, int]), "literal");
 
       let base = |x: Parser<Node>| {
         any(&[
           seq4((st("R"), st("["), &x, st("]")), |x| wrap(UnaryTemplate(Unary::Reverse, x.2))),
+          map(&typed, |x| x),
           seq2(
             (&id, opt(seq3((st("("), separate(&x, st(","), 0), st(")")), |x| x.1))),
             |x| match x.1 {
@@ -152,9 +291,18 @@ fn template(input: &str) -> Result<Box<dyn Template<Lambda>>> {
           ),
           seq3((st("("), &x, st(")")), |x| x.1),
           seq2((st("$"), &number), |x| wrap(VariableTemplate(x.1))),
+          map(st("*"), |_| wrap(WildcardTemplate {})),
         ])
       };
 
+      let scale = |x: Parser<Node>| {
+        let factor = seq2((st("*"), &number), |x| x.1 as i64);
+        seq2((x, opt(factor)), |x| match x.1 {
+          Some(factor) => wrap(ScaleTemplate(x.0, factor)),
+          None => x.0,
+        })
+      };
+
       let binaries = |ops: Vec<(&'static str, Binary)>| {
         move |x: Parser<Node>| {
           let mut options = Vec::with_capacity(ops.len() + 1);
@@ -176,10 +324,11 @@ fn template(input: &str) -> Result<Box<dyn Template<Lambda>>> {
         }
       };
 
-      let (cell, root) = lazy();
+      let (cell, root) = lazy("root");
       let result = seq2((&ws, &root), |x| x.1);
       let precedence: Vec<Box<dyn Fn(Parser<Node>) -> Parser<Node>>> = vec![
         Box::new(base),
+        Box::new(scale),
         Box::new(binaries(vec![(".", Binary::Join)])),
         Box::new(unary("~", Unary::Not)),
         Box::new(binaries(vec![("&", Binary::Conjunction), ("|", Binary::Disjunction)])),
@@ -208,21 +357,24 @@ impl Template<Lambda> for BinaryTemplate {
     }
   }
   fn split(&self, x: &Lambda) -> Vec<Args<Lambda>> {
+    self.split_iter(x).collect()
+  }
+  fn split_iter<'a>(&'a self, x: &Lambda) -> SplitIter<'a, Lambda> {
     let base = expand(self.0, x);
     let commutes = self.0.data().commutes;
     if !commutes && base.is_empty() {
       let default = Lambda::default();
-      let mut x1 = self.1.split(&default);
-      let mut x2 = self.2.split(&default);
-      return x1.drain(..).chain(x2.drain(..)).collect();
+      let x1 = self.1.split_iter(&default);
+      let x2 = self.2.split_iter(&default);
+      return unique(Box::new(x1.chain(x2)));
     }
-    let bits: Vec<_> = if commutes {
-      (0..(1 << base.len())).collect()
+    let n = base.len();
+    let bits: Box<dyn Iterator<Item = usize>> = if commutes {
+      Box::new(0..(1 << n))
     } else {
-      (0..(base.len() - 1)).map(|i| (1 << (i + 1)) - 1).collect()
+      Box::new((0..(n - 1)).map(|i| (1 << (i + 1)) - 1))
     };
-    let mut result = vec![];
-    for i in bits {
+    let split = move |i: usize| {
       let mut xs = (vec![], vec![]);
       for (j, x) in base.iter().enumerate() {
         if (1 << j) & i > 0 {
@@ -233,9 +385,31 @@ impl Template<Lambda> for BinaryTemplate {
       }
       let x1 = self.1.split(&collapse(self.0, xs.0));
       let x2 = self.2.split(&collapse(self.0, xs.1));
-      append(&x1, &x2, &mut result);
+      cross(x1, x2)
+    };
+    let (wild1, wild2) = (self.1.wildcard(), self.2.wildcard());
+    if !commutes || wild1 == wild2 {
+      return unique(Box::new(bits.flat_map(split)));
     }
-    result
+    // A wildcard takes the fewest terms that let the other side split.
+    let taken = |i: usize| if wild1 { i.count_ones() } else { n as u32 - i.count_ones() };
+    let mut bits: Vec<_> = bits.collect();
+    bits.sort_by_key(|i| taken(*i));
+    let (mut result, mut level) = (vec![], None);
+    for i in bits {
+      if level.is_some_and(|x| x < taken(i)) {
+        break;
+      }
+      let mut xs = split(i);
+      if !xs.is_empty() {
+        level = Some(taken(i));
+        result.append(&mut xs);
+      }
+    }
+    unique(Box::new(result.into_iter()))
+  }
+  fn wildcard(&self) -> bool {
+    self.0.data().commutes && (self.1.wildcard() || self.2.wildcard())
   }
 }
 
@@ -270,6 +444,29 @@ impl Template<Lambda> for CustomTemplate {
   }
 }
 
+// A scale template multiplies an integer by a constant factor, e.g. for numerals
+// like "do sau" (200). Splitting divides, so it only matches multiples of the factor.
+
+struct ScaleTemplate(Box<dyn Template<Lambda>>, i64);
+
+impl Template<Lambda> for ScaleTemplate {
+  fn merge(&self, xs: &Args<Lambda>) -> Lambda {
+    match self.0.merge(xs).int().and_then(|x| x.checked_mul(self.1)) {
+      Some(x) => Lambda::new(Expr::Int(x)),
+      None => Lambda::default(),
+    }
+  }
+  fn split(&self, x: &Lambda) -> Vec<Args<Lambda>> {
+    match (x.expr(), x.int()) {
+      (Expr::Unknown, _) => self.0.split(x),
+      (_, Some(y)) if self.1 != 0 && y % self.1 == 0 => {
+        self.0.split(&Lambda::new(Expr::Int(y / self.1)))
+      }
+      _ => vec![],
+    }
+  }
+}
+
 struct TerminalTemplate(String, Lambda);
 
 impl Template<Lambda> for TerminalTemplate {
@@ -277,10 +474,13 @@ impl Template<Lambda> for TerminalTemplate {
     self.1.clone()
   }
   fn split(&self, x: &Lambda) -> Vec<Args<Lambda>> {
-    match x.expr() {
-      Expr::Terminal(name) if *name == self.0 => vec![vec![]],
-      _ => vec![],
-    }
+    let matches = match self.1.expr() {
+      Expr::Date(y) => x.date() == Some(*y),
+      Expr::Float(y) => x.float() == Some(*y),
+      Expr::Int(y) => x.int() == Some(*y),
+      _ => matches!(x.expr(), Expr::Terminal(name) if *name == self.0),
+    };
+    if matches { vec![vec![]] } else { vec![] }
   }
 }
 
@@ -293,6 +493,9 @@ impl Template<Lambda> for UnaryTemplate {
   fn split(&self, x: &Lambda) -> Vec<Args<Lambda>> {
     self.1.split(&involute(self.0, x))
   }
+  fn split_iter<'a>(&'a self, x: &Lambda) -> SplitIter<'a, Lambda> {
+    self.1.split_iter(&involute(self.0, x))
+  }
 }
 
 // Internal helpers for the templates above.
@@ -320,6 +523,140 @@ fn involute(x: Unary, y: &Lambda) -> Lambda {
   }
 }
 
+// Normalization rewrites a lambda into a canonical form, so that expressions that
+// differ only in surface form compare equal. We flatten nested binary operators,
+// drop duplicate operands of commutative operators, and cancel doubled unary ones.
+// With dnf set, we also push negations inward, distribute & over |, and drop any
+// conjunctive term that is absorbed by a smaller one.
+
+impl Lambda {
+  pub fn normalize(&self, dnf: bool) -> Lambda {
+    match self.expr() {
+      Expr::Binary(op, children) => {
+        let children: Vec<_> = children.iter().map(|x| x.normalize(dnf)).collect();
+        let flat: Vec<_> = children.iter().flat_map(|x| expand(*op, x)).collect();
+        if !op.data().commutes {
+          return collapse(*op, flat);
+        } else if !dnf || *op != Binary::Conjunction {
+          return collapse(*op, dedup(flat));
+        }
+        let terms = flat.iter().fold(vec![vec![]], |acc, x| {
+          let options = expand(Binary::Disjunction, x);
+          let mut result = Vec::with_capacity(acc.len() * options.len());
+          for (prefix, option) in acc.iter().flat_map(|y| options.iter().map(move |z| (y, z))) {
+            let mut term: Vec<_> = prefix.clone();
+            term.append(&mut expand(Binary::Conjunction, option));
+            result.push(term);
+          }
+          result
+        });
+        let terms: Vec<_> = terms.into_iter().map(dedup).collect();
+        let subsumed = |x: &Vec<Lambda>| {
+          let covers = |y: &Vec<Lambda>| y.len() < x.len() && y.iter().all(|z| x.contains(z));
+          terms.iter().any(covers)
+        };
+        let terms = terms.iter().filter(|x| !subsumed(x));
+        let terms = terms.map(|x| collapse(Binary::Conjunction, x.clone())).collect();
+        collapse(Binary::Disjunction, dedup(terms))
+      }
+      Expr::Custom(name, children) => {
+        let children = children.iter().map(|x| x.normalize(dnf)).collect();
+        Lambda::new(Expr::Custom(name.clone(), children))
+      }
+      Expr::Unary(op, child) => {
+        let child = child.normalize(dnf);
+        match child.expr() {
+          Expr::Binary(x, children) if dnf && *op == Unary::Not && x.data().commutes => {
+            let dual = match x {
+              Binary::Conjunction => Binary::Disjunction,
+              _ => Binary::Conjunction,
+            };
+            let children = children.iter().map(|y| involute(Unary::Not, y)).collect();
+            Lambda::new(Expr::Binary(dual, children)).normalize(dnf)
+          }
+          _ => involute(*op, &child),
+        }
+      }
+      Expr::Date(_) | Expr::Float(_) | Expr::Int(_) => self.clone(),
+      Expr::Terminal(_) | Expr::Unknown => self.clone(),
+    }
+  }
+}
+
+// Substitution replaces terminals with lambdas, e.g. to plug resolved anaphora into
+// a parsed question. substitute_with takes a callback that fills each hole, which is
+// any terminal for which it returns Some. The result is rebuilt the same way that
+// templates merge their arguments: nested operators are flattened, doubled unary
+// operators cancel, and filling a hole with an empty lambda drops it from & and |
+// but empties any join or custom function that contains it.
+
+impl Lambda {
+  pub fn substitute(&self, name: &str, value: &Lambda) -> Lambda {
+    self.substitute_with(&|x| if x == name { Some(value.clone()) } else { None })
+  }
+
+  pub fn substitute_with(&self, f: &dyn Fn(&str) -> Option<Lambda>) -> Lambda {
+    match self.expr() {
+      Expr::Binary(op, children) => {
+        let mut xs = Vec::with_capacity(children.len());
+        for child in children.iter().map(|x| x.substitute_with(f)) {
+          if child.empty() && !op.data().commutes {
+            return Lambda::default();
+          }
+          xs.append(&mut expand(*op, &child));
+        }
+        collapse(*op, xs)
+      }
+      Expr::Custom(name, children) => {
+        let xs: Vec<_> = children.iter().map(|x| x.substitute_with(f)).collect();
+        if xs.iter().any(|x| x.empty()) {
+          return Lambda::default();
+        }
+        Lambda::new(Expr::Custom(name.clone(), xs))
+      }
+      Expr::Terminal(name) => f(name).unwrap_or_else(|| self.clone()),
+      Expr::Unary(op, child) => involute(*op, &child.substitute_with(f)),
+      _ => self.clone(),
+    }
+  }
+}
+
+// Subsumption checks whether one lambda's denotation contains another's. The check
+// is sound but incomplete: it understands conjunction, disjunction, joins, and
+// negation, but treats custom functions as opaque, so it may miss some entailments.
+// An empty lambda places no constraints on its denotation, so it subsumes all others.
+
+impl Lambda {
+  pub fn subsumes(&self, other: &Lambda) -> bool {
+    subsumes(&self.normalize(false), &other.normalize(false))
+  }
+}
+
+fn subsumes(x: &Lambda, y: &Lambda) -> bool {
+  if x == y {
+    return true;
+  }
+  match (x.expr(), y.expr()) {
+    (Expr::Unknown, _) => true,
+    (Expr::Binary(Binary::Conjunction, xs), _) => xs.iter().all(|x| subsumes(x, y)),
+    (_, Expr::Binary(Binary::Disjunction, ys)) => ys.iter().all(|y| subsumes(x, y)),
+    (Expr::Binary(Binary::Disjunction, xs), _) if xs.iter().any(|x| subsumes(x, y)) => true,
+    (_, Expr::Binary(Binary::Conjunction, ys)) => ys.iter().any(|y| subsumes(x, y)),
+    (Expr::Binary(Binary::Join, xs), Expr::Binary(Binary::Join, ys)) => {
+      let tail = |zs: &[Lambda]| collapse(Binary::Join, zs[1..].to_vec());
+      xs[0] == ys[0] && subsumes(&tail(xs), &tail(ys))
+    }
+    (Expr::Unary(Unary::Not, x), Expr::Unary(Unary::Not, y)) => subsumes(y, x),
+    _ => false,
+  }
+}
+
+fn dedup(mut x: Vec<Lambda>) -> Vec<Lambda> {
+  x.sort_by(|a, b| a.repr().cmp(b.repr()));
+  x.dedup();
+  x
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -444,6 +781,103 @@ mod tests {
     );
   }
 
+  #[test]
+  fn wildcards_work() {
+    let (template, unit): (_, Vec<Args<Lambda>>) = (t("type.food & *"), vec![vec![]]);
+    assert_eq!(merge(&*template, vec![]), l("type.food"));
+    assert_eq!(template.split(&l("color.red & type.food")), unit);
+    assert_eq!(template.split(&l("type.food")), unit);
+    assert_eq!(template.split(&l("color.red")), empty());
+    let template = t("* & $0");
+    assert_eq!(template.split(&l("owner.I & type.dog")), [[(0, l("owner.I & type.dog"))]]);
+    assert_eq!(template.split(&l("type.dog")), [[(0, l("type.dog"))]]);
+    let template = t("type.dog & * & $0");
+    assert_eq!(template.split(&l("owner.I & type.dog")), [[(0, l("owner.I"))]]);
+    assert_eq!(template.split(&l("color.red")), empty());
+    let template = t("owner.* & $0");
+    assert_eq!(merge(&*template, vec![l("type.dog")]), l("type.dog"));
+    assert_eq!(
+      template.split(&l("owner.I & type.dog")),
+      [[(0, l("owner.I & type.dog"))], [(0, l("type.dog"))]]
+    );
+  }
+
+  #[test]
+  fn splitting_drops_duplicate_splits() {
+    let template = t("$0 & $1");
+    assert_eq!(
+      template.split(&l("a & a")),
+      [[(0, none()), (1, l("a & a"))], [(0, l("a")), (1, l("a"))], [(0, l("a & a")), (1, none())]]
+    );
+    assert_eq!(template.split(&l("a & a & a & b")).len(), 8);
+  }
+
+  #[test]
+  fn split_iter_is_lazy() {
+    let template = t("$0 & $1");
+    let lambda = l(&(0..24).map(|i| format!("x{}", i)).collect::<Vec<_>>().join(" & "));
+    let first: Vec<_> = template.split_iter(&lambda).take(2).collect();
+    let rest = l(&lambda.repr()[5..]);
+    assert_eq!(first, [[(0, none()), (1, lambda.clone())], [(0, l("x0")), (1, rest)]]);
+    let template = t("$0 & $1");
+    let lambda = l("a & b & c");
+    assert_eq!(template.split_iter(&lambda).collect::<Vec<_>>(), template.split(&lambda));
+  }
+
+  #[test]
+  fn typed_terminals_work() {
+    assert_eq!(*l("2").expr(), Expr::Int(2));
+    assert_eq!(*l("-2.5").expr(), Expr::Float(-2.5));
+    assert_eq!(*l("2024-02-29").expr(), Expr::Date(Date::new(2024, 2, 29).unwrap()));
+    assert_eq!(*l("2023-02-29").expr(), Expr::Terminal("2023-02-29".into()));
+    assert_eq!(*l("2abc").expr(), Expr::Terminal("2abc".into()));
+    assert_eq!(l("count.2.0").repr(), "count.2.0");
+    assert_eq!(*l("2.0").expr(), Expr::Float(2.0));
+    assert_eq!(l("2.0").repr(), "2.0");
+    assert_ne!(l("2.0").repr(), l("2").repr());
+    assert_eq!(Lambda::base_lex("2"), l("2"));
+    assert_eq!(Lambda::base_lex("2.50").float(), Some(2.5));
+    assert_eq!(Lambda::base_lex("two").int(), None);
+  }
+
+  #[test]
+  fn typed_terminal_templates_work() {
+    let lexed = |x, y| Lambda::new(Expr::Binary(Binary::Join, vec![l(x), Lambda::base_lex(y)]));
+    let template = t("count.2 & date.2024-01-05");
+    assert_eq!(template.split(&l("count.2 & date.2024-01-05")), [vec![]]);
+    let lambda = Lambda::new(Expr::Binary(
+      Binary::Conjunction,
+      vec![lexed("count", "2.0"), lexed("date", "2024-01-05")],
+    ));
+    assert_eq!(template.split(&lambda), [vec![]]);
+    let template = t("price.2.5");
+    assert_eq!(template.split(&lexed("price", "2.50")), [vec![]]);
+    assert_eq!(template.split(&l("price.2.50")), [vec![]]);
+    assert_eq!(template.split(&l("price.3")), empty());
+    assert_eq!(template.split(&lexed("price", "two")), empty());
+  }
+
+  #[test]
+  fn scale_templates_work() {
+    let template = t("count.$0 * 100");
+    assert_eq!(merge(&*template, vec![l("3")]), l("count.300"));
+    assert_eq!(merge(&*template, vec![l("three")]), Lambda::default());
+    assert_eq!(template.split(&l("count.300")), [[(0, l("3"))]]);
+    assert_eq!(template.split(&l("count.250")), empty());
+    assert_eq!(template.split(&l("count.many")), empty());
+  }
+
+  #[test]
+  fn date_math_works() {
+    let date = |x: &str| x.parse::<Date>().unwrap();
+    assert_eq!(date("1970-01-01").days(), 0);
+    assert_eq!(date("2000-03-01").days() - date("2000-02-28").days(), 2);
+    assert_eq!(date("2023-12-31").add_days(1), date("2024-01-01"));
+    assert_eq!(date("1969-12-31"), Date::from_days(-1));
+    assert_eq!(Date::from_days(date("2024-02-29").days()).to_string(), "2024-02-29");
+    assert_eq!("2024-13-01".parse::<Date>(), Err("Invalid date: 2024-13-01".into()));
+  }
+
   #[test]
   fn parse_handles_empty_payloads() {
     assert_eq!(l("-"), Lambda::default());
@@ -468,6 +902,74 @@ mod tests {
     assert_eq!(lambda.repr(), "(b.a | d.c) & Tell(x) & f.e");
   }
 
+  #[test]
+  fn normalize_flattens_and_dedups() {
+    let lambda = Lambda::new(Expr::Binary(Binary::Conjunction, vec![l("a & b"), l("b & c")]));
+    assert_eq!(lambda.repr(), "(a & b) & (b & c)");
+    assert_eq!(lambda.normalize(false), l("a & b & c"));
+    assert_eq!(l("a.b & a.b").normalize(false), l("a.b"));
+    assert_eq!(l("a.(b.c)").normalize(false).repr(), "a.b.c");
+  }
+
+  #[test]
+  fn normalize_cancels_unary_operators() {
+    let not = |x| Lambda::new(Expr::Unary(Unary::Not, x));
+    let reverse = |x| Lambda::new(Expr::Unary(Unary::Reverse, x));
+    assert_eq!(not(not(l("a & b"))).repr(), "~(~(a & b))");
+    assert_eq!(not(not(l("a & b"))).normalize(false), l("a & b"));
+    assert_eq!(reverse(reverse(l("name"))).normalize(false), l("name"));
+    assert_eq!(not(l("a & b")).normalize(false), l("~(a & b)"));
+  }
+
+  #[test]
+  fn normalize_converts_to_dnf() {
+    assert_eq!(l("a & (b | c)").normalize(true), l("(a & b) | (a & c)"));
+    assert_eq!(l("(a | b) & (a | c)").normalize(true), l("a | (b & c)"));
+    assert_eq!(l("~(a | ~b)").normalize(true), l("~a & b"));
+    assert_eq!(l("~(a & (b | c))").normalize(true), l("~a | (~b & ~c)"));
+    assert_eq!(l("Tell(x & (y | z))").normalize(true), l("Tell((x & y) | (x & z))"));
+  }
+
+  #[test]
+  fn substitute_works() {
+    let lambda = l("Ask(R[want].X)");
+    assert_eq!(lambda.substitute("X", &l("I")), l("Ask(R[want].I)"));
+    assert_eq!(lambda.substitute("X", &l("R[friend].I")), l("Ask(R[want].R[friend].I)"));
+    assert_eq!(lambda.substitute("Y", &l("I")), lambda);
+    assert_eq!(lambda.substitute("X", &none()), none());
+    assert_eq!(l("~X & type.dog").substitute("X", &l("~owner.I")), l("owner.I & type.dog"));
+    assert_eq!(l("X & type.dog").substitute("X", &none()), l("type.dog"));
+    assert_eq!(l("R[X].I").substitute("X", &l("R[name]")), l("name.I"));
+  }
+
+  #[test]
+  fn substitute_with_fills_holes() {
+    let holes = |x: &str| match x {
+      "X" => Some(l("I")),
+      "Y" => Some(l("color.red | color.blue")),
+      _ => None,
+    };
+    let lambda = l("Tell(X, type.food & Y)");
+    assert_eq!(lambda.substitute_with(&holes), l("Tell(I, type.food & (color.red | color.blue))"));
+  }
+
+  #[test]
+  fn subsumes_works() {
+    let subsumes = |x, y| l(x).subsumes(&l(y));
+    assert!(subsumes("type.food", "type.food & color.red"));
+    assert!(!subsumes("type.food & color.red", "type.food"));
+    assert!(subsumes("a | b", "a"));
+    assert!(subsumes("a | b", "b & c"));
+    assert!(!subsumes("a", "a | b"));
+    assert!(subsumes("owner.(a | b)", "owner.a"));
+    assert!(subsumes("a.b.c", "a.b.(c & d)"));
+    assert!(!subsumes("a.b.c", "a.d.c"));
+    assert!(subsumes("~(a & b)", "~a"));
+    assert!(!subsumes("~a", "~(a & b)"));
+    assert!(!subsumes("Tell(a)", "Tell(a & b)"));
+    assert!(Lambda::default().subsumes(&l("Tell(a)")));
+  }
+
   #[bench]
   fn parse_benchmark(b: &mut Bencher) {
     b.iter(|| Lambda::parse("Tell(abc & def.ghi, jkl | (mno & pqr))").unwrap());
@@ -499,6 +1001,14 @@ mod tests {
     b.iter(|| template.split(&lambda));
   }
 
+  #[bench]
+  fn template_split_duplicates_benchmark(b: &mut Bencher) {
+    let lambda = Lambda::parse("a & a & a & b & b & c").unwrap();
+    let template = Lambda::template("$0 & $1").unwrap();
+    assert_eq!(template.split(&lambda).len(), 24);
+    b.iter(|| template.split(&lambda));
+  }
+
   #[bench]
   fn template_split_hard_benchmark(b: &mut Bencher) {
     let lambda = Lambda::parse("a & b & c.d").unwrap();
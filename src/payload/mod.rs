@@ -1,4 +1,8 @@
 pub mod base;
 pub mod cached;
+pub mod evaluate;
+pub mod frame;
 pub mod json;
 pub mod lambda;
+pub mod query;
+pub mod simple;
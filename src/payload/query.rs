@@ -0,0 +1,425 @@
+use super::super::lib::base::Result;
+use super::lambda::{Binary, Date, Expr, Lambda, Unary};
+
+// Translates lambda DCS expressions into SPARQL or Cypher queries, so that parsed questions
+// can be answered by a real knowledge graph. Each query returns the set that evaluate.rs
+// would compute over a graph with one edge per (subject, relation, object) triple:
+//
+//   - A chain of joins becomes a property path, with R[r] as an inverse step, so that
+//     r.R[s].x becomes "?x :r/^:s :x" in SPARQL and "(x)-[:r]->()<-[:s]-({id: 'x'})"
+//     in Cypher.
+//   - Conjunction, disjunction, and negation become intersected patterns, UNION, and
+//     FILTER NOT EXISTS in SPARQL, and AND, OR, and NOT in Cypher. Complements are taken
+//     over every entity in the graph.
+//   - Custom functions become calls to functions of the same name, applied to each
+//     combination of their arguments' values. "Ask" returns its argument, as it does in
+//     the evaluator, so Ask(x) is the same query as x.
+//
+// In SPARQL, entities, relations, and functions are IRIs under the given prefix, and
+// numbers and dates are typed literals. In Cypher, every value is a node with an "id"
+// property, relations are relationship types, and the result is a list of ids.
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+pub fn sparql(lambda: &Lambda, prefix: &str) -> Result<String> {
+  let lines = State::default().sparql(lambda, "x")?;
+  let body: Vec<_> = indent(lines).map(|x| format!("{}\n", x)).collect();
+  Ok(format!(
+    "PREFIX : <{}>\nPREFIX xsd: <{}>\nSELECT DISTINCT ?x WHERE {{\n{}}}\n",
+    prefix,
+    XSD,
+    body.concat()
+  ))
+}
+
+pub fn cypher(lambda: &Lambda) -> Result<String> {
+  let (mut state, lambda) = (State::default(), unwrap(lambda)?);
+  if let Expr::Custom(name, children) = lambda.expr() {
+    let (nodes, filters, args) = state.cypher_args(children)?;
+    let call = format!("RETURN DISTINCT {}({}) AS x\n", ident(name), args.join(", "));
+    if children.is_empty() {
+      return Ok(call);
+    }
+    return Ok(format!("MATCH {}\nWHERE {}\n{}", nodes.join(", "), filters.join(" AND "), call));
+  }
+  Ok(format!("MATCH (x)\nWHERE {}\nRETURN DISTINCT x.id AS x\n", state.cypher(lambda, "x")?))
+}
+
+#[derive(Default)]
+struct State {
+  count: usize,
+}
+
+impl State {
+  fn fresh(&mut self) -> String {
+    self.count += 1;
+    format!("x{}", self.count)
+  }
+
+  fn sparql(&mut self, lambda: &Lambda, var: &str) -> Result<Vec<String>> {
+    let lambda = unwrap(lambda)?;
+    if let Some(x) = value(lambda) {
+      return Ok(vec![format!("VALUES ?{} {{ {} }}", var, x.sparql())]);
+    }
+    match lambda.expr() {
+      Expr::Binary(Binary::Conjunction, children) => self.sparql_and(children, var),
+      Expr::Binary(Binary::Disjunction, children) => {
+        if children.is_empty() {
+          return Ok(vec!["FILTER(false)".into()]);
+        }
+        let mut result = vec![];
+        for (i, x) in children.iter().enumerate() {
+          result.push(if i == 0 { "{" } else { "} UNION {" }.into());
+          result.extend(indent(self.sparql(x, var)?));
+        }
+        result.push("}".into());
+        Ok(result)
+      }
+      Expr::Binary(Binary::Join, children) => {
+        let (last, relations) = match children.split_last() {
+          Some(x) => x,
+          None => Err("Unable to translate an empty join")?,
+        };
+        let mut steps = vec![];
+        for x in relations {
+          let (name, reverse) = relation(x)?;
+          steps.push(format!("{}{}", if reverse { "^" } else { "" }, iri(name)));
+        }
+        let path = steps.join("/");
+        if let Some(x) = value(unwrap(last)?) {
+          return Ok(vec![format!("?{} {} {} .", var, path, x.sparql())]);
+        }
+        let next = self.fresh();
+        let mut result = vec![format!("?{} {} ?{} .", var, path, next)];
+        result.extend(self.sparql(last, &next)?);
+        Ok(result)
+      }
+      Expr::Custom(name, children) => {
+        let (mut result, mut args) = (vec!["{".to_string()], vec![]);
+        for x in children {
+          let next = self.fresh();
+          result.extend(indent(self.sparql(x, &next)?));
+          args.push(format!("?{}", next));
+        }
+        result.push(format!("  BIND({}({}) AS ?{})", iri(name), args.join(", "), var));
+        result.push("}".into());
+        Ok(result)
+      }
+      Expr::Unary(Unary::Not, _) => self.sparql_and(std::slice::from_ref(lambda), var),
+      Expr::Unary(Unary::Reverse, _) => {
+        Err(format!("Unable to translate relation: {}", lambda.repr()))?
+      }
+      _ => Err("Unable to translate an empty lambda")?,
+    }
+  }
+
+  fn sparql_and(&mut self, children: &[Lambda], var: &str) -> Result<Vec<String>> {
+    let (mut result, mut filters) = (vec![], vec![]);
+    for x in children {
+      if let Expr::Unary(Unary::Not, y) = x.expr() {
+        filters.push("FILTER NOT EXISTS {".to_string());
+        filters.extend(indent(self.sparql(y, var)?));
+        filters.push("}".into());
+      } else {
+        result.extend(self.sparql(x, var)?);
+      }
+    }
+    // A filter can only remove bindings, so if nothing else binds the variable, we bind
+    // it to every entity that appears in some triple.
+    if result.is_empty() {
+      let (p, o) = (self.fresh(), self.fresh());
+      result.push(format!("{{ ?{} ?{} ?{} }} UNION {{ ?{} ?{} ?{} }}", var, p, o, o, p, var));
+    }
+    result.append(&mut filters);
+    Ok(result)
+  }
+
+  fn cypher(&mut self, lambda: &Lambda, var: &str) -> Result<String> {
+    let lambda = unwrap(lambda)?;
+    if let Some(x) = value(lambda) {
+      return Ok(format!("{}.id = {}", var, x.cypher()));
+    }
+    match lambda.expr() {
+      Expr::Binary(Binary::Join, children) => {
+        let (last, relations) = match children.split_last() {
+          Some(x) => x,
+          None => Err("Unable to translate an empty join")?,
+        };
+        let mut path = format!("({})", var);
+        for (i, x) in relations.iter().enumerate() {
+          let (name, reverse) = relation(x)?;
+          let (l, r) = if reverse { ("<-", "-") } else { ("-", "->") };
+          path.push_str(&format!("{}[:{}]{}", l, ident(name), r));
+          if i + 1 < relations.len() {
+            path.push_str("()");
+          }
+        }
+        if let Some(x) = value(unwrap(last)?) {
+          return Ok(format!("EXISTS {{ {}({{id: {}}}) }}", path, x.cypher()));
+        }
+        let next = self.fresh();
+        let filter = self.cypher(last, &next)?;
+        Ok(format!("EXISTS {{ MATCH {}({}) WHERE {} }}", path, next, filter))
+      }
+      Expr::Binary(op, children) => {
+        let conjunction = *op == Binary::Conjunction;
+        if children.is_empty() {
+          return Ok(if conjunction { "true" } else { "false" }.into());
+        }
+        let mut result = vec![];
+        for x in children {
+          result.push(group(x, self.cypher(x, var)?));
+        }
+        Ok(result.join(if conjunction { " AND " } else { " OR " }))
+      }
+      Expr::Custom(name, children) => {
+        let (nodes, mut filters, args) = self.cypher_args(children)?;
+        filters.push(format!("{}.id = {}({})", var, ident(name), args.join(", ")));
+        if children.is_empty() {
+          return Ok(filters.concat());
+        }
+        Ok(format!("EXISTS {{ MATCH {} WHERE {} }}", nodes.join(", "), filters.join(" AND ")))
+      }
+      Expr::Unary(Unary::Not, child) => {
+        Ok(format!("NOT {}", group(child, self.cypher(child, var)?)))
+      }
+      Expr::Unary(Unary::Reverse, _) => {
+        Err(format!("Unable to translate relation: {}", lambda.repr()))?
+      }
+      _ => Err("Unable to translate an empty lambda")?,
+    }
+  }
+
+  // Returns a node pattern, a filter, and a value for each argument of a custom function.
+  fn cypher_args(
+    &mut self,
+    children: &[Lambda],
+  ) -> Result<(Vec<String>, Vec<String>, Vec<String>)> {
+    let (mut nodes, mut filters, mut args) = (vec![], vec![], vec![]);
+    for x in children {
+      let next = self.fresh();
+      filters.push(group(x, self.cypher(x, &next)?));
+      nodes.push(format!("({})", next));
+      args.push(format!("{}.id", next));
+    }
+    Ok((nodes, filters, args))
+  }
+}
+
+// Terminals that parse as numbers or dates are typed, as they are by Lambda's accessors.
+
+enum Value<'a> {
+  Date(Date),
+  Name(&'a str),
+  Number(String),
+}
+
+impl<'a> Value<'a> {
+  fn cypher(&self) -> String {
+    match self {
+      Value::Date(x) => format!("date('{}')", x),
+      Value::Name(x) => format!("'{}'", x.replace('\\', "\\\\").replace('\'', "\\'")),
+      Value::Number(x) => x.clone(),
+    }
+  }
+
+  fn sparql(&self) -> String {
+    match self {
+      Value::Date(x) => format!("\"{}\"^^xsd:date", x),
+      Value::Name(x) => iri(x),
+      Value::Number(x) => x.clone(),
+    }
+  }
+}
+
+fn value(lambda: &Lambda) -> Option<Value<'_>> {
+  let name = match lambda.expr() {
+    Expr::Date(_) | Expr::Float(_) | Expr::Int(_) => None,
+    Expr::Terminal(x) => Some(x.as_str()),
+    _ => return None,
+  };
+  if let Some(x) = lambda.date() {
+    return Some(Value::Date(x));
+  }
+  let number =
+    lambda.int().map(|x| x.to_string()).or_else(|| lambda.float().map(|x| x.to_string()));
+  number.map(Value::Number).or_else(|| name.map(Value::Name))
+}
+
+fn relation(lambda: &Lambda) -> Result<(&str, bool)> {
+  match lambda.expr() {
+    Expr::Terminal(x) => return Ok((x, false)),
+    Expr::Unary(Unary::Reverse, x) => {
+      if let Expr::Terminal(y) = x.expr() {
+        return Ok((y, true));
+      }
+    }
+    _ => (),
+  }
+  Err(format!("Expected a relation: {}", lambda.repr()))?
+}
+
+fn unwrap(lambda: &Lambda) -> Result<&Lambda> {
+  match lambda.expr() {
+    Expr::Custom(name, children) if name == "Ask" => match children.as_slice() {
+      [x] => unwrap(x),
+      _ => Err(format!("Ask takes 1 argument; got: {}", children.len()))?,
+    },
+    _ => Ok(lambda),
+  }
+}
+
+// Helpers for formatting names and nested clauses.
+
+fn group(lambda: &Lambda, text: String) -> String {
+  let lambda = unwrap(lambda).unwrap_or(lambda);
+  let binary = match lambda.expr() {
+    Expr::Binary(op, children) => *op != Binary::Join && children.len() > 1,
+    _ => false,
+  };
+  if binary {
+    format!("({})", text)
+  } else {
+    text
+  }
+}
+
+fn ident(name: &str) -> String {
+  let simple = |x: char| x.is_ascii_alphanumeric() || x == '_';
+  let leading = name.starts_with(|x: char| x.is_ascii_alphabetic() || x == '_');
+  if leading && name.chars().all(simple) {
+    name.to_string()
+  } else {
+    format!("`{}`", name.replace('`', "``"))
+  }
+}
+
+fn indent(lines: Vec<String>) -> impl Iterator<Item = String> {
+  lines.into_iter().map(|x| format!("  {}", x))
+}
+
+fn iri(name: &str) -> String {
+  let mut result = ":".to_string();
+  for x in name.bytes() {
+    if x.is_ascii_alphanumeric() || x == b'_' {
+      result.push(x as char);
+    } else {
+      result.push_str(&format!("%{:02X}", x));
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::base::Payload;
+  use super::*;
+
+  fn lines(text: Result<String>) -> Vec<String> {
+    text.unwrap().lines().map(|x| x.to_string()).collect()
+  }
+
+  fn body(input: &str) -> Vec<String> {
+    let text = sparql(&Lambda::parse(input).unwrap(), "http://example.org/");
+    let result = lines(text);
+    result[3..result.len() - 1].iter().map(|x| x[2..].to_string()).collect()
+  }
+
+  fn filter(input: &str) -> String {
+    lines(cypher(&Lambda::parse(input).unwrap()))[1][6..].to_string()
+  }
+
+  #[test]
+  fn lambdas_are_translated_to_sparql() {
+    assert_eq!(
+      lines(sparql(&Lambda::parse("R[name].owner.me").unwrap(), "urn:kb:")),
+      [
+        "PREFIX : <urn:kb:>",
+        "PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>",
+        "SELECT DISTINCT ?x WHERE {",
+        "  ?x ^:name/:owner :me .",
+        "}",
+      ]
+    );
+    assert_eq!(body("Ask(type.dog & owner.me)"), ["?x :type :dog .", "?x :owner :me ."]);
+    assert_eq!(
+      body("type.dog | type.cat"),
+      ["{", "  ?x :type :dog .", "} UNION {", "  ?x :type :cat .", "}"]
+    );
+    assert_eq!(
+      body("R[type].owner.(type.dog & ~name.Rex)"),
+      [
+        "?x ^:type/:owner ?x1 .",
+        "?x1 :type :dog .",
+        "FILTER NOT EXISTS {",
+        "  ?x1 :name :Rex .",
+        "}",
+      ]
+    );
+    assert_eq!(
+      body("~owner.me"),
+      ["{ ?x ?x1 ?x2 } UNION { ?x2 ?x1 ?x }", "FILTER NOT EXISTS {", "  ?x :owner :me .", "}"]
+    );
+    assert_eq!(
+      body("Count(type.dog)"),
+      ["{", "  ?x1 :type :dog .", "  BIND(:Count(?x1) AS ?x)", "}"]
+    );
+    assert_eq!(body("age.3"), ["?x :age 3 ."]);
+    assert_eq!(body("born.2020-02-29"), ["?x :born \"2020-02-29\"^^xsd:date ."]);
+    let name = Lambda::new(Expr::Terminal("Kim Lee".into()));
+    assert_eq!(lines(sparql(&name, ""))[3], "  VALUES ?x { :Kim%20Lee }");
+  }
+
+  #[test]
+  fn lambdas_are_translated_to_cypher() {
+    assert_eq!(
+      lines(cypher(&Lambda::parse("R[name].owner.me").unwrap())),
+      [
+        "MATCH (x)",
+        "WHERE EXISTS { (x)<-[:name]-()-[:owner]->({id: 'me'}) }",
+        "RETURN DISTINCT x.id AS x",
+      ]
+    );
+    assert_eq!(
+      filter("type.dog & ~(owner.me | owner.you)"),
+      "EXISTS { (x)-[:type]->({id: 'dog'}) } AND NOT (EXISTS { (x)-[:owner]->({id: 'me'}) } \
+       OR EXISTS { (x)-[:owner]->({id: 'you'}) })"
+    );
+    assert_eq!(
+      filter("R[type].owner.(type.dog & name.Rex)"),
+      "EXISTS { MATCH (x)<-[:type]-()-[:owner]->(x1) WHERE EXISTS { (x1)-[:type]->({id: 'dog'}) } \
+       AND EXISTS { (x1)-[:name]->({id: 'Rex'}) } }",
+    );
+    let name = Lambda::new(Expr::Terminal("O'Neil".into()));
+    assert_eq!(lines(cypher(&name))[1], "WHERE x.id = 'O\\'Neil'");
+    assert_eq!(
+      filter("part.(Max(age.3, 2020-02-29))"),
+      [
+        "EXISTS { MATCH (x)-[:part]->(x1) WHERE EXISTS { MATCH (x2), (x3) WHERE ",
+        "EXISTS { (x2)-[:age]->({id: 3}) } AND x3.id = date('2020-02-29') AND ",
+        "x1.id = Max(x2.id, x3.id) } }",
+      ]
+      .concat()
+    );
+    assert_eq!((ident("has_part"), ident("has-part")), ("has_part".into(), "`has-part`".into()));
+    assert_eq!(
+      lines(cypher(&Lambda::parse("Count(type.dog)").unwrap())),
+      [
+        "MATCH (x1)",
+        "WHERE EXISTS { (x1)-[:type]->({id: 'dog'}) }",
+        "RETURN DISTINCT Count(x1.id) AS x",
+      ]
+    );
+  }
+
+  #[test]
+  fn translation_errors() {
+    let error = |x: &str| cypher(&Lambda::parse(x).unwrap()).err().unwrap();
+    assert_eq!(error("(a | b).c"), "Expected a relation: a | b".into());
+    assert_eq!(error("-"), "Unable to translate an empty lambda".into());
+    assert_eq!(error("R[name]"), "Unable to translate relation: R[name]".into());
+    assert_eq!(error("Ask(me, you)"), "Ask takes 1 argument; got: 2".into());
+    let error = sparql(&Lambda::parse("R[name]").unwrap(), "").err().unwrap();
+    assert_eq!(error, "Unable to translate relation: R[name]".into());
+  }
+}
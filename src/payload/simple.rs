@@ -0,0 +1,141 @@
+use super::super::lib::base::Result;
+use super::base::{Args, Template, VariableTemplate, WildcardTemplate};
+use super::cached::{Base, Cached};
+use std::fmt::Display;
+use std::str::FromStr;
+
+// An adapter that implements Base for any atomic semantics type, so that Cached<T>
+// is a Payload without any further boilerplate. A Simple type needs:
+//
+//  - FromStr, used to lex tokens and to parse template literals
+//  - Display, which must be canonical, as with any Base type
+//  - Default, the empty value, which templates treat as absent
+//
+// Its default templates are a literal value, a variable "$N", or a wildcard "*".
+// Types with structure should override template, e.g. with the combinators in
+// payload::base. Types that lexers should match against words should override
+// lexeme to return the word for a value.
+
+pub trait Simple: 'static + Default + Display + FromStr + PartialEq + Send + Sync {
+  fn lexeme(&self) -> Option<&str> {
+    None
+  }
+
+  fn template(input: &str) -> Result<Box<dyn Template<Cached<Self>>>> {
+    template(input)
+  }
+}
+
+impl<T: Simple> Base for T {
+  fn base_lex(input: &str) -> Self {
+    input.parse().unwrap_or_default()
+  }
+
+  fn base_unlex(&self) -> Option<&str> {
+    self.lexeme()
+  }
+
+  fn default_static() -> Cached<Self> {
+    Cached::new(T::default())
+  }
+
+  fn template(input: &str) -> Result<Box<dyn Template<Cached<Self>>>> {
+    <T as Simple>::template(input)
+  }
+}
+
+pub fn template<T: Simple>(input: &str) -> Result<Box<dyn Template<Cached<T>>>> {
+  let input = input.trim();
+  if input == "*" {
+    return Ok(Box::new(WildcardTemplate {}));
+  } else if let Some(index) = input.strip_prefix('$') {
+    let index = index.parse().map_err(|_| format!("Invalid variable: {}", input))?;
+    return Ok(Box::new(VariableTemplate(index)));
+  }
+  match input.parse::<T>() {
+    Ok(x) => Ok(Box::new(LiteralTemplate(Cached::new(x)))),
+    Err(_) => Err(format!("Invalid literal: {}", input))?,
+  }
+}
+
+struct LiteralTemplate<T: Simple>(Cached<T>);
+
+impl<T: Simple> Template<Cached<T>> for LiteralTemplate<T> {
+  fn merge(&self, _: &Args<Cached<T>>) -> Cached<T> {
+    self.0.clone()
+  }
+  fn split(&self, x: &Cached<T>) -> Vec<Args<Cached<T>>> {
+    if *x == self.0 { vec![vec![]] } else { vec![] }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::base::Payload;
+  use super::*;
+
+  #[derive(Debug, Default, PartialEq)]
+  enum Color {
+    #[default]
+    None,
+    Red,
+    Blue,
+  }
+
+  impl Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+      write!(f, "{}", self.lexeme().unwrap_or("-"))
+    }
+  }
+
+  impl FromStr for Color {
+    type Err = String;
+    fn from_str(x: &str) -> std::result::Result<Self, String> {
+      match x {
+        "red" => Ok(Color::Red),
+        "blue" => Ok(Color::Blue),
+        _ => Err(format!("Unknown color: {}", x)),
+      }
+    }
+  }
+
+  impl Simple for Color {
+    fn lexeme(&self) -> Option<&str> {
+      match self {
+        Color::None => None,
+        Color::Red => Some("red"),
+        Color::Blue => Some("blue"),
+      }
+    }
+  }
+
+  type Value = Cached<Color>;
+
+  fn c(input: &str) -> Value {
+    Value::parse(input).unwrap()
+  }
+
+  #[test]
+  fn simple_payloads_work() {
+    assert_eq!(c("red"), Value::new(Color::Red));
+    assert_eq!(c("-"), Value::default());
+    assert_eq!(Value::base_lex("blue"), c("blue"));
+    assert_eq!(Value::base_lex("green"), Value::default());
+    assert_eq!(c("blue").base_unlex(), Some("blue"));
+    assert_eq!(Value::parse("green"), Err("Invalid literal: green".into()));
+  }
+
+  #[test]
+  fn simple_templates_work() {
+    let literal = Value::template("red").unwrap();
+    assert_eq!(literal.merge(&vec![]), c("red"));
+    assert_eq!(literal.split(&c("red")), [vec![]]);
+    assert!(literal.split(&c("blue")).is_empty());
+    let variable = Value::template("$1").unwrap();
+    assert_eq!(variable.merge(&vec![(1, c("blue"))]), c("blue"));
+    assert_eq!(variable.split(&c("blue")), [[(1, c("blue"))]]);
+    let wildcard = Value::template("*").unwrap();
+    assert_eq!(wildcard.merge(&vec![]), Value::default());
+    assert_eq!(wildcard.split(&c("red")), [vec![]]);
+  }
+}
@@ -0,0 +1,180 @@
+use super::hindi::lexer::HindiLexer;
+use super::lib::base::{Error, Result};
+use super::nlu::base::Grammar as BaseGrammar;
+use super::nlu::corrector::Corrector as BaseCorrector;
+use super::nlu::fantasy;
+use super::nlu::generator::Generator as BaseGenerator;
+use super::nlu::parser::Parser as BaseParser;
+use super::payload::base::Payload;
+use super::payload::json::Json;
+use super::payload::lambda::Lambda;
+use super::{check_scheme, describe, report, string, unparsed};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rand::SeedableRng;
+
+// Bindings for driving the grammar from Python:
+//
+//   import fantasy
+//   grammar = fantasy.compile(open("src/hindi/hindi.grammar").read())
+//   fantasy.Parser(grammar).parse("mujhe pani chahie", scheme="latin")
+//
+// Results are dicts with the same shape as the wasm bindings' objects: parse and
+// generate return the value's repr with the derivation's "matches" and "text", and
+// correct returns the CLI's "--format json" report. Errors raise ValueError.
+//
+// Build the module with the "extension-module" feature. The "python" feature alone
+// links against libpython, so that the tests below can run the bindings.
+//
+// Parsers, generators, and correctors borrow their grammar. Each one holds a reference
+// to the Python grammar object, which keeps it alive, and grammars are never mutated
+// after they're compiled, so the borrow stays valid for as long as its owner exists.
+
+type Value = BaseGrammar<Option<Lambda>, Lambda>;
+
+#[pyclass(frozen, unsendable)]
+pub struct Grammar(Value);
+
+#[pyclass(unsendable)]
+pub struct Parser {
+  parser: BaseParser<'static, Option<Lambda>, Lambda>,
+  _grammar: Py<Grammar>,
+}
+
+#[pyclass(unsendable)]
+pub struct Generator {
+  generator: BaseGenerator<'static, Option<Lambda>, Lambda>,
+  _grammar: Py<Grammar>,
+}
+
+#[pyclass(unsendable)]
+pub struct Corrector {
+  corrector: BaseCorrector<'static, Lambda>,
+  parser: BaseParser<'static, Option<Lambda>, Lambda>,
+  _grammar: Py<Grammar>,
+}
+
+#[pyfunction]
+fn compile(text: &str) -> PyResult<Grammar> {
+  let grammar = fantasy::compile(text, HindiLexer::new);
+  let grammar = grammar.map_err(|x| format!("Failed to compile grammar:\n\n{:?}", x));
+  Ok(Grammar(grammar.map_err(|x| py_error(x.into()))?))
+}
+
+#[pymethods]
+impl Parser {
+  #[new]
+  fn new(grammar: &Bound<'_, Grammar>) -> Self {
+    let parser = BaseParser::new(unsafe { borrow(grammar) });
+    Self { parser, _grammar: grammar.clone().unbind() }
+  }
+
+  #[pyo3(signature = (input, scheme = "latin"))]
+  fn parse(&self, py: Python<'_>, input: &str, scheme: &str) -> PyResult<PyObject> {
+    to_py(py, check_scheme(scheme).and_then(|()| {
+      let tree = self.parser.parse(input);
+      let tree = tree.ok_or_else(|| unparsed(input))?;
+      describe(&tree.matches(), scheme).set_path("value", string(tree.value.repr()))
+    }))
+  }
+}
+
+#[pymethods]
+impl Generator {
+  #[new]
+  fn new(grammar: &Bound<'_, Grammar>) -> Self {
+    let generator = BaseGenerator::new(unsafe { borrow(grammar) });
+    Self { generator, _grammar: grammar.clone().unbind() }
+  }
+
+  #[pyo3(signature = (semantics, seed = 0, scheme = "latin"))]
+  fn generate(
+    &self,
+    py: Python<'_>,
+    semantics: &str,
+    seed: u64,
+    scheme: &str,
+  ) -> PyResult<PyObject> {
+    to_py(py, check_scheme(scheme).and_then(|()| {
+      let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+      let lambda = Some(Lambda::parse(semantics)?);
+      let tree = self.generator.generate(&mut rng, &lambda);
+      let tree = tree.ok_or_else(|| format!("Failed to generate output: {:?}", semantics))?;
+      describe(&tree.matches(), scheme).set_path("value", string(tree.value.repr()))
+    }))
+  }
+}
+
+#[pymethods]
+impl Corrector {
+  #[new]
+  fn new(grammar: &Bound<'_, Grammar>) -> Self {
+    let value = unsafe { borrow(grammar) };
+    let (corrector, parser) = (BaseCorrector::new(value), BaseParser::new(value));
+    Self { corrector, parser, _grammar: grammar.clone().unbind() }
+  }
+
+  #[pyo3(signature = (input, seed = 0, scheme = "latin"))]
+  fn correct(&self, py: Python<'_>, input: &str, seed: u64, scheme: &str) -> PyResult<PyObject> {
+    to_py(py, check_scheme(scheme).and_then(|()| {
+      let tree = self.parser.parse(input);
+      let tree = tree.ok_or_else(|| unparsed(input))?;
+      let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+      let correction = self.corrector.correct(&mut rng, &tree);
+      let value = tree.value.repr();
+      Ok(report(input, seed, scheme, value, &tree.matches(), &correction))
+    }))
+  }
+}
+
+#[pymodule]
+#[pyo3(name = "fantasy")]
+fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+  m.add_class::<Grammar>()?;
+  m.add_class::<Parser>()?;
+  m.add_class::<Generator>()?;
+  m.add_class::<Corrector>()?;
+  m.add_function(wrap_pyfunction!(self::compile, m)?)
+}
+
+// Extends the lifetime of a grammar borrow, so that a pyclass can store the borrower.
+//
+// Safety: the caller must keep the grammar object alive for as long as it uses the
+// result. Our pyclasses do so by holding a Py<Grammar> in a field that is declared, and
+// so dropped, after the borrower. Grammars are frozen, so their values never move.
+
+unsafe fn borrow(grammar: &Bound<'_, Grammar>) -> &'static Value {
+  &*(&grammar.get().0 as *const Value)
+}
+
+fn py_error(error: Error) -> PyErr {
+  PyValueError::new_err(format!("{:?}", error))
+}
+
+fn to_py(py: Python<'_>, result: Result<Json>) -> PyResult<PyObject> {
+  let text = result.map_err(py_error)?.to_string_json();
+  Ok(py.import_bound("json")?.call_method1("loads", (text,))?.unbind())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn borrowers_keep_their_grammar_alive() {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+      let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+      let grammar = Bound::new(py, compile(&data).unwrap()).unwrap();
+      let (parser, corrector) = (Parser::new(&grammar), Corrector::new(&grammar));
+      std::mem::drop(grammar);
+      let value = |x: PyObject| x.bind(py).get_item("value").unwrap().extract::<String>();
+      let result = parser.parse(py, "mujhe pani chahie", "latin").unwrap();
+      assert_eq!(value(result).unwrap(), "Tell(I, want.type.water)");
+      let result = corrector.correct(py, "mujhe pani chahie", 17, "latin").unwrap();
+      assert_eq!(value(result).unwrap(), "Tell(I, want.type.water)");
+      let error = parser.parse(py, "mujhe pani chahie", "klingon").err().unwrap();
+      assert!(error.is_instance_of::<PyValueError>(py));
+    });
+  }
+}
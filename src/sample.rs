@@ -0,0 +1,89 @@
+use super::lib::base::Result;
+use super::nlu::base::Derivation;
+use super::nlu::generator::Generator;
+use super::payload::base::Payload;
+use super::payload::lambda::Lambda;
+
+// Generates sentences in bulk, for building synthetic training corpora. Without a list
+// of semantics, we sample from the whole grammar; with one, we cycle through the list,
+// generating a sentence for each value in turn. Each sentence comes with the repr of
+// its value, so a list of samples is a corpus that "./main $grammar eval" can read.
+//
+// The semantics file has one value per line. Blank lines and lines starting with "#"
+// are skipped.
+
+type Rng = rand::rngs::StdRng;
+
+pub fn read_semantics(text: &str) -> Result<Vec<Lambda>> {
+  let lines = text.lines().enumerate().filter(|(_, x)| {
+    let x = x.trim();
+    !x.is_empty() && !x.starts_with('#')
+  });
+  let parse = |(i, x): (usize, &str)| {
+    let error = |y| format!("Invalid semantics (line {}):\n\n{:?}", i + 1, y);
+    Ok(Lambda::parse(x.trim()).map_err(error)?)
+  };
+  lines.map(parse).collect()
+}
+
+pub fn sample<'a>(
+  generator: &Generator<'a, Option<Lambda>, Lambda>,
+  rng: &mut Rng,
+  semantics: &[Lambda],
+  count: usize,
+) -> Result<Vec<Derivation<'a, Option<Lambda>, Lambda>>> {
+  let values: Vec<_> = if semantics.is_empty() {
+    vec![None]
+  } else {
+    semantics.iter().map(|x| Some(x.clone())).collect()
+  };
+  let mut result = Vec::with_capacity(count);
+  for value in values.iter().cycle().take(count) {
+    let tree = generator.generate(rng, value).ok_or_else(|| match value {
+      Some(x) => format!("Failed to generate output: {:?}", x.repr()),
+      None => "Failed to generate output".to_string(),
+    })?;
+    result.push(tree);
+  }
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::hindi::lexer::HindiLexer;
+  use super::super::nlu::base::Grammar;
+  use super::super::nlu::fantasy::compile;
+  use super::*;
+  use rand::SeedableRng;
+
+  fn make_grammar() -> Grammar<Option<Lambda>, Lambda> {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    compile(&data, HindiLexer::new).unwrap()
+  }
+
+  #[test]
+  fn samples_cycle_through_semantics() {
+    let grammar = make_grammar();
+    let generator = Generator::new(&grammar);
+    let mut rng = Rng::seed_from_u64(17);
+    let semantics = read_semantics(
+      "
+      # A comment, followed by two values.
+      Tell(I, want.type.water)
+      Tell(I, want.type.food)
+    ",
+    )
+    .unwrap();
+    let samples = sample(&generator, &mut rng, &semantics, 5).unwrap();
+    let values: Vec<_> = samples.iter().map(|x| x.value.repr().to_string()).collect();
+    let (water, food) = ("Tell(I, want.type.water)", "Tell(I, want.type.food)");
+    assert_eq!(values, [water, food, water, food, water]);
+
+    let samples = sample(&generator, &mut rng, &[], 10).unwrap();
+    assert_eq!(samples.len(), 10);
+    assert!(samples.iter().all(|x| !x.matches().is_empty()));
+
+    let error = read_semantics("Tell(I,").err().unwrap();
+    assert!(format!("{:?}", error).starts_with("Invalid semantics (line 1)"));
+  }
+}
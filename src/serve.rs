@@ -0,0 +1,258 @@
+use super::lib::base::{HashMap, Result};
+use super::nlu::base::Grammar;
+use super::nlu::corrector::Corrector;
+use super::nlu::generator::Generator;
+use super::nlu::parser::Parser;
+use super::payload::base::Payload;
+use super::payload::json::{Expr, Json};
+use super::payload::lambda::Lambda;
+use super::{check_scheme, describe, dict, parse_seed, report, string, unparsed};
+use rand::SeedableRng;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::SystemTime;
+
+// A minimal HTTP server, for callers that want to query a grammar over the network:
+//
+//   GET /parse?input=mujhe+pani+chahie&scheme=latin
+//   GET /generate?semantics=Tell(I,+want.type.water)&seed=17
+//   GET /correct?input=do+accha+acche+larki+ko+pani+chahie&seed=17
+//
+// The "scheme" and "seed" parameters are optional: they default to "latin" and to the
+// current time. Responses are JSON. parse and generate return the value's repr along
+// with the derivation's "matches" and "text", and generate also returns its seed.
+// correct returns the same report as the CLI's "--format json". Errors return a 4xx
+// status and a dict with an "error".
+//
+// We compile the grammar and index it once, then handle each connection on its own
+// thread. All threads share the same parser, generator, and corrector.
+
+type Query = HashMap<String, String>;
+
+type Value = Grammar<Option<Lambda>, Lambda>;
+
+struct Handlers<'a> {
+  corrector: Corrector<'a, Lambda>,
+  generator: Generator<'a, Option<Lambda>, Lambda>,
+  parser: Parser<'a, Option<Lambda>, Lambda>,
+}
+
+impl<'a> Handlers<'a> {
+  fn new(grammar: &'a Value) -> Self {
+    let (corrector, generator) = (Corrector::new(grammar), Generator::new(grammar));
+    Self { corrector, generator, parser: Parser::new(grammar) }
+  }
+}
+
+pub fn serve(grammar: &Value, address: &str) -> Result<()> {
+  let listener =
+    TcpListener::bind(address).map_err(|x| format!("Failed to bind {}: {}", address, x))?;
+  let handlers = Handlers::new(grammar);
+  println!("Listening on: {}", address);
+  std::thread::scope(|scope| {
+    for stream in listener.incoming().filter_map(|x| x.ok()) {
+      let handlers = &handlers;
+      scope.spawn(move || {
+        if let Err(x) = handle(handlers, stream) {
+          eprintln!("Failed to handle request: {:?}", x);
+        }
+      });
+    }
+  });
+  Ok(())
+}
+
+fn handle(handlers: &Handlers, mut stream: TcpStream) -> Result<()> {
+  let mut reader = BufReader::new(&stream);
+  let (mut line, mut header) = (String::new(), String::new());
+  reader.read_line(&mut line).map_err(|x| x.to_string())?;
+  while reader.read_line(&mut header).map_err(|x| x.to_string())? > 2 {
+    header.clear();
+  }
+  let (status, json) = respond(handlers, line.trim_end());
+  let body = json.to_string_json();
+  let reason = match status {
+    200 => "OK",
+    404 => "Not Found",
+    405 => "Method Not Allowed",
+    _ => "Bad Request",
+  };
+  let headers = format!(
+    "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+     Connection: close\r\n\r\n",
+    status,
+    reason,
+    body.len(),
+  );
+  let response = [headers.as_bytes(), body.as_bytes()].concat();
+  Ok(stream.write_all(&response).map_err(|x| x.to_string())?)
+}
+
+// Takes an HTTP request line, like "GET /parse?input=... HTTP/1.1", and returns a
+// status code and a JSON response body.
+
+fn respond(handlers: &Handlers, line: &str) -> (u16, Json) {
+  let mut parts = line.split(' ');
+  let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+  let (path, query) = target.split_once('?').unwrap_or((target, ""));
+  let error = |x: &str| dict(vec![("error", string(x))]);
+  if method != "GET" {
+    return (405, error(&format!("Unsupported method: {}", method)));
+  }
+  let handler: fn(&Handlers, &Query) -> Result<Json> = match path {
+    "/correct" => correct,
+    "/generate" => generate,
+    "/parse" => parse,
+    _ => return (404, error(&format!("Unknown path: {}", path))),
+  };
+  let result = decode_query(query).and_then(|x| handler(handlers, &x));
+  result.map(|x| (200, x)).unwrap_or_else(|x| (400, error(&format!("{:?}", x))))
+}
+
+fn correct(handlers: &Handlers, query: &Query) -> Result<Json> {
+  let (input, seed, scheme) = (param(query, "input")?, seed(query)?, scheme(query)?);
+  let tree = handlers.parser.parse(input);
+  let tree = tree.ok_or_else(|| unparsed(input))?;
+  let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+  let correction = handlers.corrector.correct(&mut rng, &tree);
+  Ok(report(input, seed, scheme, tree.value.repr(), &tree.matches(), &correction))
+}
+
+fn generate(handlers: &Handlers, query: &Query) -> Result<Json> {
+  let (semantics, seed, scheme) = (param(query, "semantics")?, seed(query)?, scheme(query)?);
+  let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+  let tree = handlers.generator.generate(&mut rng, &Some(Lambda::parse(semantics)?));
+  let tree = tree.ok_or_else(|| format!("Failed to generate output: {:?}", semantics))?;
+  let json = describe(&tree.matches(), scheme).set_path("value", string(tree.value.repr()));
+  json?.set_path("seed", Json::new(Expr::Integer(seed as i64)))
+}
+
+fn parse(handlers: &Handlers, query: &Query) -> Result<Json> {
+  let (input, scheme) = (param(query, "input")?, scheme(query)?);
+  let tree = handlers.parser.parse(input);
+  let tree = tree.ok_or_else(|| unparsed(input))?;
+  describe(&tree.matches(), scheme).set_path("value", string(tree.value.repr()))
+}
+
+// Helpers for reading query parameters.
+
+fn decode(text: &str) -> Result<String> {
+  let (mut bytes, mut i) = (vec![], 0);
+  let data = text.as_bytes();
+  while i < data.len() {
+    let byte = match data[i] {
+      b'+' => b' ',
+      b'%' => {
+        let hex = text.get(i + 1..i + 3).and_then(|x| u8::from_str_radix(x, 16).ok());
+        i += 2;
+        hex.ok_or_else(|| format!("Invalid escape in query: {}", text))?
+      }
+      x => x,
+    };
+    bytes.push(byte);
+    i += 1;
+  }
+  Ok(String::from_utf8(bytes).map_err(|_| format!("Invalid UTF-8 in query: {}", text))?)
+}
+
+fn decode_query(query: &str) -> Result<Query> {
+  let items = query.split('&').filter(|x| !x.is_empty()).map(|x| {
+    let (key, value) = x.split_once('=').unwrap_or((x, ""));
+    Ok((decode(key)?, decode(value)?))
+  });
+  items.collect()
+}
+
+fn param<'a>(query: &'a Query, key: &str) -> Result<&'a str> {
+  Ok(query.get(key).ok_or_else(|| format!("Missing parameter: {}", key))?)
+}
+
+fn scheme(query: &Query) -> Result<&str> {
+  let scheme = query.get("scheme").map(|x| x.as_str()).unwrap_or("latin");
+  check_scheme(scheme)?;
+  Ok(scheme)
+}
+
+fn seed(query: &Query) -> Result<u64> {
+  match query.get("seed") {
+    Some(x) => parse_seed(x),
+    None => Ok(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::hindi::lexer::HindiLexer;
+  use super::super::nlu::fantasy::compile;
+  use super::*;
+  use std::io::Read;
+
+  fn make_grammar() -> Value {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    compile(&data, HindiLexer::new).unwrap()
+  }
+
+  #[test]
+  fn test_decode_query() {
+    let query = decode_query("input=mujhe+pani%20chahie&seed=17&empty").unwrap();
+    assert_eq!(query.get("input").unwrap(), "mujhe pani chahie");
+    assert_eq!(query.get("seed").unwrap(), "17");
+    assert_eq!(query.get("empty").unwrap(), "");
+    assert_eq!(decode("%E0%A4%AA").unwrap(), "\u{92a}");
+    assert_eq!(decode("%zz"), Err("Invalid escape in query: %zz".into()));
+  }
+
+  #[test]
+  fn requests_are_handled_on_shared_handlers() {
+    let grammar = make_grammar();
+    let handlers = Handlers::new(&grammar);
+    let get = |x: &str| {
+      let (status, json) = respond(&handlers, &format!("GET {} HTTP/1.1", x));
+      (status, json.to_string_json())
+    };
+    std::thread::scope(|scope| {
+      let threads: Vec<_> = (0..4)
+        .map(|_| scope.spawn(|| get("/parse?input=mujhe+pani+chahie")))
+        .collect();
+      for thread in threads {
+        let (status, json) = thread.join().unwrap();
+        assert_eq!(status, 200);
+        assert!(json.contains(r#""value":"Tell(I, want.type.water)""#));
+      }
+    });
+
+    let correct = "/correct?input=do+accha+acche+larki+ko+pani+chahie&seed=17";
+    let (status, json) = std::thread::scope(|scope| scope.spawn(|| get(correct)).join().unwrap());
+    assert_eq!(status, 200);
+    assert!(json.contains(r#""category":"agreement""#));
+    assert!(json.contains(r#""seed":17"#));
+    let (status, json) = get("/generate?semantics=Tell(I,+want.type.water)&seed=17");
+    assert_eq!(status, 200);
+    assert!(json.contains(r#""value":"Tell(I, want.type.water)""#));
+
+    assert_eq!(get("/parse?input=mujhe&scheme=klingon").0, 400);
+    let seed = "/generate?semantics=Tell(I,+want.type.water)&seed=18446744073709551615";
+    assert_eq!(get(seed).1, r#"{"error":"Invalid seed: \"18446744073709551615\""}"#);
+    assert_eq!(get("/parse").1, r#"{"error":"Missing parameter: input"}"#);
+    assert_eq!(get("/unknown").0, 404);
+    assert_eq!(respond(&handlers, "POST /parse HTTP/1.1").0, 405);
+  }
+
+  #[test]
+  fn responses_are_sent_over_http() {
+    let grammar = make_grammar();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let handlers = Handlers::new(&grammar);
+    std::thread::scope(|scope| {
+      scope.spawn(|| handle(&handlers, listener.accept().unwrap().0).unwrap());
+      let mut stream = TcpStream::connect(address).unwrap();
+      stream.write_all(b"GET /parse?input=mujhe+pani+chahie HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+      let mut response = String::new();
+      stream.read_to_string(&mut response).unwrap();
+      assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+      assert!(response.contains("Content-Type: application/json\r\n"));
+      assert!(response.ends_with("}"));
+    });
+  }
+}
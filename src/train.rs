@@ -0,0 +1,236 @@
+use super::eval::{describe, matches, read_corpus};
+use super::lib::base::{HashMap, Result};
+use super::nlu::base::{Derivation, Grammar, Rule};
+use super::nlu::parser::Parser;
+use super::payload::lambda::Lambda;
+
+// Learns rules' merge scores from a corpus in the eval format, so that we don't have to
+// tune disambiguation scores like "(< -0.5)" by hand. We train a structured perceptron
+// over the n best parses of each utterance: when the best parse has the wrong value, we
+// take the best parse that has the gold value, and for each rule, we add the difference
+// between the number of times that the gold and predicted parses use it to its score.
+// If no parse in the n best has the gold value, the utterance is unreachable, and we
+// skip it. Lexer scores are fixed, as are the scores of generation-only rules.
+//
+// The final scores are each rule's average score over every step of training, which is
+// less sensitive to the order of the corpus than the scores after the last step.
+
+const LEARNING_RATE: f32 = 1.0;
+const N_BEST: usize = 16;
+
+type Value = Grammar<Option<Lambda>, Lambda>;
+
+pub struct Training {
+  pub total: usize,
+  pub epochs: Vec<usize>,
+  pub correct: usize,
+  pub unreachable: Vec<(usize, String)>,
+  pub changes: Vec<(String, f32, f32)>,
+}
+
+impl Training {
+  pub fn summary(&self) -> Vec<String> {
+    let rate = |x: usize| 100.0 * x as f32 / std::cmp::max(self.total, 1) as f32;
+    let mut result = vec![];
+    for (i, x) in self.epochs.iter().enumerate() {
+      result.push(format!("Epoch {}: {}/{} ({:.1}%)", i + 1, x, self.total, rate(*x)));
+    }
+    let (correct, total) = (self.correct, self.total);
+    result.push(format!("Correct: {}/{} ({:.1}%)", correct, total, rate(correct)));
+    for (line, input) in &self.unreachable {
+      result.push(format!("Unreachable (line {}): {}", line, input));
+    }
+    result.push("Changed scores:".into());
+    for (rule, old, new) in &self.changes {
+      result.push(format!("{:>6.2} -> {:<6.2}  {}", old, new, rule));
+    }
+    result
+  }
+}
+
+pub fn train(grammar: &mut Value, corpus: &str, epochs: usize) -> Result<Training> {
+  let rows = read_corpus(corpus)?;
+  let index: HashMap<_, _> =
+    grammar.rules.iter().enumerate().map(|(i, x)| (x as *const Rule<_, _>, i)).collect();
+  let initial: Vec<_> = grammar.rules.iter().map(|x| x.merge.score).collect();
+  let mut sums = vec![0.0; initial.len()];
+  let mut updated = vec![false; initial.len()];
+  let (mut steps, mut results, mut unreachable) = (0, vec![], vec![]);
+
+  for _ in 0..epochs {
+    let mut correct = 0;
+    unreachable.clear();
+    for (line, input, expected) in &rows {
+      let delta = {
+        let parser = Parser::new(grammar);
+        let n_best = parser.parse_n_best(input, N_BEST);
+        let gold = n_best.iter().find(|x| matches(&x.0.value, expected));
+        match (n_best.first(), gold) {
+          (Some(x), Some(y)) if std::ptr::eq(x, y) => {
+            correct += 1;
+            None
+          }
+          (_, None) => {
+            unreachable.push((*line, input.to_string()));
+            None
+          }
+          (x, Some(y)) => {
+            let mut delta = vec![0.0; initial.len()];
+            let mut count = |tree: &Derivation<_, _>, sign: f32| {
+              tree.nodes().for_each(|(z, _)| delta[index[&(z.rule as *const _)]] += sign);
+            };
+            count(&y.0, 1.0);
+            x.iter().for_each(|z| count(&z.0, -1.0));
+            Some(delta)
+          }
+        }
+      };
+      for (i, x) in delta.into_iter().flatten().enumerate() {
+        let rule = &mut grammar.rules[i];
+        if x != 0.0 && rule.merge.score.is_finite() {
+          rule.merge.score += LEARNING_RATE * x;
+          updated[i] = true;
+        }
+      }
+      grammar.rules.iter().zip(sums.iter_mut()).for_each(|(x, y)| *y += x.merge.score);
+      steps += 1;
+    }
+    results.push(correct);
+  }
+
+  let mut changes = vec![];
+  for (i, rule) in grammar.rules.iter_mut().enumerate() {
+    if updated[i] {
+      rule.merge.score = sums[i] / steps as f32;
+    }
+  }
+  for (i, rule) in grammar.rules.iter().enumerate() {
+    if rule.merge.score != initial[i] {
+      changes.push((describe(grammar, rule), initial[i], rule.merge.score));
+    }
+  }
+
+  let parser = Parser::new(grammar);
+  let correct = rows.iter().filter(|(_, input, expected)| {
+    parser.parse(input).map(|x| matches(&x.value, expected)).unwrap_or_default()
+  });
+  let correct = correct.count();
+  Ok(Training { total: rows.len(), epochs: results, correct, unreachable, changes })
+}
+
+// To choose utterances to annotate next, we rank an unlabeled pool by how uncertain the
+// parser is about each one. The pool has one utterance per line; blank lines and lines
+// starting with "#" are skipped. Utterances that fail to parse come first, followed by
+// the rest in order of the margin between the scores of their best parse and the best
+// parse with a different value. An utterance whose n best parses all share one value
+// has an infinite margin. Each selection includes its top two distinct values.
+
+pub struct Selection {
+  pub line: usize,
+  pub input: String,
+  pub margin: Option<f32>,
+  pub values: Vec<String>,
+}
+
+impl Selection {
+  pub fn summary(&self) -> Vec<String> {
+    let (line, input) = (self.line, &self.input);
+    let mut result = vec![match self.margin {
+      Some(x) => format!("Margin {:.2} (line {}): {}", x, line, input),
+      None => format!("Failed to parse (line {}): {}", line, input),
+    }];
+    self.values.iter().for_each(|x| result.push(format!("  {}", x)));
+    result
+  }
+}
+
+pub fn select(grammar: &Value, pool: &str, count: usize) -> Vec<Selection> {
+  let parser = Parser::new(grammar);
+  let mut result = vec![];
+  for (i, row) in pool.lines().enumerate() {
+    let input = row.trim();
+    if input.is_empty() || input.starts_with('#') {
+      continue;
+    }
+    let mut values: Vec<(Lambda, f32)> = vec![];
+    for (tree, score) in parser.parse_n_best(input, N_BEST) {
+      if values.len() < 2 && !values.iter().any(|x| matches(&x.0, &tree.value)) {
+        values.push((tree.value, score));
+      }
+    }
+    let margin = match values.as_slice() {
+      [] => None,
+      [_] => Some(f32::INFINITY),
+      [x, y, ..] => Some(x.1 - y.1),
+    };
+    let values = values.into_iter().map(|x| x.0.repr().into()).collect();
+    result.push(Selection { line: i + 1, input: input.into(), margin, values });
+  }
+  let key = |x: &Selection| x.margin.unwrap_or(f32::NEG_INFINITY);
+  result.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap_or(std::cmp::Ordering::Equal));
+  result.truncate(count);
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::hindi::lexer::HindiLexer;
+  use super::super::nlu::fantasy::compile;
+  use super::*;
+
+  #[test]
+  fn scores_are_learned() {
+    // Start from a grammar without its hand-tuned scores, so that "mera larka kaun hai"
+    // is ambiguous: "mera larka" could be "my boy" or "my son".
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let mut grammar: Value = compile(&data, HindiLexer::new).unwrap();
+    let rules = grammar.rules.iter_mut().filter(|x| x.merge.score.is_finite());
+    rules.for_each(|x| x.merge.score = 0.0);
+    let corpus = "
+      mujhe pani chahie\tTell(I, want.type.water)
+      mera larka kaun hai\tAsk(R[name].(gender.male & parent.I))
+      xyzzy\tTell(I, want.type.water)
+    ";
+    let training = train(&mut grammar, corpus, 10).unwrap();
+    assert_eq!((training.epochs[0], training.epochs[9], training.correct), (1, 2, 2));
+    assert_eq!(training.unreachable, [(4, "xyzzy".to_string())]);
+    let summary = training.summary();
+    assert_eq!(summary[10..13], [
+      "Correct: 2/3 (66.7%)",
+      "Unreachable (line 4): xyzzy",
+      "Changed scores:",
+    ]);
+    let score = |x: &str| training.changes.iter().find(|y| y.0.starts_with(x)).map(|y| y.2 - y.1);
+    assert!(score("$Determiner = $NounKa").unwrap() < 0.0);
+    assert!(score("$Relation = $PersonKa larka").unwrap() > 0.0);
+
+    let value = Parser::new(&grammar).value("mera larka kaun hai").unwrap();
+    assert_eq!(value.repr(), "Ask((R[name]).(gender.male & parent.I))");
+    let error = train(&mut grammar, "mera larka kaun hai", 1).err().unwrap();
+    assert_eq!(error, "Invalid row (line 1): expected: utterance<TAB>semantics".into());
+  }
+
+  #[test]
+  fn uncertain_utterances_are_selected() {
+    let data = std::fs::read_to_string("src/hindi/hindi.grammar").unwrap();
+    let grammar: Value = compile(&data, HindiLexer::new).unwrap();
+    let pool = "
+      # Each utterance below is more informative than the one before it.
+      mujhe pani chahie
+      aap kaun hain
+      mujhe mujhe
+    ";
+    let selections = select(&grammar, pool, 3);
+    let summary: Vec<_> = selections.iter().flat_map(|x| x.summary()).collect();
+    assert_eq!(summary, [
+      "Failed to parse (line 5): mujhe mujhe",
+      "Margin 10.00 (line 4): aap kaun hain",
+      "  Ask((R[name]).you)",
+      "  Tell(you, name.kaun)",
+      "Margin inf (line 3): mujhe pani chahie",
+      "  Tell(I, want.type.water)",
+    ]);
+    let lines: Vec<_> = select(&grammar, pool, 2).iter().map(|x| x.line).collect();
+    assert_eq!(lines, [5, 4]);
+  }
+}
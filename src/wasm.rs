@@ -0,0 +1,74 @@
+use super::hindi::lexer::HindiLexer;
+use super::lib::base::{Error, Result};
+use super::nlu::base::Grammar;
+use super::nlu::corrector::Corrector;
+use super::nlu::fantasy::compile;
+use super::nlu::generator::Generator;
+use super::nlu::parser::Parser;
+use super::payload::base::Payload;
+use super::payload::json::Json;
+use super::payload::lambda::Lambda;
+use super::{check_scheme, describe, report, string, unparsed};
+use rand::SeedableRng;
+use wasm_bindgen::prelude::*;
+
+type JsResult<T> = std::result::Result<T, JsValue>;
+
+// Bindings for the web demo. JavaScript compiles a grammar once and then makes calls
+// against it. Each call returns a plain object: parse and generate return the value's
+// repr along with the derivation's "matches" and "text", in the given scheme, and
+// correct returns the same report as the CLI's "--format json".
+//
+// Seeds are u32 values so that they are ordinary numbers in JavaScript.
+
+#[wasm_bindgen]
+pub struct Fantasy {
+  grammar: Grammar<Option<Lambda>, Lambda>,
+}
+
+#[wasm_bindgen]
+impl Fantasy {
+  #[wasm_bindgen(constructor)]
+  pub fn new(grammar: &str) -> JsResult<Fantasy> {
+    let grammar = compile(grammar, HindiLexer::new);
+    let grammar = grammar.map_err(|x| format!("Failed to compile grammar:\n\n{:?}", x));
+    Ok(Self { grammar: grammar.map_err(|x| js_error(x.into()))? })
+  }
+
+  pub fn correct(&self, input: &str, seed: u32, scheme: &str) -> JsResult<JsValue> {
+    to_js(check_scheme(scheme).and_then(|()| {
+      let tree = Parser::new(&self.grammar).parse(input);
+      let tree = tree.ok_or_else(|| unparsed(input))?;
+      let mut rng = rand::rngs::StdRng::seed_from_u64(u64::from(seed));
+      let correction = Corrector::new(&self.grammar).correct(&mut rng, &tree);
+      let value = tree.value.repr();
+      Ok(report(input, u64::from(seed), scheme, value, &tree.matches(), &correction))
+    }))
+  }
+
+  pub fn generate(&self, semantics: &str, seed: u32, scheme: &str) -> JsResult<JsValue> {
+    to_js(check_scheme(scheme).and_then(|()| {
+      let mut rng = rand::rngs::StdRng::seed_from_u64(u64::from(seed));
+      let lambda = Some(Lambda::parse(semantics)?);
+      let tree = Generator::new(&self.grammar).generate(&mut rng, &lambda);
+      let tree = tree.ok_or_else(|| format!("Failed to generate output: {:?}", semantics))?;
+      describe(&tree.matches(), scheme).set_path("value", string(tree.value.repr()))
+    }))
+  }
+
+  pub fn parse(&self, input: &str, scheme: &str) -> JsResult<JsValue> {
+    to_js(check_scheme(scheme).and_then(|()| {
+      let tree = Parser::new(&self.grammar).parse(input);
+      let tree = tree.ok_or_else(|| unparsed(input))?;
+      describe(&tree.matches(), scheme).set_path("value", string(tree.value.repr()))
+    }))
+  }
+}
+
+fn js_error(error: Error) -> JsValue {
+  JsValue::from_str(&format!("{:?}", error))
+}
+
+fn to_js(result: Result<Json>) -> JsResult<JsValue> {
+  js_sys::JSON::parse(&result.map_err(js_error)?.to_string_json())
+}